@@ -0,0 +1,252 @@
+// Chromium based browser driven over the DevTools protocol, used by the
+// Discord browser login
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use dioxus_logger::tracing::{info, warn};
+use serde::Deserialize;
+use which::which;
+
+/// Env var (or `.env` entry) naming the browser to use, either a path or a
+/// command on the PATH, e.g. `BROWSER_PATH=brave-browser`.
+pub const BROWSER_ENV: &str = "BROWSER_PATH";
+
+// Chrome writes its debugging port to the profile once it listens
+const BROWSER_START_TIMEOUT: Duration = Duration::from_secs(20);
+
+// Commands looked up on the PATH, in order of preference
+const BROWSER_COMMANDS: [&str; 9] = [
+    "google-chrome",
+    "google-chrome-stable",
+    "chromium",
+    "chromium-browser",
+    "microsoft-edge",
+    "microsoft-edge-stable",
+    "chrome",
+    "msedge",
+    "brave-browser",
+];
+
+#[derive(Deserialize)]
+struct Target {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(rename = "webSocketDebuggerUrl")]
+    websocket_url: Option<String>,
+}
+
+/// A browser window with a throwaway profile and remote debugging enabled.
+///
+/// The browser is closed and its profile deleted when dropped, so nothing
+/// typed into it outlives the login.
+pub struct Browser {
+    process: Option<Child>,
+    profile_dir: PathBuf,
+    port: u16,
+}
+
+impl Browser {
+    /// Opens `start_url` in a new window of the browser found by
+    /// `find_browser`, and waits until its DevTools endpoint is up.
+    pub async fn launch(start_url: &str) -> Result<Browser, Box<dyn std::error::Error>> {
+        let browser_path = find_browser()?;
+        let profile_dir = std::env::temp_dir().join(format!("lounge-lizard-browser-{}-{}", std::process::id(), unique_suffix()));
+        std::fs::create_dir_all(&profile_dir)?;
+
+        info!("Starting {} for the browser login", browser_path.display());
+        let child = Command::new(&browser_path)
+            .arg(format!("--user-data-dir={}", profile_dir.display()))
+            // Port 0 lets the browser pick a free one, see DevToolsActivePort
+            .arg("--remote-debugging-port=0")
+            .arg("--new-window")
+            .arg("--no-first-run")
+            .arg("--no-default-browser-check")
+            .arg("--disable-extensions")
+            .arg(start_url)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+
+        // Dropped on failure from here on, which removes the profile again
+        let mut browser = Browser { process: None, profile_dir, port: 0 };
+        browser.process = Some(child.map_err(|e| format!("Failed to start {}: {}", browser_path.display(), e))?);
+        browser.port = browser.wait_for_port().await?;
+        Ok(browser)
+    }
+
+    /// DevTools websocket url of the page that was opened.
+    pub async fn page_websocket_url(&self) -> Result<String, Box<dyn std::error::Error>> {
+        let targets: Vec<Target> = reqwest::get(format!("http://127.0.0.1:{}/json", self.port))
+            .await?
+            .json()
+            .await?;
+
+        targets
+            .into_iter()
+            .filter(|target| target.kind == "page")
+            .find_map(|target| target.websocket_url)
+            .ok_or_else(|| "The browser has no open page to watch".into())
+    }
+
+    async fn wait_for_port(&mut self) -> Result<u16, Box<dyn std::error::Error>> {
+        let port_file = self.profile_dir.join("DevToolsActivePort");
+        let started = tokio::time::Instant::now();
+
+        while started.elapsed() < BROWSER_START_TIMEOUT {
+            if let Some(port) = std::fs::read_to_string(&port_file).ok().as_deref().and_then(parse_active_port) {
+                return Ok(port);
+            }
+
+            // A browser that was already running may hand the url to its
+            // own window and exit instead of starting a new instance
+            if let Some(process) = self.process.as_mut() {
+                if let Ok(Some(status)) = process.try_wait() {
+                    return Err(format!("The browser exited before the login page opened ({})", status).into());
+                }
+            }
+
+            tokio::time::sleep(Duration::from_millis(250)).await;
+        }
+
+        Err("The browser did not open its debugging port in time".into())
+    }
+}
+
+impl Drop for Browser {
+    fn drop(&mut self) {
+        if let Some(mut child) = self.process.take() {
+            info!("Closing the login browser");
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+
+        // The browser may still hold files for a moment after exiting
+        for _ in 0..5 {
+            if std::fs::remove_dir_all(&self.profile_dir).is_ok() || !self.profile_dir.exists() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(200));
+        }
+        warn!("Failed to remove the browser profile at {}", self.profile_dir.display());
+    }
+}
+
+/// Finds Chrome, Chromium or Edge.
+///
+/// `BROWSER_PATH` wins when set. Otherwise the usual commands are looked up
+/// on the PATH, then the default install locations of each OS are tried.
+pub fn find_browser() -> Result<PathBuf, String> {
+    dotenv::dotenv().ok();
+    let configured = std::env::var(BROWSER_ENV).ok();
+
+    pick_browser(
+        configured.as_deref(),
+        |command| which(command).ok(),
+        &install_locations(),
+        |path| path.is_file(),
+    )
+}
+
+fn pick_browser(
+    configured: Option<&str>,
+    lookup: impl Fn(&str) -> Option<PathBuf>,
+    install_locations: &[PathBuf],
+    exists: impl Fn(&Path) -> bool,
+) -> Result<PathBuf, String> {
+    if let Some(configured) = configured.map(str::trim).filter(|configured| !configured.is_empty()) {
+        let path = PathBuf::from(configured);
+        if exists(&path) {
+            return Ok(path);
+        }
+        return lookup(configured).ok_or_else(|| format!("{} is set to {}, which was not found", BROWSER_ENV, configured));
+    }
+
+    BROWSER_COMMANDS
+        .iter()
+        .find_map(|command| lookup(command))
+        .or_else(|| install_locations.iter().find(|path| exists(path)).cloned())
+        .ok_or_else(|| format!("No Chrome, Chromium or Edge found. Install one or set {} to the browser to use.", BROWSER_ENV))
+}
+
+// Default install locations of the browsers that aren't on the PATH
+fn install_locations() -> Vec<PathBuf> {
+    let mut locations = Vec::new();
+
+    if cfg!(target_os = "windows") {
+        let roots = ["ProgramFiles", "ProgramFiles(x86)", "LocalAppData"]
+            .iter()
+            .filter_map(|var| std::env::var_os(var).map(PathBuf::from));
+        for root in roots {
+            locations.push(root.join(r"Google\Chrome\Application\chrome.exe"));
+            locations.push(root.join(r"Chromium\Application\chrome.exe"));
+            locations.push(root.join(r"Microsoft\Edge\Application\msedge.exe"));
+        }
+    } else if cfg!(target_os = "macos") {
+        let apps = [
+            "Google Chrome.app/Contents/MacOS/Google Chrome",
+            "Chromium.app/Contents/MacOS/Chromium",
+            "Microsoft Edge.app/Contents/MacOS/Microsoft Edge",
+            "Brave Browser.app/Contents/MacOS/Brave Browser",
+        ];
+        let mut roots = vec![PathBuf::from("/Applications")];
+        if let Some(home) = std::env::var_os("HOME") {
+            roots.push(PathBuf::from(home).join("Applications"));
+        }
+        for root in roots {
+            locations.extend(apps.iter().map(|app| root.join(app)));
+        }
+    } else {
+        locations.extend(
+            [
+                "/usr/bin/google-chrome",
+                "/opt/google/chrome/chrome",
+                "/usr/bin/chromium",
+                "/usr/bin/chromium-browser",
+                "/snap/bin/chromium",
+                "/var/lib/flatpak/exports/bin/org.chromium.Chromium",
+                "/opt/microsoft/msedge/msedge",
+            ]
+            .iter()
+            .map(PathBuf::from),
+        );
+    }
+
+    locations
+}
+
+// First line of DevToolsActivePort is the port, the second the browser's path
+fn parse_active_port(contents: &str) -> Option<u16> {
+    contents.lines().next()?.trim().parse().ok().filter(|port| *port != 0)
+}
+
+fn unique_suffix() -> u128 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_nanos()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_configured_then_path_then_install_location() {
+        let installed = vec![PathBuf::from("/opt/chromium/chrome")];
+        let on_path = |command: &str| (command == "chromium").then(|| PathBuf::from("/usr/bin/chromium"));
+        let exists = |path: &Path| path == Path::new("/opt/chromium/chrome") || path == Path::new("/custom/chrome");
+
+        assert_eq!(pick_browser(Some("/custom/chrome"), on_path, &installed, exists), Ok(PathBuf::from("/custom/chrome")));
+        assert_eq!(pick_browser(Some("chromium"), on_path, &installed, exists), Ok(PathBuf::from("/usr/bin/chromium")));
+        assert!(pick_browser(Some("firefox"), on_path, &installed, exists).is_err());
+
+        assert_eq!(pick_browser(None, on_path, &installed, exists), Ok(PathBuf::from("/usr/bin/chromium")));
+        assert_eq!(pick_browser(Some(" "), |_: &str| None, &installed, exists), Ok(PathBuf::from("/opt/chromium/chrome")));
+        assert!(pick_browser(None, |_: &str| None, &[], exists).is_err());
+    }
+
+    #[test]
+    fn reads_the_active_port() {
+        assert_eq!(parse_active_port("45321\n/devtools/browser/abc\n"), Some(45321));
+        assert_eq!(parse_active_port(""), None);
+        assert_eq!(parse_active_port("0\n"), None);
+    }
+}
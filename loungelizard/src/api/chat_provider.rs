@@ -0,0 +1,234 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+use crate::api::discord::discord_provider::DiscordProvider;
+use crate::api::mongo_format::mongo_structs::{LinkedAccount, User};
+use crate::api::ms_teams::ms_teams_provider::MSTeamsProvider;
+use crate::api::slack::slack_provider::SlackProvider;
+use crate::api::unified_message::{UnifiedMessage, UnifiedReaction};
+
+/// Chat platforms that Lounge Lizard can talk to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Platform {
+    Discord,
+    Slack,
+    MSTeams,
+}
+
+impl Platform {
+    /// Name used for `current_platform` in the pages.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Platform::Discord => "Discord",
+            Platform::Slack => "Slack",
+            Platform::MSTeams => "MSTeams",
+        }
+    }
+}
+
+/// A top level container of channels: a Discord guild (or the DM list),
+/// a Slack team or a Teams team.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatWorkspace {
+    pub id: String,
+    pub name: String,
+    pub icon_url: Option<String>,
+    pub platform: Platform,
+}
+
+/// What a channel is for, so pages can list and open it the right way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ChannelKind {
+    /// A channel of a workspace
+    #[default]
+    Channel,
+    /// A direct or group conversation
+    Direct,
+    /// Holds no messages itself, only posts (threads) that each start with a title
+    Forum,
+    /// A thread that is read and written like a channel (Discord threads and forum posts)
+    Thread { archived: bool },
+}
+
+/// A place messages can be read from and sent to.
+///
+/// `last_activity` is when the channel last had a message, if the platform
+/// tells without reading the channel.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatChannel {
+    pub id: String,
+    pub workspace_id: String,
+    pub name: String,
+    pub platform: Platform,
+    #[serde(default)]
+    pub kind: ChannelKind,
+    #[serde(default)]
+    pub last_activity: Option<DateTime<Utc>>,
+}
+
+/// Which part of a channel's history to read, relative to a message id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HistoryCursor {
+    /// The newest messages
+    Latest,
+    /// Messages older than the given one
+    Before(String),
+    /// Messages newer than the given one
+    After(String),
+    /// Messages on both sides of the given one, including it
+    Around(String),
+}
+
+/// A file sent along with a message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutgoingAttachment {
+    pub filename: String,
+    pub data: Vec<u8>,
+}
+
+/// A message about to be sent.
+///
+/// `reply_to` is quoted on Discord and answered in its thread on Slack and
+/// Teams. `subject` is the title of a Teams post, the other platforms show
+/// it as a bold first line.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct OutgoingMessage {
+    pub content: String,
+    pub subject: Option<String>,
+    pub reply_to: Option<UnifiedMessage>,
+    pub attachment: Option<OutgoingAttachment>,
+}
+
+impl OutgoingMessage {
+    /// Content with the subject put in front of it, for platforms without subjects.
+    /// `bold` wraps a word in the platform's markup.
+    pub fn content_with_subject(&self, bold: impl Fn(&str) -> String) -> String {
+        match self.subject.as_deref().filter(|subject| !subject.is_empty()) {
+            Some(subject) => format!("{}\n{}", bold(subject), self.content),
+            None => self.content.clone(),
+        }
+    }
+}
+
+/// Common set of operations every chat backend supports.
+///
+/// Backends that can't perform an operation return an error describing why
+/// instead of silently succeeding. Pages keep talking to their platform
+/// directly only for what has no counterpart elsewhere, like Discord's
+/// gateway and custom emoji, Slack's event listeners or Teams' delta sync.
+#[async_trait(?Send)]
+pub trait ChatProvider {
+    fn platform(&self) -> Platform;
+
+    async fn list_workspaces(&self) -> Result<Vec<ChatWorkspace>, Box<dyn Error>>;
+
+    async fn list_channels(&self, workspace: &ChatWorkspace) -> Result<Vec<ChatChannel>, Box<dyn Error>>;
+
+    /// Threads kept as channels of their own under `channel`, archived ones
+    /// included. Platforms that keep replies next to their root message have none.
+    async fn list_threads(&self, _channel: &ChatChannel) -> Result<Vec<ChatChannel>, Box<dyn Error>> {
+        Ok(Vec::new())
+    }
+
+    /// Up to `limit` messages of a channel at `cursor`, oldest first.
+    async fn fetch_history(&self, channel: &ChatChannel, cursor: &HistoryCursor, limit: usize) -> Result<Vec<UnifiedMessage>, Box<dyn Error>>;
+
+    async fn send_message(&self, channel: &ChatChannel, message: &OutgoingMessage) -> Result<(), Box<dyn Error>>;
+
+    async fn edit_message(&self, channel: &ChatChannel, message: &UnifiedMessage, content: &str) -> Result<(), Box<dyn Error>>;
+
+    async fn delete_message(&self, channel: &ChatChannel, message: &UnifiedMessage) -> Result<(), Box<dyn Error>>;
+
+    /// `emoji` is a `UnifiedReaction::key` or an emoji picked by the user.
+    async fn add_reaction(&self, channel: &ChatChannel, message: &UnifiedMessage, emoji: &str) -> Result<(), Box<dyn Error>>;
+
+    async fn remove_reaction(&self, channel: &ChatChannel, message: &UnifiedMessage, emoji: &str) -> Result<(), Box<dyn Error>>;
+
+    /// Names of the people who reacted with `reaction`.
+    async fn reaction_users(&self, _channel: &ChatChannel, _message: &UnifiedMessage, reaction: &UnifiedReaction) -> Result<Vec<String>, Box<dyn Error>> {
+        Ok(reaction.users.clone())
+    }
+}
+
+/// Picks the page at `cursor` out of a whole history, for platforms that
+/// can only return all of it. `history` is oldest first.
+pub fn page_of(history: Vec<UnifiedMessage>, cursor: &HistoryCursor, limit: usize) -> Vec<UnifiedMessage> {
+    let position = |id: &str| history.iter().position(|message| message.id == id);
+    let (start, end) = match cursor {
+        HistoryCursor::Latest => (history.len().saturating_sub(limit), history.len()),
+        HistoryCursor::Before(id) => {
+            let end = position(id).unwrap_or(0);
+            (end.saturating_sub(limit), end)
+        }
+        HistoryCursor::After(id) => {
+            let start = position(id).map_or(history.len(), |i| i + 1);
+            (start, (start + limit).min(history.len()))
+        }
+        HistoryCursor::Around(id) => match position(id) {
+            Some(i) => {
+                let start = i.saturating_sub(limit.saturating_sub(1) / 2);
+                (start, (start + limit.max(1)).min(history.len()))
+            }
+            None => (0, 0),
+        },
+    };
+
+    history.into_iter().skip(start).take(end - start).collect()
+}
+
+/// Builds a provider for every linked account of every platform, so a user
+/// with several Slack workspaces, Discord accounts or Teams tenants gets one
+/// per account, not just for the active one.
+pub fn connected_providers(user: &User) -> Vec<Box<dyn ChatProvider>> {
+    let mut providers: Vec<Box<dyn ChatProvider>> = Vec::new();
+
+    for discord in user.discord.iter().filter(|discord| discord.is_linked()) {
+        providers.push(Box::new(DiscordProvider::new(discord)));
+    }
+    for slack in user.slack.iter().filter(|slack| slack.is_linked()) {
+        providers.push(Box::new(SlackProvider::new(slack)));
+    }
+    for ms_teams in user.ms_teams.iter().filter(|ms_teams| ms_teams.is_linked()) {
+        providers.push(Box::new(MSTeamsProvider::new(ms_teams.access_token.clone())));
+    }
+
+    providers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn one_provider_per_linked_account() {
+        let mut user = User::default();
+        user.discord.active_mut().token = "first".to_string();
+        user.discord.add();
+        user.discord.active_mut().token = "second".to_string();
+        user.slack.active_mut().app_id = "A1".to_string();
+        // An account whose login was abandoned has no provider
+        user.ms_teams.add();
+
+        let platforms: Vec<Platform> = connected_providers(&user).iter().map(|provider| provider.platform()).collect();
+        assert_eq!(platforms, [Platform::Discord, Platform::Discord, Platform::Slack]);
+    }
+
+    #[test]
+    fn pages_of_a_whole_history() {
+        let history: Vec<UnifiedMessage> = (1..=5)
+            .filter_map(|id| UnifiedMessage::from_discord(&json!({ "id": id.to_string(), "content": "", "timestamp": "2024-09-26T12:45:00+00:00" })))
+            .collect();
+        let ids = |cursor: HistoryCursor, limit| -> Vec<String> {
+            page_of(history.clone(), &cursor, limit).into_iter().map(|message| message.id).collect()
+        };
+
+        assert_eq!(ids(HistoryCursor::Latest, 2), ["4", "5"]);
+        assert_eq!(ids(HistoryCursor::Before("3".to_string()), 5), ["1", "2"]);
+        assert_eq!(ids(HistoryCursor::After("3".to_string()), 1), ["4"]);
+        assert_eq!(ids(HistoryCursor::Around("3".to_string()), 3), ["2", "3", "4"]);
+        assert!(ids(HistoryCursor::Around("9".to_string()), 3).is_empty());
+    }
+}
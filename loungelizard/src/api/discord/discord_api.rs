@@ -1,56 +1,255 @@
-use reqwest::{multipart, Client};
+use reqwest::multipart;
+use bytes::Bytes;
 use reqwest::header::{AUTHORIZATION, HeaderValue};
 use serde_json::Value;
 use std::error::Error;
 use std::time::Duration;
-use serde::Deserialize;
-use std::process::{Command, Stdio};
-use tokio::time::sleep;
+use tokio::time::timeout;
 use futures_util::{StreamExt, SinkExt}; // Add these for split and send
 use tokio_tungstenite::tungstenite::protocol::Message; // Correct tungstenite import
 use serde_json::json;
 use serde::ser::StdError;
 use tokio_tungstenite::connect_async;
-use std::process::Child; // To manage the child process
-use std::path::PathBuf;
-use std::fs;
+use tracing::warn;
+use crate::api::browser::Browser;
+use crate::api::discord::discord_http;
+use crate::api::discord::discord_emoji::encode_emoji_key;
+use crate::api::chat_provider::HistoryCursor;
+
+// The `token` taken by the REST calls below is the whole Authorization value, which
+// Discord::authorization() builds for user and bot tokens
+
+// Outcome of the password step of a Discord login
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoginResponse {
+    // Logged in, holds the user id and the auth token
+    Token(String, String),
+    // The account has two-factor authentication, a code is needed to finish
+    Mfa(MfaChallenge),
+}
+
+// Second step of a login to an account with two-factor authentication
+#[derive(Debug, Clone, PartialEq)]
+pub struct MfaChallenge {
+    pub user_id: String,
+    // Single use ticket tying the code to the password step
+    pub ticket: String,
+    // Ways the account can be verified, in order of preference
+    pub methods: Vec<MfaMethod>,
+}
+
+// Kinds of codes Discord accepts for the second step
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MfaMethod {
+    Totp,
+    Sms,
+    Backup,
+}
+
+impl MfaMethod {
+    // FUNCTION: Name used in the Discord endpoints and in the login form
+    pub fn key(self) -> &'static str {
+        match self {
+            MfaMethod::Totp => "totp",
+            MfaMethod::Sms => "sms",
+            MfaMethod::Backup => "backup",
+        }
+    }
 
+    // FUNCTION: Reverse of key()
+    pub fn from_key(key: &str) -> Option<MfaMethod> {
+        match key {
+            "totp" => Some(MfaMethod::Totp),
+            "sms" => Some(MfaMethod::Sms),
+            "backup" => Some(MfaMethod::Backup),
+            _ => None,
+        }
+    }
 
-// FUNCTION: Sends login to Discord and returns auth token and user id
-pub async fn login_request(username: String, password: String) -> Result<(String, String), Box<dyn Error>> {
-    let client = Client::new();
+    // FUNCTION: Description shown in the login form
+    pub fn label(self) -> &'static str {
+        match self {
+            MfaMethod::Totp => "Authenticator app",
+            MfaMethod::Sms => "SMS code",
+            MfaMethod::Backup => "Backup code",
+        }
+    }
+}
+
+// FUNCTION: Sends login to Discord and returns the auth token and user id, or the MFA
+// challenge to answer with submit_mfa_code()
+pub async fn login_request(username: String, password: String) -> Result<LoginResponse, Box<dyn Error>> {
     let body = serde_json::json!({ "login": &username, "password": &password });
 
-    let response = client
-        .post("https://discord.com/api/v9/auth/login")
-        .json(&body)
-        .send()
-        .await?;
+    let response = discord_http::send(|client| {
+        client
+            .post("https://discord.com/api/v9/auth/login")
+            .json(&body)
+    })
+    .await?;
+
+    if response.status().is_success() {
+        let json_response: Value = response.json().await?;
+        Ok(parse_login_response(&json_response)?)
+    } else {
+        Err(discord_error("Login request failed", response).await)
+    }
+}
+
+// FUNCTION: Finishes an MFA login with a code of the given kind and returns the auth token
+pub async fn submit_mfa_code(challenge: &MfaChallenge, method: MfaMethod, code: &str) -> Result<String, Box<dyn Error>> {
+    let url = format!("https://discord.com/api/v9/auth/mfa/{}", method.key());
+    // Authenticator and backup codes are often copied with spaces or dashes
+    let code: String = code.chars().filter(|c| c.is_ascii_alphanumeric()).collect();
+    let body = serde_json::json!({ "code": code, "ticket": &challenge.ticket });
+
+    let response = discord_http::send(|client| {
+        client
+            .post(&url)
+            .json(&body)
+    })
+    .await?;
 
     if response.status().is_success() {
         let json_response: Value = response.json().await?;
-        let user_id = json_response["user_id"].as_str()
-            .ok_or("Missing user_id in response")?
-            .to_string();
         let token = json_response["token"].as_str()
             .ok_or("Missing token in response")?
             .to_string();
-        Ok((user_id, token))
+        Ok(token)
     } else {
-        Err(format!("Login request failed with status: {}", response.status()).into())
+        Err(discord_error("Verification failed", response).await)
     }
 }
 
+// FUNCTION: Texts an MFA code to the phone of the account, returns the masked number
+pub async fn send_mfa_sms(challenge: &MfaChallenge) -> Result<String, Box<dyn Error>> {
+    let body = serde_json::json!({ "ticket": &challenge.ticket });
+
+    let response = discord_http::send(|client| {
+        client
+            .post("https://discord.com/api/v9/auth/mfa/sms/send")
+            .json(&body)
+    })
+    .await?;
+
+    if response.status().is_success() {
+        let json_response: Value = response.json().await?;
+        Ok(json_response["phone"].as_str().unwrap_or("your phone").to_string())
+    } else {
+        Err(discord_error("Sending the SMS code failed", response).await)
+    }
+}
+
+// FUNCTION: Reads the answer to /auth/login, which holds either a token or an MFA ticket
+fn parse_login_response(json_response: &Value) -> Result<LoginResponse, String> {
+    let user_id = json_response["user_id"].as_str()
+        .ok_or("Missing user_id in response")?
+        .to_string();
+
+    if let Some(token) = json_response["token"].as_str() {
+        return Ok(LoginResponse::Token(user_id, token.to_string()));
+    }
+
+    if json_response["mfa"].as_bool() != Some(true) {
+        return Err("Missing token in response".to_string());
+    }
+
+    let ticket = json_response["ticket"].as_str()
+        .ok_or("Missing MFA ticket in response")?
+        .to_string();
+    let methods: Vec<MfaMethod> = [MfaMethod::Totp, MfaMethod::Sms, MfaMethod::Backup]
+        .into_iter()
+        .filter(|method| json_response[method.key()].as_bool() == Some(true))
+        .collect();
+
+    if methods.is_empty() {
+        // Security keys only, which can't be used from here
+        return Err("This account only allows security key logins, use the browser login".to_string());
+    }
+
+    Ok(LoginResponse::Mfa(MfaChallenge { user_id, ticket, methods }))
+}
+
+// FUNCTION: Error with Discord's own message when it sent one, e.g. "Invalid two-factor code"
+async fn discord_error(context: &str, response: reqwest::Response) -> Box<dyn Error> {
+    let status = response.status();
+    let body: Value = response.json().await.unwrap_or_default();
+
+    // Field errors are nested, e.g. {"errors": {"login": {"_errors": [{"message": ...}]}}}
+    let message = body["errors"]
+        .as_object()
+        .and_then(|errors| errors.values().next())
+        .and_then(|field| field["_errors"][0]["message"].as_str())
+        .or_else(|| body["message"].as_str());
+
+    match message {
+        Some(message) => format!("{}: {}", context, message).into(),
+        None => format!("{} with status: {}", context, status).into(),
+    }
+}
+
+// Permissions asked for when inviting a bot: view channels, send messages, send messages in
+// threads, read message history, attach files, embed links, add reactions, use external emoji
+pub const BOT_PERMISSIONS: u64 =
+    (1 << 10) | (1 << 11) | (1 << 38) | (1 << 16) | (1 << 15) | (1 << 14) | (1 << 6) | (1 << 18);
+
+// FUNCTION: Gets the account the token belongs to, "bot" is true for bot accounts
+pub async fn get_current_user(token: String) -> Result<Value, Box<dyn Error>> {
+    let authorization = HeaderValue::from_str(&token)?;
+
+    let response = discord_http::send(|client| {
+        client
+            .get("https://discord.com/api/v9/users/@me")
+            .header(AUTHORIZATION, authorization.clone())
+    })
+    .await?;
+
+    if response.status().is_success() {
+        let response_json = response.json().await?;
+        Ok(response_json)
+    } else {
+        Err(format!("Get current user request failed with status: {}", response.status()).into())
+    }
+}
+
+// FUNCTION: Gets the application of a bot token, its id is the client id of the invite url
+pub async fn get_bot_application(token: String) -> Result<Value, Box<dyn Error>> {
+    let authorization = HeaderValue::from_str(&token)?;
+
+    let response = discord_http::send(|client| {
+        client
+            .get("https://discord.com/api/v9/oauth2/applications/@me")
+            .header(AUTHORIZATION, authorization.clone())
+    })
+    .await?;
+
+    if response.status().is_success() {
+        let response_json = response.json().await?;
+        Ok(response_json)
+    } else {
+        Err(format!("Get bot application request failed with status: {}", response.status()).into())
+    }
+}
+
+// FUNCTION: Url a server admin opens to add the bot to a server with BOT_PERMISSIONS
+pub fn bot_invite_url(application_id: &str) -> String {
+    format!(
+        "https://discord.com/oauth2/authorize?client_id={}&scope=bot&permissions={}",
+        application_id, BOT_PERMISSIONS
+    )
+}
+
 // FUNCTION: Get user's DM's
 pub async fn get_channels(token: String) -> Result<Value, Box<dyn Error>> {
-    let client = Client::new();
+    let authorization = HeaderValue::from_str(&token)?;
     let url = "https://discord.com/api/v9/users/@me/channels";
 
-    let response = client
-        .get(url)
-        .header(AUTHORIZATION, HeaderValue::from_str(&token)?)
-        .send()
-        .await?;
+    let response = discord_http::send(|client| {
+        client
+            .get(url)
+            .header(AUTHORIZATION, authorization.clone())
+    })
+    .await?;
 
     if response.status().is_success() {
         let response_json = response.json().await?;
@@ -60,15 +259,16 @@ pub async fn get_channels(token: String) -> Result<Value, Box<dyn Error>> {
     }
 }
 
-// FUNCTION: Gets user's servers (guilds)
+// FUNCTION: Gets user's servers (guilds), or the servers a bot was added to
 pub async fn get_guilds(token: String) -> Result<Value, Box<dyn Error>> {
-    let client = Client::new();
+    let authorization = HeaderValue::from_str(&token)?;
 
-    let response = client
-        .get("https://discord.com/api/v9/users/@me/guilds")
-        .header(AUTHORIZATION, HeaderValue::from_str(&token)?)
-        .send()
-        .await?;
+    let response = discord_http::send(|client| {
+        client
+            .get("https://discord.com/api/v9/users/@me/guilds")
+            .header(AUTHORIZATION, authorization.clone())
+    })
+    .await?;
 
     if response.status().is_success() {
         let response_json = response.json().await?;
@@ -80,14 +280,15 @@ pub async fn get_guilds(token: String) -> Result<Value, Box<dyn Error>> {
 
 // FUNCTION: Get server channels
 pub async fn get_guild_channels(token: String, guild_id: String) -> Result<Value, Box<dyn Error>> {
-    let client = Client::new();
+    let authorization = HeaderValue::from_str(&token)?;
     let url = format!("https://discord.com/api/v9/guilds/{}/channels", guild_id);
 
-    let response = client
-        .get(&url)
-        .header(AUTHORIZATION, HeaderValue::from_str(&token)?)
-        .send()
-        .await?;
+    let response = discord_http::send(|client| {
+        client
+            .get(&url)
+            .header(AUTHORIZATION, authorization.clone())
+    })
+    .await?;
 
     if response.status().is_success() {
         let response_json = response.json().await?;
@@ -97,18 +298,19 @@ pub async fn get_guild_channels(token: String, guild_id: String) -> Result<Value
     }
 }
 
-// FUNCTION: Sends message to a server channel
-pub async fn send_message(token: String, channel_id: String, message: String) -> Result<Value, Box<dyn Error>> {
-    let client = Client::new();
+// FUNCTION: Sends message to a server channel, as a reply when reply_to holds a message id
+pub async fn send_message(token: String, channel_id: String, message: String, reply_to: Option<String>) -> Result<Value, Box<dyn Error>> {
+    let authorization = HeaderValue::from_str(&token)?;
     let url = format!("https://discord.com/api/v9/channels/{}/messages", channel_id);
-    let body = serde_json::json!({ "content": message });
+    let body = message_body(message, reply_to);
 
-    let response = client
-        .post(&url)
-        .header(AUTHORIZATION, HeaderValue::from_str(&token)?)
-        .json(&body)
-        .send()
-        .await?;
+    let response = discord_http::send(|client| {
+        client
+            .post(&url)
+            .header(AUTHORIZATION, authorization.clone())
+            .json(&body)
+    })
+    .await?;
 
     if response.status().is_success() {
         let response_json = response.json().await?;
@@ -118,27 +320,30 @@ pub async fn send_message(token: String, channel_id: String, message: String) ->
     }
 }
 
-// FUNCTION: Sends message to a server channel
-pub async fn send_message_attachment(token: String, channel_id: String, message: String, attachment: Vec<u8>, attachment_name: String) -> Result<Value, Box<dyn Error>> {
-    let client = Client::new();
+// FUNCTION: Sends message with a file to a server channel, as a reply when reply_to holds a message id
+pub async fn send_message_attachment(token: String, channel_id: String, message: String, attachment: Vec<u8>, attachment_name: String, reply_to: Option<String>) -> Result<Value, Box<dyn Error>> {
+    let authorization = HeaderValue::from_str(&token)?;
     let url = format!("https://discord.com/api/v9/channels/{}/messages", channel_id);
-    let body = serde_json::json!({ "content": message });
-
-    // Create a multipart form with the file content and the message content
-    let form = multipart::Form::new()
-        .text("content", message) // Add the message content as a text part
-        .part(
-            "files[0]", // The name of the part that Discord expects for file attachments
-            multipart::Part::bytes(attachment)
-                .file_name(attachment_name), // Add the file as a multipart part with a file name
-        );
-
-    let response = client
-        .post(&url)
-        .header(AUTHORIZATION, HeaderValue::from_str(&token)?)
-        .multipart(form)
-        .send()
-        .await?;
+    let body = message_body(message, reply_to);
+    // Shared by the retries instead of copying the file for each
+    let attachment = Bytes::from(attachment);
+
+    let response = discord_http::send(|client| {
+        // Create a multipart form with the file content and the message content, again for every retry
+        let form = multipart::Form::new()
+            .text("payload_json", body.to_string()) // Content and reply reference go in the JSON part
+            .part(
+                "files[0]", // The name of the part that Discord expects for file attachments
+                multipart::Part::stream(attachment.clone())
+                    .file_name(attachment_name.clone()), // Add the file as a multipart part with a file name
+            );
+
+        client
+            .post(&url)
+            .header(AUTHORIZATION, authorization.clone())
+            .multipart(form)
+    })
+    .await?;
 
     if response.status().is_success() {
         let response_json = response.json().await?;
@@ -148,24 +353,27 @@ pub async fn send_message_attachment(token: String, channel_id: String, message:
     }
 }
 
-// FUNCTION: Sends a reaction to a message in a server channel
-pub async fn send_reaction(token: String, channel_id: String, message_id: String, emoji: String) -> Result<(), Box<dyn Error>> {
-    let client = Client::new();
-    let emoji_clean = strip_quotes(&emoji); // Remove quotes if present
-    let message_id_clean = strip_quotes(&message_id);
+// FUNCTION: JSON body of a new message, message_reference makes it a reply
+fn message_body(message: String, reply_to: Option<String>) -> Value {
+    let mut body = json!({ "content": message });
+    if let Some(message_id) = reply_to {
+        // Replying to a deleted message sends a plain message instead of failing
+        body["message_reference"] = json!({ "message_id": message_id, "fail_if_not_exists": false });
+    }
+    body
+}
 
-    let url = format!(
-        "https://discord.com/api/v9/channels/{}/messages/{}/reactions/{}/@me",
-        channel_id, message_id_clean, emoji_clean
-    );
-    println!("send_reaction received emoji: {}", emoji_clean);
-    println!("url is: {}", url);
+// FUNCTION: Sends a reaction to a message in a server channel. Custom emoji are given as `name:id`
+pub async fn send_reaction(token: String, channel_id: String, message_id: String, emoji: String) -> Result<(), Box<dyn Error>> {
+    let authorization = HeaderValue::from_str(&token)?;
+    let url = format!("{}/@me", reactions_url(&channel_id, &message_id, &emoji));
 
-    let response = client
-        .put(&url)
-        .header(AUTHORIZATION, HeaderValue::from_str(&token)?)
-        .send()
-        .await?;
+    let response = discord_http::send(|client| {
+        client
+            .put(&url)
+            .header(AUTHORIZATION, authorization.clone())
+    })
+    .await?;
 
     if response.status().is_success() {
         Ok(())
@@ -174,24 +382,217 @@ pub async fn send_reaction(token: String, channel_id: String, message_id: String
     }
 }
 
+// FUNCTION: Takes back the user's own reaction to a message
+pub async fn remove_reaction(token: String, channel_id: String, message_id: String, emoji: String) -> Result<(), Box<dyn Error>> {
+    let authorization = HeaderValue::from_str(&token)?;
+    let url = format!("{}/@me", reactions_url(&channel_id, &message_id, &emoji));
 
-// FUNCTION: Get messages from a channel
-pub async fn get_messages(token: String, channel_id: String) -> Result<Value, Box<dyn Error>> {
-    let client = Client::builder()
-    .timeout(Duration::from_secs(2)) // Set a 2-second timeout
-    .build()?;
-    let url = format!("https://discord.com/api/v9/channels/{}/messages", channel_id);
-    
-    println!("in get messages");
+    let response = discord_http::send(|client| {
+        client
+            .delete(&url)
+            .header(AUTHORIZATION, authorization.clone())
+    })
+    .await?;
+
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("Remove reaction request failed with status: {}", response.status()).into())
+    }
+}
 
-    let response = client
-        .get(&url)
-        .header(AUTHORIZATION, HeaderValue::from_str(&token)?)
-        .send()
-        .await?;
+// FUNCTION: Users who reacted to a message with an emoji, up to the first 100
+pub async fn get_reaction_users(token: String, channel_id: String, message_id: String, emoji: String) -> Result<Value, Box<dyn Error>> {
+    let authorization = HeaderValue::from_str(&token)?;
+    let url = format!("{}?limit=100", reactions_url(&channel_id, &message_id, &emoji));
+
+    let response = discord_http::send(|client| {
+        client
+            .get(&url)
+            .header(AUTHORIZATION, authorization.clone())
+    })
+    .await?;
+
+    if response.status().is_success() {
+        let response_json = response.json().await?;
+        Ok(response_json)
+    } else {
+        Err(format!("Get reaction users request failed with status: {}", response.status()).into())
+    }
+}
+
+// FUNCTION: Url of one emoji's reactions on a message
+fn reactions_url(channel_id: &str, message_id: &str, emoji: &str) -> String {
+    format!(
+        "https://discord.com/api/v9/channels/{}/messages/{}/reactions/{}",
+        channel_id,
+        strip_quotes(message_id),
+        encode_emoji_key(strip_quotes(emoji))
+    )
+}
+
+// FUNCTION: Custom emoji of a server
+pub async fn get_guild_emojis(token: String, guild_id: String) -> Result<Value, Box<dyn Error>> {
+    let authorization = HeaderValue::from_str(&token)?;
+    let url = format!("https://discord.com/api/v9/guilds/{}/emojis", guild_id);
+
+    let response = discord_http::send(|client| {
+        client
+            .get(&url)
+            .header(AUTHORIZATION, authorization.clone())
+    })
+    .await?;
+
+    if response.status().is_success() {
+        let response_json = response.json().await?;
+        Ok(response_json)
+    } else {
+        Err(format!("Get guild emojis request failed with status: {}", response.status()).into())
+    }
+}
+
+
+// FUNCTION: Changes the text of a message the user sent, returns the updated message
+pub async fn edit_message(token: String, channel_id: String, message_id: String, message: String) -> Result<Value, Box<dyn Error>> {
+    let authorization = HeaderValue::from_str(&token)?;
+    let url = format!("https://discord.com/api/v9/channels/{}/messages/{}", channel_id, message_id);
+    let body = serde_json::json!({ "content": message });
+
+    let response = discord_http::send(|client| {
+        client
+            .patch(&url)
+            .header(AUTHORIZATION, authorization.clone())
+            .json(&body)
+    })
+    .await?;
+
+    if response.status().is_success() {
+        let response_json = response.json().await?;
+        Ok(response_json)
+    } else {
+        Err(format!("Edit message request failed with status: {}", response.status()).into())
+    }
+}
+
+// FUNCTION: Deletes a message the user sent
+pub async fn delete_message(token: String, channel_id: String, message_id: String) -> Result<(), Box<dyn Error>> {
+    let authorization = HeaderValue::from_str(&token)?;
+    let url = format!("https://discord.com/api/v9/channels/{}/messages/{}", channel_id, message_id);
+
+    let response = discord_http::send(|client| {
+        client
+            .delete(&url)
+            .header(AUTHORIZATION, authorization.clone())
+    })
+    .await?;
+
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("Delete message request failed with status: {}", response.status()).into())
+    }
+}
+
+// Channel types that only hold posts, each post being a thread: forum and media channels
+pub const FORUM_CHANNEL_TYPES: [u64; 2] = [15, 16];
+// Channel types with messages of their own that threads can be started from: text and announcement
+pub const THREAD_PARENT_CHANNEL_TYPES: [u64; 2] = [0, 5];
+
+// FUNCTION: Active threads of a channel. Discord only lists them per server,
+// so the server's threads are filtered down to the ones started in the channel.
+pub async fn get_active_threads(token: String, guild_id: String, channel_id: String) -> Result<Vec<Value>, Box<dyn Error>> {
+    let authorization = HeaderValue::from_str(&token)?;
+    let url = format!("https://discord.com/api/v9/guilds/{}/threads/active", guild_id);
+
+    let response = discord_http::send(|client| {
+        client
+            .get(&url)
+            .header(AUTHORIZATION, authorization.clone())
+    })
+    .await?;
+
+    if response.status().is_success() {
+        let response_json: Value = response.json().await?;
+        Ok(threads_in_channel(&response_json, &channel_id))
+    } else {
+        Err(format!("Get active threads request failed with status: {}", response.status()).into())
+    }
+}
+
+// FUNCTION: Public archived threads of a channel, most recently archived first
+pub async fn get_archived_threads(token: String, channel_id: String) -> Result<Vec<Value>, Box<dyn Error>> {
+    let authorization = HeaderValue::from_str(&token)?;
+    let url = format!("https://discord.com/api/v9/channels/{}/threads/archived/public", channel_id);
+
+    let response = discord_http::send(|client| {
+        client
+            .get(&url)
+            .header(AUTHORIZATION, authorization.clone())
+    })
+    .await?;
+
+    if response.status().is_success() {
+        let response_json: Value = response.json().await?;
+        Ok(threads_in_channel(&response_json, &channel_id))
+    } else {
+        Err(format!("Get archived threads request failed with status: {}", response.status()).into())
+    }
+}
+
+// FUNCTION: Threads of a thread list response that belong to the given channel
+fn threads_in_channel(response: &Value, channel_id: &str) -> Vec<Value> {
+    response["threads"]
+        .as_array()
+        .map(|threads| {
+            threads
+                .iter()
+                .filter(|thread| thread["parent_id"].as_str() == Some(channel_id))
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// FUNCTION: Creates a post in a forum or media channel, returns the new thread.
+// The thread's id is also the id of the post's first message.
+pub async fn create_forum_post(token: String, channel_id: String, title: String, message: String) -> Result<Value, Box<dyn Error>> {
+    let authorization = HeaderValue::from_str(&token)?;
+    let url = format!("https://discord.com/api/v9/channels/{}/threads", channel_id);
+    let body = json!({ "name": title, "message": { "content": message } });
+
+    let response = discord_http::send(|client| {
+        client
+            .post(&url)
+            .header(AUTHORIZATION, authorization.clone())
+            .json(&body)
+    })
+    .await?;
+
+    if response.status().is_success() {
+        let response_json = response.json().await?;
+        Ok(response_json)
+    } else {
+        Err(discord_error("Create forum post", response).await)
+    }
+}
+
+// Messages per page, Discord allows up to 100
+pub const MESSAGE_PAGE_SIZE: usize = 50;
+
+// FUNCTION: Get a page of messages from a channel, newest first
+pub async fn get_messages_page(token: String, channel_id: String, cursor: &HistoryCursor, limit: usize) -> Result<Value, Box<dyn Error>> {
+    let authorization = HeaderValue::from_str(&token)?;
+    let url = messages_url(&channel_id, cursor, limit);
+
+    let response = discord_http::send(|client| {
+        client
+            .get(&url)
+            .header(AUTHORIZATION, authorization.clone())
+            .timeout(Duration::from_secs(2)) // Set a 2-second timeout
+    })
+    .await?;
 
     if response.status().is_success() {
-        println!("response success");
         let response_json = response.json().await?;
         Ok(response_json)
     } else {
@@ -199,125 +600,155 @@ pub async fn get_messages(token: String, channel_id: String) -> Result<Value, Bo
     }
 }
 
+// FUNCTION: Url of a page of channel messages
+fn messages_url(channel_id: &str, cursor: &HistoryCursor, limit: usize) -> String {
+    let mut url = format!(
+        "https://discord.com/api/v9/channels/{}/messages?limit={}",
+        channel_id,
+        limit.clamp(1, 100)
+    );
+
+    match cursor {
+        HistoryCursor::Latest => {}
+        HistoryCursor::Before(id) => url.push_str(&format!("&before={}", id)),
+        HistoryCursor::After(id) => url.push_str(&format!("&after={}", id)),
+        HistoryCursor::Around(id) => url.push_str(&format!("&around={}", id)),
+    }
+    url
+}
+
 fn strip_quotes(s: &str) -> &str {
     s.trim_matches('"')
 }
 
-#[derive(Deserialize)]
-struct Tab {
-    id: String,
-    webSocketDebuggerUrl: String,
-}
+// Discord may take a while to log in when a captcha or MFA code is asked for
+const BROWSER_LOGIN_TIMEOUT: Duration = Duration::from_secs(5 * 60);
 
+// FUNCTION: Opens the Discord login page in Chrome, Chromium or Edge and returns the token of
+// the first authorized request. None if the browser was closed before logging in.
 pub async fn launch_chrome_and_monitor_auth() -> Result<Option<String>, Box<dyn StdError>> {
-    // Step 1: Specify the path to your existing Chrome profile
-    let start_url = "https://discord.com/login";
-
-    // Step 2: Open Chrome with remote debugging enabled and use the existing profile
-    let chrome_path = "C:\\Program Files\\Google\\Chrome\\Application\\chrome.exe";
-    //let user_data_dir = r"C:\TempProfile"; // Path to the newly copied Chrome profile
-    let user_data_dir = std::env::current_dir()
-        .map(|path| path.join("resources").join("TempProfile"))
-        .unwrap_or_else(|_| PathBuf::from("resources/TempProfile"));
-
-    // Convert to a string for passing to the command
-    let user_data_dir_str = user_data_dir.to_str().unwrap_or_else(|| {
-        panic!("Failed to convert user_data_dir to a string");
-    });
+    // Closed, and its profile deleted, when it goes out of scope
+    let browser = Browser::launch("https://discord.com/login").await?;
+    let websocket_url = browser.page_websocket_url().await?;
 
-    // Launch Chrome with remote debugging enabled
-    let mut command = Command::new(chrome_path)
-    .arg(format!("--user-data-dir={}", user_data_dir_str))
-    .arg("--remote-debugging-port=9222")
-    .arg("--new-instance") // Ensure Chrome opens a new window
-    .arg("--no-first-run")
-    .arg("--no-default-browser-check") // Skip default browser prompt
-    .arg("--disable-extensions") // Optional: Disable extensions
-    .arg("--incognito")
-    .arg(start_url)
-    .stdout(Stdio::piped())
-    .stderr(Stdio::null())
-    .spawn();
-
-    let mut chrome_process: Option<Child> = match command {
-        Ok(child) => Some(child),
-        Err(e) => {
-            eprintln!("Failed to start Chrome: {}", e);
-            return Ok(None);
-        }
-    };
+    // Monitor the network traffic of the page for the authorization header
+    let (ws_stream, _) = connect_async(websocket_url.as_str()).await?;
+    let (mut write, read) = ws_stream.split();
 
-    // Wait for Chrome to start
-    println!("Waiting for Chrome to start...");
-    sleep(Duration::from_secs(2)).await; // Wait 2 seconds for Chrome to fully start
+    let enable_network = json!({
+        "id": 1,
+        "method": "Network.enable",
+    });
+    write.send(Message::Text(enable_network.to_string())).await?;
 
-    // Query the debugging endpoint to get the WebSocket debugger URL
-    let url = "http://localhost:9222/json";
-    let client = Client::new();
-    let response = client.get(url).send().await?;
+    match timeout(BROWSER_LOGIN_TIMEOUT, capture_authorization(read)).await {
+        Ok(token) => Ok(token),
+        Err(_) => Err("Timed out waiting for the Discord login in the browser".into()),
+    }
+}
 
-    if response.status().is_success() {
-        let tabs: Vec<Tab> = response.json().await?;
-        if let Some(tab) = tabs.first() {
-            let websocket_url = &tab.webSocketDebuggerUrl;
-            println!("WebSocket Debugger URL: {}", websocket_url);
-
-            // Now, monitor the network traffic and capture the authorization header
-            let (ws_stream, _) = connect_async(websocket_url).await?;
-            let (mut write, mut read) = ws_stream.split();
-
-            // Enable Network domain in CDP
-            let enable_network = json!({
-                "id": 1,
-                "method": "Network.enable",
-            });
-            write.send(Message::Text(enable_network.to_string())).await?;
-            println!("Sent Network.enable command");
-
-            // Monitor WebSocket messages for the authorization header
-            while let Some(msg) = read.next().await {
-                match msg {
-                    Ok(Message::Text(text)) => {
-                        let parsed: serde_json::Value = match serde_json::from_str(&text) {
-                            Ok(parsed) => parsed,
-                            Err(e) => {
-                                eprintln!("Failed to parse WebSocket message: {}", e);
-                                continue;
-                            }
-                        };
-                        
-                        // Log all received messages for debugging
-                        println!("Received message: {}", serde_json::to_string_pretty(&parsed).unwrap());
-        
-                        // Check for 'Network.requestWillBeSent' or 'Network.responseReceived' methods
-                        if let Some(method) = parsed["method"].as_str() {
-                            if method == "Network.responseReceived" || method == "Network.requestWillBeSent" {
-                                if let Some(params) = parsed["params"].as_object() {
-                                    // Handle response data safely by checking the key existence
-                                    if let Some(request) = params.get("request") {
-                                        if let Some(headers) = request.get("headers").and_then(|h| h.as_object()) {
-                                            if let Some(auth_header) = headers.get("Authorization") {
-                                                println!("Authorization Header Found: {}", auth_header);
-                                                let token = auth_header.as_str().unwrap_or_default().to_string();
-                                                // Close the Chrome window and end the program
-                                                if let Some(mut process) = chrome_process.take() {
-                                                    process.kill().expect("Failed to kill Chrome process");
-                                                    println!("Chrome window closed.");
-                                                }
-                                                return Ok(Some(token));  // Return the captured token
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    },
-                    Err(e) => eprintln!("Error reading WebSocket message: {}", e),
-                    _ => {}
+// FUNCTION: Reads DevTools events until a request carries an Authorization header
+async fn capture_authorization<S>(mut read: S) -> Option<String>
+where
+    S: futures_util::Stream<Item = Result<Message, tokio_tungstenite::tungstenite::Error>> + Unpin,
+{
+    while let Some(msg) = read.next().await {
+        match msg {
+            Ok(Message::Text(text)) => {
+                let parsed: Value = match serde_json::from_str(&text) {
+                    Ok(parsed) => parsed,
+                    Err(e) => {
+                        warn!("Failed to parse DevTools message: {}", e);
+                        continue;
+                    }
+                };
+
+                if let Some(token) = authorization_header(&parsed) {
+                    return Some(token);
                 }
             }
+            Ok(Message::Close(_)) => break,
+            Err(e) => {
+                // The browser was closed
+                warn!("DevTools connection ended: {}", e);
+                break;
+            }
+            _ => {}
         }
     }
+    None
+}
+
+// FUNCTION: Authorization header of a Network.requestWillBeSent event, if any
+fn authorization_header(event: &Value) -> Option<String> {
+    if event["method"].as_str() != Some("Network.requestWillBeSent") {
+        return None;
+    }
+
+    event["params"]["request"]["headers"]["Authorization"]
+        .as_str()
+        .filter(|token| !token.is_empty())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_mfa_login_responses() {
+        let logged_in = json!({ "user_id": "1", "token": "abc" });
+        assert_eq!(parse_login_response(&logged_in), Ok(LoginResponse::Token("1".to_string(), "abc".to_string())));
+
+        let mfa = json!({ "user_id": "1", "mfa": true, "ticket": "t", "totp": true, "sms": false, "backup": true });
+        assert_eq!(
+            parse_login_response(&mfa),
+            Ok(LoginResponse::Mfa(MfaChallenge {
+                user_id: "1".to_string(),
+                ticket: "t".to_string(),
+                methods: vec![MfaMethod::Totp, MfaMethod::Backup],
+            }))
+        );
+
+        let webauthn_only = json!({ "user_id": "1", "mfa": true, "ticket": "t", "totp": false, "webauthn": "{}" });
+        assert!(parse_login_response(&webauthn_only).is_err());
+        assert!(parse_login_response(&json!({ "user_id": "1" })).is_err());
+    }
 
-    Err("Failed to retrieve the WebSocket URL or capture authorization header.".into())
-}
\ No newline at end of file
+    #[test]
+    fn builds_message_page_urls() {
+        assert_eq!(
+            messages_url("10", &HistoryCursor::Latest, 50),
+            "https://discord.com/api/v9/channels/10/messages?limit=50"
+        );
+        assert_eq!(
+            messages_url("10", &HistoryCursor::Before("99".to_string()), 500),
+            "https://discord.com/api/v9/channels/10/messages?limit=100&before=99"
+        );
+        assert!(messages_url("10", &HistoryCursor::Around("99".to_string()), 0).ends_with("?limit=1&around=99"));
+    }
+
+    #[test]
+    fn invites_bots_with_the_needed_permissions() {
+        let url = bot_invite_url("1234");
+        assert!(url.starts_with("https://discord.com/oauth2/authorize?client_id=1234&scope=bot&permissions="));
+        assert!(url.ends_with(&BOT_PERMISSIONS.to_string()));
+        assert_eq!(BOT_PERMISSIONS, 1u64 << 38 | 1 << 16 | 1 << 11 | 1 << 10 | 1 << 18 | 1 << 15 | 1 << 14 | 1 << 6);
+    }
+
+    #[test]
+    fn replies_and_threads_of_a_channel() {
+        assert_eq!(message_body("hi".to_string(), None), json!({ "content": "hi" }));
+        assert_eq!(message_body("hi".to_string(), Some("7".to_string()))["message_reference"]["message_id"], "7");
+
+        let response = json!({ "threads": [
+            { "id": "1", "parent_id": "10", "name": "a" },
+            { "id": "2", "parent_id": "20", "name": "b" },
+            { "id": "3", "parent_id": "10", "name": "c" }
+        ], "members": [] });
+        let threads = threads_in_channel(&response, "10");
+        let names: Vec<&str> = threads.iter().map(|thread| thread["name"].as_str().unwrap()).collect();
+        assert_eq!(names, ["a", "c"]);
+        assert!(threads_in_channel(&json!({}), "10").is_empty());
+    }
+}
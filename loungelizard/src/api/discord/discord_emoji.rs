@@ -0,0 +1,98 @@
+use serde_json::Value;
+
+// Offered in the reaction picker next to the server's own emoji
+pub const STANDARD_REACTIONS: [&str; 10] = ["👍", "👎", "❤️", "😂", "😮", "😢", "🎉", "👀", "❗", "❓"];
+
+// An emoji as Discord sends it in reactions and emoji lists. Custom (server) emoji
+// have an id and are shown from the CDN, standard ones only have the character as name.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DiscordEmoji {
+    pub name: String,
+    pub id: Option<String>,
+    pub animated: bool,
+}
+
+impl DiscordEmoji {
+    // FUNCTION: Reads an emoji object, e.g. {"id": "41", "name": "lizard", "animated": false}
+    pub fn from_json(emoji: &Value) -> DiscordEmoji {
+        DiscordEmoji {
+            name: emoji["name"].as_str().unwrap_or_default().to_string(),
+            id: emoji["id"].as_str().map(|id| id.to_string()),
+            animated: emoji["animated"].as_bool().unwrap_or(false),
+        }
+    }
+
+    // FUNCTION: How the reaction endpoints name the emoji, `name:id` or the character itself
+    pub fn key(&self) -> String {
+        match &self.id {
+            Some(id) => format!("{}:{}", self.name, id),
+            None => self.name.clone(),
+        }
+    }
+
+    // FUNCTION: Image of a custom emoji, None for standard ones
+    pub fn image_url(&self) -> Option<String> {
+        self.id.as_ref().map(|id| {
+            format!("https://cdn.discordapp.com/emojis/{}.{}", id, if self.animated { "gif" } else { "png" })
+        })
+    }
+
+    // FUNCTION: Text to show where the image can't be, custom emoji as :name:
+    pub fn label(&self) -> String {
+        match self.id {
+            Some(_) => format!(":{}:", self.name),
+            None => self.name.clone(),
+        }
+    }
+}
+
+// FUNCTION: Custom emoji of a server's emoji list that can be used right now
+pub fn usable_emojis(emojis: &Value) -> Vec<DiscordEmoji> {
+    emojis
+        .as_array()
+        .into_iter()
+        .flatten()
+        // Emoji of a lapsed server boost stay listed but are unavailable
+        .filter(|emoji| emoji["available"].as_bool().unwrap_or(true))
+        .map(DiscordEmoji::from_json)
+        .filter(|emoji| emoji.id.is_some())
+        .collect()
+}
+
+// FUNCTION: Percent-encodes an emoji key for use in a url path
+pub fn encode_emoji_key(key: &str) -> String {
+    key.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'_' | b'-' | b'.' | b'~' | b':' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn custom_and_standard_emoji() {
+        let custom = DiscordEmoji::from_json(&json!({ "id": "41", "name": "party_lizard", "animated": true }));
+        assert_eq!(custom.key(), "party_lizard:41");
+        assert_eq!(custom.image_url().as_deref(), Some("https://cdn.discordapp.com/emojis/41.gif"));
+
+        let standard = DiscordEmoji::from_json(&json!({ "id": null, "name": "👍" }));
+        assert_eq!(standard.key(), "👍");
+        assert_eq!(standard.image_url(), None);
+
+        assert_eq!(encode_emoji_key("party_lizard:41"), "party_lizard:41");
+        assert_eq!(encode_emoji_key("👍"), "%F0%9F%91%8D");
+
+        let listed = json!([
+            { "id": "1", "name": "a", "available": true },
+            { "id": "2", "name": "b", "available": false },
+            { "id": "3", "name": "c" }
+        ]);
+        let names: Vec<String> = usable_emojis(&listed).into_iter().map(|emoji| emoji.name).collect();
+        assert_eq!(names, ["a", "c"]);
+    }
+}
@@ -0,0 +1,336 @@
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::error::Error;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::time::{interval_at, sleep, Instant};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::protocol::Message;
+use tracing::{info, warn};
+
+use crate::api::discord::discord_emoji::DiscordEmoji;
+use crate::api::unified_message::UnifiedMessage;
+
+const GATEWAY_URL: &str = "wss://gateway.discord.gg";
+const GATEWAY_QUERY: &str = "/?v=9&encoding=json";
+
+// Gateway opcodes
+const OP_DISPATCH: u64 = 0;
+const OP_HEARTBEAT: u64 = 1;
+const OP_IDENTIFY: u64 = 2;
+const OP_RESUME: u64 = 6;
+const OP_RECONNECT: u64 = 7;
+const OP_INVALID_SESSION: u64 = 9;
+const OP_HELLO: u64 = 10;
+const OP_HEARTBEAT_ACK: u64 = 11;
+
+// GUILD_MESSAGES | GUILD_MESSAGE_REACTIONS | DIRECT_MESSAGES | DIRECT_MESSAGE_REACTIONS | MESSAGE_CONTENT
+const INTENTS: u64 = (1 << 9) | (1 << 10) | (1 << 12) | (1 << 13) | (1 << 15);
+
+// Reconnect backoff bounds
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+// Close codes after which reconnecting is pointless (bad token, bad intents, ...)
+const FATAL_CLOSE_CODES: [u16; 6] = [4004, 4010, 4011, 4012, 4013, 4014];
+
+// Events forwarded to the UI
+#[derive(Debug, Clone, PartialEq)]
+pub enum GatewayEvent {
+    // Sent after every fresh identify, anything before it may have been missed
+    Ready { user_id: String },
+    MessageCreate(UnifiedMessage),
+    // Partial updates (embeds resolving, ...) carry no author and are skipped
+    MessageUpdate(UnifiedMessage),
+    MessageDelete { channel_id: String, message_id: String },
+    ReactionAdd { channel_id: String, message_id: String, emoji: DiscordEmoji, user_id: String, me: bool },
+    ReactionRemove { channel_id: String, message_id: String, emoji: DiscordEmoji, user_id: String, me: bool },
+}
+
+impl GatewayEvent {
+    // FUNCTION: Channel the event belongs to, if any
+    pub fn channel_id(&self) -> Option<&str> {
+        match self {
+            GatewayEvent::Ready { .. } => None,
+            GatewayEvent::MessageCreate(message) | GatewayEvent::MessageUpdate(message) => Some(&message.channel_id),
+            GatewayEvent::MessageDelete { channel_id, .. }
+            | GatewayEvent::ReactionAdd { channel_id, .. }
+            | GatewayEvent::ReactionRemove { channel_id, .. } => Some(channel_id),
+        }
+    }
+}
+
+// Resume state kept between connections
+#[derive(Debug, Clone, Default)]
+struct GatewaySession {
+    session_id: Option<String>,
+    resume_url: Option<String>,
+    sequence: Option<u64>,
+    user_id: String,
+}
+
+impl GatewaySession {
+    fn can_resume(&self) -> bool {
+        self.session_id.is_some() && self.sequence.is_some()
+    }
+
+    fn reset(&mut self) {
+        self.session_id = None;
+        self.resume_url = None;
+        self.sequence = None;
+    }
+}
+
+// Why a connection ended
+enum Disconnect {
+    // Try to resume the session on the next connection
+    Resume,
+    // Session is gone, identify again
+    Reidentify,
+    // Don't reconnect
+    Fatal(String),
+}
+
+// FUNCTION: Keeps a gateway connection open and forwards message events until the task is dropped
+pub async fn run_gateway(token: String, events: broadcast::Sender<GatewayEvent>) {
+    let mut session = GatewaySession::default();
+    let mut backoff = MIN_BACKOFF;
+
+    loop {
+        let started = Instant::now();
+
+        match connect(&token, &mut session, &events).await {
+            Ok(Disconnect::Resume) => info!("Discord gateway disconnected, resuming"),
+            Ok(Disconnect::Reidentify) => {
+                info!("Discord gateway session invalidated, identifying again");
+                session.reset();
+            }
+            Ok(Disconnect::Fatal(reason)) => {
+                warn!("Discord gateway stopped: {}", reason);
+                return;
+            }
+            Err(e) => warn!("Discord gateway connection failed: {}", e),
+        }
+
+        // A connection that stayed up for a while resets the backoff
+        if started.elapsed() > MAX_BACKOFF {
+            backoff = MIN_BACKOFF;
+        }
+        sleep(backoff).await;
+        backoff = next_backoff(backoff);
+    }
+}
+
+// FUNCTION: Doubles the reconnect delay up to the maximum
+fn next_backoff(current: Duration) -> Duration {
+    (current * 2).min(MAX_BACKOFF)
+}
+
+// FUNCTION: Runs a single gateway connection until it drops
+async fn connect(token: &str, session: &mut GatewaySession, events: &broadcast::Sender<GatewayEvent>) -> Result<Disconnect, Box<dyn Error>> {
+    let resuming = session.can_resume();
+    let base_url = match (&session.resume_url, resuming) {
+        (Some(url), true) => url.clone(),
+        _ => GATEWAY_URL.to_string(),
+    };
+
+    let (ws_stream, _) = connect_async(format!("{}{}", base_url.trim_end_matches('/'), GATEWAY_QUERY)).await?;
+    let (mut write, mut read) = ws_stream.split();
+
+    // The first payload is always HELLO with the heartbeat interval
+    let hello = match read.next().await {
+        Some(Ok(Message::Text(text))) => serde_json::from_str::<Value>(&text)?,
+        _ => return Ok(Disconnect::Resume),
+    };
+    if hello["op"].as_u64() != Some(OP_HELLO) {
+        return Err("Expected HELLO from the Discord gateway".into());
+    }
+    let heartbeat_ms = hello["d"]["heartbeat_interval"].as_u64().unwrap_or(41_250);
+
+    let handshake = if resuming {
+        json!({
+            "op": OP_RESUME,
+            "d": {
+                "token": token,
+                "session_id": session.session_id,
+                "seq": session.sequence,
+            }
+        })
+    } else {
+        json!({
+            "op": OP_IDENTIFY,
+            "d": {
+                "token": token,
+                "intents": INTENTS,
+                "properties": {
+                    "os": std::env::consts::OS,
+                    "browser": "loungelizard",
+                    "device": "loungelizard",
+                },
+            }
+        })
+    };
+    write.send(Message::Text(handshake.to_string())).await?;
+
+    let period = Duration::from_millis(heartbeat_ms);
+    let mut heartbeat = interval_at(Instant::now() + period, period);
+    let mut acked = true;
+
+    loop {
+        tokio::select! {
+            _ = heartbeat.tick() => {
+                // No ACK since the last beat means the connection is a zombie
+                if !acked {
+                    return Ok(Disconnect::Resume);
+                }
+                acked = false;
+                write.send(Message::Text(json!({ "op": OP_HEARTBEAT, "d": session.sequence }).to_string())).await?;
+            }
+            message = read.next() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(frame))) => {
+                        let code = frame.map(|f| u16::from(f.code)).unwrap_or(1000);
+                        if FATAL_CLOSE_CODES.contains(&code) {
+                            return Ok(Disconnect::Fatal(format!("closed with code {}", code)));
+                        }
+                        return Ok(Disconnect::Resume);
+                    }
+                    Some(Ok(_)) => continue,
+                    Some(Err(e)) => return Err(e.into()),
+                    None => return Ok(Disconnect::Resume),
+                };
+
+                let payload: Value = match serde_json::from_str(&text) {
+                    Ok(payload) => payload,
+                    Err(e) => {
+                        warn!("Failed to parse gateway payload: {}", e);
+                        continue;
+                    }
+                };
+
+                if let Some(sequence) = payload["s"].as_u64() {
+                    session.sequence = Some(sequence);
+                }
+
+                match payload["op"].as_u64() {
+                    Some(OP_DISPATCH) => {
+                        let event_type = payload["t"].as_str().unwrap_or_default();
+                        let data = &payload["d"];
+
+                        if event_type == "READY" {
+                            session.session_id = data["session_id"].as_str().map(|id| id.to_string());
+                            session.resume_url = data["resume_gateway_url"].as_str().map(|url| url.to_string());
+                            session.user_id = data["user"]["id"].as_str().unwrap_or_default().to_string();
+                            info!("Discord gateway ready");
+                            let _ = events.send(GatewayEvent::Ready { user_id: session.user_id.clone() });
+                        } else if let Some(event) = parse_dispatch(event_type, data, &session.user_id) {
+                            // Sending only fails when no page is listening right now
+                            let _ = events.send(event);
+                        }
+                    }
+                    Some(OP_HEARTBEAT) => {
+                        write.send(Message::Text(json!({ "op": OP_HEARTBEAT, "d": session.sequence }).to_string())).await?;
+                    }
+                    Some(OP_HEARTBEAT_ACK) => acked = true,
+                    Some(OP_RECONNECT) => return Ok(Disconnect::Resume),
+                    Some(OP_INVALID_SESSION) => {
+                        // d tells whether the session can still be resumed
+                        return Ok(if payload["d"].as_bool().unwrap_or(false) {
+                            Disconnect::Resume
+                        } else {
+                            Disconnect::Reidentify
+                        });
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+// FUNCTION: Turns a dispatch payload into a UI event, None for events the UI doesn't use
+fn parse_dispatch(event_type: &str, data: &Value, own_user_id: &str) -> Option<GatewayEvent> {
+    match event_type {
+        "MESSAGE_CREATE" => UnifiedMessage::from_discord(data).map(GatewayEvent::MessageCreate),
+        "MESSAGE_UPDATE" => {
+            if data["author"].is_null() {
+                return None;
+            }
+            UnifiedMessage::from_discord(data).map(GatewayEvent::MessageUpdate)
+        }
+        "MESSAGE_DELETE" => Some(GatewayEvent::MessageDelete {
+            channel_id: data["channel_id"].as_str()?.to_string(),
+            message_id: data["id"].as_str()?.to_string(),
+        }),
+        "MESSAGE_REACTION_ADD" | "MESSAGE_REACTION_REMOVE" => {
+            let user_id = data["user_id"].as_str()?.to_string();
+            let channel_id = data["channel_id"].as_str()?.to_string();
+            let message_id = data["message_id"].as_str()?.to_string();
+            // Emoji of deleted custom emoji come without a name
+            data["emoji"]["name"].as_str()?;
+            let emoji = DiscordEmoji::from_json(&data["emoji"]);
+            let me = user_id == own_user_id;
+
+            Some(if event_type == "MESSAGE_REACTION_ADD" {
+                GatewayEvent::ReactionAdd { channel_id, message_id, emoji, user_id, me }
+            } else {
+                GatewayEvent::ReactionRemove { channel_id, message_id, emoji, user_id, me }
+            })
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        assert_eq!(next_backoff(Duration::from_secs(1)), Duration::from_secs(2));
+        assert_eq!(next_backoff(Duration::from_secs(40)), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn parses_message_events() {
+        let create = json!({
+            "id": "10",
+            "channel_id": "5",
+            "content": "hi",
+            "timestamp": "2024-01-01T00:00:00+00:00",
+            "author": { "id": "1", "username": "lizard" }
+        });
+        match parse_dispatch("MESSAGE_CREATE", &create, "1") {
+            Some(GatewayEvent::MessageCreate(message)) => {
+                assert_eq!(message.id, "10");
+                assert_eq!(message.channel_id, "5");
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        // Embed-only updates have no author
+        assert_eq!(parse_dispatch("MESSAGE_UPDATE", &json!({ "id": "10", "channel_id": "5" }), "1"), None);
+
+        let reaction = json!({ "user_id": "1", "channel_id": "5", "message_id": "10", "emoji": { "id": null, "name": "👍" } });
+        assert_eq!(
+            parse_dispatch("MESSAGE_REACTION_ADD", &reaction, "1"),
+            Some(GatewayEvent::ReactionAdd {
+                channel_id: "5".to_string(),
+                message_id: "10".to_string(),
+                emoji: DiscordEmoji { name: "👍".to_string(), ..Default::default() },
+                user_id: "1".to_string(),
+                me: true,
+            })
+        );
+
+        let removed = json!({ "user_id": "2", "channel_id": "5", "message_id": "10", "emoji": { "id": "41", "name": "lizard" } });
+        match parse_dispatch("MESSAGE_REACTION_REMOVE", &removed, "1") {
+            Some(GatewayEvent::ReactionRemove { emoji, me, .. }) => {
+                assert_eq!(emoji.key(), "lizard:41");
+                assert!(!me);
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use std::cmp::Reverse;
+
+use crate::api::discord::discord_api::MESSAGE_PAGE_SIZE;
+use crate::api::unified_message::UnifiedMessage;
+
+// Message lists here are newest first, like the pages Discord returns
+
+// FUNCTION: Whether a page came back full, so there may be more beyond it
+pub fn is_full_page(page_len: usize) -> bool {
+    page_len >= MESSAGE_PAGE_SIZE
+}
+
+// FUNCTION: Adds a page fetched before the oldest shown message to the end of the list
+pub fn append_older(messages: &mut Vec<UnifiedMessage>, page: Vec<UnifiedMessage>) {
+    let older: Vec<UnifiedMessage> = page
+        .into_iter()
+        .filter(|message| !messages.iter().any(|shown| shown.id == message.id))
+        .collect();
+    messages.extend(older);
+}
+
+// FUNCTION: Puts messages fetched after the newest shown one at the front of the list,
+// messages that are already shown are updated instead
+pub fn merge_newer(messages: &mut Vec<UnifiedMessage>, page: Vec<UnifiedMessage>) {
+    let mut newer = Vec::new();
+    for message in page {
+        match messages.iter_mut().find(|shown| shown.id == message.id) {
+            Some(shown) => *shown = message,
+            None => newer.push(message),
+        }
+    }
+
+    // Snowflake ids grow with time
+    newer.sort_by_key(|message| Reverse(message.id.parse::<u64>().unwrap_or_default()));
+    messages.splice(0..0, newer);
+}
+
+// FUNCTION: Updates the shown messages that are in a page fetched around one of them,
+// e.g. after reacting. Nothing is added, so no gaps open up in the list.
+pub fn refresh_shown(messages: &mut [UnifiedMessage], page: Vec<UnifiedMessage>) {
+    for message in page {
+        if let Some(shown) = messages.iter_mut().find(|shown| shown.id == message.id) {
+            *shown = message;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: &str, body: &str) -> UnifiedMessage {
+        UnifiedMessage::from_discord(&serde_json::json!({ "id": id, "content": body, "timestamp": "2024-09-26T12:45:00+00:00" })).unwrap()
+    }
+
+    fn ids(messages: &[UnifiedMessage]) -> Vec<&str> {
+        messages.iter().map(|message| message.id.as_str()).collect()
+    }
+
+    #[test]
+    fn pages_extend_the_history_without_duplicates() {
+        let mut messages = vec![message("30", "c"), message("20", "b")];
+
+        append_older(&mut messages, vec![message("20", "b"), message("10", "a")]);
+        assert_eq!(ids(&messages), ["30", "20", "10"]);
+
+        merge_newer(&mut messages, vec![message("40", "d"), message("50", "e"), message("30", "c edited")]);
+        assert_eq!(ids(&messages), ["50", "40", "30", "20", "10"]);
+        assert_eq!(messages[2].body, "c edited");
+
+        refresh_shown(&mut messages, vec![message("10", "a edited"), message("5", "not shown")]);
+        assert_eq!(messages.len(), 5);
+        assert_eq!(messages[4].body, "a edited");
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use reqwest::header::HeaderMap;
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode};
+use serde_json::Value;
+use tokio::time::{sleep, timeout, Duration, Instant};
+use tracing::{info, warn};
+
+// Shared client for Discord's REST api. Requests to the same route go out one at a time,
+// and wait for their rate limit bucket to reset instead of failing with 429.
+
+// A request still rate limited after this many retries fails
+const MAX_RATE_LIMIT_RETRIES: u32 = 3;
+// Most requests come from click handlers that block the window until they return, so they
+// only wait out short limits and otherwise fail with an error the page can show
+const UI_RATE_LIMIT_WAIT: Duration = Duration::from_secs(2);
+// Requests run with patiently() wait longer, e.g. the daily limits of some routes still fail
+const BACKGROUND_RATE_LIMIT_WAIT: Duration = Duration::from_secs(60);
+// Used when a 429 comes without saying how long to wait
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(1);
+
+lazy_static::lazy_static! {
+    // One client for all requests, so connections are reused
+    static ref DISCORD_HTTP: DiscordHttp = DiscordHttp::default();
+}
+
+tokio::task_local! {
+    // How long requests of the current task may wait for rate limits in total
+    static MAX_RATE_LIMIT_WAIT: Duration;
+}
+
+// What Discord's X-RateLimit-* headers told about a route's bucket
+#[derive(Debug, Clone, PartialEq, Default)]
+struct RateLimit {
+    bucket: Option<String>,
+    remaining: Option<u64>,
+    reset_after: Option<Duration>,
+    global: bool,
+}
+
+#[derive(Default)]
+struct DiscordHttp {
+    client: Client,
+    // Requests to a route wait for their turn here, in order
+    queues: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+    // Bucket of each route once Discord named it, routes can share a bucket
+    route_buckets: Mutex<HashMap<String, String>>,
+    // Buckets with no requests left, until they reset
+    exhausted_until: Mutex<HashMap<String, Instant>>,
+    // Set by a global rate limit, which holds back every route
+    global_until: Mutex<Option<Instant>>,
+}
+
+// FUNCTION: Sends a Discord request through the shared client. build makes the request and
+// is called again for every retry, so it shouldn't do anything but build it.
+pub async fn send(build: impl Fn(&Client) -> RequestBuilder) -> Result<Response, Box<dyn Error>> {
+    let max_wait = MAX_RATE_LIMIT_WAIT.try_with(|max_wait| *max_wait).unwrap_or(UI_RATE_LIMIT_WAIT);
+    DISCORD_HTTP.send(build, max_wait).await
+}
+
+// FUNCTION: Runs requests that aren't awaited by the UI thread, e.g. in a spawned task, letting
+// them wait out longer rate limits instead of failing
+pub async fn patiently<F: Future>(requests: F) -> F::Output {
+    MAX_RATE_LIMIT_WAIT.scope(BACKGROUND_RATE_LIMIT_WAIT, requests).await
+}
+
+// FUNCTION: Error for a request that would have to wait longer than it may
+fn rate_limited(wait: Duration) -> Box<dyn Error> {
+    format!("Discord is rate limiting these requests, try again in {}s", wait.as_secs_f64().ceil()).into()
+}
+
+impl DiscordHttp {
+    async fn send(&self, build: impl Fn(&Client) -> RequestBuilder, max_wait: Duration) -> Result<Response, Box<dyn Error>> {
+        let request = build(&self.client).build()?;
+        let route = route_key(request.method(), request.url().path());
+        let started = Instant::now();
+
+        // Requests ahead in the queue may be waiting out a limit themselves
+        let queue = self.queue(&route);
+        let _turn = timeout(max_wait, queue.lock()).await.map_err(|_| rate_limited(max_wait))?;
+
+        let mut request = Some(request);
+        let mut retries = 0;
+        loop {
+            let wait = self.wait_needed(&route);
+            if started.elapsed() + wait > max_wait {
+                return Err(rate_limited(wait));
+            }
+            if !wait.is_zero() {
+                info!("Waiting {:?} for the rate limit of {}", wait, route);
+                sleep(wait).await;
+            }
+
+            let request = match request.take() {
+                Some(request) => request,
+                None => build(&self.client).build()?,
+            };
+            let response = self.client.execute(request).await?;
+            let limit = RateLimit::from_headers(response.headers());
+            self.update(&route, &limit);
+
+            if response.status() != StatusCode::TOO_MANY_REQUESTS {
+                return Ok(response);
+            }
+
+            let body: Value = response.json().await.unwrap_or_default();
+            let retry_after = retry_after(&body, &limit);
+            if retries == MAX_RATE_LIMIT_RETRIES || started.elapsed() + retry_after > max_wait {
+                return Err(rate_limited(retry_after));
+            }
+
+            warn!("Rate limited on {}, retrying in {:?}", route, retry_after);
+            self.hold_back(&route, limit.global || body["global"].as_bool().unwrap_or(false), retry_after);
+            retries += 1;
+        }
+    }
+
+    fn queue(&self, route: &str) -> Arc<tokio::sync::Mutex<()>> {
+        let mut queues = self.queues.lock().unwrap();
+        Arc::clone(queues.entry(route.to_string()).or_default())
+    }
+
+    fn bucket_key(&self, route: &str) -> String {
+        let route_buckets = self.route_buckets.lock().unwrap();
+        route_buckets.get(route).cloned().unwrap_or_else(|| route.to_string())
+    }
+
+    // How long until the route's bucket and the global limit allow another request
+    fn wait_needed(&self, route: &str) -> Duration {
+        let bucket = self.bucket_key(route);
+        let global_until = *self.global_until.lock().unwrap();
+        let bucket_until = self.exhausted_until.lock().unwrap().get(&bucket).copied();
+
+        global_until
+            .max(bucket_until)
+            .map(|until| until.saturating_duration_since(Instant::now()))
+            .unwrap_or_default()
+    }
+
+    fn update(&self, route: &str, limit: &RateLimit) {
+        if let Some(bucket) = &limit.bucket {
+            // Buckets are per major parameter (channel, server, ...) of the route
+            let key = format!("{}:{}", bucket, major_parameter(route).unwrap_or_default());
+            self.route_buckets.lock().unwrap().insert(route.to_string(), key);
+        }
+
+        let bucket = self.bucket_key(route);
+        let mut exhausted_until = self.exhausted_until.lock().unwrap();
+        match (limit.remaining, limit.reset_after) {
+            (Some(0), Some(reset_after)) => {
+                exhausted_until.insert(bucket, Instant::now() + reset_after);
+            }
+            (Some(_), _) => {
+                exhausted_until.remove(&bucket);
+            }
+            _ => {}
+        }
+    }
+
+    fn hold_back(&self, route: &str, global: bool, retry_after: Duration) {
+        let until = Instant::now() + retry_after;
+        if global {
+            *self.global_until.lock().unwrap() = Some(until);
+        } else {
+            let bucket = self.bucket_key(route);
+            self.exhausted_until.lock().unwrap().insert(bucket, until);
+        }
+    }
+}
+
+impl RateLimit {
+    fn from_headers(headers: &HeaderMap) -> RateLimit {
+        let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+
+        RateLimit {
+            bucket: header("x-ratelimit-bucket").map(|bucket| bucket.to_string()),
+            remaining: header("x-ratelimit-remaining").and_then(|remaining| remaining.parse().ok()),
+            reset_after: header("x-ratelimit-reset-after")
+                .and_then(|reset_after| reset_after.parse().ok())
+                .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok()),
+            global: header("x-ratelimit-global") == Some("true") || header("x-ratelimit-scope") == Some("global"),
+        }
+    }
+}
+
+// FUNCTION: How long a 429 asks to wait, the body's retry_after is in seconds with a fraction
+fn retry_after(body: &Value, limit: &RateLimit) -> Duration {
+    body["retry_after"]
+        .as_f64()
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .or(limit.reset_after)
+        .unwrap_or(DEFAULT_RETRY_AFTER)
+}
+
+// FUNCTION: Route of a request for queueing, e.g. "GET /channels/10/messages/:id". Ids other than the
+// major parameter (the channel, server or webhook) are left out, the way Discord buckets them.
+fn route_key(method: &Method, path: &str) -> String {
+    let segments: Vec<&str> = path.split('/').filter(|segment| !segment.is_empty()).collect();
+    let mut parts = Vec::with_capacity(segments.len());
+
+    for (i, segment) in segments.iter().enumerate() {
+        let previous = if i > 0 { segments[i - 1] } else { "" };
+        let is_major = matches!(previous, "channels" | "guilds" | "webhooks");
+        parts.push(if previous == "reactions" {
+            ":emoji"
+        } else if !is_major && segment.bytes().all(|b| b.is_ascii_digit()) {
+            ":id"
+        } else {
+            segment
+        });
+    }
+    format!("{} /{}", method, parts.join("/"))
+}
+
+// FUNCTION: The channel, server or webhook id of a route made by route_key
+fn major_parameter(route: &str) -> Option<&str> {
+    route.split('/').find(|segment| !segment.is_empty() && segment.bytes().all(|b| b.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+    use serde_json::json;
+
+    #[test]
+    fn routes_keep_only_the_major_parameter() {
+        let route = route_key(&Method::GET, "/api/v9/channels/10/messages/99");
+        assert_eq!(route, "GET /api/v9/channels/10/messages/:id");
+        assert_eq!(major_parameter(&route), Some("10"));
+
+        assert_eq!(
+            route_key(&Method::PUT, "/api/v9/channels/10/messages/99/reactions/%F0%9F%91%8D/@me"),
+            "PUT /api/v9/channels/10/messages/:id/reactions/:emoji/@me"
+        );
+        assert_eq!(route_key(&Method::GET, "/api/v9/users/@me/guilds"), "GET /api/v9/users/@me/guilds");
+    }
+
+    #[test]
+    fn reads_rate_limits() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ratelimit-bucket", HeaderValue::from_static("abcd"));
+        headers.insert("x-ratelimit-remaining", HeaderValue::from_static("0"));
+        headers.insert("x-ratelimit-reset-after", HeaderValue::from_static("1.5"));
+        let limit = RateLimit::from_headers(&headers);
+        assert_eq!(
+            limit,
+            RateLimit { bucket: Some("abcd".to_string()), remaining: Some(0), reset_after: Some(Duration::from_millis(1500)), global: false }
+        );
+
+        assert_eq!(retry_after(&json!({ "retry_after": 0.25, "global": false }), &limit), Duration::from_millis(250));
+        assert_eq!(retry_after(&json!({}), &limit), Duration::from_millis(1500));
+        assert_eq!(retry_after(&json!({}), &RateLimit::default()), DEFAULT_RETRY_AFTER);
+    }
+
+    #[tokio::test]
+    async fn ui_requests_fail_instead_of_waiting_long() {
+        let http = DiscordHttp::default();
+        let route = route_key(&Method::GET, "/api/v9/channels/10/messages");
+        http.hold_back(&route, false, Duration::from_secs(30));
+
+        let started = Instant::now();
+        let sent = http.send(|client| client.get("https://discord.com/api/v9/channels/10/messages"), UI_RATE_LIMIT_WAIT).await;
+        assert!(sent.unwrap_err().to_string().contains("rate limiting"));
+        assert!(started.elapsed() < UI_RATE_LIMIT_WAIT);
+
+        let max_wait = patiently(async { MAX_RATE_LIMIT_WAIT.get() }).await;
+        assert_eq!(max_wait, BACKGROUND_RATE_LIMIT_WAIT);
+    }
+}
@@ -0,0 +1,276 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::error::Error;
+
+use crate::api::chat_provider::*;
+use crate::api::discord::discord_api;
+use crate::api::mongo_format::mongo_structs::Discord;
+use crate::api::unified_message::{UnifiedMessage, UnifiedReaction};
+
+// Workspace id used for the user's direct messages, which don't belong to a guild
+pub const DM_WORKSPACE_ID: &str = "@me";
+
+// Milliseconds from the Unix epoch to the first second of 2015, where Discord ids start counting
+const DISCORD_EPOCH: i64 = 1_420_070_400_000;
+
+pub struct DiscordProvider {
+    // Authorization header value, see Discord::authorization()
+    token: String,
+    // Bots can't list direct messages
+    is_bot: bool,
+}
+
+impl DiscordProvider {
+    pub fn new(account: &Discord) -> Self {
+        DiscordProvider {
+            token: account.authorization(),
+            is_bot: account.is_bot(),
+        }
+    }
+
+    // FUNCTION: Posts in a forum channel, returns the post. Forum posts are threads
+    // that start with a title, which the other platforms have no counterpart for.
+    pub async fn create_forum_post(&self, forum: &ChatChannel, title: &str, content: &str) -> Result<ChatChannel, Box<dyn Error>> {
+        let thread = discord_api::create_forum_post(self.token.clone(), forum.id.clone(), title.to_string(), content.to_string()).await?;
+        chat_channel(&thread, &forum.workspace_id).ok_or_else(|| "Discord returned a post without an id".into())
+    }
+}
+
+// FUNCTION: When a Discord id (message, channel, ...) was made, it starts with the milliseconds since DISCORD_EPOCH
+fn snowflake_time(id: &str) -> Option<DateTime<Utc>> {
+    let id: u64 = id.parse().ok()?;
+    DateTime::from_timestamp_millis((id >> 22) as i64 + DISCORD_EPOCH)
+}
+
+// FUNCTION: When a channel last had a message. One that never had any is as old as the channel.
+fn last_activity(channel: &Value) -> Option<DateTime<Utc>> {
+    channel["last_message_id"]
+        .as_str()
+        .or(channel["id"].as_str())
+        .and_then(snowflake_time)
+}
+
+// FUNCTION: What a channel is for, from its Discord type. None for the ones without
+// messages, like voice channels and categories.
+fn channel_kind(channel: &Value) -> Option<ChannelKind> {
+    match channel["type"].as_u64()? {
+        // DM and group DM
+        1 | 3 => Some(ChannelKind::Direct),
+        t if discord_api::THREAD_PARENT_CHANNEL_TYPES.contains(&t) => Some(ChannelKind::Channel),
+        t if discord_api::FORUM_CHANNEL_TYPES.contains(&t) => Some(ChannelKind::Forum),
+        // Announcement, public and private threads
+        10..=12 => Some(ChannelKind::Thread {
+            archived: channel["thread_metadata"]["archived"].as_bool().unwrap_or(false),
+        }),
+        _ => None,
+    }
+}
+
+// FUNCTION: ChatChannel of a Discord channel or thread, None if it holds no messages
+fn chat_channel(channel: &Value, workspace_id: &str) -> Option<ChatChannel> {
+    let kind = channel_kind(channel)?;
+    Some(ChatChannel {
+        id: channel["id"].as_str()?.to_string(),
+        workspace_id: workspace_id.to_string(),
+        name: match kind {
+            ChannelKind::Direct => dm_channel_name(channel),
+            _ => channel["name"].as_str().unwrap_or_default().to_string(),
+        },
+        platform: Platform::Discord,
+        kind,
+        last_activity: last_activity(channel),
+    })
+}
+
+// FUNCTION: Display name of a DM channel built from its recipients
+fn dm_channel_name(channel: &Value) -> String {
+    if let Some(name) = channel["name"].as_str() {
+        if !name.is_empty() {
+            return name.to_string();
+        }
+    }
+
+    channel["recipients"]
+        .as_array()
+        .map(|recipients| {
+            recipients
+                .iter()
+                .filter_map(|r| r["global_name"].as_str().or(r["username"].as_str()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .unwrap_or_default()
+}
+
+#[async_trait(?Send)]
+impl ChatProvider for DiscordProvider {
+    fn platform(&self) -> Platform {
+        Platform::Discord
+    }
+
+    async fn list_workspaces(&self) -> Result<Vec<ChatWorkspace>, Box<dyn Error>> {
+        let guilds = discord_api::get_guilds(self.token.clone()).await?;
+
+        let mut workspaces = Vec::new();
+        if !self.is_bot {
+            workspaces.push(ChatWorkspace {
+                id: DM_WORKSPACE_ID.to_string(),
+                name: "Direct Messages".to_string(),
+                icon_url: None,
+                platform: Platform::Discord,
+            });
+        }
+
+        for guild in guilds.as_array().into_iter().flatten() {
+            let id = guild["id"].as_str().unwrap_or_default().to_string();
+            let icon_url = guild["icon"]
+                .as_str()
+                .map(|icon| format!("https://cdn.discordapp.com/icons/{}/{}.png", id, icon));
+            workspaces.push(ChatWorkspace {
+                name: guild["name"].as_str().unwrap_or_default().to_string(),
+                id,
+                icon_url,
+                platform: Platform::Discord,
+            });
+        }
+
+        Ok(workspaces)
+    }
+
+    async fn list_channels(&self, workspace: &ChatWorkspace) -> Result<Vec<ChatChannel>, Box<dyn Error>> {
+        let channels = if workspace.id == DM_WORKSPACE_ID {
+            discord_api::get_channels(self.token.clone()).await?
+        } else {
+            discord_api::get_guild_channels(self.token.clone(), workspace.id.clone()).await?
+        };
+
+        Ok(channels
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|channel| chat_channel(channel, &workspace.id))
+            .collect())
+    }
+
+    async fn list_threads(&self, channel: &ChatChannel) -> Result<Vec<ChatChannel>, Box<dyn Error>> {
+        if channel.workspace_id == DM_WORKSPACE_ID {
+            return Ok(Vec::new());
+        }
+
+        let mut threads = discord_api::get_active_threads(self.token.clone(), channel.workspace_id.clone(), channel.id.clone()).await?;
+        // A thread can be unarchived between the two requests
+        for thread in discord_api::get_archived_threads(self.token.clone(), channel.id.clone()).await? {
+            if !threads.iter().any(|listed| listed["id"] == thread["id"]) {
+                threads.push(thread);
+            }
+        }
+
+        Ok(threads
+            .iter()
+            .filter_map(|thread| chat_channel(thread, &channel.workspace_id))
+            .collect())
+    }
+
+    async fn fetch_history(&self, channel: &ChatChannel, cursor: &HistoryCursor, limit: usize) -> Result<Vec<UnifiedMessage>, Box<dyn Error>> {
+        let messages = discord_api::get_messages_page(self.token.clone(), channel.id.clone(), cursor, limit).await?;
+
+        // Discord returns the newest message first
+        let mut history = UnifiedMessage::from_discord_list(&messages);
+        history.reverse();
+        Ok(history)
+    }
+
+    async fn send_message(&self, channel: &ChatChannel, message: &OutgoingMessage) -> Result<(), Box<dyn Error>> {
+        let content = message.content_with_subject(|subject| format!("**{}**", subject));
+        let reply_to = message.reply_to.as_ref().map(|replied| replied.id.clone());
+
+        match &message.attachment {
+            Some(attachment) => {
+                discord_api::send_message_attachment(
+                    self.token.clone(),
+                    channel.id.clone(),
+                    content,
+                    attachment.data.clone(),
+                    attachment.filename.clone(),
+                    reply_to,
+                )
+                .await?
+            }
+            None => discord_api::send_message(self.token.clone(), channel.id.clone(), content, reply_to).await?,
+        };
+        Ok(())
+    }
+
+    async fn edit_message(&self, channel: &ChatChannel, message: &UnifiedMessage, content: &str) -> Result<(), Box<dyn Error>> {
+        discord_api::edit_message(self.token.clone(), channel.id.clone(), message.id.clone(), content.to_string()).await?;
+        Ok(())
+    }
+
+    async fn delete_message(&self, channel: &ChatChannel, message: &UnifiedMessage) -> Result<(), Box<dyn Error>> {
+        discord_api::delete_message(self.token.clone(), channel.id.clone(), message.id.clone()).await
+    }
+
+    async fn add_reaction(&self, channel: &ChatChannel, message: &UnifiedMessage, emoji: &str) -> Result<(), Box<dyn Error>> {
+        discord_api::send_reaction(
+            self.token.clone(),
+            channel.id.clone(),
+            message.id.clone(),
+            emoji.to_string(),
+        )
+        .await
+    }
+
+    async fn remove_reaction(&self, channel: &ChatChannel, message: &UnifiedMessage, emoji: &str) -> Result<(), Box<dyn Error>> {
+        discord_api::remove_reaction(
+            self.token.clone(),
+            channel.id.clone(),
+            message.id.clone(),
+            emoji.to_string(),
+        )
+        .await
+    }
+
+    async fn reaction_users(&self, channel: &ChatChannel, message: &UnifiedMessage, reaction: &UnifiedReaction) -> Result<Vec<String>, Box<dyn Error>> {
+        let users = discord_api::get_reaction_users(self.token.clone(), channel.id.clone(), message.id.clone(), reaction.key.clone()).await?;
+
+        Ok(users
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|user| user["global_name"].as_str().or(user["username"].as_str()).unwrap_or("Unknown User").to_string())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn channels_are_as_recent_as_their_last_message() {
+        // Ids from Discord's documentation, 175928847299117063 was made 2016-04-30 11:18:25.796 UTC
+        assert_eq!(snowflake_time("175928847299117063").unwrap().timestamp_millis(), 1_462_015_105_796);
+        assert_eq!(snowflake_time("not an id"), None);
+
+        let active = json!({ "id": "41771983423143937", "last_message_id": "175928847299117063" });
+        let never_used = json!({ "id": "175928847299117063", "last_message_id": null });
+        assert_eq!(last_activity(&active), snowflake_time("175928847299117063"));
+        assert_eq!(last_activity(&never_used), snowflake_time("175928847299117063"));
+    }
+
+    #[test]
+    fn channel_kinds_follow_the_discord_type() {
+        assert_eq!(channel_kind(&json!({ "type": 0 })), Some(ChannelKind::Channel));
+        assert_eq!(channel_kind(&json!({ "type": 1 })), Some(ChannelKind::Direct));
+        assert_eq!(channel_kind(&json!({ "type": 15 })), Some(ChannelKind::Forum));
+        assert_eq!(
+            channel_kind(&json!({ "type": 11, "thread_metadata": { "archived": true } })),
+            Some(ChannelKind::Thread { archived: true })
+        );
+        // Voice channels and categories hold no messages
+        assert_eq!(channel_kind(&json!({ "type": 2 })), None);
+        assert_eq!(channel_kind(&json!({ "type": 4 })), None);
+    }
+}
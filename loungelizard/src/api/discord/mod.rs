@@ -1 +1,6 @@
-pub mod discord_api;
\ No newline at end of file
+pub mod discord_api;
+pub mod discord_provider;
+pub mod discord_gateway;
+pub mod discord_history;
+pub mod discord_emoji;
+pub mod discord_http;
\ No newline at end of file
@@ -0,0 +1,118 @@
+use futures::future::join_all;
+use std::cmp::Reverse;
+
+use crate::api::chat_provider::*;
+use crate::api::mongo_format::mongo_structs::User;
+use crate::api::unified_message::UnifiedMessage;
+
+// Only the most recently active channels of each workspace are scanned to keep the request count sane
+const CHANNELS_PER_WORKSPACE: usize = 10;
+// Newest messages kept per channel
+const MESSAGES_PER_CHANNEL: usize = 3;
+// Maximum number of entries shown in the inbox
+const INBOX_LIMIT: usize = 100;
+
+/// One entry of the unified inbox: a message and where it was posted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InboxItem {
+    pub workspace_name: String,
+    pub channel: ChatChannel,
+    pub message: UnifiedMessage,
+}
+
+/// Collects the latest messages of every connected platform, newest first.
+///
+/// A platform failing does not stop the others, its error is returned next
+/// to the items so the page can show it.
+pub async fn latest_activity(user: &User) -> (Vec<InboxItem>, Vec<String>) {
+    let mut items = Vec::new();
+    let mut errors = Vec::new();
+
+    for provider in connected_providers(user) {
+        match provider_activity(provider.as_ref()).await {
+            Ok(mut provider_items) => items.append(&mut provider_items),
+            Err(e) => errors.push(format!("{}: {}", provider.platform().as_str(), e)),
+        }
+    }
+
+    sort_newest_first(&mut items);
+    items.truncate(INBOX_LIMIT);
+    (items, errors)
+}
+
+async fn provider_activity(provider: &dyn ChatProvider) -> Result<Vec<InboxItem>, Box<dyn std::error::Error>> {
+    let mut items = Vec::new();
+
+    for workspace in provider.list_workspaces().await? {
+        let channels = match provider.list_channels(&workspace).await {
+            Ok(channels) => channels,
+            // One unreadable workspace shouldn't hide the rest of the platform
+            Err(_) => continue,
+        };
+
+        let channels = channels_to_scan(channels);
+        let histories = join_all(channels.iter().map(|channel| provider.fetch_history(channel, &HistoryCursor::Latest, MESSAGES_PER_CHANNEL))).await;
+
+        for (channel, history) in channels.into_iter().zip(histories) {
+            let Ok(history) = history else { continue };
+            for message in history {
+                items.push(InboxItem {
+                    workspace_name: workspace.name.clone(),
+                    channel: channel.clone(),
+                    message,
+                });
+            }
+        }
+    }
+
+    Ok(items)
+}
+
+/// Picks the channels of a workspace worth reading. Channels whose last
+/// activity is known are ranked by it and only the newest ones are kept.
+/// The others can't be ranked without reading them, so all of them are read
+/// and their messages compete on time in `latest_activity`. Forums have no
+/// messages of their own and are left out.
+fn channels_to_scan(channels: Vec<ChatChannel>) -> Vec<ChatChannel> {
+    let (mut ranked, unranked): (Vec<ChatChannel>, Vec<ChatChannel>) = channels
+        .into_iter()
+        .filter(|channel| channel.kind != ChannelKind::Forum)
+        .partition(|channel| channel.last_activity.is_some());
+
+    ranked.sort_by_key(|channel| Reverse(channel.last_activity));
+    ranked.truncate(CHANNELS_PER_WORKSPACE);
+    ranked.extend(unranked);
+    ranked
+}
+
+fn sort_newest_first(items: &mut [InboxItem]) {
+    items.sort_by(|a, b| b.message.timestamp.cmp(&a.message.timestamp));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+
+    #[test]
+    fn scans_the_most_recently_active_channels() {
+        let now = Utc::now();
+        let channel = |id: usize, last_activity| ChatChannel {
+            id: id.to_string(),
+            workspace_id: "W".to_string(),
+            name: format!("channel-{}", id),
+            platform: Platform::Discord,
+            kind: ChannelKind::Channel,
+            last_activity,
+        };
+
+        // Listed oldest first, so taking the first ones would miss the busy channels
+        let mut channels: Vec<ChatChannel> =
+            (0..15).map(|i| channel(i, Some(now - Duration::minutes(15 - i as i64)))).collect();
+        channels.push(channel(15, None));
+        channels.push(channel(16, None));
+
+        let ids: Vec<String> = channels_to_scan(channels).into_iter().map(|channel| channel.id).collect();
+        assert_eq!(ids, ["14", "13", "12", "11", "10", "9", "8", "7", "6", "5", "15", "16"]);
+    }
+}
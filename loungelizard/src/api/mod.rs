@@ -1,4 +1,9 @@
 pub mod discord; 
 pub mod slack;
 pub mod ms_teams;
-pub mod mongo_format;
\ No newline at end of file
+pub mod mongo_format;
+pub mod chat_provider;
+pub mod unified_message;
+pub mod inbox;
+pub mod tunnel;
+pub mod browser;
\ No newline at end of file
@@ -0,0 +1,206 @@
+// Single user storage in a JSON file, for running without a database server
+use async_trait::async_trait;
+use bson::{from_document, oid::ObjectId, to_document, Bson, Document};
+use dioxus_logger::tracing::info;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use crate::api::mongo_format::mongo_structs::*;
+use crate::api::mongo_format::user_store::*;
+use crate::api::mongo_format::migrations::*;
+
+const LOCAL_STORE_FILE: &str = "users.json";
+const APP_DIR: &str = "LoungeLizard";
+
+/// Keeps the user documents in a JSON file on this machine.
+///
+/// The whole file is read and rewritten on every call, which is fine for
+/// the handful of accounts a desktop install has.
+pub struct LocalStore {
+    path: PathBuf,
+    // Serializes read-modify-write cycles within the app
+    lock: Mutex<()>,
+}
+
+impl LocalStore {
+    pub fn new(path: PathBuf) -> LocalStore {
+        LocalStore { path, lock: Mutex::new(()) }
+    }
+
+    /// `LOCAL_STORE_PATH` if set, otherwise `users.json` in the app's folder
+    /// of the user's data directory.
+    pub fn default_path() -> PathBuf {
+        dotenv::dotenv().ok();
+        match std::env::var("LOCAL_STORE_PATH") {
+            Ok(path) if !path.trim().is_empty() => PathBuf::from(path.trim()),
+            _ => data_dir().join(APP_DIR).join(LOCAL_STORE_FILE),
+        }
+    }
+
+    // Older documents are upgraded and users stored before ids existed are
+    // given one, and the file is saved right away so the ids handed out by
+    // find_user stay valid
+    fn load(&self) -> Result<Vec<User>, StoreError> {
+        let documents: Vec<serde_json::Value> = match fs::read_to_string(&self.path) {
+            Ok(contents) => serde_json::from_str(&contents)?,
+            // Nothing stored yet
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut changed = false;
+        let mut users = Vec::with_capacity(documents.len());
+        for document in documents {
+            // Parsed as extended JSON so ids come back as ObjectIds
+            let Bson::Document(document) = Bson::try_from(document)? else {
+                return Err(StoreError::Backend("stored user is not an object".to_string()));
+            };
+
+            let (mut user, migrated) = load_user(document)?;
+            if user.id.is_none() {
+                user.id = Some(ObjectId::new());
+                changed = true;
+            }
+            changed |= migrated;
+            users.push(user);
+        }
+
+        if changed {
+            self.save(&users)?;
+        }
+        Ok(users)
+    }
+
+    // Written to a temporary file first so a crash never leaves half a file behind
+    fn save(&self, users: &[User]) -> Result<(), StoreError> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let temp_path = self.path.with_extension("json.tmp");
+        let mut file = create_private(&temp_path)?;
+        file.write_all(serde_json::to_string_pretty(users)?.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl UserStore for LocalStore {
+    fn name(&self) -> &'static str {
+        "local file"
+    }
+
+    async fn find_user(&self, login: &str) -> Result<Option<User>, StoreError> {
+        let _guard = self.lock.lock().unwrap();
+        Ok(self
+            .load()?
+            .into_iter()
+            .find(|user| user.username == login || user.email == login))
+    }
+
+    async fn find_by_id(&self, id: &ObjectId) -> Result<Option<User>, StoreError> {
+        let _guard = self.lock.lock().unwrap();
+        Ok(self.load()?.into_iter().find(|user| user.id.as_ref() == Some(id)))
+    }
+
+    async fn insert_user(&self, user: &User) -> Result<ObjectId, StoreError> {
+        let _guard = self.lock.lock().unwrap();
+        let mut users = self.load()?;
+        if users.iter().any(|existing| existing.username == user.username || existing.email == user.email) {
+            return Err(StoreError::AlreadyExists);
+        }
+
+        let id = ObjectId::new();
+        users.push(User { id: Some(id), revision: 0, schema_version: CURRENT_SCHEMA_VERSION, ..user.clone() });
+        self.save(&users)?;
+        info!("User stored in {}", self.path.display());
+        Ok(id)
+    }
+
+    async fn patch_user(&self, id: &ObjectId, revision: i64, patch: Document) -> Result<i64, StoreError> {
+        let _guard = self.lock.lock().unwrap();
+        let mut users = self.load()?;
+        let stored = users
+            .iter_mut()
+            .find(|stored| stored.id.as_ref() == Some(id))
+            .ok_or(StoreError::NotFound)?;
+        if stored.revision != revision {
+            return Err(StoreError::Conflict);
+        }
+
+        // Same semantics as a Mongo $set on top level fields
+        let mut document = to_document(stored)?;
+        document.extend(patch);
+        *stored = from_document(document)?;
+        stored.revision = revision + 1;
+
+        self.save(&users)?;
+        Ok(revision + 1)
+    }
+}
+
+// Platform data directory, e.g. %APPDATA% or ~/.local/share
+fn data_dir() -> PathBuf {
+    let var = |name: &str| std::env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+
+    if cfg!(windows) {
+        var("APPDATA").unwrap_or_else(|| PathBuf::from("."))
+    } else if cfg!(target_os = "macos") {
+        var("HOME")
+            .map(|home| home.join("Library").join("Application Support"))
+            .unwrap_or_else(|| PathBuf::from("."))
+    } else {
+        var("XDG_DATA_HOME")
+            .or_else(|| var("HOME").map(|home| home.join(".local").join("share")))
+            .unwrap_or_else(|| PathBuf::from("."))
+    }
+}
+
+// The file holds password hashes, so only the owner may read it
+#[cfg(unix)]
+fn create_private(path: &Path) -> std::io::Result<fs::File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    fs::OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(path)
+}
+
+#[cfg(not(unix))]
+fn create_private(path: &Path) -> std::io::Result<fs::File> {
+    fs::File::create(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn stores_and_patches_users() {
+        let path = std::env::temp_dir()
+            .join(format!("lounge-lizard-store-{}", std::process::id()))
+            .join(LOCAL_STORE_FILE);
+        let store = LocalStore::new(path.clone());
+
+        let mut user = User::default();
+        user.username = "lizard".to_string();
+        user.email = "lizard@example.com".to_string();
+
+        assert_eq!(store.find_user("lizard").await.unwrap(), None);
+        let id = store.insert_user(&user).await.unwrap();
+        assert!(matches!(store.insert_user(&user).await, Err(StoreError::AlreadyExists)));
+
+        user.discord.active_mut().token = "enc:v1:token".to_string();
+        let patch = user_patch(&user, &[UserField::Discord]).unwrap();
+        assert_eq!(store.patch_user(&id, 0, patch.clone()).await.unwrap(), 1);
+
+        // A second window still holding revision 0
+        assert!(matches!(store.patch_user(&id, 0, patch).await, Err(StoreError::Conflict)));
+
+        let stored = store.find_user("lizard@example.com").await.unwrap().unwrap();
+        assert_eq!(stored, User { id: Some(id), revision: 1, ..user });
+        assert_eq!(store.find_by_id(&id).await.unwrap(), Some(stored));
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,141 @@
+// Upgrades of stored user documents to the layout of mongo_structs::User
+use bson::{doc, from_document, Bson, Document};
+use dioxus_logger::tracing::info;
+use crate::api::mongo_format::mongo_structs::*;
+use crate::api::mongo_format::user_store::StoreError;
+
+/// Layout version written by this build, stored in `schema_version`.
+///
+/// Bump it and append a step to `MIGRATIONS` whenever a change to the user
+/// structs needs more than a `#[serde(default)]`.
+pub const CURRENT_SCHEMA_VERSION: u32 = 2;
+
+type Migration = fn(&mut Document);
+
+// MIGRATIONS[n] upgrades a document from version n to n + 1
+const MIGRATIONS: [Migration; CURRENT_SCHEMA_VERSION as usize] = [
+    v0_original_layout,
+    v1_single_accounts,
+];
+
+/// Brings a stored document up to `CURRENT_SCHEMA_VERSION`.
+///
+/// Returns whether anything was changed, so the caller can write the
+/// upgraded document back. Documents written by a newer build are refused
+/// rather than being loaded with fields missing.
+pub fn migrate(document: &mut Document) -> Result<bool, StoreError> {
+    let version = stored_version(document);
+    if version > CURRENT_SCHEMA_VERSION {
+        return Err(StoreError::NewerSchema(version));
+    }
+    if version == CURRENT_SCHEMA_VERSION {
+        return Ok(false);
+    }
+
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(document);
+    }
+    document.insert("schema_version", CURRENT_SCHEMA_VERSION as i64);
+    info!("Upgraded user document from schema {} to {}", version, CURRENT_SCHEMA_VERSION);
+    Ok(true)
+}
+
+/// Migrates a stored document and reads it as a `User`. The flag tells
+/// whether the document changed and should be saved again.
+pub fn load_user(mut document: Document) -> Result<(User, bool), StoreError> {
+    let migrated = migrate(&mut document)?;
+    Ok((from_document(document)?, migrated))
+}
+
+// Documents saved before versioning have no schema_version at all
+fn stored_version(document: &Document) -> u32 {
+    match document.get("schema_version") {
+        Some(Bson::Int32(version)) => *version as u32,
+        Some(Bson::Int64(version)) => *version as u32,
+        Some(Bson::Double(version)) => *version as u32,
+        _ => 0,
+    }
+}
+
+// The layout of the first accounts, as in the old mongo.json: Slack had a
+// `bot` token and a `redirect_host`, and Teams kept a single `token`
+fn v0_original_layout(document: &mut Document) {
+    if let Ok(slack) = document.get_document_mut("slack") {
+        slack.remove("bot");
+        slack.remove("redirect_host");
+    }
+
+    if let Ok(ms_teams) = document.get_document_mut("ms_teams") {
+        if let Some(token) = ms_teams.remove("token") {
+            if !ms_teams.contains_key("access_token") {
+                ms_teams.insert("access_token", token);
+            }
+        }
+    }
+}
+
+// Each platform held one account, now they hold a list of linked accounts
+fn v1_single_accounts(document: &mut Document) {
+    for platform in ["slack", "discord", "ms_teams"] {
+        if let Some(Bson::Document(account)) = document.remove(platform) {
+            document.insert(platform, doc! { "linked": [account], "active": 0_i64 });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upgrades_original_documents() {
+        let mut document = doc! {
+            "password": "hash",
+            "username": "lizard",
+            "email": "lizard@example.com",
+            "slack": {
+                "app_id": "A123",
+                "bot": { "token": "xoxb", "scope": "chat:write" },
+                "client_id": "",
+                "client_secret": "",
+                "config_token": "",
+                "oauth_url": "",
+                "redirect_host": "",
+                "team": { "name": "", "id": "" },
+                "user": { "token": "xoxp", "scope": "" },
+                "verif_token": "",
+            },
+            "discord": { "token": "" },
+            "ms_teams": { "token": "teams-token" },
+        };
+
+        assert!(migrate(&mut document).unwrap());
+        assert!(!migrate(&mut document).unwrap());
+
+        let (user, migrated) = load_user(document).unwrap();
+        assert!(!migrated);
+        assert_eq!(user.schema_version, CURRENT_SCHEMA_VERSION);
+        assert_eq!(user.slack.labels().len(), 1);
+        assert_eq!(user.slack.active().app_id, "A123");
+        assert_eq!(user.slack.active().user.token, "xoxp");
+        assert_eq!(user.ms_teams.active().access_token, "teams-token");
+    }
+
+    #[test]
+    fn refuses_newer_documents() {
+        let mut document = doc! { "schema_version": CURRENT_SCHEMA_VERSION as i64 + 1 };
+        assert!(matches!(migrate(&mut document), Err(StoreError::NewerSchema(_))));
+    }
+
+    #[test]
+    fn template_document_is_current() {
+        let template: serde_json::Value = serde_json::from_str(include_str!("mongo.json")).unwrap();
+        let Ok(Bson::Document(document)) = Bson::try_from(template) else {
+            panic!("mongo.json is not a document");
+        };
+
+        let (user, migrated) = load_user(document).unwrap();
+        assert!(!migrated);
+        assert!(user.id.is_some());
+    }
+}
@@ -1,2 +1,7 @@
 pub mod mongo_structs;
-pub mod mongo_funcs;
\ No newline at end of file
+pub mod mongo_funcs;
+pub mod vault;
+pub mod passwords;
+pub mod user_store;
+pub mod local_store;
+pub mod migrations;
\ No newline at end of file
@@ -1,17 +1,161 @@
 use serde::{Deserialize, Serialize};
 use slack_morphism::prelude::*;
+use bson::oid::ObjectId;
+use crate::api::mongo_format::migrations::CURRENT_SCHEMA_VERSION;
+
+// Every stored struct falls back to its Default for missing fields, so
+// adding a field never makes older documents unreadable. Changes that defaults
+// can't cover go through migrations.rs.
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct User {
+    // Document id, None until the user is stored
+    #[serde(rename = "_id", default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    // Bumped on every update, see UserStore::patch_user
+    #[serde(default)]
+    pub revision: i64,
+    // Layout version of the stored document, 0 for documents from before versioning
+    #[serde(default)]
+    pub schema_version: u32,
     pub password: String,
     pub username: String,
     pub email: String,
-    pub slack: Slack,
-    pub discord: Discord,
-    pub ms_teams: MSTeams,
+    // Every Slack workspace, Discord account and Teams tenant the user linked
+    pub slack: Accounts<Slack>,
+    pub discord: Accounts<Discord>,
+    pub ms_teams: Accounts<MSTeams>,
+    // Salt of the key the platform credentials are encrypted with, see vault.rs
+    #[serde(default)]
+    pub vault_salt: String,
+}
+
+/// The linked accounts of one platform and the one currently in use.
+///
+/// Always holds at least one account; a platform nobody logged in to yet is
+/// a single default account that `is_linked` reports as not linked.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "StoredAccounts<T>", bound(deserialize = "T: Deserialize<'de> + Default"))]
+pub struct Accounts<T> {
+    linked: Vec<T>,
+    active: usize,
+}
+
+// Read as is and then fixed up, so an empty list or a stale index can't
+// break active()
+#[derive(Deserialize)]
+#[serde(default)]
+struct StoredAccounts<T> {
+    linked: Vec<T>,
+    active: usize,
+}
+
+impl<T> Default for StoredAccounts<T> {
+    fn default() -> Self {
+        StoredAccounts { linked: Vec::new(), active: 0 }
+    }
+}
+
+impl<T: Default> From<StoredAccounts<T>> for Accounts<T> {
+    fn from(stored: StoredAccounts<T>) -> Self {
+        let mut linked = stored.linked;
+        if linked.is_empty() {
+            linked.push(T::default());
+        }
+        let active = stored.active.min(linked.len() - 1);
+        Accounts { linked, active }
+    }
+}
+
+impl<T: Default> Default for Accounts<T> {
+    fn default() -> Self {
+        Accounts { linked: vec![T::default()], active: 0 }
+    }
+}
+
+/// What the account switcher needs to know about an account.
+pub trait LinkedAccount: Default {
+    /// Whether the login for this account was completed.
+    fn is_linked(&self) -> bool;
+
+    /// Name shown in the switcher, e.g. the Slack workspace.
+    fn label(&self) -> Option<String>;
+}
+
+impl<T: LinkedAccount> Accounts<T> {
+    pub fn active(&self) -> &T {
+        &self.linked[self.active]
+    }
+
+    pub fn active_mut(&mut self) -> &mut T {
+        &mut self.linked[self.active]
+    }
+
+    pub fn active_index(&self) -> usize {
+        self.active
+    }
+
+    /// Makes another linked account the active one.
+    pub fn switch_to(&mut self, index: usize) -> bool {
+        if index < self.linked.len() {
+            self.active = index;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Makes an unlinked account active, for logging in to another
+    /// workspace. One left over from an abandoned login is reused.
+    pub fn add(&mut self) -> usize {
+        self.active = match self.linked.iter().position(|account| !account.is_linked()) {
+            Some(index) => index,
+            None => {
+                self.linked.push(T::default());
+                self.linked.len() - 1
+            }
+        };
+        self.active
+    }
+
+    /// Folds the active account into an older entry for the same workspace,
+    /// so logging in to it again replaces that entry instead of listing it twice.
+    pub fn merge_active(&mut self, same: impl Fn(&T, &T) -> bool) {
+        let active = self.active;
+        let existing = (0..self.linked.len()).find(|&index| index != active && same(&self.linked[index], &self.linked[active]));
+        if let Some(existing) = existing {
+            let account = self.linked.remove(active);
+            let existing = if existing > active { existing - 1 } else { existing };
+            self.linked[existing] = account;
+            self.active = existing;
+        }
+    }
+
+    /// Name of every account for the switcher, in order.
+    pub fn labels(&self) -> Vec<String> {
+        self.linked
+            .iter()
+            .enumerate()
+            .map(|(index, account)| match account.label() {
+                Some(label) if account.is_linked() => label,
+                _ if account.is_linked() => format!("Account {}", index + 1),
+                _ => "New account".to_string(),
+            })
+            .collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.linked.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.linked.iter_mut()
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Slack {
     pub app_id: String,
     pub client_id: String,
@@ -22,31 +166,130 @@ pub struct Slack {
     pub team: Team,
     pub user: Slack_User,
     pub verif_token: String,
+    // Older records were saved before the signing secret was stored
+    #[serde(default)]
+    pub signing_secret: String,
+    // How events reach the app, older records used the events endpoint
+    #[serde(default)]
+    pub transport: SlackTransport,
+    // App-level token (xapp-...) used to open Socket Mode connections
+    #[serde(default)]
+    pub app_token: String,
+    // Unix time the config token expires, from tooling.tokens.rotate
+    #[serde(default)]
+    pub config_token_exp: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum SlackTransport {
+    // Slack posts events to the local endpoint through an ngrok tunnel
+    #[default]
+    Events,
+    // Events arrive over a websocket opened with the app-level token
+    SocketMode,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Team {
     pub name: String,
     pub id: String,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Slack_User {
     pub token: String,
     pub scope: String,
     pub id: String,
+    // Only set when the app has token rotation turned on, user tokens
+    // don't expire otherwise
+    #[serde(default)]
+    pub refresh_token: String,
+    #[serde(default)]
+    pub expires_at: u64,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Discord {
     pub token: String,
+    // Older records only held user tokens
+    #[serde(default)]
+    pub token_type: DiscordTokenType,
+    // Id of the logged in account, to tell its own messages apart
+    #[serde(default)]
+    pub user_id: String,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum DiscordTokenType {
+    // Token of a regular account, from the login form or the browser
+    #[default]
+    User,
+    // Token of a bot application from https://discord.com/developers/applications
+    Bot,
+}
+
+impl Discord {
+    /// Value of the `Authorization` header for the REST API. Bot tokens need
+    /// the `Bot` prefix, user tokens are sent as they are.
+    pub fn authorization(&self) -> String {
+        match self.token_type {
+            DiscordTokenType::User => self.token.clone(),
+            DiscordTokenType::Bot => format!("Bot {}", self.token),
+        }
+    }
+
+    pub fn is_bot(&self) -> bool {
+        self.token_type == DiscordTokenType::Bot
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct MSTeams {
     pub access_token: String,
     pub refresh_token: String,
     pub expiration: String,
+    // Directory and account the tokens belong to, read from the access token
+    #[serde(default)]
+    pub tenant_id: String,
+    #[serde(default)]
+    pub account: String,
+}
+
+impl LinkedAccount for Slack {
+    fn is_linked(&self) -> bool {
+        !self.app_id.is_empty()
+    }
+
+    fn label(&self) -> Option<String> {
+        Some(self.team.name.clone()).filter(|name| !name.is_empty())
+    }
+}
+
+impl LinkedAccount for Discord {
+    fn is_linked(&self) -> bool {
+        !self.token.is_empty()
+    }
+
+    fn label(&self) -> Option<String> {
+        None
+    }
+}
+
+impl LinkedAccount for MSTeams {
+    fn is_linked(&self) -> bool {
+        !self.access_token.is_empty()
+    }
+
+    fn label(&self) -> Option<String> {
+        [&self.account, &self.tenant_id]
+            .into_iter()
+            .find(|label| !label.is_empty())
+            .cloned()
+    }
 }
 
 
@@ -80,12 +323,16 @@ impl Default for ResponseData {
 impl Default for User {
     fn default() -> Self {
         User {
+            id: None,
+            revision: 0,
+            schema_version: CURRENT_SCHEMA_VERSION,
             password: String::new(),
             username: String::new(),
             email: String::new(),
-            slack: Slack::default(),
-            discord: Discord::default(),
-            ms_teams: MSTeams::default(),
+            slack: Accounts::default(),
+            discord: Accounts::default(),
+            ms_teams: Accounts::default(),
+            vault_salt: String::new(),
         }
     }
 }
@@ -103,6 +350,10 @@ impl Default for Slack {
             team: Team::default(),
             user: Slack_User::default(),
             verif_token: String::new(),
+            signing_secret: String::new(),
+            transport: SlackTransport::Events,
+            app_token: String::new(),
+            config_token_exp: 0,
         }
     }
 }
@@ -124,6 +375,8 @@ impl Default for Slack_User {
             token: String::new(),
             scope: String::new(),
             id: String::new(),
+            refresh_token: String::new(),
+            expires_at: 0,
         }
     }
 }
@@ -133,6 +386,8 @@ impl Default for Discord {
     fn default() -> Self {
         Discord {
             token: String::new(),
+            token_type: DiscordTokenType::User,
+            user_id: String::new(),
         }
     }
 }
@@ -143,7 +398,9 @@ impl Default for MSTeams {
         MSTeams {
             access_token: String::new(),
             refresh_token: String::new(),
-            expiration: String::new()
+            expiration: String::new(),
+            tenant_id: String::new(),
+            account: String::new(),
         }
     }
 }
@@ -157,6 +414,80 @@ pub struct ModSlackOAuthV2Response {
     pub bot_user_id: Option<SlackUserId>,
     pub app_id: SlackAppId,
     pub team: SlackTeamInfo,
-    pub authed_user: SlackOAuthV2AuthedUser,
+    pub authed_user: ModSlackOAuthV2AuthedUser,
     pub incoming_webhook: Option<SlackOAuthIncomingWebHook>,
-}
\ No newline at end of file
+}
+
+// slack_morphism's authed user has no refresh token, which Slack only sends
+// for apps with token rotation turned on
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ModSlackOAuthV2AuthedUser {
+    pub id: SlackUserId,
+    pub scope: Option<SlackApiTokenScope>,
+    pub access_token: Option<SlackApiTokenValue>,
+    pub token_type: Option<SlackApiTokenType>,
+    pub refresh_token: Option<String>,
+    pub expires_in: Option<u64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workspace(team_id: &str) -> Slack {
+        let mut slack = Slack::default();
+        slack.app_id = format!("A-{}", team_id);
+        slack.team.id = team_id.to_string();
+        slack
+    }
+
+    #[test]
+    fn adds_and_switches_accounts() {
+        let mut accounts = Accounts::<Slack>::default();
+        *accounts.active_mut() = workspace("T1");
+
+        assert_eq!(accounts.add(), 1);
+        // An abandoned login is reused rather than piling up
+        assert_eq!(accounts.add(), 1);
+        *accounts.active_mut() = workspace("T2");
+
+        assert!(accounts.switch_to(0));
+        assert!(!accounts.switch_to(2));
+        assert_eq!(accounts.active().team.id, "T1");
+        assert_eq!(accounts.labels(), ["Account 1", "Account 2"]);
+    }
+
+    #[test]
+    fn logging_in_again_replaces_the_old_entry() {
+        let mut accounts = Accounts::<Slack>::default();
+        *accounts.active_mut() = workspace("T1");
+        accounts.add();
+        *accounts.active_mut() = workspace("T2");
+        accounts.add();
+        *accounts.active_mut() = Slack { app_id: "A-new".to_string(), ..workspace("T1") };
+
+        accounts.merge_active(|a, b| a.team.id == b.team.id);
+
+        assert_eq!(accounts.labels().len(), 2);
+        assert_eq!(accounts.active_index(), 0);
+        assert_eq!(accounts.active().app_id, "A-new");
+    }
+
+    #[test]
+    fn reads_empty_account_lists() {
+        let accounts: Accounts<Discord> = bson::from_document(bson::doc! { "linked": [], "active": 3_i64 }).unwrap();
+        assert_eq!(accounts.labels().len(), 1);
+        assert_eq!(accounts.active_index(), 0);
+    }
+
+    #[test]
+    fn bot_tokens_are_sent_with_their_prefix() {
+        let bot = Discord { token: "abc".to_string(), token_type: DiscordTokenType::Bot, ..Default::default() };
+        assert!(bot.is_bot());
+        assert_eq!(bot.authorization(), "Bot abc");
+
+        let user = Discord { token_type: DiscordTokenType::User, ..bot };
+        assert!(!user.is_bot());
+        assert_eq!(user.authorization(), "abc");
+    }
+}
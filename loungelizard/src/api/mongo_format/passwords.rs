@@ -0,0 +1,97 @@
+// Login password hashing and policy
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use rand::Rng;
+
+pub const MIN_PASSWORD_LEN: usize = 8;
+pub const MAX_PASSWORD_LEN: usize = 128;
+
+/// Result of checking a login password against the stored one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PasswordCheck {
+    /// Matches the stored Argon2 hash.
+    Valid,
+    /// Matches, but the record still holds the password in plain text and
+    /// has to be rehashed.
+    ValidPlaintext,
+    Invalid,
+}
+
+/// Hashes a password with Argon2id and a random salt, as a PHC string
+/// (`$argon2id$v=19$...`) that carries its own parameters and salt.
+pub fn hash_password(password: &str) -> Result<String, String> {
+    let salt = SaltString::encode_b64(&rand::thread_rng().gen::<[u8; 16]>()).map_err(|e| e.to_string())?;
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| e.to_string())
+}
+
+/// Checks a password against what is stored in the user document.
+pub fn verify_password(password: &str, stored: &str) -> PasswordCheck {
+    match PasswordHash::new(stored) {
+        Ok(hash) => match Argon2::default().verify_password(password.as_bytes(), &hash) {
+            Ok(_) => PasswordCheck::Valid,
+            Err(_) => PasswordCheck::Invalid,
+        },
+        // Accounts created before hashing store the password as is
+        Err(_) if !stored.is_empty() && constant_time_eq(password.as_bytes(), stored.as_bytes()) => {
+            PasswordCheck::ValidPlaintext
+        }
+        Err(_) => PasswordCheck::Invalid,
+    }
+}
+
+/// Password rules for new accounts: 8 to 128 characters with at least one
+/// letter and one number. Symbols and spaces are allowed.
+pub fn check_password_policy(password: &str) -> Result<(), String> {
+    let length = password.chars().count();
+    if length < MIN_PASSWORD_LEN {
+        return Err(format!("Password needs at least {} characters", MIN_PASSWORD_LEN));
+    }
+    if length > MAX_PASSWORD_LEN {
+        return Err(format!("Password can't be longer than {} characters", MAX_PASSWORD_LEN));
+    }
+    if !password.chars().any(char::is_alphabetic) || !password.chars().any(|c| c.is_ascii_digit()) {
+        return Err("Password needs a letter and a number".to_string());
+    }
+    if password.trim().len() != password.len() {
+        return Err("Password can't start or end with a space".to_string());
+    }
+    Ok(())
+}
+
+// Doesn't stop at the first differing byte, so timing doesn't leak the prefix
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_and_verifies_passwords() {
+        let hash = hash_password("Lizard123!").unwrap();
+
+        assert!(hash.starts_with("$argon2id$"));
+        assert_ne!(hash, hash_password("Lizard123!").unwrap());
+        assert_eq!(verify_password("Lizard123!", &hash), PasswordCheck::Valid);
+        assert_eq!(verify_password("Lizard124!", &hash), PasswordCheck::Invalid);
+
+        // Records from before hashing
+        assert_eq!(verify_password("Lizard123", "Lizard123"), PasswordCheck::ValidPlaintext);
+        assert_eq!(verify_password("Lizard124", "Lizard123"), PasswordCheck::Invalid);
+        assert_eq!(verify_password("", ""), PasswordCheck::Invalid);
+    }
+
+    #[test]
+    fn enforces_password_policy() {
+        assert!(check_password_policy("lounge lizard #1").is_ok());
+        assert!(check_password_policy("Liz4rd").is_err());
+        assert!(check_password_policy("onlyletters").is_err());
+        assert!(check_password_policy("12345678").is_err());
+        assert!(check_password_policy(" padded123").is_err());
+        assert!(check_password_policy(&"a1".repeat(65)).is_err());
+    }
+}
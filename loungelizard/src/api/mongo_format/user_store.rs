@@ -0,0 +1,227 @@
+// Storage of the user accounts, in MongoDB or in a local file
+use async_trait::async_trait;
+use bson::{oid::ObjectId, to_bson, Bson, Document};
+use mongodb::sync::Client;
+use std::fmt;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use crate::api::mongo_format::mongo_structs::*;
+use crate::api::mongo_format::mongo_funcs::MongoStore;
+use crate::api::mongo_format::local_store::LocalStore;
+use crate::api::mongo_format::vault::VaultError;
+
+#[derive(Debug)]
+pub enum StoreError {
+    /// No stored user has this id.
+    NotFound,
+    /// The username or the email is taken by another user.
+    AlreadyExists,
+    /// The user was updated since it was loaded, e.g. from another window.
+    Conflict,
+    /// The user was never stored, so it has no id to update by.
+    NotStored,
+    /// The credentials could not be encrypted or decrypted.
+    Vault(VaultError),
+    /// The stored user was saved by a newer version of the app.
+    NewerSchema(u32),
+    /// The database or the file failed.
+    Backend(String),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StoreError::NotFound => write!(f, "user not found"),
+            StoreError::AlreadyExists => write!(f, "username or email already taken"),
+            StoreError::Conflict => write!(f, "account was changed in another window, please try again"),
+            StoreError::NotStored => write!(f, "user has not been stored yet"),
+            StoreError::Vault(e) => write!(f, "{}", e),
+            StoreError::NewerSchema(version) => write!(f, "account was saved by a newer version of the app (schema {}), please update", version),
+            StoreError::Backend(e) => write!(f, "storage error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<VaultError> for StoreError {
+    fn from(e: VaultError) -> Self {
+        StoreError::Vault(e)
+    }
+}
+
+impl From<mongodb::error::Error> for StoreError {
+    fn from(e: mongodb::error::Error) -> Self {
+        StoreError::Backend(e.to_string())
+    }
+}
+
+impl From<bson::ser::Error> for StoreError {
+    fn from(e: bson::ser::Error) -> Self {
+        StoreError::Backend(e.to_string())
+    }
+}
+
+impl From<bson::de::Error> for StoreError {
+    fn from(e: bson::de::Error) -> Self {
+        StoreError::Backend(e.to_string())
+    }
+}
+
+impl From<bson::extjson::de::Error> for StoreError {
+    fn from(e: bson::extjson::de::Error) -> Self {
+        StoreError::Backend(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for StoreError {
+    fn from(e: serde_json::Error) -> Self {
+        StoreError::Backend(e.to_string())
+    }
+}
+
+impl From<std::io::Error> for StoreError {
+    fn from(e: std::io::Error) -> Self {
+        StoreError::Backend(e.to_string())
+    }
+}
+
+/// Fields of `User` that can be updated after it was created.
+///
+/// The id, revision, username and email are deliberately not in here.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UserField {
+    Password,
+    VaultSalt,
+    Slack,
+    Discord,
+    MSTeams,
+}
+
+impl UserField {
+    pub const ALL: [UserField; 5] = [
+        UserField::Password,
+        UserField::VaultSalt,
+        UserField::Slack,
+        UserField::Discord,
+        UserField::MSTeams,
+    ];
+
+    /// Name of the field in the stored document.
+    pub fn key(self) -> &'static str {
+        match self {
+            UserField::Password => "password",
+            UserField::VaultSalt => "vault_salt",
+            UserField::Slack => "slack",
+            UserField::Discord => "discord",
+            UserField::MSTeams => "ms_teams",
+        }
+    }
+
+    fn value(self, user: &User) -> Result<Bson, bson::ser::Error> {
+        match self {
+            UserField::Password => to_bson(&user.password),
+            UserField::VaultSalt => to_bson(&user.vault_salt),
+            UserField::Slack => to_bson(&user.slack),
+            UserField::Discord => to_bson(&user.discord),
+            UserField::MSTeams => to_bson(&user.ms_teams),
+        }
+    }
+}
+
+/// The given fields of an already sealed user, ready for `patch_user`.
+pub fn user_patch(user: &User, fields: &[UserField]) -> Result<Document, StoreError> {
+    let mut patch = Document::new();
+    for field in fields {
+        patch.insert(field.key(), field.value(user)?);
+    }
+    Ok(patch)
+}
+
+/// Where user documents are read from and written to.
+///
+/// Credentials are sealed by the caller, stores only ever see encrypted
+/// values.
+#[async_trait]
+pub trait UserStore: Send + Sync {
+    /// Name used in logs.
+    fn name(&self) -> &'static str;
+
+    /// Finds the user whose username or email is `login`.
+    async fn find_user(&self, login: &str) -> Result<Option<User>, StoreError>;
+
+    /// Loads the user with this id.
+    async fn find_by_id(&self, id: &ObjectId) -> Result<Option<User>, StoreError>;
+
+    /// Adds a new user and returns its id. Fails with `AlreadyExists` when
+    /// the username or the email is taken.
+    async fn insert_user(&self, user: &User) -> Result<ObjectId, StoreError>;
+
+    /// Overwrites top level fields of the user, see `user_patch`, in one
+    /// atomic write.
+    ///
+    /// Only applies when the stored revision is still `revision`, otherwise
+    /// fails with `Conflict`. Returns the new revision.
+    async fn patch_user(&self, id: &ObjectId, revision: i64, patch: Document) -> Result<i64, StoreError>;
+}
+
+/// Which store to use, picked with the `USER_STORE` env var (or `.env`).
+#[derive(Debug, Clone, PartialEq)]
+pub enum StoreKind {
+    /// `USER_STORE=mongo`, the default, configured by `MONGO_URI`
+    Mongo,
+    /// `USER_STORE=local`, a JSON file in the user's data directory, or at
+    /// `LOCAL_STORE_PATH`. No server is needed.
+    Local,
+}
+
+impl StoreKind {
+    /// Reads the store configuration from the environment.
+    pub fn from_env() -> Result<StoreKind, String> {
+        dotenv::dotenv().ok();
+        StoreKind::parse(std::env::var("USER_STORE").ok().as_deref())
+    }
+
+    fn parse(kind: Option<&str>) -> Result<StoreKind, String> {
+        match kind.map(|kind| kind.trim().to_lowercase()).as_deref() {
+            None | Some("") | Some("mongo") | Some("mongodb") => Ok(StoreKind::Mongo),
+            Some("local") | Some("offline") => Ok(StoreKind::Local),
+            Some(other) => Err(format!("Unknown USER_STORE: {}", other)),
+        }
+    }
+}
+
+/// Creates the store of the given kind. The Mongo store uses the shared
+/// client, so reconnecting from the login screen also reconnects the store.
+pub fn open_store(kind: &StoreKind, mongo_client: Arc<Mutex<Option<Client>>>) -> Arc<dyn UserStore> {
+    match kind {
+        StoreKind::Mongo => Arc::new(MongoStore::new(mongo_client)),
+        StoreKind::Local => Arc::new(LocalStore::new(LocalStore::default_path())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_store_kind() {
+        assert_eq!(StoreKind::parse(None), Ok(StoreKind::Mongo));
+        assert_eq!(StoreKind::parse(Some("MongoDB")), Ok(StoreKind::Mongo));
+        assert_eq!(StoreKind::parse(Some(" local ")), Ok(StoreKind::Local));
+        assert!(StoreKind::parse(Some("sqlite")).is_err());
+    }
+
+    #[test]
+    fn patches_only_the_requested_fields() {
+        let mut user = User::default();
+        user.username = "lizard".to_string();
+        user.discord.active_mut().token = "enc:v1:token".to_string();
+
+        let patch = user_patch(&user, &[UserField::Discord, UserField::VaultSalt]).unwrap();
+
+        let keys: Vec<&str> = patch.keys().map(String::as_str).collect();
+        assert_eq!(keys, ["discord", "vault_salt"]);
+        assert_eq!(patch.get_document("discord").unwrap().get_array("linked").unwrap()[0].as_document().unwrap().get_str("token"), Ok("enc:v1:token"));
+    }
+}
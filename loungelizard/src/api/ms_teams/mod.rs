@@ -1,3 +1,6 @@
 #[allow(unused_variables, unused_imports)]
 pub mod ms_teams_api;
-pub mod ms_teams_app_setup;
\ No newline at end of file
+pub mod ms_teams_app_setup;
+pub mod ms_teams_provider;
+pub mod ms_teams_notifications;
+pub mod ms_teams_tokens;
\ No newline at end of file
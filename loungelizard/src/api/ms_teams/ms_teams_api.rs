@@ -4,38 +4,84 @@ use serde_json::{json, Value};
 use std::error::Error;
 use std::collections::{HashMap, HashSet};
 use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
+use reqwest::StatusCode;
+use std::fmt;
 use futures::stream::{self, StreamExt};
 use chrono::{DateTime, Duration, ParseError, Utc};
 use ammonia::Builder;
 use regex::Regex;
 
-type UserCache = HashMap<String, (String, String)>; // user_id -> (displayName, profilePicture)
+pub type UserCache = HashMap<String, (String, String)>; // user_id -> (displayName, profilePicture)
+
+// Tokens are refreshed this long before they expire, so a request never
+// starts with a token that runs out halfway
+const REFRESH_MARGIN_SECONDS: i64 = 5 * 60;
 
 /*
-    Check token validity for API usage.
+    Error of a Graph request that came back with an error status
 
-    Description: Check the expiration of a token to the current
-    time. If it is invalid, request a new one using a refresh
-    token. Otherwise, continue as normal. This should be used
-    before every API call is made.
+    Description: Keeps the HTTP status next to the message, so callers can
+    tell an expired or revoked token (401) apart from other failures and
+    refresh it. See is_unauthorized().
+*/
+#[derive(Debug)]
+pub struct GraphError {
+    pub status: StatusCode,
+    pub message: String,
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for GraphError {}
+
+/*
+    Turn a failed response into a GraphError
 
-    Arguments: A client id (client_id: &str), access token
-    (access_token: &str), refresh token (refresh_token: &str),
-    and a time that the access token expires (expiration: &str)
+    Arguments: The response with an error status (response: reqwest::Response)
+    and what was being done (context: &str)
 
-    Returns: Returns if the access token is valid (bool)
+    Returns: The error with the status and the body of the response (Box<dyn Error>)
 */
-async fn ensure_valid_token(client_id: &str, access_token: &str, refresh_token: &str, expiration: &str) -> Result<(bool, String, String, String), Box<dyn Error>> {
-    let expiration_time: chrono::DateTime<Utc> = chrono::DateTime::parse_from_rfc3339(expiration)
-        .map_err(|e: chrono::ParseError| format!("Failed to parse expiration time: {}", e))?
-        .with_timezone(&Utc);
+pub async fn graph_error(response: reqwest::Response, context: &str) -> Box<dyn Error> {
+    let status = response.status();
+    let error_body = response.text().await.unwrap_or_default();
+    Box::new(GraphError {
+        status,
+        message: format!("{}: HTTP {} - {}", context, status, error_body),
+    })
+}
 
-    if Utc::now() >= expiration_time {
-        let (new_access_token, new_refresh_token, new_expiration) = refresh_access_token(client_id, refresh_token).await?;
-        Ok((false, new_access_token, new_refresh_token, new_expiration))
-    }
-    else {
-        Ok((true, "".to_string(), "".to_string(), "".to_string()))
+/*
+    Check if a request failed because of the access token
+
+    Arguments: An error returned by one of the Graph calls (error: &dyn Error)
+
+    Returns: If Graph answered 401 Unauthorized (bool)
+*/
+pub fn is_unauthorized(error: &(dyn Error + 'static)) -> bool {
+    error.downcast_ref::<GraphError>().map_or(false, |error| error.status == StatusCode::UNAUTHORIZED)
+}
+
+/*
+    Check if an access token should be refreshed
+
+    Description: Compares the expiration stored with the token to the given
+    time, with a few minutes of margin. Tokens without a readable expiration,
+    such as ones saved by older versions, are refreshed too.
+
+    Arguments: The time the access token expires (expiration: &str) and the
+    current time (now: DateTime<Utc>)
+
+    Returns: If the token is expired or about to expire (bool)
+*/
+pub fn needs_refresh(expiration: &str, now: DateTime<Utc>) -> bool {
+    match DateTime::parse_from_rfc3339(expiration) {
+        Ok(expiration) => now + Duration::seconds(REFRESH_MARGIN_SECONDS) >= expiration.with_timezone(&Utc),
+        Err(_) => true,
     }
 }
 
@@ -51,9 +97,11 @@ async fn ensure_valid_token(client_id: &str, access_token: &str, refresh_token:
     Arguments: The client id (client_id: &str) and a refresh token
     (refresh_token: &str)
 
-    Returns: Returns a refreshed/valid access token (String)
+    Returns: Returns a refreshed/valid access token, the refresh
+    token to use next time and when the access token expires
+    (String, String, String)
 */
-async fn refresh_access_token(client_id: &str, refresh_token: &str) -> Result<(String, String, String), Box<dyn Error>> {
+pub async fn refresh_access_token(client_id: &str, refresh_token: &str) -> Result<(String, String, String), Box<dyn Error>> {
     let token_url = "https://login.microsoft.com/organizations/oauth2/token";
 
     let mut params = HashMap::new();
@@ -70,14 +118,13 @@ async fn refresh_access_token(client_id: &str, refresh_token: &str) -> Result<(S
         .await?;
     
     if !response.status().is_success() {
-        let status = response.status();
-        let error_body = response.text().await?;
-        return Err(format!("Failed to get access token: HTTP {} - {}", status, error_body).into());
+        return Err(graph_error(response, "Failed to get access token").await);
     }
 
     let token_response: HashMap<String, String> = response.json().await?;
     let access_token = token_response.get("access_token").ok_or("Access token not found")?.clone();
-    let refresh_token = token_response.get("refresh_token").ok_or("Refresh token not found")?.clone();
+    // The refresh token is usually rotated, keep the old one if it wasn't
+    let refresh_token = token_response.get("refresh_token").cloned().unwrap_or_else(|| refresh_token.to_string());
     
     let expires_in: u64 = token_response
         .get("expires_in")
@@ -111,7 +158,7 @@ pub async fn get_user(access_token: &str) -> Result<Value, Box<dyn Error>> {
 
     }
     else {
-        Err(format!("Failed to retrieve user: {}", response.status()).into())
+        Err(graph_error(response, "Failed to retrieve user").await)
     }
 }
 
@@ -162,7 +209,7 @@ pub async fn get_teams(access_token: &str) -> Result<Value, Box<dyn Error>> {
         }
     }
     else {
-        Err(format!("Failed to retrieve teams: {}", response.status()).into())
+        Err(graph_error(response, "Failed to retrieve teams").await)
     }
 }
 
@@ -193,9 +240,7 @@ pub async fn get_users(access_token: &str, team_id: &str) -> Result<UserCache, B
         Ok(user_cache)
     }
     else {
-        let status = response.status();
-        let error_body = response.text().await?;
-        Err(format!("Failed to retrieve users: HTTP {} - {}", status, error_body).into())
+        Err(graph_error(response, "Failed to retrieve users").await)
     }
 }
 
@@ -246,7 +291,7 @@ pub async fn get_channels(access_token: &str, team_id: &str) -> Result<Value, Bo
         }
     }
     else {
-        Err(format!("Failed to retrieve channels: {}", response.status()).into())
+        Err(graph_error(response, "Failed to retrieve channels").await)
     }
 }
 
@@ -302,12 +347,122 @@ pub async fn get_messages(access_token: &str, team_id: &str, channel_id: &str, u
                 break;
             }
         }
+        else {
+            return Err(graph_error(response, "Failed to retrieve messages").await);
+        }
     }
     
     sort_messages_by_time(&mut all_messages);
     Ok(Value::Array(all_messages))
 }
 
+/*
+    Changes to a Channel's Messages since the last delta request
+
+    Description: Messages that were created or edited are returned parsed the
+    same way as get_messages() does, removed holds the ids of deleted Messages.
+    delta_link is what the next call to get_messages_delta() should start from.
+*/
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MessageDelta {
+    pub changed: Vec<Value>,
+    pub removed: Vec<String>,
+    pub delta_link: String,
+}
+
+/*
+    Get changed Messages in a Channel using a delta query
+
+    Endpoint: https://graph.microsoft.com/v1.0/teams/{team_id}/channels/{channel_id}/messages/delta
+
+    MS Graphs Ref: https://learn.microsoft.com/en-us/graph/api/chatmessage-delta?view=graph-rest-1.0&tabs=http
+
+    Description: Instead of downloading the whole Channel again, only asks for
+    the Messages that changed since the previous call. The first call for a
+    Channel has no delta link yet, so it starts from the given time, which
+    should be around when the Channel was fully loaded with get_messages().
+    Graph answers with pages linked by @odata.nextLink and the last page
+    carries the @odata.deltaLink for the next round. Deleted Messages come
+    back with deletedDateTime set (or as @removed entries).
+
+    Arguments: User's delegated access token with sufficient perms (access_token: &str),
+    a team id retrieved from get_teams() (team_id: &str), a channel id retrieved from
+    get_channels() (channel_id: &str), the delta link returned by the previous call if
+    any (delta_link: Option<&str>), the time to start from on the first call
+    (since: DateTime<Utc>), and the team's users (user_cache: &UserCache)
+
+    Returns: The changed and removed Messages and the next delta link (MessageDelta)
+*/
+pub async fn get_messages_delta(access_token: &str, team_id: &str, channel_id: &str, delta_link: Option<&str>, since: DateTime<Utc>, user_cache: &UserCache) -> Result<MessageDelta, Box<dyn Error>> {
+    let mut url = match delta_link {
+        Some(link) => link.to_string(),
+        None => format!(
+            "https://graph.microsoft.com/v1.0/teams/{}/channels/{}/messages/delta?$expand=replies&$filter=lastModifiedDateTime gt {}",
+            team_id, channel_id, since.format("%Y-%m-%dT%H:%M:%S%.3fZ")
+        ),
+    };
+
+    let client = reqwest::Client::new();
+    let mut delta = MessageDelta::default();
+
+    loop {
+        let response = client
+            .get(&url)
+            .header(AUTHORIZATION, format!("Bearer {}", access_token))
+            .header(CONTENT_TYPE, "application/json")
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(graph_error(response, "Failed to retrieve message delta").await);
+        }
+
+        let page: Value = response.json().await?;
+        for message in page.get("value").and_then(|val| val.as_array()).unwrap_or(&Vec::new()) {
+            add_to_delta(&mut delta, message, user_cache);
+        }
+
+        if let Some(next_link) = page.get("@odata.nextLink").and_then(|link| link.as_str()) {
+            url = next_link.to_string();
+        }
+        else if let Some(next_delta) = page.get("@odata.deltaLink").and_then(|link| link.as_str()) {
+            delta.delta_link = next_delta.to_string();
+            break;
+        }
+        else {
+            return Err("Message delta response had neither a next link nor a delta link".into());
+        }
+    }
+
+    Ok(delta)
+}
+
+/*
+    Sort one Message of a delta page into a MessageDelta
+
+    Description: Only Messages Graph reports as deleted are removed. Senders
+    missing from the team's users (e.g. people who left) keep their Messages,
+    shown as "Unknown User" by parse_message(). System messages have no sender
+    at all and are skipped, like get_messages() leaves them out.
+
+    Arguments: The delta being built (delta: &mut MessageDelta), a Message from
+    the delta page (message: &Value), and the team's users (user_cache: &UserCache)
+
+    Returns: N/A
+*/
+fn add_to_delta(delta: &mut MessageDelta, message: &Value, user_cache: &UserCache) {
+    let id = message.get("id").and_then(|id| id.as_str()).unwrap_or("").to_string();
+    let deleted = message.get("@removed").is_some() || message.get("deletedDateTime").is_some_and(|dt| dt.is_string());
+    let system_message = message["from"].is_null();
+
+    if deleted {
+        delta.removed.push(id);
+    }
+    else if !system_message {
+        delta.changed.push(parse_message(message, user_cache));
+    }
+}
+
 /*
     Send a message using a delegated access token to a specified Channel
 
@@ -345,9 +500,7 @@ pub async fn send_message(access_token: &str, team_id: &str, channel_id: &str, m
         .await?;
 
     if !response.status().is_success() {
-        let status = response.status();
-        let error_body = response.text().await?;
-        eprintln!("Failed to send message: HTTP {} - {}", status, error_body);
+        return Err(graph_error(response, "Failed to send message").await);
     }
     Ok(())
 }
@@ -371,13 +524,106 @@ pub async fn send_message_reply(access_token: &str, team_id: &str, channel_id: &
         .await?;
 
     if !response.status().is_success() {
-        let status = response.status();
-        let error_body = response.text().await?;
-        eprintln!("Failed to send reply: HTTP {} - {}", status, error_body);
+        return Err(graph_error(response, "Failed to send reply").await);
     }
     Ok(())
 }
 
+/*
+    Edit the content of a message the user sent to a Channel
+
+    Endpoint: https://graph.microsoft.com/v1.0/teams/{team_id}/channels/{channel_id}/messages/{message_id}
+
+    MS Graphs Ref: https://learn.microsoft.com/en-us/graph/api/chatmessage-update?view=graph-rest-1.0&tabs=http
+
+    Description: Calls the endpoint to replace the body of the specified Message.
+    Graph only allows the sender of a message to change its content.
+
+    Arguments: User's delegated access token with sufficient perms (access_token: &str),
+    a team id (team_id: &str), a channel id (channel_id: &str), the id of the message
+    to edit (message_id: &str) and the new content (message: &str)
+
+    Returns: N/A
+*/
+pub async fn edit_message(access_token: &str, team_id: &str, channel_id: &str, message_id: &str, message: &str) -> Result<(), Box<dyn Error>> {
+    let url = format!("https://graph.microsoft.com/v1.0/teams/{}/channels/{}/messages/{}", team_id, channel_id, message_id);
+    patch_message_body(access_token, &url, message).await
+}
+
+pub async fn edit_message_reply(access_token: &str, team_id: &str, channel_id: &str, message_id: &str, reply_id: &str, message: &str) -> Result<(), Box<dyn Error>> {
+    let url = format!("https://graph.microsoft.com/v1.0/teams/{}/channels/{}/messages/{}/replies/{}", team_id, channel_id, message_id, reply_id);
+    patch_message_body(access_token, &url, message).await
+}
+
+async fn patch_message_body(access_token: &str, url: &str, message: &str) -> Result<(), Box<dyn Error>> {
+    let body = json!({
+        "body": {
+            "contentType": "html",
+            "content": message
+        }
+    });
+
+    let client = reqwest::Client::new();
+    let response = client
+        .patch(url)
+        .header(AUTHORIZATION, format!("Bearer {}", access_token))
+        .header(CONTENT_TYPE, "application/json")
+        .json(&body)
+        .send()
+        .await?;
+
+    if response.status().is_success() {
+        Ok(())
+    }
+    else {
+        Err(graph_error(response, "Failed to edit message").await)
+    }
+}
+
+/*
+    Delete a message the user sent to a Channel
+
+    Endpoint: https://graph.microsoft.com/v1.0/teams/{team_id}/channels/{channel_id}/messages/{message_id}/softDelete
+
+    MS Graphs Ref: https://learn.microsoft.com/en-us/graph/api/chatmessage-softdelete?view=graph-rest-1.0&tabs=http
+
+    Description: Calls the endpoint to soft delete the specified Message. The message
+    stays in the channel history with its content removed, like in the Teams client.
+
+    Arguments: User's delegated access token with sufficient perms (access_token: &str),
+    a team id (team_id: &str), a channel id (channel_id: &str) and the id of the message
+    to delete (message_id: &str)
+
+    Returns: N/A
+*/
+pub async fn delete_message(access_token: &str, team_id: &str, channel_id: &str, message_id: &str) -> Result<(), Box<dyn Error>> {
+    let url = format!("https://graph.microsoft.com/v1.0/teams/{}/channels/{}/messages/{}/softDelete", team_id, channel_id, message_id);
+    soft_delete(access_token, &url).await
+}
+
+pub async fn delete_message_reply(access_token: &str, team_id: &str, channel_id: &str, message_id: &str, reply_id: &str) -> Result<(), Box<dyn Error>> {
+    let url = format!("https://graph.microsoft.com/v1.0/teams/{}/channels/{}/messages/{}/replies/{}/softDelete", team_id, channel_id, message_id, reply_id);
+    soft_delete(access_token, &url).await
+}
+
+async fn soft_delete(access_token: &str, url: &str) -> Result<(), Box<dyn Error>> {
+    let client = reqwest::Client::new();
+    let response = client
+        .post(url)
+        .header(AUTHORIZATION, format!("Bearer {}", access_token))
+        .header(CONTENT_TYPE, "application/json")
+        .body("")
+        .send()
+        .await?;
+
+    if response.status().is_success() {
+        Ok(())
+    }
+    else {
+        Err(graph_error(response, "Failed to delete message").await)
+    }
+}
+
 pub async fn send_reaction(access_token: &str, team_id: &str, channel_id: &str, message_id: &str, reaction: &str) -> Result<(), Box<dyn Error>> {
     let url = format!("https://graph.microsoft.com/v1.0/teams/{}/channels/{}/messages/{}/setReaction", team_id, channel_id, message_id);
 
@@ -397,9 +643,7 @@ pub async fn send_reaction(access_token: &str, team_id: &str, channel_id: &str,
         Ok(())
     }
     else {
-        let status = response.status();
-        let error_body = response.text().await?;
-        Err(format!("Failed to add reaction: HTTP {} - {}", status, error_body).into())
+        Err(graph_error(response, "Failed to add reaction").await)
     }
 }
 
@@ -422,9 +666,7 @@ pub async fn send_reaction_reply(access_token: &str, team_id: &str, channel_id:
         Ok(())
     }
     else {
-        let status = response.status();
-        let error_body = response.text().await?;
-        Err(format!("Failed to add reaction: HTTP {} - {}", status, error_body).into())
+        Err(graph_error(response, "Failed to add reaction").await)
     }
 }
 
@@ -447,9 +689,7 @@ pub async fn remove_reaction(access_token: &str, team_id: &str, channel_id: &str
         Ok(())
     }
     else {
-        let status = response.status();
-        let error_body = response.text().await?;
-        Err(format!("Failed to add reaction: HTTP {} - {}", status, error_body).into())
+        Err(graph_error(response, "Failed to add reaction").await)
     }
 }
 
@@ -472,9 +712,7 @@ pub async fn remove_reaction_reply(access_token: &str, team_id: &str, channel_id
         Ok(())
     }
     else {
-        let status = response.status();
-        let error_body = response.text().await?;
-        Err(format!("Failed to add reaction: HTTP {} - {}", status, error_body).into())
+        Err(graph_error(response, "Failed to add reaction").await)
     }
 }
 
@@ -494,7 +732,7 @@ pub async fn get_team_picture(access_token: &str, team_id: &str) -> Result<Strin
         Ok(format!("data:image/jpeg;base64,{}", base64_image))
     }
     else {
-        Err(format!("Failed to retrieve team picture: HTTP {}", response.status()).into())
+        Err(graph_error(response, "Failed to retrieve team picture").await)
     }
 }
 
@@ -514,7 +752,7 @@ pub async fn get_user_picture(access_token: &str, user_id: &str) -> Result<Strin
         Ok(format!("data:image/jpeg;base64,{}", base64_image))
     }
     else {
-        Err(format!("Failed to retrieve profile picture: HTTP {}", response.status()).into())
+        Err(graph_error(response, "Failed to retrieve profile picture").await)
     }
 }
 
@@ -565,6 +803,7 @@ pub fn parse_message(message: &Value, user_cache: &UserCache) -> Value {
     let user = message.get("from").map(|u| parse_user(u, user_cache));
     let reactions = parse_reactions(message.get("reactions").unwrap_or(&json!([])), user_cache);
     let replies = message.get("replies").and_then(|r| r.as_array()).unwrap_or(&Vec::new()).iter().map(|reply| parse_reply(reply, user_cache)).collect::<Vec<_>>();
+    let edited = message.get("lastEditedDateTime").map_or(false, |dt| dt.is_string());
 
     json!({
         "id": message.get("id"),
@@ -573,7 +812,8 @@ pub fn parse_message(message: &Value, user_cache: &UserCache) -> Value {
         "time": time,
         "user": user.unwrap_or(json!({})),
         "reactions": reactions,
-        "replies": replies
+        "replies": replies,
+        "edited": edited
     })
 }
 
@@ -610,6 +850,7 @@ fn parse_reply(reply: &Value, user_cache: &UserCache) -> Value {
     let time = reply.get("createdDateTime").and_then(|dt| dt.as_str()).unwrap_or("").to_string();
     let user = reply.get("from").map(|u| parse_user(u, user_cache));
     let reply_reactions = parse_reactions(reply.get("reactions").unwrap_or(&json!([])), user_cache);
+    let edited = reply.get("lastEditedDateTime").map_or(false, |dt| dt.is_string());
 
     json!({
         "id": reply.get("id"),
@@ -617,7 +858,8 @@ fn parse_reply(reply: &Value, user_cache: &UserCache) -> Value {
         "time": time,
         "content": content,
         "user": user.unwrap_or(json!({})),
-        "reactions": reply_reactions
+        "reactions": reply_reactions,
+        "edited": edited
     })
 }
 
@@ -640,3 +882,51 @@ fn sort_messages_by_time(messages: &mut Vec<Value>) {
         time_a.cmp(time_b)
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delta_removes_only_deleted_messages() {
+        let mut user_cache = UserCache::new();
+        user_cache.insert("u1".to_string(), ("Ada".to_string(), "".to_string()));
+        let from = |user_id: &str| json!({ "user": { "id": user_id } });
+
+        let mut delta = MessageDelta::default();
+        for message in [
+            json!({ "id": "1", "createdDateTime": "2024-01-01T00:00:00Z", "from": from("u1") }),
+            json!({ "id": "2", "createdDateTime": "2024-01-01T00:01:00Z", "from": from("left-the-team") }),
+            json!({ "id": "3", "deletedDateTime": "2024-01-01T00:02:00Z", "from": from("u1") }),
+            json!({ "id": "4", "@removed": { "reason": "deleted" } }),
+            json!({ "id": "5", "createdDateTime": "2024-01-01T00:03:00Z", "from": null }),
+        ] {
+            add_to_delta(&mut delta, &message, &user_cache);
+        }
+
+        let names: Vec<&str> = delta.changed.iter().map(|m| m["user"]["displayName"].as_str().unwrap()).collect();
+        assert_eq!(names, vec!["Ada", "Unknown User"]);
+        assert_eq!(delta.removed, vec!["3", "4"]);
+    }
+
+    #[test]
+    fn refreshes_tokens_close_to_expiry() {
+        let now = DateTime::parse_from_rfc3339("2024-01-01T12:00:00Z").unwrap().with_timezone(&Utc);
+
+        assert!(!needs_refresh("2024-01-01T13:00:00+00:00", now));
+        assert!(needs_refresh("2024-01-01T12:03:00+00:00", now));
+        assert!(needs_refresh("2024-01-01T11:00:00+00:00", now));
+        assert!(needs_refresh("", now));
+    }
+
+    #[test]
+    fn detects_unauthorized_graph_errors() {
+        let unauthorized: Box<dyn Error> = Box::new(GraphError { status: StatusCode::UNAUTHORIZED, message: "expired".to_string() });
+        let forbidden: Box<dyn Error> = Box::new(GraphError { status: StatusCode::FORBIDDEN, message: "denied".to_string() });
+        let other: Box<dyn Error> = "timed out".into();
+
+        assert!(is_unauthorized(unauthorized.as_ref()));
+        assert!(!is_unauthorized(forbidden.as_ref()));
+        assert!(!is_unauthorized(other.as_ref()));
+    }
+}
\ No newline at end of file
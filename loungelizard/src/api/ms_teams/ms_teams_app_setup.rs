@@ -1,5 +1,4 @@
 use reqwest::header::{CONTENT_TYPE};
-use serde_json::Value;
 use std::process::Command;
 use tokio::sync::oneshot;
 use std::collections::HashMap;
@@ -10,18 +9,18 @@ use which::which;
 use tiny_http::{Server, Response};
 use rand::Rng;
 use sha2::{Digest, Sha256};
-use base64::{encode_config, URL_SAFE_NO_PAD};
+use base64::{decode_config, encode_config, URL_SAFE_NO_PAD};
 //use webbrowser;
 
-// Constants 
-const NGROK_TUNNEL_SEARCH: &str = "http://127.0.0.1:4040/api/tunnels";
-//const NGROK_TUNNEL: &str = "https://lizardlounge.ngrok.app/callback"; // Static Domain if paying for ngrok service. Replace all redirect_uri references.
-const NGROK_PORT: &str = "8080";
+use crate::api::tunnel::{public_url, LOCAL_PORT};
+
+// Azure app registration used for the login and for refreshing its tokens
+pub const MS_TEAMS_CLIENT_ID: &str = "51e0dbc4-59a4-4cb4-a020-1b8ef7495470";
 
 /*
     Run MS Teams app setup code in the correct order
 
-    Description: Starts the process of running ngrok, getting the ngrok uri
+    Description: Starts the process of opening the tunnel, getting its uri
     updating the MS Azure manifest, authorizing a user, and getting an
     access token. More information on each aspect found in their respective
     function.
@@ -35,8 +34,8 @@ const NGROK_PORT: &str = "8080";
 
 pub async fn start_ms_teams(ms_teams_client_id: &str) -> Result<(String, String, String), Box<dyn Error>> {
     
-    // Start ngrok if necessary and update the manifest, wait some time to ensure the update
-    let redirect_uri = start_ngrok().await?;
+    // Open the tunnel if necessary and update the manifest, wait some time to ensure the update
+    let redirect_uri = format!("{}/callback", public_url().await.map_err(|e| e.to_string())?);
 
     let _ = update_manifest_uri(ms_teams_client_id, &redirect_uri).await;
 
@@ -57,7 +56,7 @@ pub async fn start_ms_teams(ms_teams_client_id: &str) -> Result<(String, String,
     this authorization code needs a PCKE pair (proof key for code exchange).
     See generate_pcke_pair() for more info.
 
-    Arguments: The client_id (client_id: &str), the ngrok tunnel callback uri
+    Arguments: The client_id (client_id: &str), the tunnel callback uri
     used (redirect_uri: &str), and a PCKE code challenge (code_challenge: &str)
 
     Returns: The authorization code needed for an access token (String)
@@ -196,7 +195,7 @@ async fn get_access_token(client_id: &str, redirect_uri: &str, auth_code: &str,
 */
 fn start_local_server(tx: oneshot::Sender<String>) -> Result<(), Box<dyn Error + Send + Sync>> {
 
-    let http_server = format!("127.0.0.1:{}", NGROK_PORT);
+    let http_server = format!("127.0.0.1:{}", LOCAL_PORT);
     let server = Server::http(http_server)?;
     if let Some(request) = server.incoming_requests().next() {
         let url = request.url().to_string();
@@ -219,93 +218,6 @@ fn start_local_server(tx: oneshot::Sender<String>) -> Result<(), Box<dyn Error +
     Ok(())
 }
 
-/*
-    Start ngrok tunnel.
-
-    Endpoint: 127.0.0.1:4040/api/tunnels
-
-    Command: ngrok http 'NGROK_TUNNEL_SEARCH'
-
-    Description: Starts an ngrok tunnel on the given port if one does not
-    already exist. If it does not, it will create a new tunnel using a command
-    programmatically. Otherwise, it returns the existing tunnel if it matches
-    the desired port, if it doesn't it will kill the tunnel. Port 4040 shows
-    information on connections, including Ngrok. By doing a get request at this
-    endpoint, we can determine how many tunnels and public_urls are connected to
-    our local machine. I.e. we can grab the ngrok url generated in order to use
-    it to update the manifest. With this, we can receive information more freely
-    through our local server, since MS Teams doesn't want to send information to
-    a local server normally.
-
-    Arguments: N/A
-
-    Returns: The tunnel url will be returned with an appended "/callback" (String)
-
-*/
-async fn start_ngrok() -> Result<String, Box<dyn Error>> {
-
-    let client = reqwest::Client::new();
-
-    // Try to retrieve ngrok tunnel
-    let response = client.get(NGROK_TUNNEL_SEARCH).send();
-    if let Ok(resp) = response.await {
-        if resp.status().is_success() {
-            let tunnels: Value = resp.json().await?;
-            if let Some(tunnel) = tunnels.get("tunnels").and_then(|t| t.as_array()).and_then(|t| t.first()) {
-                if let Some(public_url) = tunnel.get("public_url").and_then(|p| p.as_str()) {
-                    let formatted_url = format!("{}/callback", public_url);
-                    return Ok(formatted_url);
-                }
-            }
-        }
-    }
-    
-    // Start a new ngrok tunnel
-    let local_url = format!("http://localhost:{}", NGROK_PORT);
-    let ngrok_path = which("ngrok").expect("Ngrok (ngrok) not found. Please ensure it is installed and available in PATH.");
-    let ngrok_process = Command::new(ngrok_path)
-        .arg("http")
-        .arg(local_url)
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .spawn();
-    if let Err(err) = ngrok_process {
-        eprintln!("Failed to start NGROK tunnel: {}", err);
-        return Err(Box::new(err))
-    }
-
-    // Wait some time and try to retrieve ngrok tunnel again
-    std::thread::sleep(std::time::Duration::from_secs(2));
-    let response = client.get(NGROK_TUNNEL_SEARCH).send().await?;
-    if response.status().is_success() {
-        let tunnels: Value = response.json().await?;
-        if let Some(tunnel) = tunnels.get("tunnels").and_then(|t| t.as_array()).and_then(|t| t.first()) {
-            if let Some(public_url) = tunnel.get("public_url").and_then(|p| p.as_str()) {
-                let formatted_url = format!("{}/callback", public_url);
-                return Ok(formatted_url);
-            }
-        }
-    }
-
-    Err("Failed to start or retrieve ngrok tunnel".into())
-}
-
-async fn kill_ngrok() -> Result<(), Box<dyn Error>> {
-    if cfg!(target_os = "windows") {
-        Command::new("taskkill")
-            .args(&["/F", "/IM", "ngrok.exe"])
-            .spawn()?
-            .wait()?;
-    }
-    else {
-        Command::new("pkill")
-            .arg("ngrok")
-            .spawn()?
-            .wait()?;
-    }
-    Ok(())
-}
-
 /*
     Update Azure Manifest.
 
@@ -434,4 +346,47 @@ pub async fn dummy_token_check(token: &str) -> bool {
         Ok(resp) => resp.status().is_success(),
         Err(_) => false,
     }
-}
\ No newline at end of file
+}
+
+/*
+    Read the tenant and account of an access token
+
+    Description: Microsoft access tokens are JWTs whose payload names the
+    directory (tid) and the signed in account (upn, or preferred_username
+    for personal accounts). Used to tell linked Teams tenants apart in the
+    account switcher. The signature is not checked, the token is our own.
+
+    Arguments: A delegated access token (access_token: &str)
+
+    Returns: The tenant id and account name, None if the token can't be read
+    (Option<(String, String)>)
+*/
+pub fn token_tenant(access_token: &str) -> Option<(String, String)> {
+    let payload = access_token.split('.').nth(1)?;
+    let claims: serde_json::Value = serde_json::from_slice(&decode_config(payload, URL_SAFE_NO_PAD).ok()?).ok()?;
+
+    let tenant_id = claims["tid"].as_str()?.to_string();
+    let account = ["upn", "preferred_username", "unique_name"]
+        .iter()
+        .find_map(|claim| claims[*claim].as_str())
+        .unwrap_or_default()
+        .to_string();
+    Some((tenant_id, account))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_tenant_from_access_token() {
+        let payload = encode_config(r#"{"tid":"contoso-tenant","upn":"lizard@contoso.com"}"#, URL_SAFE_NO_PAD);
+        let token = format!("header.{}.signature", payload);
+
+        assert_eq!(
+            token_tenant(&token),
+            Some(("contoso-tenant".to_string(), "lizard@contoso.com".to_string()))
+        );
+        assert_eq!(token_tenant("not-a-jwt"), None);
+    }
+}
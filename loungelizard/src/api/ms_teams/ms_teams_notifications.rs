@@ -0,0 +1,306 @@
+use serde_json::{json, Value};
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use chrono::Utc;
+use rand::Rng;
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
+use tiny_http::{Header, Method, Response, Server};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tracing::{info, warn};
+
+use crate::api::ms_teams::ms_teams_api::graph_error;
+use crate::api::tunnel::{public_url, LOCAL_PORT};
+
+// Graph caps channel message subscriptions at one hour, renew a bit before that
+const SUBSCRIPTION_MINUTES: i64 = 55;
+const RENEW_AFTER: Duration = Duration::from_secs(50 * 60);
+
+/*
+    Check if change notifications are turned on
+
+    Description: Notifications need a public tunnel to the local server, so
+    they are opt in with MS_TEAMS_NOTIFICATIONS=1. Without them the Teams page
+    falls back to delta queries on a timer.
+
+    Arguments: N/A
+
+    Returns: If the notification receiver should be started (bool)
+*/
+pub fn notifications_enabled() -> bool {
+    std::env::var("MS_TEAMS_NOTIFICATIONS").map_or(false, |value| value == "1" || value.eq_ignore_ascii_case("true"))
+}
+
+/*
+    Change notification receiver for Channel Messages
+
+    Description: Runs a local server on the same port the OAuth callback uses,
+    exposed through the tunnel, and keeps one Graph subscription on the
+    Channel being viewed. Every notification yields the id of the Channel that
+    changed, which the page then syncs with get_messages_delta(). The server
+    thread stops when the listener is dropped.
+*/
+pub struct NotificationListener {
+    receiver: UnboundedReceiver<String>,
+    stop: Arc<AtomicBool>,
+    client_state: String,
+    notification_url: String,
+    // (subscription id, channel id, created at)
+    subscription: Option<(String, String, Instant)>,
+}
+
+impl NotificationListener {
+    pub async fn start() -> Result<NotificationListener, Box<dyn Error>> {
+        let notification_url = format!("{}/notifications", public_url().await.map_err(|e| e.to_string())?);
+
+        let client_state: String = rand::thread_rng()
+            .sample_iter(&rand::distributions::Alphanumeric)
+            .take(32)
+            .map(char::from)
+            .collect();
+
+        let (sender, receiver) = unbounded_channel();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let server_state = client_state.clone();
+        let server_stop = Arc::clone(&stop);
+        std::thread::spawn(move || {
+            if let Err(e) = start_notification_server(sender, server_state, server_stop) {
+                warn!("Teams notification server stopped: {}", e);
+            }
+        });
+
+        Ok(NotificationListener {
+            receiver,
+            stop,
+            client_state,
+            notification_url,
+            subscription: None,
+        })
+    }
+
+    /*
+        Make sure the subscription covers the given Channel
+
+        Description: Replaces the subscription when the Channel changed and
+        renews it before Graph expires it. Does nothing otherwise.
+    */
+    pub async fn watch(&mut self, access_token: &str, team_id: &str, channel_id: &str) -> Result<(), Box<dyn Error>> {
+        if let Some((_, watched, created)) = &self.subscription {
+            if watched == channel_id && created.elapsed() < RENEW_AFTER {
+                return Ok(());
+            }
+        }
+
+        if let Some((subscription_id, _, _)) = self.subscription.take() {
+            let _ = delete_subscription(access_token, &subscription_id).await;
+        }
+
+        let subscription_id = create_subscription(access_token, team_id, channel_id, &self.notification_url, &self.client_state).await?;
+        info!("Subscribed to Teams channel {}", channel_id);
+        self.subscription = Some((subscription_id, channel_id.to_string(), Instant::now()));
+        Ok(())
+    }
+
+    // Waits for the next notified Channel id
+    pub async fn next(&mut self) -> Option<String> {
+        self.receiver.recv().await
+    }
+}
+
+impl Drop for NotificationListener {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/*
+    Subscribe to changes of a Channel's Messages
+
+    Endpoint: https://graph.microsoft.com/v1.0/subscriptions
+
+    MS Graphs Ref: https://learn.microsoft.com/en-us/graph/api/subscription-post-subscriptions?view=graph-rest-1.0&tabs=http
+
+    Description: Graph validates the notification url right away by sending it a
+    validationToken, so the local server has to be running before this is called.
+
+    Arguments: User's delegated access token with sufficient perms (access_token: &str),
+    a team id (team_id: &str), a channel id (channel_id: &str), the public url of the
+    receiver (notification_url: &str), and a secret echoed in notifications (client_state: &str)
+
+    Returns: The subscription id (String)
+*/
+pub async fn create_subscription(access_token: &str, team_id: &str, channel_id: &str, notification_url: &str, client_state: &str) -> Result<String, Box<dyn Error>> {
+    let expiration = Utc::now() + chrono::Duration::minutes(SUBSCRIPTION_MINUTES);
+    let body = json!({
+        "changeType": "created,updated,deleted",
+        "notificationUrl": notification_url,
+        "resource": format!("/teams/{}/channels/{}/messages", team_id, channel_id),
+        "expirationDateTime": expiration.to_rfc3339(),
+        "clientState": client_state,
+    });
+
+    let client = reqwest::Client::new();
+    let response = client
+        .post("https://graph.microsoft.com/v1.0/subscriptions")
+        .header(AUTHORIZATION, format!("Bearer {}", access_token))
+        .header(CONTENT_TYPE, "application/json")
+        .json(&body)
+        .send()
+        .await?;
+
+    if response.status().is_success() {
+        let subscription: Value = response.json().await?;
+        Ok(subscription.get("id").and_then(|id| id.as_str()).ok_or("Subscription id not found")?.to_string())
+    }
+    else {
+        Err(graph_error(response, "Failed to create subscription").await)
+    }
+}
+
+/*
+    Delete a subscription
+
+    Endpoint: https://graph.microsoft.com/v1.0/subscriptions/{subscription_id}
+
+    MS Graphs Ref: https://learn.microsoft.com/en-us/graph/api/subscription-delete?view=graph-rest-1.0&tabs=http
+
+    Arguments: User's delegated access token (access_token: &str) and the id
+    returned by create_subscription() (subscription_id: &str)
+
+    Returns: N/A
+*/
+pub async fn delete_subscription(access_token: &str, subscription_id: &str) -> Result<(), Box<dyn Error>> {
+    let client = reqwest::Client::new();
+    let response = client
+        .delete(format!("https://graph.microsoft.com/v1.0/subscriptions/{}", subscription_id))
+        .header(AUTHORIZATION, format!("Bearer {}", access_token))
+        .send()
+        .await?;
+
+    if response.status().is_success() {
+        Ok(())
+    }
+    else {
+        Err(graph_error(response, "Failed to delete subscription").await)
+    }
+}
+
+/*
+    Start a local server to receive change notifications
+
+    Description: Answers Graph's validation request by echoing the
+    validationToken as plain text, and for notification posts sends the id
+    of every changed Channel to tx. Notifications with another clientState
+    are ignored. Runs until stop is set.
+
+    Arguments: A channel sender for the changed Channel ids (tx: UnboundedSender<String>),
+    the secret given to create_subscription() (client_state: String), and a stop flag
+    (stop: Arc<AtomicBool>)
+
+    Returns: N/A
+*/
+fn start_notification_server(tx: UnboundedSender<String>, client_state: String, stop: Arc<AtomicBool>) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let http_server = format!("127.0.0.1:{}", LOCAL_PORT);
+    let server = Server::http(http_server)?;
+
+    while !stop.load(Ordering::Relaxed) {
+        let Some(mut request) = server.recv_timeout(Duration::from_secs(1))? else { continue };
+        let url = request.url().to_string();
+
+        if !url.starts_with("/notifications") {
+            request.respond(Response::empty(404))?;
+            continue;
+        }
+
+        if let Some(token) = validation_token(&url) {
+            let header = Header::from_bytes("Content-Type", "text/plain").map_err(|_| "Invalid header")?;
+            request.respond(Response::from_string(token).with_header(header))?;
+            continue;
+        }
+
+        if *request.method() == Method::Post {
+            let mut body = String::new();
+            request.as_reader().read_to_string(&mut body)?;
+            let payload: Value = serde_json::from_str(&body).unwrap_or(Value::Null);
+
+            for notification in payload.get("value").and_then(|v| v.as_array()).unwrap_or(&Vec::new()) {
+                if notification.get("clientState").and_then(|c| c.as_str()) != Some(client_state.as_str()) {
+                    continue;
+                }
+                if let Some(channel_id) = notification.get("resource").and_then(|r| r.as_str()).and_then(channel_from_resource) {
+                    let _ = tx.send(channel_id);
+                }
+            }
+        }
+
+        // Graph wants a quick 2xx, the sync happens on the page
+        request.respond(Response::empty(202))?;
+    }
+
+    Ok(())
+}
+
+fn validation_token(url: &str) -> Option<String> {
+    let query = url.split('?').nth(1)?;
+    query.split('&').find_map(|param| {
+        let mut key_val = param.splitn(2, '=');
+        match (key_val.next(), key_val.next()) {
+            (Some("validationToken"), Some(value)) => Some(percent_decode(value)),
+            _ => None,
+        }
+    })
+}
+
+// Graph sends the validation token url encoded, with '+' for spaces
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+                match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+// Resources look like teams('{team_id}')/channels('{channel_id}')/messages('{message_id}')
+fn channel_from_resource(resource: &str) -> Option<String> {
+    let start = resource.find("channels('")? + "channels('".len();
+    let end = resource[start..].find("')")? + start;
+    Some(resource[start..end].to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_notification_urls() {
+        assert_eq!(
+            channel_from_resource("teams('abc')/channels('19:xyz@thread.tacv2')/messages('123')"),
+            Some("19:xyz@thread.tacv2".to_string())
+        );
+        assert_eq!(channel_from_resource("chats('abc')/messages('123')"), None);
+
+        assert_eq!(
+            validation_token("/notifications?validationToken=Validation%3a+Testing+client"),
+            Some("Validation: Testing client".to_string())
+        );
+        assert_eq!(validation_token("/notifications"), None);
+    }
+}
@@ -0,0 +1,353 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::error::Error;
+use std::future::Future;
+use std::sync::Mutex;
+
+use crate::api::chat_provider::*;
+use crate::api::ms_teams::ms_teams_api::{self, UserCache};
+use crate::api::ms_teams::ms_teams_tokens::TeamsTokens;
+use crate::api::unified_message::{UnifiedMessage, UnifiedReaction};
+
+// Where the access token of a provider comes from
+enum Credentials {
+    Fixed(String),
+    Refreshing(TeamsTokens),
+}
+
+/*
+    ChatProvider backed by Microsoft Graph
+
+    Description: Wraps the ms_teams_api calls behind the common ChatProvider
+    interface. Replies are returned as their own messages with thread_id set
+    to the root message, which is what Graph needs to address them.
+*/
+pub struct MSTeamsProvider {
+    credentials: Credentials,
+    // team_id -> members, Graph has no sender names on messages
+    user_caches: Mutex<HashMap<String, UserCache>>,
+    // Id of the signed in user, to tell which reactions are theirs
+    user_id: Mutex<Option<String>>,
+}
+
+/*
+    Changes to a Channel since the last call to fetch_changes()
+
+    Description: changed holds every Message of the threads that were created
+    or edited, roots followed by their replies like fetch_history() returns
+    them. removed holds the ids of deleted Messages.
+*/
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct HistoryChanges {
+    pub changed: Vec<UnifiedMessage>,
+    pub removed: Vec<String>,
+    pub delta_link: String,
+}
+
+impl MSTeamsProvider {
+    pub fn new(access_token: String) -> Self {
+        Self::with_credentials(Credentials::Fixed(access_token))
+    }
+
+    /*
+        Provider for the Teams page
+
+        Description: Gets the token from TeamsTokens before every request, so
+        it is refreshed when it expires or Graph rejects it.
+
+        Arguments: The tokens of the logged in user (tokens: TeamsTokens)
+    */
+    pub fn refreshing(tokens: TeamsTokens) -> Self {
+        Self::with_credentials(Credentials::Refreshing(tokens))
+    }
+
+    fn with_credentials(credentials: Credentials) -> Self {
+        MSTeamsProvider {
+            credentials,
+            user_caches: Mutex::new(HashMap::new()),
+            user_id: Mutex::new(None),
+        }
+    }
+
+    async fn call<T, F, Fut>(&self, request: F) -> Result<T, Box<dyn Error>>
+    where
+        F: Fn(String) -> Fut,
+        Fut: Future<Output = Result<T, Box<dyn Error>>>,
+    {
+        match &self.credentials {
+            Credentials::Fixed(access_token) => request(access_token.clone()).await,
+            Credentials::Refreshing(tokens) => tokens.call(request).await,
+        }
+    }
+
+    async fn user_cache(&self, team_id: &str) -> Result<UserCache, Box<dyn Error>> {
+        if let Some(cache) = self.user_caches.lock().unwrap().get(team_id) {
+            return Ok(cache.clone());
+        }
+
+        let cache = self.call(|access_token| async move { ms_teams_api::get_users(&access_token, team_id).await }).await?;
+        self.user_caches
+            .lock()
+            .unwrap()
+            .insert(team_id.to_string(), cache.clone());
+        Ok(cache)
+    }
+
+    async fn user_id(&self) -> Result<String, Box<dyn Error>> {
+        if let Some(user_id) = self.user_id.lock().unwrap().clone() {
+            return Ok(user_id);
+        }
+
+        let user = self.call(|access_token| async move { ms_teams_api::get_user(&access_token).await }).await?;
+        let user_id = user["id"].as_str().unwrap_or_default().to_string();
+        *self.user_id.lock().unwrap() = Some(user_id.clone());
+        Ok(user_id)
+    }
+
+    // Converts parsed Graph messages, each a root with its replies
+    async fn threads(&self, messages: &[serde_json::Value], channel: &ChatChannel) -> Result<Vec<UnifiedMessage>, Box<dyn Error>> {
+        let user_id = self.user_id().await?;
+        let mut threads: Vec<UnifiedMessage> = messages
+            .iter()
+            .flat_map(|message| UnifiedMessage::from_teams_thread(message, &channel.id))
+            .collect();
+        for message in &mut threads {
+            message.mark_own_reactions(&user_id);
+        }
+        Ok(threads)
+    }
+
+    /*
+        Get what changed in a Channel using a delta query
+
+        Description: See ms_teams_api::get_messages_delta(), the Messages come
+        converted the same way fetch_history() does it.
+
+        Arguments: The Channel (channel: &ChatChannel), the delta link returned
+        by the previous call if any (delta_link: Option<&str>), and the time to
+        start from on the first call (since: DateTime<Utc>)
+
+        Returns: The changed and removed Messages and the next delta link (HistoryChanges)
+    */
+    pub async fn fetch_changes(&self, channel: &ChatChannel, delta_link: Option<&str>, since: DateTime<Utc>) -> Result<HistoryChanges, Box<dyn Error>> {
+        let user_cache = self.user_cache(&channel.workspace_id).await?;
+        let user_cache = &user_cache;
+        let delta = self
+            .call(|access_token| async move {
+                ms_teams_api::get_messages_delta(&access_token, &channel.workspace_id, &channel.id, delta_link, since, user_cache).await
+            })
+            .await?;
+
+        Ok(HistoryChanges {
+            changed: self.threads(&delta.changed, channel).await?,
+            removed: delta.removed,
+            delta_link: delta.delta_link,
+        })
+    }
+}
+
+/*
+    Apply HistoryChanges to a Channel's Messages
+
+    Description: A changed thread replaces the root and replies it had, which
+    also drops replies that are gone. Removed ids are dropped together with
+    their replies. Threads stay ordered by the time of their root.
+
+    Arguments: The Messages from fetch_history() (messages: &mut Vec<UnifiedMessage>)
+    and the changes from fetch_changes() (changes: &HistoryChanges)
+
+    Returns: If anything changed (bool)
+*/
+pub fn apply_changes(messages: &mut Vec<UnifiedMessage>, changes: &HistoryChanges) -> bool {
+    if changes.changed.is_empty() && changes.removed.is_empty() {
+        return false;
+    }
+
+    let gone: Vec<&str> = changes
+        .removed
+        .iter()
+        .map(|id| id.as_str())
+        .chain(changes.changed.iter().filter(|m| m.thread_id.is_none()).map(|m| m.id.as_str()))
+        .collect();
+    messages.retain(|message| {
+        !gone.contains(&message.id.as_str()) && !message.thread_id.as_deref().is_some_and(|root| gone.contains(&root))
+    });
+    messages.extend(changes.changed.iter().cloned());
+
+    // Replies sort by their root, after it
+    let root_times: HashMap<String, DateTime<Utc>> = messages
+        .iter()
+        .filter(|message| message.thread_id.is_none())
+        .map(|message| (message.id.clone(), message.timestamp))
+        .collect();
+    messages.sort_by_key(|message| {
+        let root_id = message.thread_id.as_ref().unwrap_or(&message.id);
+        let root_time = root_times.get(root_id).copied().unwrap_or(message.timestamp);
+        (root_time, root_id.clone(), message.thread_id.is_some(), message.timestamp)
+    });
+    true
+}
+
+#[async_trait(?Send)]
+impl ChatProvider for MSTeamsProvider {
+    fn platform(&self) -> Platform {
+        Platform::MSTeams
+    }
+
+    async fn list_workspaces(&self) -> Result<Vec<ChatWorkspace>, Box<dyn Error>> {
+        let teams = self.call(|access_token| async move { ms_teams_api::get_teams(&access_token).await }).await?;
+
+        Ok(teams
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|team| ChatWorkspace {
+                id: team["id"].as_str().unwrap_or_default().to_string(),
+                name: team["displayName"].as_str().unwrap_or_default().to_string(),
+                icon_url: team["teamPicture"].as_str().map(|picture| picture.to_string()),
+                platform: Platform::MSTeams,
+            })
+            .collect())
+    }
+
+    async fn list_channels(&self, workspace: &ChatWorkspace) -> Result<Vec<ChatChannel>, Box<dyn Error>> {
+        let channels = self.call(|access_token| async move { ms_teams_api::get_channels(&access_token, &workspace.id).await }).await?;
+
+        Ok(channels
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|channel| ChatChannel {
+                id: channel["id"].as_str().unwrap_or_default().to_string(),
+                workspace_id: workspace.id.clone(),
+                name: channel["displayName"].as_str().unwrap_or_default().to_string(),
+                platform: Platform::MSTeams,
+                kind: ChannelKind::Channel,
+                last_activity: None,
+            })
+            .collect())
+    }
+
+    async fn fetch_history(&self, channel: &ChatChannel, cursor: &HistoryCursor, limit: usize) -> Result<Vec<UnifiedMessage>, Box<dyn Error>> {
+        let user_cache = self.user_cache(&channel.workspace_id).await?;
+        let user_cache = &user_cache;
+        let messages = self
+            .call(|access_token| async move { ms_teams_api::get_messages(&access_token, &channel.workspace_id, &channel.id, user_cache).await })
+            .await?;
+
+        // Graph has no page size for channel messages with replies, so the whole channel is read
+        let history = self.threads(messages.as_array().map(|m| m.as_slice()).unwrap_or_default(), channel).await?;
+        Ok(page_of(history, cursor, limit))
+    }
+
+    async fn send_message(&self, channel: &ChatChannel, message: &OutgoingMessage) -> Result<(), Box<dyn Error>> {
+        if message.attachment.is_some() {
+            return Err("Sending files to Teams is not supported".into());
+        }
+
+        let content = message.content.as_str();
+        match &message.reply_to {
+            Some(replied) => {
+                // Replies always go to the root of the thread
+                let root_id = replied.thread_id.as_deref().unwrap_or(&replied.id);
+                self.call(|access_token| async move {
+                    ms_teams_api::send_message_reply(&access_token, &channel.workspace_id, &channel.id, root_id, content).await
+                })
+                .await
+            }
+            None => {
+                let subject = message.subject.as_deref().unwrap_or_default();
+                self.call(|access_token| async move {
+                    ms_teams_api::send_message(&access_token, &channel.workspace_id, &channel.id, content, subject).await
+                })
+                .await
+            }
+        }
+    }
+
+    async fn edit_message(&self, channel: &ChatChannel, message: &UnifiedMessage, content: &str) -> Result<(), Box<dyn Error>> {
+        self.call(|access_token| async move {
+            match &message.thread_id {
+                Some(root_id) => ms_teams_api::edit_message_reply(&access_token, &channel.workspace_id, &channel.id, root_id, &message.id, content).await,
+                None => ms_teams_api::edit_message(&access_token, &channel.workspace_id, &channel.id, &message.id, content).await,
+            }
+        })
+        .await
+    }
+
+    async fn delete_message(&self, channel: &ChatChannel, message: &UnifiedMessage) -> Result<(), Box<dyn Error>> {
+        self.call(|access_token| async move {
+            match &message.thread_id {
+                Some(root_id) => ms_teams_api::delete_message_reply(&access_token, &channel.workspace_id, &channel.id, root_id, &message.id).await,
+                None => ms_teams_api::delete_message(&access_token, &channel.workspace_id, &channel.id, &message.id).await,
+            }
+        })
+        .await
+    }
+
+    async fn add_reaction(&self, channel: &ChatChannel, message: &UnifiedMessage, emoji: &str) -> Result<(), Box<dyn Error>> {
+        self.call(|access_token| async move {
+            match &message.thread_id {
+                Some(root_id) => ms_teams_api::send_reaction_reply(&access_token, &channel.workspace_id, &channel.id, root_id, &message.id, emoji).await,
+                None => ms_teams_api::send_reaction(&access_token, &channel.workspace_id, &channel.id, &message.id, emoji).await,
+            }
+        })
+        .await
+    }
+
+    async fn remove_reaction(&self, channel: &ChatChannel, message: &UnifiedMessage, emoji: &str) -> Result<(), Box<dyn Error>> {
+        self.call(|access_token| async move {
+            match &message.thread_id {
+                Some(root_id) => ms_teams_api::remove_reaction_reply(&access_token, &channel.workspace_id, &channel.id, root_id, &message.id, emoji).await,
+                None => ms_teams_api::remove_reaction(&access_token, &channel.workspace_id, &channel.id, &message.id, emoji).await,
+            }
+        })
+        .await
+    }
+
+    async fn reaction_users(&self, channel: &ChatChannel, _message: &UnifiedMessage, reaction: &UnifiedReaction) -> Result<Vec<String>, Box<dyn Error>> {
+        let user_cache = self.user_cache(&channel.workspace_id).await?;
+        Ok(reaction
+            .users
+            .iter()
+            .map(|user| user_cache.get(user).map_or_else(|| "Unknown User".to_string(), |(name, _)| name.clone()))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn message(id: &str, time: &str, content: &str, thread_id: Option<&str>) -> UnifiedMessage {
+        let parsed = json!({ "id": id, "time": time, "content": content });
+        UnifiedMessage::from_teams(&parsed, "channel", thread_id).unwrap()
+    }
+
+    #[test]
+    fn apply_changes_replaces_threads_and_removes() {
+        let mut messages = vec![
+            message("1", "2024-01-01T00:00:00Z", "first", None),
+            message("1a", "2024-01-01T00:05:00Z", "old reply", Some("1")),
+            message("2", "2024-01-01T00:01:00Z", "second", None),
+            message("2a", "2024-01-01T00:02:00Z", "reply to second", Some("2")),
+        ];
+        let changes = HistoryChanges {
+            changed: vec![
+                message("3", "2024-01-01T00:03:00Z", "third", None),
+                message("1", "2024-01-01T00:00:00Z", "first, edited", None),
+                message("1b", "2024-01-01T00:06:00Z", "new reply", Some("1")),
+            ],
+            removed: vec!["2".to_string()],
+            delta_link: "next".to_string(),
+        };
+
+        assert!(apply_changes(&mut messages, &changes));
+        let bodies: Vec<&str> = messages.iter().map(|m| m.body.as_str()).collect();
+        assert_eq!(bodies, vec!["first, edited", "new reply", "third"]);
+
+        assert!(!apply_changes(&mut messages, &HistoryChanges::default()));
+    }
+}
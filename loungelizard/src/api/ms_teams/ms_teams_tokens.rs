@@ -0,0 +1,223 @@
+use chrono::Utc;
+use std::error::Error;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::api::mongo_format::mongo_funcs::update_user;
+use crate::api::mongo_format::mongo_structs::*;
+use crate::api::mongo_format::user_store::*;
+use crate::api::ms_teams::ms_teams_api::{is_unauthorized, needs_refresh, refresh_access_token};
+use crate::api::ms_teams::ms_teams_app_setup::MS_TEAMS_CLIENT_ID;
+
+// How often the background refresher looks at the expiration
+const CHECK_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_secs(60);
+
+// Only one refresh at a time, so two requests failing together don't both
+// spend the same refresh token
+lazy_static::lazy_static! {
+    static ref REFRESHING: Mutex<()> = Mutex::new(());
+}
+
+/*
+    Keeps the Teams tokens of the logged in user valid
+
+    Description: Hands out the access token of the active Teams account and
+    refreshes it a few minutes before it expires. The rotated access and
+    refresh tokens are written to the user and saved with update_user(), so
+    they survive a restart. call() also retries a Graph request once with a
+    new token when Graph answers 401, e.g. after the token was revoked.
+
+    The user lock is never held while talking to Microsoft, callers must not
+    hold it either while using this.
+*/
+#[derive(Clone)]
+pub struct TeamsTokens {
+    user_lock: Arc<Mutex<User>>,
+    user_store: Arc<dyn UserStore>,
+}
+
+impl TeamsTokens {
+    pub fn new(user_lock: Arc<Mutex<User>>, user_store: Arc<dyn UserStore>) -> Self {
+        TeamsTokens { user_lock, user_store }
+    }
+
+    /*
+        Access token for the next request
+
+        Description: Refreshes the token first if it expires within a few
+        minutes. If that fails the current token is returned anyway, Graph
+        will reject it if it really is expired and the caller can ask the
+        user to log in again.
+
+        Returns: The access token of the active Teams account (String)
+    */
+    pub async fn access_token(&self) -> String {
+        let (access_token, expiration) = {
+            let user = self.user_lock.lock().await;
+            let teams = user.ms_teams.active();
+            (teams.access_token.clone(), teams.expiration.clone())
+        };
+
+        if !needs_refresh(&expiration, Utc::now()) {
+            return access_token;
+        }
+
+        match self.refresh(&access_token).await {
+            Ok(refreshed) => refreshed,
+            Err(e) => {
+                warn!("Failed to refresh the Teams token: {}", e);
+                access_token
+            }
+        }
+    }
+
+    /*
+        Replace an expired or rejected access token
+
+        Description: Trades the refresh token of the account that owns the
+        given access token for a new pair and saves it. When another request
+        already refreshed that account the newer token is returned as is.
+
+        Arguments: The access token that expired or was rejected (stale_token: &str)
+
+        Returns: The new access token (String)
+    */
+    pub async fn refresh(&self, stale_token: &str) -> Result<String, Box<dyn Error>> {
+        let _refreshing = REFRESHING.lock().await;
+
+        let refresh_token = {
+            let user = self.user_lock.lock().await;
+            let teams = user.ms_teams.active();
+            if teams.access_token != stale_token && !needs_refresh(&teams.expiration, Utc::now()) {
+                return Ok(teams.access_token.clone());
+            }
+            teams.refresh_token.clone()
+        };
+
+        if refresh_token.is_empty() {
+            return Err("Teams session expired, please log in again".into());
+        }
+
+        let (access_token, new_refresh_token, expiration) = refresh_access_token(MS_TEAMS_CLIENT_ID, &refresh_token).await?;
+        info!("Refreshed the Teams access token");
+
+        let mut user = self.user_lock.lock().await;
+        let rotated = MSTeams {
+            access_token: access_token.clone(),
+            refresh_token: new_refresh_token,
+            expiration,
+            ..Default::default()
+        };
+        if !apply_refresh(&mut user, &refresh_token, &rotated) {
+            return Ok(access_token);
+        }
+
+        match update_user(&*self.user_store, &mut user, &[UserField::MSTeams]).await {
+            // Another window saved the user meanwhile and it was reloaded,
+            // keep the new tokens on top of that
+            Err(StoreError::Conflict) if apply_refresh(&mut user, &refresh_token, &rotated) => {
+                if let Err(e) = update_user(&*self.user_store, &mut user, &[UserField::MSTeams]).await {
+                    warn!("Failed to save the refreshed Teams token: {}", e);
+                }
+            }
+            Err(e) => warn!("Failed to save the refreshed Teams token: {}", e),
+            Ok(()) => {}
+        }
+
+        Ok(access_token)
+    }
+
+    /*
+        Run a Graph request with a valid token
+
+        Description: Passes a fresh access token to the request. If Graph
+        rejects it with 401 the token is refreshed and the request is sent
+        once more, any other error is returned right away.
+
+        Arguments: The request, given the access token to use
+        (request: Fn(String) -> Future)
+
+        Returns: What the request returned (T)
+    */
+    pub async fn call<T, F, Fut>(&self, request: F) -> Result<T, Box<dyn Error>>
+    where
+        F: Fn(String) -> Fut,
+        Fut: Future<Output = Result<T, Box<dyn Error>>>,
+    {
+        let access_token = self.access_token().await;
+
+        match request(access_token.clone()).await {
+            Err(e) if is_unauthorized(e.as_ref()) => {
+                info!("Teams token was rejected, refreshing it");
+                let access_token = self.refresh(&access_token).await?;
+                request(access_token).await
+            }
+            result => result,
+        }
+    }
+
+    /*
+        Refresh the token in the background
+
+        Description: Checks the expiration every minute for as long as the
+        future runs, so the token is renewed even while nothing is sent.
+        Meant to be spawned by the Teams page.
+    */
+    pub async fn keep_fresh(self) {
+        loop {
+            tokio::time::sleep(CHECK_INTERVAL).await;
+            self.access_token().await;
+        }
+    }
+}
+
+// Puts the new tokens on the account that was refreshed, which may no
+// longer be the active one. Returns false if that account is gone.
+fn apply_refresh(user: &mut User, used_refresh_token: &str, rotated: &MSTeams) -> bool {
+    match user.ms_teams.iter_mut().find(|teams| teams.refresh_token == used_refresh_token) {
+        Some(teams) => {
+            teams.access_token = rotated.access_token.clone();
+            teams.refresh_token = rotated.refresh_token.clone();
+            teams.expiration = rotated.expiration.clone();
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refreshed_tokens_go_to_the_refreshed_account() {
+        let mut user = User::default();
+        *user.ms_teams.active_mut() = MSTeams {
+            access_token: "first-access".to_string(),
+            refresh_token: "first-refresh".to_string(),
+            tenant_id: "first-tenant".to_string(),
+            ..Default::default()
+        };
+        user.ms_teams.add();
+        user.ms_teams.active_mut().refresh_token = "second-refresh".to_string();
+
+        let rotated = MSTeams {
+            access_token: "new-access".to_string(),
+            refresh_token: "new-refresh".to_string(),
+            expiration: "2024-01-01T13:00:00+00:00".to_string(),
+            ..Default::default()
+        };
+
+        assert!(apply_refresh(&mut user, "first-refresh", &rotated));
+        assert_eq!(user.ms_teams.active().refresh_token, "second-refresh");
+
+        user.ms_teams.switch_to(0);
+        assert_eq!(user.ms_teams.active().access_token, "new-access");
+        assert_eq!(user.ms_teams.active().refresh_token, "new-refresh");
+        assert_eq!(user.ms_teams.active().tenant_id, "first-tenant");
+
+        assert!(!apply_refresh(&mut user, "first-refresh", &rotated));
+    }
+}
@@ -30,21 +30,25 @@ pub async fn events_api(
     // We spawn an async task that will run our request consumer
     // The request consumer is a function that will continuously process any incoming requests
 
+    // Requests are signed with the app's signing secret, not the legacy verification token
+    let signing_secret = user_lock.lock().await.slack.active().signing_secret.clone();
+    main_events::set_signing_secret(signing_secret.clone()).await;
+
     // Clone the configuration for the push events listener
     let push_events_config = Arc::new(SlackPushEventsListenerConfig::new(
-        user_lock.lock().await.slack.verif_token.clone().into(),
+        signing_secret.clone().into(),
         
     ));
 
     // Clone the configuration for the interaction events listener
     let interactions_events_config = Arc::new(SlackInteractionEventsListenerConfig::new(
-        user_lock.lock().await.slack.verif_token.clone().into(),
+        signing_secret.clone().into(),
         
     ));
 
     // Clone the configuration for the command events listener
     let command_events_config = Arc::new(SlackCommandEventsListenerConfig::new(
-        user_lock.lock().await.slack.verif_token.clone().into(),
+        signing_secret.into(),
          
     ));
 
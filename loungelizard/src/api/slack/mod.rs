@@ -1,7 +1,8 @@
-#[allow(unused_variables, unused_imports)]
-pub mod ngrok_s;
 pub mod event_server; 
+pub mod socket_mode;
 pub mod config_env;
 pub mod server_utils;
 pub mod emoji;
+pub mod slack_provider;
+pub mod token_rotation;
 
@@ -16,17 +16,43 @@ use dioxus_logger::tracing::{debug, error, warn, info};
 use slack_morphism::prelude::*;
 use crate::api::mongo_format::mongo_structs::*;
 
-use mongodb::{sync::Client, bson::doc};
+use mongodb::bson::doc;
 use crate::api::mongo_format::mongo_funcs::*; 
+use crate::api::mongo_format::user_store::*;
 use reqwest::header::{CONTENT_TYPE, CONTENT_LENGTH, HOST};
 use reqwest::{Client as ReqwestClient};
 use std::collections::HashMap;
+use chrono::Utc;
+use crate::api::slack::server_utils::signature::*;
 
 // Import the necessary error handling types
 // Define the global queue as a Mutex wrapped around a vector of QueueItem
 lazy_static::lazy_static! {
     static ref GLOBAL_QUEUE: Arc<Mutex<Vec<Value>>> = Arc::new(Mutex::new(Vec::new()));
     static ref TEMP_CODE: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
+    static ref SIGNING_SECRET: Arc<Mutex<String>> = Arc::new(Mutex::new(String::new()));
+}
+
+/// Sets the signing secret incoming event requests are verified against.
+///
+/// Called by `events_api` before the server starts listening.
+pub async fn set_signing_secret(secret: String) {
+    *SIGNING_SECRET.lock().await = secret;
+}
+
+/// Queues an event for `request_consumer`.
+///
+/// Used by transports that don't go through `main_event_api`, like Socket Mode.
+pub async fn push_event(event: Value) {
+    GLOBAL_QUEUE.lock().await.push(event);
+}
+
+// Plain text response with the given status
+fn status_response(status: StatusCode, message: &str) -> Result<Response<BoxBody<Bytes, Infallible>>, Box<dyn std::error::Error + Send + Sync>> {
+    Response::builder()
+        .status(status)
+        .body(Full::new(Bytes::from(message.to_string())).boxed())
+        .map_err(|e| e.into())
 }
 
 /// Consume incoming requests from the global queue.
@@ -39,7 +65,7 @@ lazy_static::lazy_static! {
 
 pub async fn request_consumer(
     user_lock: Arc<Mutex<User>>,
-    client_lock: Arc<Mutex<Option<Client>>>,
+    user_store: Arc<dyn UserStore>,
     // TODO Intake an instance or modifiable of the UI
 )-> Result<serde_json::Value, Box<dyn std::error::Error + Send + Sync>> {
     // This is a loop that will run indefinitely
@@ -94,8 +120,8 @@ pub async fn request_consumer(
                 match client_r
                         .post("https://slack.com/api/oauth.v2.access")
                         .basic_auth(
-                            user.slack.client_id.as_str(),
-                            Some(user.slack.client_secret.as_str()))
+                            user.slack.active().client_id.as_str(),
+                            Some(user.slack.active().client_secret.as_str()))
                         .header(HOST, "slack.com")
                         .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
                         .form(&form_data)
@@ -116,12 +142,17 @@ pub async fn request_consumer(
                     // Attempt to parse the response body as JSON
                     match serde_json::from_str::<ModSlackOAuthV2Response>(&raw_body) {
                         Ok(oauth_response) => {
-                            user.slack.app_id       = oauth_response.app_id.to_string();
-                            user.slack.team.id      = oauth_response.team.id.to_string();
-                            user.slack.team.name    = oauth_response.team.name.unwrap().to_string();
-                            user.slack.user.token   = oauth_response.authed_user.access_token.unwrap().to_string();
-                            user.slack.user.scope   = oauth_response.authed_user.scope.unwrap().to_string();
-                            user.slack.user.id      = oauth_response.authed_user.id.to_string();
+                            user.slack.active_mut().app_id       = oauth_response.app_id.to_string();
+                            user.slack.active_mut().team.id      = oauth_response.team.id.to_string();
+                            user.slack.active_mut().team.name    = oauth_response.team.name.unwrap().to_string();
+                            user.slack.active_mut().user.token   = oauth_response.authed_user.access_token.unwrap().to_string();
+                            user.slack.active_mut().user.scope   = oauth_response.authed_user.scope.unwrap().to_string();
+                            user.slack.active_mut().user.id      = oauth_response.authed_user.id.to_string();
+                            user.slack.active_mut().user.refresh_token = oauth_response.authed_user.refresh_token.unwrap_or_default();
+                            user.slack.active_mut().user.expires_at = oauth_response.authed_user.expires_in
+                                .map_or(0, |expires_in| Utc::now().timestamp() as u64 + expires_in);
+                            // Reinstalling into a workspace that is already linked replaces it
+                            user.slack.merge_active(|a, b| a.team.id == b.team.id);
                         }
                         Err(e) => {
                             error!("Failed to parse JSON: {}", e);
@@ -132,7 +163,9 @@ pub async fn request_consumer(
                 }
             }
 
-            update_slack(user.clone(), client_lock.clone()).await;
+            if let Err(e) = update_user(&*user_store, &mut user, &[UserField::Slack]).await {
+                error!("Failed to save the Slack install: {}", e);
+            }
 
             
 
@@ -151,13 +184,46 @@ pub async fn main_event_api(
             // If it's a POST request
             &Method::POST => {
                 // We have received a POST request, which means we have received an event from Slack
+
+                // The signature headers are needed after the body is consumed
+                let header_value = |name: &str| {
+                    req.headers().get(name).and_then(|value| value.to_str().ok()).map(|value| value.to_string())
+                };
+                let timestamp = header_value("x-slack-request-timestamp");
+                let signature = header_value("x-slack-signature");
                 
                 // We need to collect the body of the request
                 let whole_body = req.collect().await?.to_bytes();
+                // The signature covers the raw body, a lossy conversion could let a tampered one through
+                let byte_string = match std::str::from_utf8(&whole_body) {
+                    Ok(byte_string) => byte_string,
+                    Err(err) => {
+                        warn!("Slack request body is not UTF-8: {}", err);
+                        return status_response(StatusCode::BAD_REQUEST, "Bad Request");
+                    }
+                };
+
+                // The endpoint is public through ngrok, only accept requests signed by Slack
+                let signing_secret = SIGNING_SECRET.lock().await.clone();
+                if let Err(err) = verify_request(
+                    &signing_secret,
+                    timestamp.as_deref(),
+                    signature.as_deref(),
+                    byte_string,
+                    Utc::now().timestamp(),
+                ) {
+                    warn!("Rejected Slack request: {}", err);
+                    return status_response(StatusCode::UNAUTHORIZED, "Unauthorized");
+                }
 
                 // We need to parse the body as a JSON string
-                let byte_string = String::from_utf8_lossy(&whole_body);
-                let json_value: Value = serde_json::from_str(&byte_string).unwrap();
+                let json_value: Value = match serde_json::from_str(byte_string) {
+                    Ok(json_value) => json_value,
+                    Err(err) => {
+                        warn!("Malformed Slack request body: {}", err);
+                        return status_response(StatusCode::BAD_REQUEST, "Bad Request");
+                    }
+                };
 
                 // We need to check if the event is a challenge or not
                 if let Value::Object(ref map) = json_value {
@@ -4,4 +4,5 @@ pub mod interaction_events;
 pub mod command_events;
 pub mod error_handler;
 pub mod request_server;
-pub mod setup_server;
\ No newline at end of file
+pub mod setup_server;
+pub mod signature;
\ No newline at end of file
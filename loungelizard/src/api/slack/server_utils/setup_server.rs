@@ -3,8 +3,9 @@ use serde::Deserialize;
 use slack_morphism::prelude::*;
 use std::fs;
 use url::Url;
-use crate::api::slack::ngrok_s::*;
-use dioxus_logger::tracing::{info, error, warn};
+use crate::api::tunnel::public_url;
+use crate::api::slack::token_rotation::rotate_config_token;
+use dioxus_logger::tracing::{error, warn};
 use crate::api::mongo_format::mongo_structs::*;
 use std::sync::Arc;
 use tokio::sync::Mutex;
@@ -12,8 +13,11 @@ use reqwest::header::{CONTENT_TYPE, CONTENT_LENGTH, HOST};
 use reqwest::{Client as ReqwestClient, Response};
 use std::collections::HashMap;
 
+// Address of the local endpoint started by events_api
+const LOCAL_REDIRECT_URL: &str = "http://localhost:8080";
 
-pub async fn start_endpoint() -> Result<SlackAppManifest, Box<dyn std::error::Error>>
+
+pub async fn start_endpoint(transport: SlackTransport) -> Result<SlackAppManifest, Box<dyn std::error::Error>>
 {
     // Define the path to the JSON file
     let file_path = "src/api/slack/manifest/manifest.json";
@@ -24,61 +28,20 @@ pub async fn start_endpoint() -> Result<SlackAppManifest, Box<dyn std::error::Er
     // Parse the manifest file into a `SlackAppManifest` struct
     let mut manifest_struct: SlackAppManifest = serde_json::from_str(&manifest_file).expect("Unable to parse JSON");
 
-    // Try to fetch ngrok tunnels
-    let response = match fetch_ngrok_tunnels().await {
-        Ok(response) => {
-            info!("Fetched ngrok tunnels successfully.");
-            response
-        },
-        Err(err) => {
-            info!("Failed to fetch ngrok tunnels: {}. Attempting to start ngrok session...", err);
-
-            // Attempt to start the ngrok session
-            match ngrok_start_session("8080") {
-                Ok(_child) => {
-                    info!("ngrok session started successfully.");
-
-                    // Wait for a brief period to allow ngrok to start
-                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+    // Socket Mode apps get their events over a websocket, no tunnel needed
+    if transport == SlackTransport::SocketMode {
+        return Ok(socket_mode_manifest(manifest_struct));
+    }
 
-                    // Retry fetching ngrok tunnels
-                    match fetch_ngrok_tunnels().await {
-                        Ok(response) => {
-                            info!("Successfully fetched ngrok tunnels after starting session.");
-                            response // Return this response
-                        },
-                        Err(err) => {
-                            // If we fail again, log and return the error
-                            info!("Failed to fetch ngrok tunnels after starting session: {}", err);
-                            return Err(Box::new(err)); // Return the error wrapped in Box
-                        }
-                    }
-                },
-                Err(start_err) => {
-                    info!("Failed to start ngrok session: {}", start_err);
-                    return Err(Box::new(start_err)); // Return the error wrapped in Box
-                }
-            }
+    // Public url of the local endpoint, see api::tunnel for the available backends
+    let redirect_url = match public_url().await {
+        Ok(url) => url,
+        Err(err) => {
+            error!("Failed to open tunnel: {}", err);
+            return Err(err.to_string().into());
         }
     };
 
-    let mut redirect_url = String::new();
-    // Extract the public URL from the first active tunnel
-    if let Some(tunnels) = response.get("tunnels").and_then(|t| t.as_array()) {
-        if let Some(tunnel) = tunnels.first() {
-            if let Some(public_url) = tunnel.get("public_url").and_then(|url| url.as_str()) {
-                // Set the environment variable `SLACK_REDIRECT_HOST` to the public URL
-                redirect_url = public_url.to_string();
-            } else {
-                eprintln!("Public URL not found in the tunnel data.");
-            }
-        } else {
-            eprintln!("No tunnels found.");
-        }
-    } else {
-        eprintln!("Tunnels field not found in the response.");
-    }
-
     // Update newly created URL into manifest befor creating the app
     if let Some(ref mut settings) = manifest_struct.settings {
         if let Some(ref mut event_subscriptions) = settings.event_subscriptions {
@@ -111,6 +74,25 @@ pub async fn start_endpoint() -> Result<SlackAppManifest, Box<dyn std::error::Er
    
 }
 
+/// Turns the manifest into one for a Socket Mode app.
+///
+/// Socket Mode apps must not have a request URL. The OAuth redirect happens in
+/// the user's own browser, so it can point straight at the local endpoint.
+pub fn socket_mode_manifest(mut manifest_struct: SlackAppManifest) -> SlackAppManifest {
+    if let Some(ref mut settings) = manifest_struct.settings {
+        settings.socket_mode_enabled = Some(true);
+        if let Some(ref mut event_subscriptions) = settings.event_subscriptions {
+            event_subscriptions.request_url = None;
+        }
+    }
+
+    if let Some(ref mut oauth_config) = manifest_struct.oauth_config {
+        oauth_config.redirect_urls = Some(vec![Url::parse(LOCAL_REDIRECT_URL).expect("Failed to parse URL")]);
+    }
+
+    manifest_struct
+}
+
 
 pub async fn update_slack_app(
     user: User 
@@ -119,59 +101,16 @@ pub async fn update_slack_app(
     // Create a new Slack client 
     let client  = SlackClient::new(SlackClientHyperConnector::new().expect("failed to create hyper connector"));
 
-    let user_temp = user.clone(); 
-
-    let user_refresh_token = user_temp.clone().slack.refresh_token; 
-    let mut form_data = HashMap::new();
-    form_data.insert("refresh_token", user_refresh_token.as_str());
-
-    let client_r = ReqwestClient::new();
-
-    let response = 
-        match client_r
-                .post("https://slack.com/api/tooling.tokens.rotate")
-                .header(HOST, "slack.com")
-                .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
-                .form(&form_data)
-                .send()
-                .await
-        {
-            Ok(response) => response,
-            Err(err) => {
-                warn!("Error Encountered {}", err);
-                return Err(Box::new(err) as Box<dyn std::error::Error>);
-            }
-        };
-
-    let mut response_data_exp: ResponseData = ResponseData::default();
-    let mut new_access_token = String::new(); 
-    // Check if response is successful
-    if response.status().is_success() {
-        // Try to print the raw response before parsing
-        let raw_body = response.text().await?;
-
-        // Attempt to parse the response body as JSON
-        match serde_json::from_str::<ResponseData>(&raw_body) {
-            Ok(response_data) => {
-                new_access_token = response_data.token;
-                // Attempt to parse the response body as JSON
-                response_data_exp = serde_json::from_str(&raw_body)?;
-            }
-            Err(e) => {
-                error!("Failed to parse JSON: {}", e);
-            }
-        }
-    } else {
-        println!("Failed to get a successful response. Status: {}", response.status());
-    }
+    // The refresh token is spent by this, the caller has to store the new one
+    let response_data_exp = rotate_config_token(&user.slack.active().refresh_token).await?;
 
-    let token: SlackApiToken = SlackApiToken::new(new_access_token.into());
+    let token: SlackApiToken = SlackApiToken::new(response_data_exp.token.clone().into());
 
     // Create a new session with the client and the token
     let session = client.open_session(&token);
 
     // Start server to generate a app manifest structure 
-    let manifest_struct = match start_endpoint().await
+    let manifest_struct = match start_endpoint(user.slack.active().transport).await
     {
         Ok(manifest) => manifest,
         Err(err) => return Err(err),
@@ -179,7 +118,7 @@ pub async fn update_slack_app(
 
     // Update existing app
     let updated_app = SlackApiAppsManifestUpdateRequest::new(
-        user.slack.app_id.clone().into(),
+        user.slack.active().app_id.clone().into(),
         manifest_struct.clone()
     );
 
@@ -278,7 +217,7 @@ pub async fn create_slack_app(
     let session = client.open_session(&token);
 
     // Start server to generate a app manifest structure 
-    let manifest_struct = match start_endpoint().await
+    let manifest_struct = match start_endpoint(user.slack.active().transport).await
     {
         Ok(manifest) => manifest,
         Err(err) => return Err(err),
@@ -295,12 +234,13 @@ pub async fn create_slack_app(
     {
         Ok(response) => {
             // Set Env vars without manually inputting them 
-            user.slack.client_id = response.credentials.client_id.to_string();
-            user.slack.client_secret = response.credentials.client_secret.to_string();
-            user.slack.verif_token = response.credentials.verification_token.to_string();
-            user.slack.oauth_url = response.oauth_authorize_url.to_string();
-            user.slack.config_token = new_access_token;
-            user.slack.refresh_token = refresh_token;
+            user.slack.active_mut().client_id = response.credentials.client_id.to_string();
+            user.slack.active_mut().client_secret = response.credentials.client_secret.to_string();
+            user.slack.active_mut().verif_token = response.credentials.verification_token.to_string();
+            user.slack.active_mut().signing_secret = response.credentials.signing_secret.to_string();
+            user.slack.active_mut().oauth_url = response.oauth_authorize_url.to_string();
+            user.slack.active_mut().config_token = new_access_token;
+            user.slack.active_mut().refresh_token = refresh_token;
             Ok(())
         }
         Err(err) => Err(Box::new(err) as Box<dyn std::error::Error>),
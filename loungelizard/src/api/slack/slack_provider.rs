@@ -0,0 +1,328 @@
+use async_trait::async_trait;
+use serde_json::json;
+use slack_morphism::prelude::*;
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Mutex;
+
+use crate::api::chat_provider::*;
+use crate::api::mongo_format::mongo_structs::{Slack, Team};
+use crate::api::slack::emoji::EMOJIS;
+use crate::api::unified_message::{UnifiedMessage, UnifiedReaction};
+
+/// `ChatProvider` backed by the Slack Web API using the user token.
+pub struct SlackProvider {
+    token: String,
+    team: Team,
+    // The user's own id, to tell which reactions are theirs
+    user_id: String,
+    // user_id -> display name, filled on first history fetch
+    user_names: Mutex<Option<HashMap<String, String>>>,
+}
+
+impl SlackProvider {
+    pub fn new(account: &Slack) -> Self {
+        SlackProvider {
+            token: account.user.token.clone(),
+            team: account.team.clone(),
+            user_id: account.user.id.clone(),
+            user_names: Mutex::new(None),
+        }
+    }
+
+    fn client(&self) -> Result<SlackClient<SlackClientHyperHttpsConnector>, Box<dyn Error>> {
+        Ok(SlackClient::new(SlackClientHyperConnector::new()?))
+    }
+
+    fn api_token(&self) -> SlackApiToken {
+        SlackApiToken::new(self.token.clone().into())
+    }
+
+    /// The user's own Slack id.
+    pub fn user_id(&self) -> &str {
+        &self.user_id
+    }
+
+    /// Returns the display name cache, fetching the member list the first time.
+    pub async fn user_names(&self) -> Result<HashMap<String, String>, Box<dyn Error>> {
+        if let Some(names) = self.user_names.lock().unwrap().as_ref() {
+            return Ok(names.clone());
+        }
+
+        let client = self.client()?;
+        let token = self.api_token();
+        let session = client.open_session(&token);
+
+        let mut names = HashMap::new();
+        let mut cursor: Option<SlackCursorId> = None;
+        loop {
+            let mut request = SlackApiUsersListRequest::new().with_team_id(self.team.id.clone().into());
+            if let Some(next) = cursor.take() {
+                request = request.with_cursor(next);
+            }
+            let response = session.users_list(&request).await?;
+
+            for member in response.members {
+                let name = member
+                    .profile
+                    .as_ref()
+                    .and_then(|profile| profile.display_name.clone())
+                    .filter(|name| !name.is_empty())
+                    .or(member.real_name.clone())
+                    .or(member.name.clone())
+                    .unwrap_or_else(|| member.id.to_string());
+                names.insert(member.id.to_string(), name);
+            }
+
+            cursor = response.response_metadata.and_then(|meta| meta.next_cursor);
+            if cursor.is_none() {
+                break;
+            }
+        }
+
+        *self.user_names.lock().unwrap() = Some(names.clone());
+        Ok(names)
+    }
+
+    /// Puts display names on a message the way `fetch_history` does, for
+    /// messages that arrive as events.
+    pub async fn name_author(&self, message: &mut UnifiedMessage) {
+        let user_names = self.user_names().await.unwrap_or_default();
+        if let Some(name) = user_names.get(&message.author.id) {
+            message.author.name = name.clone();
+        }
+        message.mark_own_reactions(&self.user_id);
+    }
+
+    /// Uploads a file into a channel, with the message text as its comment.
+    async fn upload(&self, channel: &ChatChannel, attachment: &OutgoingAttachment, comment: String, thread_ts: Option<SlackTs>) -> Result<(), Box<dyn Error>> {
+        let client = self.client()?;
+        let token = self.api_token();
+        let session = client.open_session(&token);
+
+        let upload_url_request = SlackApiFilesGetUploadUrlExternalRequest::new(attachment.filename.clone(), attachment.data.len());
+        let upload_url = session.get_upload_url_external(&upload_url_request).await?;
+
+        let upload_request = SlackApiFilesUploadViaUrlRequest::new(
+            upload_url.upload_url,
+            attachment.data.clone(),
+            "application/octet-stream".to_string(),
+        );
+        session.files_upload_via_url(&upload_request).await?;
+
+        let mut complete_request = SlackApiFilesCompleteUploadExternalRequest::new(vec![
+            SlackApiFilesComplete::new(upload_url.file_id).with_title(attachment.filename.clone()),
+        ])
+        .with_channel_id(channel.id.clone().into());
+        if !comment.is_empty() {
+            complete_request = complete_request.with_initial_comment(comment);
+        }
+        if let Some(thread_ts) = thread_ts {
+            complete_request = complete_request.with_thread_ts(thread_ts);
+        }
+        session.files_complete_upload_external(&complete_request).await?;
+        Ok(())
+    }
+}
+
+/// Where a conversation goes in the channel list.
+fn channel_kind(channel: &SlackChannelInfo) -> ChannelKind {
+    if channel.flags.is_im == Some(true) || channel.flags.is_mpim == Some(true) {
+        ChannelKind::Direct
+    } else {
+        ChannelKind::Channel
+    }
+}
+
+/// Slack reactions are addressed by name, accept either a name (with or
+/// without colons) or one of the unicode emojis from `EMOJIS`.
+fn reaction_name(emoji: &str) -> String {
+    EMOJIS
+        .entries()
+        .find(|(_, value)| **value == emoji)
+        .map(|(key, _)| key.to_string())
+        .unwrap_or_else(|| emoji.trim_matches(':').to_string())
+}
+
+#[async_trait(?Send)]
+impl ChatProvider for SlackProvider {
+    fn platform(&self) -> Platform {
+        Platform::Slack
+    }
+
+    async fn list_workspaces(&self) -> Result<Vec<ChatWorkspace>, Box<dyn Error>> {
+        // A Slack install is bound to a single team
+        Ok(vec![ChatWorkspace {
+            id: self.team.id.clone(),
+            name: self.team.name.clone(),
+            icon_url: None,
+            platform: Platform::Slack,
+        }])
+    }
+
+    async fn list_channels(&self, workspace: &ChatWorkspace) -> Result<Vec<ChatChannel>, Box<dyn Error>> {
+        let client = self.client()?;
+        let token = self.api_token();
+        let session = client.open_session(&token);
+
+        let mut channels = Vec::new();
+        let mut cursor: Option<SlackCursorId> = None;
+        loop {
+            let mut request = SlackApiConversationsListRequest::new()
+                .with_exclude_archived(true)
+                .with_types(vec![
+                    SlackConversationType::Public,
+                    SlackConversationType::Private,
+                    SlackConversationType::Mpim,
+                    SlackConversationType::Im,
+                ]);
+            if let Some(next) = cursor.take() {
+                request = request.with_cursor(next);
+            }
+            let response = session.conversations_list(&request).await?;
+
+            for channel in response.channels {
+                channels.push(ChatChannel {
+                    name: channel.name.clone().unwrap_or_else(|| channel.id.to_string()),
+                    id: channel.id.to_string(),
+                    workspace_id: workspace.id.clone(),
+                    platform: Platform::Slack,
+                    kind: channel_kind(&channel),
+                    // conversations.list doesn't say when a conversation was last written in
+                    last_activity: None,
+                });
+            }
+
+            cursor = response.response_metadata.and_then(|meta| meta.next_cursor);
+            if cursor.is_none() {
+                break;
+            }
+        }
+
+        Ok(channels)
+    }
+
+    async fn fetch_history(&self, channel: &ChatChannel, cursor: &HistoryCursor, limit: usize) -> Result<Vec<UnifiedMessage>, Box<dyn Error>> {
+        let user_names = self.user_names().await.unwrap_or_default();
+
+        let client = self.client()?;
+        let token = self.api_token();
+        let session = client.open_session(&token);
+
+        let limit: u16 = limit.try_into().unwrap_or(u16::MAX);
+        let request = SlackApiConversationsHistoryRequest::new().with_channel(channel.id.clone().into());
+        let request = match cursor {
+            HistoryCursor::Latest => request.with_limit(limit),
+            HistoryCursor::Before(ts) => request.with_latest(ts.clone().into()).with_limit(limit),
+            HistoryCursor::After(ts) => request.with_oldest(ts.clone().into()).with_limit(limit),
+            // Slack pages only go one way, so this is the message and the ones before it
+            HistoryCursor::Around(ts) => request.with_latest(ts.clone().into()).with_inclusive(true).with_limit(limit),
+        };
+        let response = session.conversations_history(&request).await?;
+
+        // Slack returns the newest message first
+        Ok(response
+            .messages
+            .iter()
+            .rev()
+            .filter_map(|message| {
+                let mut unified = UnifiedMessage::from_slack_history(message, &channel.id)?;
+                if let Some(name) = user_names.get(&unified.author.id) {
+                    unified.author.name = name.clone();
+                }
+                unified.mark_own_reactions(&self.user_id);
+                Some(unified)
+            })
+            .collect())
+    }
+
+    async fn send_message(&self, channel: &ChatChannel, message: &OutgoingMessage) -> Result<(), Box<dyn Error>> {
+        let content = message.content_with_subject(|subject| format!("*{}*", subject));
+        // Replies go into the thread of the message, or start one
+        let thread_ts: Option<SlackTs> = message
+            .reply_to
+            .as_ref()
+            .map(|replied| replied.thread_id.clone().unwrap_or_else(|| replied.id.clone()).into());
+
+        if let Some(attachment) = &message.attachment {
+            return self.upload(channel, attachment, content, thread_ts).await;
+        }
+
+        let client = self.client()?;
+        let token = self.api_token();
+        let session = client.open_session(&token);
+
+        let mut request = SlackApiChatPostMessageRequest::new(
+            channel.id.clone().into(),
+            SlackMessageContent::new().with_text(content),
+        );
+        if let Some(thread_ts) = thread_ts {
+            request = request.with_thread_ts(thread_ts);
+        }
+        session.chat_post_message(&request).await?;
+        Ok(())
+    }
+
+    async fn edit_message(&self, channel: &ChatChannel, message: &UnifiedMessage, content: &str) -> Result<(), Box<dyn Error>> {
+        let client = self.client()?;
+        let token = self.api_token();
+        let session = client.open_session(&token);
+
+        // Messages written in Slack show their rich text block instead of the text, so it's replaced too
+        let blocks = vec![SlackBlock::RichText(json!({
+            "elements": [{ "type": "rich_text_section", "elements": [{ "type": "text", "text": content }] }]
+        }))];
+        let request = SlackApiChatUpdateRequest::new(
+            channel.id.clone().into(),
+            SlackMessageContent::new().with_text(content.to_string()).with_blocks(blocks),
+            message.id.clone().into(),
+        );
+        session.chat_update(&request).await?;
+        Ok(())
+    }
+
+    async fn delete_message(&self, channel: &ChatChannel, message: &UnifiedMessage) -> Result<(), Box<dyn Error>> {
+        let client = self.client()?;
+        let token = self.api_token();
+        let session = client.open_session(&token);
+
+        let request = SlackApiChatDeleteRequest::new(channel.id.clone().into(), message.id.clone().into());
+        session.chat_delete(&request).await?;
+        Ok(())
+    }
+
+    async fn add_reaction(&self, channel: &ChatChannel, message: &UnifiedMessage, emoji: &str) -> Result<(), Box<dyn Error>> {
+        let client = self.client()?;
+        let token = self.api_token();
+        let session = client.open_session(&token);
+
+        let request = SlackApiReactionsAddRequest::new(
+            channel.id.clone().into(),
+            reaction_name(emoji).into(),
+            message.id.clone().into(),
+        );
+        session.reactions_add(&request).await?;
+        Ok(())
+    }
+
+    async fn remove_reaction(&self, channel: &ChatChannel, message: &UnifiedMessage, emoji: &str) -> Result<(), Box<dyn Error>> {
+        let client = self.client()?;
+        let token = self.api_token();
+        let session = client.open_session(&token);
+
+        let request = SlackApiReactionsRemoveRequest::new(reaction_name(emoji).into())
+            .with_channel(channel.id.clone().into())
+            .with_timestamp(message.id.clone().into());
+        session.reactions_remove(&request).await?;
+        Ok(())
+    }
+
+    async fn reaction_users(&self, _channel: &ChatChannel, _message: &UnifiedMessage, reaction: &UnifiedReaction) -> Result<Vec<String>, Box<dyn Error>> {
+        let user_names = self.user_names().await?;
+        Ok(reaction
+            .users
+            .iter()
+            .map(|user| user_names.get(user).cloned().unwrap_or_else(|| user.clone()))
+            .collect())
+    }
+}
@@ -0,0 +1,104 @@
+use std::sync::Arc;
+use slack_morphism::prelude::*;
+use dioxus_logger::tracing::{info, warn};
+
+// Imported internal files
+use crate::api::slack::server_utils::*;
+use crate::api::mongo_format::mongo_structs::*;
+
+/// Prefix of the app-level tokens Socket Mode connects with.
+pub const APP_TOKEN_PREFIX: &str = "xapp-";
+
+/// Open Socket Mode connections for one Slack account.
+///
+/// Events come in over websockets opened with the app-level token, so the
+/// local endpoint is only needed for the OAuth redirect and never has to be
+/// reachable from the internet. Dropping this without `shutdown` leaves the
+/// connections running.
+pub struct SocketModeListener {
+    listener: SlackClientSocketModeListener<SlackClientHyperHttpsConnector>,
+}
+
+impl SocketModeListener {
+    /// Connects with the account's app-level token and starts forwarding
+    /// push events to the same queue the events endpoint fills.
+    pub async fn start(slack: &Slack) -> Result<SocketModeListener, Box<dyn std::error::Error + Send + Sync>> {
+        if !slack.app_token.starts_with(APP_TOKEN_PREFIX) {
+            return Err("Socket Mode needs an app-level token (xapp-...)".into());
+        }
+
+        let client: Arc<SlackHyperClient> =
+            Arc::new(SlackClient::new(SlackClientHyperConnector::new()?));
+
+        let listener_environment = Arc::new(
+            SlackClientEventsListenerEnvironment::new(client)
+                .with_error_handler(error_handler::error_handler),
+        );
+
+        let callbacks = SlackSocketModeListenerCallbacks::new()
+            .with_push_events(socket_push_events_function);
+
+        let listener = SlackClientSocketModeListener::new(
+            &SlackClientSocketModeConfig::new(),
+            listener_environment,
+            callbacks,
+        );
+
+        let app_token = SlackApiToken::new(slack.app_token.clone().into());
+        listener.listen_for(&app_token).await?;
+        listener.start().await;
+
+        info!("Slack Socket Mode connected");
+        Ok(SocketModeListener { listener })
+    }
+
+    /// Closes every websocket opened by `start`.
+    pub async fn shutdown(&self) {
+        self.listener.shutdown().await;
+        info!("Slack Socket Mode disconnected");
+    }
+}
+
+// Socket Mode hands over the same envelope Slack would have posted to the endpoint
+async fn socket_push_events_function(
+    event: SlackPushEventCallback,
+    _client: Arc<SlackHyperClient>,
+    _states: SlackClientEventsUserState,
+) -> UserCallbackResult<()> {
+    match serde_json::to_value(&event) {
+        Ok(json_value) => main_events::push_event(json_value).await,
+        Err(err) => warn!("Failed to serialize Socket Mode event: {}", err),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn needs_an_app_level_token() {
+        let slack = Slack { app_token: "xoxb-bot-token".to_string(), ..Default::default() };
+        let err = SocketModeListener::start(&slack).await.err().expect("a bot token must be rejected");
+        assert!(err.to_string().contains("xapp-"));
+    }
+
+    #[test]
+    fn forwards_the_envelope_the_consumer_reads() {
+        // request_consumer looks at event.type, the same as for events posted to the endpoint
+        let envelope = json!({
+            "team_id": "T1",
+            "api_app_id": "A1",
+            "event": { "type": "message", "channel": "C1", "user": "U1", "text": "hi", "ts": "1700000000.000100" },
+            "event_id": "Ev1",
+            "event_time": 1700000000
+        });
+        let event: SlackPushEventCallback = serde_json::from_value(envelope).unwrap();
+        let forwarded = serde_json::to_value(&event).unwrap();
+
+        assert_eq!(forwarded["event"]["type"], "message");
+        assert_eq!(forwarded["event"]["channel"], "C1");
+        assert_eq!(forwarded["team_id"], "T1");
+    }
+}
@@ -0,0 +1,308 @@
+// Scheduled rotation of the Slack configuration and user tokens
+use chrono::Utc;
+use dioxus_logger::tracing::{info, warn};
+use reqwest::header::{CONTENT_TYPE, HOST};
+use reqwest::Client as ReqwestClient;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+use crate::api::mongo_format::mongo_funcs::update_user;
+use crate::api::mongo_format::mongo_structs::*;
+use crate::api::mongo_format::user_store::*;
+
+/// Tokens are rotated this long before they expire. Slack hands out tokens
+/// that last 12 hours.
+const ROTATE_MARGIN_SECONDS: u64 = 30 * 60;
+
+/// How often the refresher looks at the expirations.
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+// Refresh tokens are single use, two rotations of the same token at once
+// would make the second one fail
+lazy_static::lazy_static! {
+    static ref ROTATING: Mutex<()> = Mutex::new(());
+}
+
+/// Which of a workspace's tokens could not be rotated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SlackToken {
+    /// The app configuration token, renewed with a new refresh token from
+    /// https://api.slack.com/apps
+    Config,
+    /// The user token, renewed by installing the app again
+    User,
+}
+
+/// A token that is about to expire and could not be rotated.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RotationFailure {
+    pub app_id: String,
+    pub workspace: String,
+    // Where the app is installed again when the user token fails
+    pub oauth_url: String,
+    pub token: SlackToken,
+    pub error: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct UserTokenResponse {
+    access_token: String,
+    refresh_token: String,
+    expires_in: u64,
+}
+
+/// Trades a configuration refresh token for a new config token and the next
+/// refresh token, with `tooling.tokens.rotate`.
+pub async fn rotate_config_token(refresh_token: &str) -> Result<ResponseData, Box<dyn Error>> {
+    let mut form_data = HashMap::new();
+    form_data.insert("refresh_token", refresh_token);
+
+    let response = ReqwestClient::new()
+        .post("https://slack.com/api/tooling.tokens.rotate")
+        .header(HOST, "slack.com")
+        .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .form(&form_data)
+        .send()
+        .await?;
+
+    Ok(serde_json::from_value(slack_response(response).await?)?)
+}
+
+/// Trades a user refresh token for a new user token with `oauth.v2.access`.
+/// Only works for apps with token rotation turned on.
+async fn rotate_user_token(slack: &Slack) -> Result<UserTokenResponse, Box<dyn Error>> {
+    let mut form_data = HashMap::new();
+    form_data.insert("grant_type", "refresh_token");
+    form_data.insert("refresh_token", slack.user.refresh_token.as_str());
+
+    let response = ReqwestClient::new()
+        .post("https://slack.com/api/oauth.v2.access")
+        .basic_auth(slack.client_id.as_str(), Some(slack.client_secret.as_str()))
+        .header(HOST, "slack.com")
+        .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .form(&form_data)
+        .send()
+        .await?;
+
+    Ok(serde_json::from_value(slack_response(response).await?)?)
+}
+
+// Slack answers 200 with `ok: false` and an error code when a call fails
+async fn slack_response(response: reqwest::Response) -> Result<Value, Box<dyn Error>> {
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("Slack returned HTTP {}", status).into());
+    }
+
+    let body: Value = response.json().await?;
+    if body["ok"].as_bool() != Some(true) {
+        return Err(body["error"].as_str().unwrap_or("unknown error").to_string().into());
+    }
+    Ok(body)
+}
+
+fn is_due(expires_at: u64, now: u64) -> bool {
+    // Records saved before the expiration was kept are rotated once to learn it
+    expires_at == 0 || now + ROTATE_MARGIN_SECONDS >= expires_at
+}
+
+fn config_due(slack: &Slack, now: u64) -> bool {
+    !slack.refresh_token.is_empty() && is_due(slack.config_token_exp, now)
+}
+
+fn user_due(slack: &Slack, now: u64) -> bool {
+    !slack.user.refresh_token.is_empty() && is_due(slack.user.expires_at, now)
+}
+
+/// Rotates every token of the linked workspaces that expires soon and saves
+/// the new ones.
+///
+/// Returns the tokens that could not be rotated, so the user can be asked
+/// to authorize again before they stop working.
+pub async fn rotate_due_tokens(user_lock: &Arc<Mutex<User>>, user_store: &Arc<dyn UserStore>) -> Vec<RotationFailure> {
+    let _rotating = ROTATING.lock().await;
+    let now = Utc::now().timestamp() as u64;
+
+    // The user isn't locked while Slack is called
+    let workspaces: Vec<Slack> = user_lock
+        .lock()
+        .await
+        .slack
+        .iter()
+        .filter(|slack| slack.is_linked())
+        .cloned()
+        .collect();
+
+    let mut rotated = Vec::new();
+    let mut failures = Vec::new();
+    for workspace in workspaces {
+        let mut updated = workspace.clone();
+
+        if config_due(&workspace, now) {
+            match rotate_config_token(&workspace.refresh_token).await {
+                Ok(tokens) => {
+                    updated.config_token = tokens.token;
+                    updated.refresh_token = tokens.refresh_token;
+                    updated.config_token_exp = tokens.exp;
+                }
+                Err(e) => failures.push(failure(&workspace, SlackToken::Config, e)),
+            }
+        }
+
+        if user_due(&workspace, now) {
+            match rotate_user_token(&workspace).await {
+                Ok(tokens) => {
+                    updated.user.token = tokens.access_token;
+                    updated.user.refresh_token = tokens.refresh_token;
+                    updated.user.expires_at = now + tokens.expires_in;
+                }
+                Err(e) => failures.push(failure(&workspace, SlackToken::User, e)),
+            }
+        }
+
+        if updated != workspace {
+            info!("Rotated the Slack tokens of {}", workspace.team.name);
+            rotated.push((workspace, updated));
+        }
+    }
+
+    if !rotated.is_empty() {
+        save_rotated(user_lock, user_store, &rotated).await;
+    }
+    failures
+}
+
+/// Replaces the configuration refresh token of a workspace whose rotation
+/// failed, with one the user generated again on https://api.slack.com/apps.
+pub async fn replace_config_token(
+    user_lock: &Arc<Mutex<User>>,
+    user_store: &Arc<dyn UserStore>,
+    app_id: &str,
+    refresh_token: &str,
+) -> Result<(), Box<dyn Error>> {
+    let refresh_token = refresh_token.trim();
+    // Same check as create_slack_app, xoxe.xoxp is the config token itself
+    if !refresh_token.starts_with("xoxe") || refresh_token.starts_with("xoxe.xoxp") {
+        return Err("Copy a configuration refresh token first".into());
+    }
+
+    // Called from the UI thread, so don't wait behind the refresher
+    let _rotating = ROTATING.try_lock().map_err(|_| "Slack tokens are being rotated, try again in a moment")?;
+    let tokens = rotate_config_token(refresh_token).await?;
+
+    let mut user = user_lock.lock().await;
+    let workspace = user
+        .slack
+        .iter_mut()
+        .find(|slack| slack.app_id == app_id)
+        .ok_or("The workspace is no longer linked")?;
+    workspace.config_token = tokens.token;
+    workspace.refresh_token = tokens.refresh_token;
+    workspace.config_token_exp = tokens.exp;
+
+    update_user(&**user_store, &mut user, &[UserField::Slack]).await?;
+    Ok(())
+}
+
+fn failure(workspace: &Slack, token: SlackToken, error: Box<dyn Error>) -> RotationFailure {
+    RotationFailure {
+        app_id: workspace.app_id.clone(),
+        workspace: workspace.team.name.clone(),
+        oauth_url: workspace.oauth_url.clone(),
+        token,
+        error: error.to_string(),
+    }
+}
+
+// The new tokens go on top of whatever is in memory now, as long as the
+// workspace still holds the refresh tokens that were used. Returns whether
+// anything changed.
+fn apply_rotated(user: &mut User, rotated: &[(Slack, Slack)]) -> bool {
+    let mut changed = false;
+    for (used, updated) in rotated {
+        let current = user.slack.iter_mut().find(|slack| {
+            slack.app_id == used.app_id
+                && slack.refresh_token == used.refresh_token
+                && slack.user.refresh_token == used.user.refresh_token
+        });
+
+        if let Some(current) = current {
+            current.config_token = updated.config_token.clone();
+            current.refresh_token = updated.refresh_token.clone();
+            current.config_token_exp = updated.config_token_exp;
+            current.user.token = updated.user.token.clone();
+            current.user.refresh_token = updated.user.refresh_token.clone();
+            current.user.expires_at = updated.user.expires_at;
+            changed = true;
+        }
+    }
+    changed
+}
+
+async fn save_rotated(user_lock: &Arc<Mutex<User>>, user_store: &Arc<dyn UserStore>, rotated: &[(Slack, Slack)]) {
+    let mut user = user_lock.lock().await;
+    if !apply_rotated(&mut user, rotated) {
+        return;
+    }
+
+    let saved = match update_user(&**user_store, &mut user, &[UserField::Slack]).await {
+        // Saved from another window meanwhile, the user was reloaded
+        Err(StoreError::Conflict) if apply_rotated(&mut user, rotated) => {
+            update_user(&**user_store, &mut user, &[UserField::Slack]).await
+        }
+        saved => saved,
+    };
+
+    // The old refresh tokens are spent, the new ones only live in memory now
+    if let Err(e) = saved {
+        warn!("Failed to save the rotated Slack tokens: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workspace() -> Slack {
+        let mut slack = Slack::default();
+        slack.app_id = "A1".to_string();
+        slack.refresh_token = "xoxe-1".to_string();
+        slack.config_token_exp = 100_000;
+        slack
+    }
+
+    #[test]
+    fn rotates_tokens_close_to_expiry() {
+        let slack = workspace();
+
+        assert!(!config_due(&slack, 100_000 - ROTATE_MARGIN_SECONDS - 1));
+        assert!(config_due(&slack, 100_000 - ROTATE_MARGIN_SECONDS));
+        assert!(config_due(&Slack { config_token_exp: 0, ..workspace() }, 0));
+
+        // User tokens without rotation never expire
+        assert!(!user_due(&slack, 100_000));
+        assert!(!config_due(&Slack { refresh_token: String::new(), ..workspace() }, 100_000));
+    }
+
+    #[test]
+    fn keeps_rotated_tokens_of_the_same_workspace_only() {
+        let mut user = User::default();
+        *user.slack.active_mut() = workspace();
+
+        let mut updated = workspace();
+        updated.config_token = "xoxe.xoxp-2".to_string();
+        updated.refresh_token = "xoxe-2".to_string();
+        let rotated = vec![(workspace(), updated)];
+
+        assert!(apply_rotated(&mut user, &rotated));
+        assert_eq!(user.slack.active().refresh_token, "xoxe-2");
+
+        // Rotated again elsewhere in the meantime
+        assert!(!apply_rotated(&mut user, &rotated));
+    }
+}
@@ -0,0 +1,102 @@
+// Cloudflare quick tunnel utilities for the Lounge Lizard tunnel
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use dioxus_logger::tracing::{info, error};
+use tokio::sync::oneshot;
+use which::which;
+
+use super::Tunnel;
+
+// Quick tunnels can take a while to register
+const CLOUDFLARED_START_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Tunnel through a cloudflared quick tunnel
+///
+/// Runs `cloudflared tunnel --url http://localhost:{port}`, which prints a
+/// random `*.trycloudflare.com` url once it is connected. No account is
+/// needed. The process is killed when dropped.
+#[derive(Default)]
+pub struct Cloudflared {
+    process: Option<Child>,
+}
+
+#[async_trait]
+impl Tunnel for Cloudflared {
+    fn name(&self) -> &'static str {
+        "cloudflared"
+    }
+
+    async fn open(&mut self, port: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let cloudflared_path = which("cloudflared").map_err(|_| "cloudflared not found. Please ensure it is installed and available in PATH.")?;
+
+        info!("Starting cloudflared quick tunnel");
+        let mut child = Command::new(cloudflared_path)
+            .arg("tunnel")
+            .arg("--url")
+            .arg(format!("http://localhost:{}", port))
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped()) // The url is only printed to stderr
+            .spawn()?;
+
+        let stderr = child.stderr.take().ok_or("cloudflared output not available")?;
+        self.process = Some(child);
+
+        // Keep reading after the url was found so cloudflared never blocks on a full pipe
+        let (tx, rx) = oneshot::channel();
+        std::thread::spawn(move || {
+            let mut tx = Some(tx);
+            for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+                if let Some(url) = quick_tunnel_url(&line) {
+                    if let Some(tx) = tx.take() {
+                        let _ = tx.send(url);
+                    }
+                }
+            }
+        });
+
+        match tokio::time::timeout(CLOUDFLARED_START_TIMEOUT, rx).await {
+            Ok(Ok(url)) => Ok(url),
+            _ => {
+                error!("cloudflared did not report a tunnel url");
+                Err("Failed to start cloudflared tunnel".into())
+            }
+        }
+    }
+}
+
+impl Drop for Cloudflared {
+    fn drop(&mut self) {
+        if let Some(mut child) = self.process.take() {
+            info!("Killing cloudflared tunnel");
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+// cloudflared logs the url inside a box, e.g. "INF |  https://abc-def.trycloudflare.com  |"
+fn quick_tunnel_url(line: &str) -> Option<String> {
+    let start = line.find("https://")?;
+    let url: String = line[start..]
+        .chars()
+        .take_while(|c| !c.is_whitespace() && *c != '|')
+        .collect();
+    url.ends_with(".trycloudflare.com").then_some(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_quick_tunnel_url() {
+        assert_eq!(
+            quick_tunnel_url("2024-01-01T00:00:00Z INF |  https://lizard-lounge-test.trycloudflare.com                                 |"),
+            Some("https://lizard-lounge-test.trycloudflare.com".to_string())
+        );
+        assert_eq!(quick_tunnel_url("INF Thank you for trying Cloudflare Tunnel. See https://developers.cloudflare.com/"), None);
+    }
+}
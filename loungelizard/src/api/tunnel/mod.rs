@@ -0,0 +1,165 @@
+use async_trait::async_trait;
+use dioxus_logger::tracing::{info, warn};
+use std::error::Error;
+use std::sync::Mutex;
+
+pub mod ngrok;
+pub mod cloudflared;
+
+use ngrok::Ngrok;
+use cloudflared::Cloudflared;
+
+/// Port of the local server that OAuth redirects, Slack events and Graph
+/// notifications are sent to.
+pub const LOCAL_PORT: &str = "8080";
+
+/// Exposes the local server at a url Slack and Microsoft can reach.
+///
+/// Implementations that spawn a process own it and stop it when dropped.
+#[async_trait]
+pub trait Tunnel: Send {
+    /// Name used in logs.
+    fn name(&self) -> &'static str;
+
+    /// Starts the tunnel if needed and returns its public base url, without
+    /// a trailing slash.
+    async fn open(&mut self, port: &str) -> Result<String, Box<dyn Error + Send + Sync>>;
+}
+
+/// Which tunnel to use, picked with the `TUNNEL` env var (or `.env`).
+#[derive(Debug, Clone, PartialEq)]
+pub enum TunnelKind {
+    /// `TUNNEL=ngrok`, the default
+    Ngrok,
+    /// `TUNNEL=cloudflared`, a quick tunnel on trycloudflare.com
+    Cloudflared,
+    /// `TUNNEL=static` with `TUNNEL_URL`, for a domain already forwarded to the local port
+    StaticUrl(String),
+    /// `TUNNEL=localhost`, nothing is exposed. Works for the Teams login and
+    /// Slack Socket Mode, not for the Slack events endpoint.
+    Localhost,
+}
+
+impl TunnelKind {
+    /// Reads the tunnel configuration from the environment.
+    pub fn from_env() -> Result<TunnelKind, String> {
+        dotenv::dotenv().ok();
+        TunnelKind::parse(
+            std::env::var("TUNNEL").ok().as_deref(),
+            std::env::var("TUNNEL_URL").ok().as_deref(),
+        )
+    }
+
+    fn parse(backend: Option<&str>, url: Option<&str>) -> Result<TunnelKind, String> {
+        match backend.map(|backend| backend.trim().to_lowercase()).as_deref() {
+            None | Some("") | Some("ngrok") => Ok(TunnelKind::Ngrok),
+            Some("cloudflared") | Some("cloudflare") => Ok(TunnelKind::Cloudflared),
+            Some("localhost") | Some("none") => Ok(TunnelKind::Localhost),
+            Some("static") => match url.map(|url| url.trim().trim_end_matches('/')) {
+                Some(url) if url.starts_with("https://") || url.starts_with("http://") => {
+                    Ok(TunnelKind::StaticUrl(url.to_string()))
+                }
+                _ => Err("TUNNEL=static needs TUNNEL_URL set to an http(s) url".to_string()),
+            },
+            Some(other) => Err(format!("Unknown TUNNEL backend: {}", other)),
+        }
+    }
+
+    fn create(self) -> Box<dyn Tunnel> {
+        match self {
+            TunnelKind::Ngrok => Box::new(Ngrok::default()),
+            TunnelKind::Cloudflared => Box::new(Cloudflared::default()),
+            TunnelKind::StaticUrl(url) => Box::new(StaticUrl { url }),
+            TunnelKind::Localhost => Box::new(Localhost),
+        }
+    }
+}
+
+/// A public url the user already forwards to the local port.
+pub struct StaticUrl {
+    url: String,
+}
+
+#[async_trait]
+impl Tunnel for StaticUrl {
+    fn name(&self) -> &'static str {
+        "static url"
+    }
+
+    async fn open(&mut self, _port: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+        Ok(self.url.clone())
+    }
+}
+
+/// No tunnel, callbacks go straight to the local server.
+pub struct Localhost;
+
+#[async_trait]
+impl Tunnel for Localhost {
+    fn name(&self) -> &'static str {
+        "localhost"
+    }
+
+    async fn open(&mut self, port: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+        Ok(format!("http://localhost:{}", port))
+    }
+}
+
+// The tunnel shared by the Slack and Teams setup, with its public url
+lazy_static::lazy_static! {
+    static ref ACTIVE_TUNNEL: Mutex<Option<(Box<dyn Tunnel>, String)>> = Mutex::new(None);
+}
+
+/// Public base url of the local server.
+///
+/// Opens the configured tunnel on first use and reuses it afterwards.
+pub async fn public_url() -> Result<String, Box<dyn Error + Send + Sync>> {
+    let cached = ACTIVE_TUNNEL.lock().unwrap().as_ref().map(|(_, url)| url.clone());
+    if let Some(url) = cached {
+        return Ok(url);
+    }
+
+    let mut tunnel = TunnelKind::from_env()?.create();
+    let url = tunnel.open(LOCAL_PORT).await?;
+    info!("Using {} tunnel at {}", tunnel.name(), url);
+
+    let mut active = ACTIVE_TUNNEL.lock().unwrap();
+    match active.as_ref() {
+        // Someone else opened one in the meantime, dropping ours stops it
+        Some((_, existing)) => Ok(existing.clone()),
+        None => {
+            *active = Some((tunnel, url.clone()));
+            Ok(url)
+        }
+    }
+}
+
+/// Stops the active tunnel, if any. Called when the app exits.
+pub fn close_tunnel() {
+    match ACTIVE_TUNNEL.lock() {
+        Ok(mut active) => {
+            if let Some((tunnel, _)) = active.take() {
+                info!("Closing {} tunnel", tunnel.name());
+            }
+        }
+        Err(_) => warn!("Tunnel lock poisoned, leaving it running"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tunnel_kind() {
+        assert_eq!(TunnelKind::parse(None, None), Ok(TunnelKind::Ngrok));
+        assert_eq!(TunnelKind::parse(Some("Cloudflared"), None), Ok(TunnelKind::Cloudflared));
+        assert_eq!(TunnelKind::parse(Some("localhost"), None), Ok(TunnelKind::Localhost));
+        assert_eq!(
+            TunnelKind::parse(Some("static"), Some("https://lizard.example.com/")),
+            Ok(TunnelKind::StaticUrl("https://lizard.example.com".to_string()))
+        );
+        assert!(TunnelKind::parse(Some("static"), None).is_err());
+        assert!(TunnelKind::parse(Some("carrier-pigeon"), None).is_err());
+    }
+}
@@ -1,22 +1,88 @@
-//src/ngrok_s.rs
-// NGROK Utilities for the Slack Lounge Lizard workspace
+//src/api/tunnel/ngrok.rs
+// NGROK Utilities for the Lounge Lizard tunnel
 use std::process::{Command, Child, Stdio};
 use std::io;
+use std::time::Duration;
 
-use dioxus_logger::tracing::{info, error, warn, Level};
+use async_trait::async_trait;
+use dioxus_logger::tracing::{info, error, warn};
 use reqwest::Error;
 use serde_json::Value;
 
+use super::Tunnel;
+
 // ! Constants for URL Paths 
 /// *******************************************************
 const NGROK_TUNNEL_SEARCH: &str = "http://127.0.0.1:4040/api/tunnels";
 
+// How often to poll the ngrok API after starting a session, 500ms apart
+const NGROK_START_ATTEMPTS: usize = 10;
+
 /// *******************************************************
 /// 
-/// ? NGROK Utilities for the Slack Lounge Lizard workspace
+/// ? NGROK Utilities for the Lounge Lizard tunnel
 /// 
 /// *******************************************************
 
+/// Tunnel through ngrok
+///
+/// Reuses a session that is already running, otherwise starts one and kills
+/// it again when dropped.
+#[derive(Default)]
+pub struct Ngrok {
+    process: Option<Child>,
+}
+
+#[async_trait]
+impl Tunnel for Ngrok {
+    fn name(&self) -> &'static str {
+        "ngrok"
+    }
+
+    async fn open(&mut self, port: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        if let Some(url) = fetch_ngrok_tunnels().await.ok().as_ref().and_then(first_public_url) {
+            info!("Reusing running ngrok session");
+            return Ok(url);
+        }
+
+        self.process = Some(ngrok_start_session(port)?);
+
+        // ngrok needs a moment before its API answers
+        for _ in 0..NGROK_START_ATTEMPTS {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            if let Some(url) = fetch_ngrok_tunnels().await.ok().as_ref().and_then(first_public_url) {
+                return Ok(url);
+            }
+        }
+
+        Err("Failed to start or retrieve ngrok tunnel".into())
+    }
+}
+
+impl Drop for Ngrok {
+    fn drop(&mut self) {
+        if let Some(mut child) = self.process.take() {
+            let _ = ngrok_kill_session(&mut child);
+            // Reap the process so it doesn't linger as a zombie
+            let _ = child.wait();
+        }
+    }
+}
+
+/// Public URL of the first tunnel in a response of the ngrok API
+///
+/// # Arguments
+///
+/// * `tunnels`: The JSON returned by `fetch_ngrok_tunnels`
+pub fn first_public_url(tunnels: &Value) -> Option<String> {
+    tunnels.get("tunnels")?
+        .as_array()?
+        .first()?
+        .get("public_url")?
+        .as_str()
+        .map(|url| url.trim_end_matches('/').to_string())
+}
+
 /// Get the URL that ngrok is listening on
 ///
 /// # Arguments
@@ -159,6 +225,15 @@ mod tests {
         assert_eq!(url, "http://localhost:8080");
     }
 
+    #[test]
+    fn test_first_public_url() {
+        let tunnels = serde_json::json!({
+            "tunnels": [{ "public_url": "https://abcd.ngrok.app", "config": { "addr": "http://localhost:8080" } }]
+        });
+        assert_eq!(first_public_url(&tunnels), Some("https://abcd.ngrok.app".to_string()));
+        assert_eq!(first_public_url(&serde_json::json!({ "tunnels": [] })), None);
+    }
+
     #[tokio::test]
     async fn test_fetch_ngrok_tunnels() {
         let json_value = fetch_ngrok_tunnels().await.unwrap();
@@ -0,0 +1,569 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use slack_morphism::prelude::*;
+use tracing::warn;
+
+use crate::api::chat_provider::Platform;
+use crate::api::discord::discord_emoji::DiscordEmoji;
+use crate::api::slack::emoji::get_emoji;
+
+/// Who sent a message.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct UnifiedAuthor {
+    pub id: String,
+    pub name: String,
+    pub avatar_url: Option<String>,
+}
+
+/// A file attached to a message.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UnifiedAttachment {
+    pub url: String,
+    pub filename: String,
+    pub content_type: Option<String>,
+}
+
+/// One emoji on a message and how many people used it.
+///
+/// `emoji` is what gets displayed, `key` is what the platform expects back
+/// when reacting with it (Slack emoji name, Teams reaction type, ...).
+/// Custom emoji come with an `image_url` to show instead of `emoji`.
+/// `users` holds user ids, `me` is whether the user is one of them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UnifiedReaction {
+    pub emoji: String,
+    pub key: String,
+    #[serde(default)]
+    pub image_url: Option<String>,
+    pub count: u32,
+    pub me: bool,
+    pub users: Vec<String>,
+}
+
+/// Start of the message a reply quotes, when the platform sends it along.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UnifiedReplyPreview {
+    pub author: String,
+    pub body: String,
+}
+
+/// Platform independent message used for rendering, search and export.
+///
+/// `thread_id` is the root message of the thread this message is a reply in,
+/// `reply_to_id` the message it directly quotes (Discord replies).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UnifiedMessage {
+    pub id: String,
+    pub platform: Platform,
+    pub channel_id: String,
+    pub author: UnifiedAuthor,
+    pub timestamp: DateTime<Utc>,
+    // Title of a Teams post
+    #[serde(default)]
+    pub subject: Option<String>,
+    pub body: String,
+    pub attachments: Vec<UnifiedAttachment>,
+    pub reactions: Vec<UnifiedReaction>,
+    pub thread_id: Option<String>,
+    pub reply_to_id: Option<String>,
+    // Missing when the quoted message was deleted
+    #[serde(default)]
+    pub reply_preview: Option<UnifiedReplyPreview>,
+    pub reply_count: u32,
+    // A thread was started from this message, it can still be without replies
+    #[serde(default)]
+    pub has_thread: bool,
+    pub edited: bool,
+}
+
+fn str_field(value: &Value, key: &str) -> String {
+    value[key].as_str().unwrap_or_default().to_string()
+}
+
+fn discord_author_name(author: &Value) -> String {
+    author["global_name"]
+        .as_str()
+        .or(author["username"].as_str())
+        .unwrap_or("Unknown User")
+        .to_string()
+}
+
+// A message without a readable time would sort as if sent in 1970, so it is skipped instead
+fn parse_rfc3339(timestamp: &Value, message_id: &str) -> Option<DateTime<Utc>> {
+    let parsed = timestamp
+        .as_str()
+        .and_then(|timestamp| DateTime::parse_from_rfc3339(timestamp).ok())
+        .map(|t| t.with_timezone(&Utc));
+    if parsed.is_none() {
+        warn!("Skipping message {} with an unreadable timestamp: {}", message_id, timestamp);
+    }
+    parsed
+}
+
+impl UnifiedMessage {
+    /// Converts a message object from the Discord REST API or gateway.
+    /// Returns `None` when the message has no id or timestamp.
+    pub fn from_discord(message: &Value) -> Option<UnifiedMessage> {
+        let id = message["id"].as_str()?.to_string();
+        let timestamp = parse_rfc3339(&message["timestamp"], &id)?;
+        let author = &message["author"];
+        let author_id = str_field(author, "id");
+
+        let attachments = message["attachments"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|attachment| {
+                Some(UnifiedAttachment {
+                    url: attachment["url"].as_str()?.to_string(),
+                    filename: str_field(attachment, "filename"),
+                    content_type: attachment["content_type"].as_str().map(|c| c.to_string()),
+                })
+            })
+            .collect();
+
+        let reactions = message["reactions"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|reaction| {
+                let emoji = DiscordEmoji::from_json(&reaction["emoji"]);
+                UnifiedReaction {
+                    emoji: emoji.label(),
+                    key: emoji.key(),
+                    image_url: emoji.image_url(),
+                    count: reaction["count"].as_u64().unwrap_or(0) as u32,
+                    me: reaction["me"].as_bool().unwrap_or(false),
+                    users: Vec::new(),
+                }
+            })
+            .collect();
+
+        Some(UnifiedMessage {
+            id,
+            platform: Platform::Discord,
+            channel_id: str_field(message, "channel_id"),
+            author: UnifiedAuthor {
+                name: discord_author_name(author),
+                avatar_url: author["avatar"].as_str().map(|avatar| {
+                    format!("https://cdn.discordapp.com/avatars/{}/{}.webp", author_id, avatar)
+                }),
+                id: author_id,
+            },
+            timestamp,
+            subject: None,
+            body: str_field(message, "content"),
+            attachments,
+            reactions,
+            thread_id: None,
+            reply_to_id: message["message_reference"]["message_id"]
+                .as_str()
+                .map(|id| id.to_string()),
+            reply_preview: message["referenced_message"].as_object().map(|referenced| UnifiedReplyPreview {
+                author: discord_author_name(&referenced["author"]),
+                body: referenced["content"].as_str().unwrap_or_default().to_string(),
+            }),
+            reply_count: message["thread"]["message_count"].as_u64().unwrap_or(0) as u32,
+            has_thread: message["thread"].is_object(),
+            edited: message["edited_timestamp"].is_string(),
+        })
+    }
+
+    /// Counts a reaction someone added, `added` holds that one reaction.
+    /// The user's own reaction is counted once, even if it was already
+    /// there because the message was refetched after reacting.
+    pub fn reaction_added(&mut self, added: UnifiedReaction) {
+        match self.reactions.iter_mut().find(|r| r.key == added.key) {
+            Some(reaction) if added.me && reaction.me => {}
+            Some(reaction) => {
+                reaction.count += added.count;
+                reaction.me |= added.me;
+                reaction.users.extend(added.users);
+            }
+            None => self.reactions.push(added),
+        }
+    }
+
+    /// Takes back the reaction `key` of `user_id`, `me` when that is the user.
+    pub fn reaction_removed(&mut self, key: &str, user_id: &str, me: bool) {
+        match self.reactions.iter_mut().find(|r| r.key == key) {
+            // The user's own removal is already in a refetched message
+            Some(reaction) if me && !reaction.me => {}
+            Some(reaction) => {
+                reaction.count = reaction.count.saturating_sub(1);
+                reaction.me &= !me;
+                reaction.users.retain(|user| user != user_id);
+            }
+            None => {}
+        }
+        self.reactions.retain(|reaction| reaction.count > 0);
+    }
+
+    /// Sets `me` on the reactions `user_id` is among, for platforms that
+    /// only list who reacted.
+    pub fn mark_own_reactions(&mut self, user_id: &str) {
+        for reaction in &mut self.reactions {
+            reaction.me = reaction.users.iter().any(|user| user == user_id);
+        }
+    }
+
+    /// Converts a list of Discord messages, skipping malformed entries.
+    pub fn from_discord_list(messages: &Value) -> Vec<UnifiedMessage> {
+        messages
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(UnifiedMessage::from_discord)
+            .collect()
+    }
+
+    /// Converts a message produced by `ms_teams_api::parse_message` or `parse_reply`.
+    /// Returns `None` when the message has no id or time.
+    pub fn from_teams(message: &Value, channel_id: &str, thread_id: Option<&str>) -> Option<UnifiedMessage> {
+        let id = message["id"].as_str()?.to_string();
+        let timestamp = parse_rfc3339(&message["time"], &id)?;
+        let user = &message["user"];
+
+        // Teams lists one entry per user and reaction, fold them per emoji
+        let mut reactions: Vec<UnifiedReaction> = Vec::new();
+        for reaction in message["reactions"].as_array().into_iter().flatten() {
+            let key = str_field(reaction, "reactionType");
+            let user_id = str_field(&reaction["user"], "id");
+            match reactions.iter_mut().find(|r| r.key == key) {
+                Some(existing) => {
+                    existing.count += 1;
+                    existing.users.push(user_id);
+                }
+                None => reactions.push(UnifiedReaction {
+                    emoji: str_field(reaction, "emoji"),
+                    key,
+                    image_url: None,
+                    count: 1,
+                    me: false,
+                    users: vec![user_id],
+                }),
+            }
+        }
+
+        Some(UnifiedMessage {
+            id,
+            platform: Platform::MSTeams,
+            channel_id: channel_id.to_string(),
+            author: UnifiedAuthor {
+                id: str_field(user, "id"),
+                name: user["displayName"].as_str().unwrap_or("Unknown User").to_string(),
+                avatar_url: user["profilePicture"]
+                    .as_str()
+                    .filter(|picture| !picture.is_empty())
+                    .map(|picture| picture.to_string()),
+            },
+            timestamp,
+            subject: message["subject"]
+                .as_str()
+                .filter(|subject| !subject.is_empty())
+                .map(|subject| subject.to_string()),
+            body: str_field(message, "content"),
+            attachments: Vec::new(),
+            reactions,
+            thread_id: thread_id.map(|id| id.to_string()),
+            reply_to_id: None,
+            reply_preview: None,
+            reply_count: message["replies"].as_array().map_or(0, |r| r.len() as u32),
+            has_thread: message["replies"].as_array().is_some_and(|r| !r.is_empty()),
+            edited: message["edited"].as_bool().unwrap_or(false),
+        })
+    }
+
+    /// Converts a Teams root message and appends its replies after it.
+    pub fn from_teams_thread(message: &Value, channel_id: &str) -> Vec<UnifiedMessage> {
+        let Some(root) = UnifiedMessage::from_teams(message, channel_id, None) else {
+            return Vec::new();
+        };
+
+        let replies: Vec<UnifiedMessage> = message["replies"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|reply| UnifiedMessage::from_teams(reply, channel_id, Some(&root.id)))
+            .collect();
+
+        let mut thread = vec![root];
+        thread.extend(replies);
+        thread
+    }
+
+    /// Converts a message from `conversations.history`. The author name is the
+    /// Slack user id unless the message carries a username, callers with a
+    /// user list can replace it. Returns `None` when the `ts` isn't a time.
+    pub fn from_slack_history(message: &SlackHistoryMessage, channel_id: &str) -> Option<UnifiedMessage> {
+        let mut unified = slack_message(
+            &message.origin,
+            Some(&message.content),
+            &message.sender,
+            channel_id,
+        )?;
+        unified.reply_count = message.parent.reply_count.unwrap_or(0) as u32;
+        unified.has_thread = unified.reply_count > 0;
+        unified.edited = message.edited.is_some();
+        Some(unified)
+    }
+
+    /// Converts a `message` push event. Edits (`message_changed`) carry the
+    /// new content in the nested message. Returns `None` for deletions and
+    /// messages whose `ts` isn't a time.
+    pub fn from_slack_event(event: &SlackMessageEvent) -> Option<UnifiedMessage> {
+        if event.deleted_ts.is_some() {
+            return None;
+        }
+
+        let channel_id = event
+            .origin
+            .channel
+            .as_ref()
+            .map(|channel| channel.to_string())
+            .unwrap_or_default();
+
+        match &event.message {
+            Some(edited) => {
+                let mut origin = event.origin.clone();
+                origin.ts = edited.ts.clone();
+                let mut unified = slack_message(&origin, edited.content.as_ref(), &edited.sender, &channel_id)?;
+                unified.edited = true;
+                Some(unified)
+            }
+            None => slack_message(&event.origin, event.content.as_ref(), &event.sender, &channel_id),
+        }
+    }
+}
+
+/// What to show for a Slack reaction name, the name itself if it isn't a known emoji.
+pub fn slack_reaction_emoji(name: &str) -> String {
+    match get_emoji(name) {
+        "" => format!(":{}:", name),
+        emoji => emoji.to_string(),
+    }
+}
+
+fn slack_message(
+    origin: &SlackMessageOrigin,
+    content: Option<&SlackMessageContent>,
+    sender: &SlackMessageSender,
+    channel_id: &str,
+) -> Option<UnifiedMessage> {
+    let Some(timestamp) = origin.ts.to_date_time_opt() else {
+        warn!("Skipping Slack message with an unreadable ts: {}", origin.ts);
+        return None;
+    };
+
+    let author_id = sender
+        .user
+        .as_ref()
+        .map(|user| user.to_string())
+        .or(sender.bot_id.as_ref().map(|bot| bot.to_string()))
+        .unwrap_or_default();
+
+    let attachments = content
+        .and_then(|c| c.files.as_ref())
+        .into_iter()
+        .flatten()
+        .filter_map(|file| {
+            Some(UnifiedAttachment {
+                url: file.url_private.as_ref()?.to_string(),
+                filename: file.name.clone().or(file.title.clone()).unwrap_or_default(),
+                content_type: file.mimetype.as_ref().map(|mime| mime.to_string()),
+            })
+        })
+        .collect();
+
+    let reactions = content
+        .and_then(|c| c.reactions.as_ref())
+        .into_iter()
+        .flatten()
+        .map(|reaction| {
+            let name = reaction.name.to_string();
+            UnifiedReaction {
+                emoji: slack_reaction_emoji(&name),
+                key: name,
+                image_url: None,
+                count: reaction.count as u32,
+                me: false,
+                users: reaction.users.iter().map(|user| user.to_string()).collect(),
+            }
+        })
+        .collect();
+
+    Some(UnifiedMessage {
+        id: origin.ts.to_string(),
+        platform: Platform::Slack,
+        channel_id: channel_id.to_string(),
+        author: UnifiedAuthor {
+            name: sender.username.clone().unwrap_or_else(|| author_id.clone()),
+            id: author_id,
+            avatar_url: None,
+        },
+        timestamp,
+        subject: None,
+        body: content.and_then(|c| c.text.clone()).unwrap_or_default(),
+        attachments,
+        reactions,
+        thread_id: origin
+            .thread_ts
+            .as_ref()
+            .filter(|thread_ts| **thread_ts != origin.ts)
+            .map(|thread_ts| thread_ts.to_string()),
+        reply_to_id: None,
+        reply_preview: None,
+        reply_count: 0,
+        has_thread: false,
+        edited: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn discord_message_with_missing_fields_does_not_panic() {
+        let message = json!({ "id": "1", "content": "hi", "timestamp": "2024-09-26T12:45:00+00:00" });
+        let unified = UnifiedMessage::from_discord(&message).unwrap();
+        assert_eq!(unified.author.name, "Unknown User");
+        assert_eq!(unified.author.avatar_url, None);
+        assert!(!unified.edited);
+        assert!(!unified.has_thread);
+
+        assert!(UnifiedMessage::from_discord(&json!({ "content": "no id" })).is_none());
+
+        // A thread started a moment ago has no replies yet
+        let starter = json!({ "id": "2", "timestamp": "2024-09-26T12:45:00+00:00", "thread": { "id": "2", "message_count": 0 } });
+        let unified = UnifiedMessage::from_discord(&starter).unwrap();
+        assert!(unified.has_thread);
+        assert_eq!(unified.reply_count, 0);
+    }
+
+    #[test]
+    fn messages_without_a_readable_time_are_skipped() {
+        assert!(UnifiedMessage::from_discord(&json!({ "id": "1", "content": "hi" })).is_none());
+        assert!(UnifiedMessage::from_discord(&json!({ "id": "1", "timestamp": "yesterday" })).is_none());
+        assert!(UnifiedMessage::from_teams(&json!({ "id": "1", "time": "" }), "channel", None).is_none());
+
+        let list = json!([
+            { "id": "1", "timestamp": "2024-09-26T12:45:00+00:00" },
+            { "id": "2" }
+        ]);
+        assert_eq!(UnifiedMessage::from_discord_list(&list).len(), 1);
+
+        let message: SlackHistoryMessage = serde_json::from_value(json!({ "ts": "not-a-ts", "user": "U1", "text": "hey" })).unwrap();
+        assert!(UnifiedMessage::from_slack_history(&message, "C1").is_none());
+    }
+
+    #[test]
+    fn discord_message_fields() {
+        let message = json!({
+            "id": "10",
+            "channel_id": "20",
+            "content": "hello",
+            "timestamp": "2024-09-26T12:45:00.000000+00:00",
+            "edited_timestamp": "2024-09-26T12:46:00.000000+00:00",
+            "author": { "id": "30", "username": "lizard", "avatar": "abc" },
+            "attachments": [{ "url": "https://cdn/x.png", "filename": "x.png", "content_type": "image/png" }],
+            "reactions": [
+                { "count": 2, "me": true, "emoji": { "id": null, "name": "👍" } },
+                { "count": 1, "me": false, "emoji": { "id": "41", "name": "lizard" } }
+            ],
+            "message_reference": { "message_id": "9" },
+            "referenced_message": { "id": "9", "content": "hi", "author": { "id": "31", "username": "gecko" } }
+        });
+        let unified = UnifiedMessage::from_discord(&message).unwrap();
+        assert_eq!(unified.channel_id, "20");
+        assert_eq!(unified.author.name, "lizard");
+        assert_eq!(unified.author.avatar_url.as_deref(), Some("https://cdn.discordapp.com/avatars/30/abc.webp"));
+        assert_eq!(unified.timestamp.to_rfc3339(), "2024-09-26T12:45:00+00:00");
+        assert_eq!(unified.attachments[0].content_type.as_deref(), Some("image/png"));
+        assert_eq!(unified.reactions[0].count, 2);
+        assert!(unified.reactions[0].me);
+        assert_eq!(unified.reactions[1].key, "lizard:41");
+        assert_eq!(unified.reactions[1].image_url.as_deref(), Some("https://cdn.discordapp.com/emojis/41.png"));
+        assert_eq!(unified.reply_to_id.as_deref(), Some("9"));
+        assert_eq!(unified.reply_preview, Some(UnifiedReplyPreview { author: "gecko".to_string(), body: "hi".to_string() }));
+        assert!(unified.edited);
+    }
+
+    #[test]
+    fn teams_thread_folds_reactions_and_flattens_replies() {
+        let message = json!({
+            "id": "root",
+            "subject": "",
+            "content": "question",
+            "time": "2024-10-01T08:00:00Z",
+            "user": { "id": "u1", "displayName": "Ada", "profilePicture": "" },
+            "reactions": [
+                { "reactionType": "like", "emoji": "👍", "user": { "id": "u2", "displayName": "Bob" } },
+                { "reactionType": "like", "emoji": "👍", "user": { "id": "u3", "displayName": "Cy" } }
+            ],
+            "replies": [
+                { "id": "r1", "content": "answer", "time": "2024-10-01T08:05:00Z", "user": { "id": "u2", "displayName": "Bob" }, "reactions": [] }
+            ]
+        });
+        let thread = UnifiedMessage::from_teams_thread(&message, "channel");
+        assert_eq!(thread.len(), 2);
+        assert_eq!(thread[0].reply_count, 1);
+        assert_eq!(thread[0].reactions.len(), 1);
+        assert_eq!(thread[0].reactions[0].count, 2);
+        assert_eq!(thread[0].reactions[0].users, ["u2", "u3"]);
+        assert_eq!(thread[0].subject, None);
+        assert_eq!(thread[0].author.avatar_url, None);
+        assert_eq!(thread[1].thread_id.as_deref(), Some("root"));
+    }
+
+    #[test]
+    fn slack_history_message() {
+        let message: SlackHistoryMessage = serde_json::from_value(json!({
+            "ts": "1727350000.000100",
+            "thread_ts": "1727340000.000100",
+            "user": "U1",
+            "text": "hey",
+            "reactions": [{ "name": "+1", "count": 1, "users": ["U2"] }],
+            "edited": { "user": "U1", "ts": "1727350001.000000" }
+        }))
+        .unwrap();
+        let unified = UnifiedMessage::from_slack_history(&message, "C1").unwrap();
+        assert_eq!(unified.id, "1727350000.000100");
+        assert_eq!(unified.timestamp.timestamp(), 1727350000);
+        assert_eq!(unified.thread_id.as_deref(), Some("1727340000.000100"));
+        assert_eq!(unified.reactions[0].emoji, "\u{1f44d}");
+        assert_eq!(unified.reactions[0].key, "+1");
+        assert!(unified.edited);
+    }
+
+    #[test]
+    fn live_reactions_count_the_own_one_once() {
+        let mut message = UnifiedMessage::from_discord(&json!({ "id": "1", "content": "hi", "timestamp": "2024-09-26T12:45:00+00:00" })).unwrap();
+        let reaction = |user: &str, me: bool| UnifiedReaction {
+            emoji: "\u{1f44d}".to_string(),
+            key: "+1".to_string(),
+            image_url: None,
+            count: 1,
+            me,
+            users: vec![user.to_string()],
+        };
+
+        message.reaction_added(reaction("U1", true));
+        message.reaction_added(reaction("U2", false));
+        // Already counted when the message was refetched after reacting
+        message.reaction_added(reaction("U1", true));
+        assert_eq!(message.reactions[0].count, 2);
+        assert!(message.reactions[0].me);
+
+        message.reaction_removed("+1", "U1", true);
+        message.reaction_removed("+1", "U1", true);
+        assert_eq!(message.reactions[0].count, 1);
+        assert!(!message.reactions[0].me);
+        assert_eq!(message.reactions[0].users, ["U2"]);
+
+        message.reaction_removed("+1", "U2", false);
+        assert!(message.reactions.is_empty());
+    }
+}
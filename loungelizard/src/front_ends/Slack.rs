@@ -1,27 +1,25 @@
 
 use dioxus:: prelude::*;
+use dioxus_logger::tracing::error;
 // Api mongo structs
 use futures::executor::block_on;
 use std::sync::Arc;
+use std::rc::Rc;
 use tokio::sync::Mutex;
-use crate::api::{mongo_format::mongo_structs::*, slack::{self, emoji::*}};
+use crate::api::{mongo_format::mongo_structs::*, slack::emoji::*};
+use crate::api::chat_provider::*;
+use crate::api::slack::slack_provider::SlackProvider;
+use crate::api::unified_message::UnifiedMessage;
 use crate::comp::slack::*;
-use slack_morphism::prelude::*;
-use std::collections::HashMap;
 
 #[component]
 pub fn Slack_fe(
 
-    public_channels:    Signal<Vec<SlackChannelInfo>>,
-    private_channels:   Signal<Vec<SlackChannelInfo>>,
-    mpim_channels:      Signal<Vec<SlackChannelInfo>>,
-    im_channels:        Signal<Vec<SlackChannelInfo>>,
-    event_messages:     Signal<HashMap<String, SlackMessageEvent>>,
-    event_messages_vec: Signal<Vec<String>>,
-    history_list:       Signal<HashMap<String, SlackHistoryMessage>>,
-    history_list_vec:   Signal<Vec<String>>,
-    current_channel:    Signal<Option<SlackChannelInfo>>,
-    user_list:          Signal<HashMap<String, SlackUser>> 
+    channels:           Signal<Vec<ChatChannel>>,
+    // Oldest first, history and the messages that arrived since
+    messages:           Signal<Vec<UnifiedMessage>>,
+    current_channel:    Signal<Option<ChatChannel>>,
+    provider:           Signal<Rc<SlackProvider>>
 
 ) -> Element {
 
@@ -34,16 +32,6 @@ pub fn Slack_fe(
     // Whether the emoji picker is shown
     let mut emoji_picker = use_signal(|| false);
 
-    // Clear the messages and message IDs when the user changes the channel
-    use_effect( move || {
-        let _ = current_channel();
-        // Clear the old messages and message IDs
-        event_messages.write().clear();  
-        event_messages_vec.write().clear();
-    });
-
-    // Reverse chronological order
-
     // The text input box for the user to compose a message
     let mut send_message = use_signal(|| "".to_string());
     // The text input box for the user to compose a message to show in the message list
@@ -53,12 +41,8 @@ pub fn Slack_fe(
     let mut _file_ex_enable = use_signal(|| false); 
     // The name of the attachment
     let mut attachment_name = use_signal(|| "".to_string());
-    // The size of the attachment
-    let mut attachment_size: Signal<usize> = use_signal(|| 0);
     // The content of the attachment
     let mut attachment_content = use_signal(|| Vec::<u8>::new());
-    // The type of the attachment
-    let mut attachment_type = use_signal(|| "".to_string());
 
     // The ID of the user
     let id_lock = user_lock().clone();
@@ -66,7 +50,7 @@ pub fn Slack_fe(
         block_on(
             async{
                 let user = id_lock.lock().await;
-                user.slack.user.id.to_string()
+                user.slack.active().user.id.to_string()
             }
         ) 
     };
@@ -76,14 +60,7 @@ pub fn Slack_fe(
         {
             match current_channel.read().as_ref() {
                 Some(channel) => {
-                    match channel.name.clone() {
-                        Some(name) => {
-                            name
-                        },
-                        None => {
-                            "".to_string()
-                        }
-                    }
+                    channel.name.clone()
                 },
                 None => {
                     "".to_string()
@@ -101,85 +78,38 @@ pub fn Slack_fe(
 
         block_on(
             async move {
-                // Lock the user's Slack token
-                let user_lock_c = user_lock().clone();
-                let user = user_lock_c.lock().await;
-                // Create a new Slack client
-                let client  = 
-                SlackClient::new(SlackClientHyperConnector::new().expect("failed to create hyper connector"));
-
-                // Create a new token from the environment variable `SLACK_CONFIG_TOKEN`
-                let token: SlackApiToken = SlackApiToken::new(user.slack.user.token.clone().into());
-                let channel_id = current_channel(); 
-
-                // Create a new session with the client and the token
-                let session = client.open_session(&token);
-
                 match current_channel() {
                     Some(channel) => {
 
-                        // Upload any files that the user has selected
-                        let mut files = Vec::<SlackFile>::new(); 
-                        if !attachment_name().is_empty() && attachment_size() > 0{
-                            // Get the URL to upload the file to
-                            let upload_url_request = SlackApiFilesGetUploadUrlExternalRequest::new(
-                                attachment_name().to_string(),
-                                attachment_size()
-                            );
-                            let upload_url_response = session.get_upload_url_external(&upload_url_request).await.unwrap();
-                            println!("Upload URL Response: {:#?}", upload_url_response);
-
-                            // Upload the file to the URL
-                            let file_upload_request = SlackApiFilesUploadViaUrlRequest::new(
-                                upload_url_response.upload_url,
-                                attachment_content(),
-                                attachment_type()
-                            );
-
-                            let _ = session.files_upload_via_url(&file_upload_request).await.unwrap();
-
-                            // Complete the upload by sending the file ID and title to Slack
-                            let upload_complete_request = SlackApiFilesCompleteUploadExternalRequest::new(
-                                vec![SlackApiFilesComplete::new(upload_url_response.file_id).with_title(attachment_name())]
-                            ).with_channel_id(channel_id.unwrap().id);
-
-                            let upload_complete_response = session.files_complete_upload_external(&upload_complete_request).await.unwrap();
-                            println!("Upload URL Complete Response: {:#?}", upload_complete_response);
-                            
-                            // Add the uploaded file to the list of files
-                            for file in upload_complete_response.files{
-                                files.push(file); 
-                            }
-                            
+                        // Attach the file that the user has selected, if any
+                        let attachment = if !attachment_name().is_empty() && !attachment_content.read().is_empty() {
+                            Some(OutgoingAttachment {
+                                filename: attachment_name(),
+                                data: attachment_content(),
+                            })
+                        } else {
+                            None
+                        };
+                        if send_message().is_empty() && attachment.is_none() {
+                            return;
                         }
-                        if !send_message().is_empty()
-                        {
 
-                            // Post a message to the Slack channel with the
-                            // message content and the uploaded files
-                            let message_content = 
-                                SlackMessageContent::new()
-                                    .with_text(send_message().to_string());
-
-                            let post_chat_response = 
-                                SlackApiChatPostMessageRequest::new(
-                                    channel.id.clone(),
-                                    message_content
-                                );
-                            let _post_message_response=
-                                session.chat_post_message(&post_chat_response)
-                                .await
-                                .unwrap();
-
-                            // Clear the message field and the attachment fields
-                            send_message.set("".to_string());
-                            message_show.set("".to_string());
-                            attachment_content.write().clear();
-                            attachment_name.set("".to_string());
-                            attachment_size.set(0);
-                            attachment_type.set("".to_string());
+                        // Post the message content and the file to the Slack channel
+                        let message = OutgoingMessage {
+                            content: send_message(),
+                            attachment,
+                            ..Default::default()
+                        };
+                        match provider().send_message(&channel, &message).await {
+                            Ok(()) => {
+                                // Clear the message field and the attachment fields
+                                send_message.set("".to_string());
+                                message_show.set("".to_string());
+                                attachment_content.write().clear();
+                                attachment_name.set("".to_string());
+                            },
+                            Err(e) => error!("Failed to send message: {}", e),
                         }
-
                     },
                     None => {}
                 }
@@ -207,7 +137,7 @@ pub fn Slack_fe(
                 }
                 ul {
                     style: "list-style-type: none; padding: 0; margin: 0;",
-                    for channel in public_channels.iter() {
+                    for channel in channels().into_iter().filter(|channel| channel.kind == ChannelKind::Channel) {
                         CH_DM_Component{
                             channel_info: channel.clone(),
                             selected_channel: current_channel
@@ -224,7 +154,7 @@ pub fn Slack_fe(
                 ul {
                     style: "list-style-type: none; padding: 0; margin: 0;",
                     //Replace these list items with your actual channel names
-                    for channel in mpim_channels.iter() {
+                    for channel in channels().into_iter().filter(|channel| channel.kind == ChannelKind::Direct) {
                         CH_DM_Component{
                             channel_info: channel.clone(),
                             selected_channel: current_channel
@@ -258,32 +188,15 @@ pub fn Slack_fe(
                         padding: 10px; width: 100%;",
                         // Render each message inside the scrollable panel
                        
-                        for message_ts in history_list_vec().iter().rev(){
-                            if let Some(message_h) = history_list().get(message_ts){
-                                
+                        if let Some(channel) = current_channel() {
+                            for message in messages().iter() {
                                 CustomMessageComponent{
-                                    message: MessageComp{
-                                        mess_h: Some(message_h.clone()),
-                                        mess_e: None
-                                    },
+                                    key: "{message.id}",
+                                    message: message.clone(),
+                                    channel: channel.clone(),
                                     user_id: user_id.clone(),
                                     current_selected_id: selected_message_id.clone(),
-                                    incoming_user: user_list.clone()
-                                }
-                                
-                            }
-                            
-                        }
-                        for message_ts in event_messages_vec().iter(){
-                            if let Some(message_e) = event_messages().get(message_ts){
-                                CustomMessageComponent{
-                                    message: MessageComp{
-                                        mess_h: None,
-                                        mess_e: Some(message_e.clone())
-                                    },
-                                    user_id: user_id.clone(),
-                                    current_selected_id: selected_message_id.clone(),
-                                    incoming_user: user_list.clone()
+                                    provider: provider
                                 }
                             }
                         }
@@ -424,9 +337,6 @@ pub fn Slack_fe(
                                                                 // Write the file to the attachment_content
                                                                 attachment_content.write().extend(file);
 
-                                                                // Set the attachment_size to the length of the attachment_content
-                                                                attachment_size.set(attachment_content.read().len());
-
                                                                 // Use std::path::Path to extract the file name without the path
                                                                 let base_name = std::path::Path::new(file_name).file_name()
                                                                 .and_then(|name| name.to_str())
@@ -460,85 +370,3 @@ pub fn Slack_fe(
         }
      )
 }
-
-/// Get the message history of a given channel
-///
-/// # Parameters
-///
-/// * `token`: The Slack bot token
-/// * `channel`: The Slack channel info
-///
-/// # Return
-///
-/// A vector of `SlackHistoryMessage` containing the message history of the given channel
-pub async fn get_history_list(
-    token: String,
-    channel: SlackChannelInfo,
-) -> Vec<SlackHistoryMessage> {
-    // Create a new Slack client
-    let client = SlackClient::new(SlackClientHyperConnector::new().expect("failed to create hyper connector"));
-
-    // Create a new token from the environment variable `SLACK_CONFIG_TOKEN`
-    let token: SlackApiToken = SlackApiToken::new(token.into());
-
-    // Create a new session with the client and the token
-    let session = client.open_session(&token);
-
-    // Get the channel message history
-    let get_channel_message_history = SlackApiConversationsHistoryRequest::new()
-        .with_channel(channel.id.clone())
-        .with_limit(999)
-        .with_inclusive(true);
-
-    // Get the channel message history response
-    let get_channel_message_history_response: SlackApiConversationsHistoryResponse =
-        session
-            .conversations_history(&get_channel_message_history)
-            .await
-            .unwrap();
-
-    // Return the channel message history
-    get_channel_message_history_response.messages
-}
-
-/// Get the user list of a given team
-///
-/// # Parameters
-///
-/// * `token`: The Slack bot token
-/// * `team_id`: The Slack team ID
-///
-/// # Return
-///
-/// A vector of `SlackUser` containing the user list of the given team
-pub async fn get_user_list(
-    token: String,
-    team_id: String,
-
-) -> Vec<SlackUser>
-{
-    // Create a new Slack client
-    let client = SlackClient::new(
-        SlackClientHyperConnector::new().expect("failed to create hyper connector")
-    );
-
-    // Create a new token from the environment variable `SLACK_CONFIG_TOKEN`
-    let token: SlackApiToken = SlackApiToken::new(token.into());
-
-    // Create a new session with the client and the token
-    let session = client.open_session(&token);
-
-    // Get the user list request
-    let user_request = SlackApiUsersListRequest::new()
-        .with_team_id(team_id.into());
-
-    // Get the user list response
-    let user_response = session.users_list(&user_request).await;
-
-    // Return the user list or an empty vector if there is an error
-    match user_response {
-        Ok(response) => response.members,
-        Err(_) => Vec::new(),
-    }
-}
-
@@ -1,12 +1,12 @@
 use dioxus::prelude::*;
-use crate::{MONGO_COLLECTION, MONGO_DATABASE};
-use bson::to_bson;
 
 use crate::api::discord::discord_api;
+use crate::api::discord::discord_api::{LoginResponse, MfaChallenge, MfaMethod};
 use futures::executor::block_on;
-use serde_json::Value;
+use crate::api::chat_provider::ChatWorkspace;
 use crate::api::mongo_format::mongo_structs::*;
-use mongodb::{sync::Client, bson::doc};
+use crate::api::mongo_format::user_store::*;
+use crate::api::mongo_format::mongo_funcs::*;
 
 use dioxus_logger::tracing::{info, error, warn};
 
@@ -19,14 +19,14 @@ pub fn DiscordLogin(
     show_discord_login_pane: Signal<bool>,
     show_discord_server_pane: Signal<bool>,
     discord_token: Signal<String>,
-    discord_guilds: Signal<Value>, 
+    discord_guilds: Signal<Vec<ChatWorkspace>>, 
     current_platform: Signal<String>, 
    
 )
 -> Element {
     // ! User Mutex Lock to access the user data
     let user_lock = use_context::<Signal<Arc<Mutex<User>>>>();
-    let client_lock = use_context::<Signal<Arc<Mutex<Option<Client>>>>>();
+    let user_store = use_context::<Signal<Arc<dyn UserStore>>>();
     // ! ========================= ! //
 
     let mut username = use_signal(|| "".to_string());
@@ -36,212 +36,150 @@ pub fn DiscordLogin(
 
     let mut login_error = use_signal(|| None::<String>);
 
-    let handle_login = move |_| {
-        let username = username.clone();
-        let password = password.clone();
-
-        block_on(async move {
-            match discord_api::login_request(username.to_string(), password.to_string()).await {
-                Ok((_user_id, auth_discord_token)) => {
-                    discord_token.set(auth_discord_token); // Call the success handler
-                    show_discord_login_pane.set(false);
-                    show_discord_server_pane.set(true);
-                    info!("Login successful");
-                }
-                Err(e) => {
-                    login_error.set(Some(e.to_string()));
-                    info!("Login failed: {}", e);
-                }
-            }
-        });
+    // Set once the password was accepted and Discord asks for a two-factor code
+    let mut mfa_challenge = use_signal(|| None::<MfaChallenge>);
+    let mut mfa_method = use_signal(|| MfaMethod::Totp);
+    let mut mfa_code = use_signal(|| "".to_string());
+    let mut mfa_notice = use_signal(|| None::<String>);
 
-        if !login_error().is_none() {return;}
+    // Shows the bot token form instead of the user login
+    let mut bot_mode = use_signal(|| false);
+    let mut bot_token = use_signal(|| "".to_string());
 
-        let mongo_lock_copies = client_lock().clone();
-        let user_lock_copies = user_lock().clone();
+    // Opens the servers with the new token and saves it on the active Discord account
+    let mut finish_login = move |auth_discord_token: String, token_type: DiscordTokenType, user_id: String| {
+        let mut account = Discord {
+            token: auth_discord_token.clone(),
+            token_type,
+            user_id,
+        };
 
-        let mongo_client = block_on(
-            async {
-                mongo_lock_copies.lock().await
+        // The browser login only captures the token
+        if account.user_id.is_empty() {
+            match block_on(discord_api::get_current_user(account.authorization())) {
+                Ok(current_user) => account.user_id = current_user["id"].as_str().unwrap_or_default().to_string(),
+                Err(e) => warn!("Failed to get the Discord user id: {}", e),
             }
-        );
+        }
+
+        let user_lock_copies = user_lock().clone();
 
         let mut user = block_on(
             async {
                 user_lock_copies.lock().await
             }
         );
-         // Clone the client if it exists (since we can't return a reference directly)
-        if let Some(client) = mongo_client.as_ref() {
-            // Convert the function into async and spawn it on the current runtime
-            let client_clone = client.clone();  // Clone the client to avoid ownership issues
 
-            // TODO Add all tokens to user profile here
-            user.discord = Discord{
-                token: discord_token.to_string()
-            };
-            
-            // Todo ====================================//
+        let link_account = |user: &mut User| {
+            *user.discord.active_mut() = account.clone();
+            user.discord.merge_active(|a, b| !a.user_id.is_empty() && a.user_id == b.user_id);
+        };
+        link_account(&mut user);
 
-            let user_clone = user.clone();
-            
-            // Use `tokio::spawn` to run the async block
-            block_on(async move {
-                let db = client_clone.database(MONGO_DATABASE);
-                let user_collection = db.collection::<User>(MONGO_COLLECTION);
-                
-                match to_bson(&user_clone.discord) {
-                    Ok(discord_bson) => {
-                        match user_collection
-                            .find_one_and_update(
-                                doc! { 
-                                    "$or": [{"username": &user_clone.username}, 
-                                            {"email": &user_clone.email}] 
-                                },
-                                doc! {
-                                    "$set": { "discord": discord_bson }
-                                }
-                            )
-                            .await 
-                        {
-                            Ok(Some(_)) => {
-                                // Document found and updated
-                                info!("Document updated successfully");
-                                logged_in.set(true);
-                                current_platform.set("Discord".to_string());
-                            }
-                            Ok(None) => {
-                                // No document matched the filter
-                                warn!("Document not found");
-                            }
-                            Err(e) => {
-                                error!("Something went wrong: {:#?}", e);
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        error!("Failed to convert Slack to BSON: {:#?}", e);
-                    }
-                }
-                
-            });
+        // update_user encrypts the tokens before they are stored
+        let mut saved = block_on(update_user(&*user_store(), &mut user, &[UserField::Discord]));
+        if let Err(StoreError::Conflict) = saved {
+            // Another window saved the user meanwhile and it was reloaded. Add the account to
+            // that copy without replacing whichever account is active in it.
+            user.discord.add();
+            link_account(&mut user);
+            saved = block_on(update_user(&*user_store(), &mut user, &[UserField::Discord]));
+        }
 
-        } else {
-            warn!("MongoDB client not found in global state.");
+        match saved {
+            Ok(()) => {
+                info!("Login successful");
+                discord_token.set(auth_discord_token);
+                show_discord_login_pane.set(false);
+                show_discord_server_pane.set(true);
+                logged_in.set(true);
+                current_platform.set("Discord".to_string());
+            }
+            Err(e) => login_error.set(Some(e.to_string())),
         }
     };
 
-    let handle_browser_login = move |_| {
-        block_on(async move {
-            // Launch Chrome and capture the WebSocket debugger URL
-            let existing_profile_path = "resources/chrome_empty_profile";
-            let chrome_path = "C:/Program Files/Google/Chrome/Application/chrome.exe";
-            let start_url = "https://discord.com/login";
+    let handle_login = move |_| {
+        login_error.set(None);
 
-            let captured_token = match discord_api::launch_chrome_and_monitor_auth().await {
-                Ok(Some(token)) => {
-                    println!("Captured Token: {}", token);
-                    Some(token)
-                }
-                Ok(None) => {
-                    warn!("No token found during monitoring");
-                    None
-                }
-                Err(e) => {
-                    error!("Failed to launch Chrome and monitor WebSocket for token: {}", e);
-                    None
-                }
-            };
-            
+        match block_on(discord_api::login_request(username.to_string(), password.to_string())) {
+            Ok(LoginResponse::Token(user_id, auth_discord_token)) => finish_login(auth_discord_token, DiscordTokenType::User, user_id),
+            Ok(LoginResponse::Mfa(challenge)) => {
+                info!("Discord asked for a two-factor code");
+                mfa_method.set(challenge.methods[0]);
+                mfa_code.set("".to_string());
+                mfa_notice.set(None);
+                mfa_challenge.set(Some(challenge));
+            }
+            Err(e) => {
+                login_error.set(Some(e.to_string()));
+                info!("Login failed: {}", e);
+            }
+        }
+    };
 
-            let auth_discord_token = if let Some(token) = captured_token {
-                token
-            } else {
-                login_error.set(Some("login failed".to_string()));
-                return;
-            };
+    let handle_mfa_login = move |_| {
+        let Some(challenge) = mfa_challenge() else { return; };
+        login_error.set(None);
+
+        match block_on(discord_api::submit_mfa_code(&challenge, mfa_method(), &mfa_code())) {
+            Ok(auth_discord_token) => {
+                mfa_challenge.set(None);
+                mfa_code.set("".to_string());
+                finish_login(auth_discord_token, DiscordTokenType::User, challenge.user_id.clone());
+            }
+            // The ticket expires after a few minutes, "Back" starts over with a new one
+            Err(e) => {
+                login_error.set(Some(e.to_string()));
+                info!("Two-factor login failed: {}", e);
+            }
+        }
+    };
 
-            discord_token.set(auth_discord_token.clone());
-            show_discord_login_pane.set(false);
-            show_discord_server_pane.set(true);
-            info!("Login successful");
-        });
+    let handle_send_sms = move |_| {
+        let Some(challenge) = mfa_challenge() else { return; };
+        login_error.set(None);
 
-        if !login_error().is_none() {
-            return;
+        match block_on(discord_api::send_mfa_sms(&challenge)) {
+            Ok(phone) => mfa_notice.set(Some(format!("Code sent to {}", phone))),
+            Err(e) => login_error.set(Some(e.to_string())),
         }
+    };
 
-        let mongo_lock_copies = client_lock().clone();
-        let user_lock_copies = user_lock().clone();
+    let handle_bot_login = move |_| {
+        login_error.set(None);
+        // Copied from the developer portal with or without the prefix
+        let token = bot_token().trim().trim_start_matches("Bot ").to_string();
+        let account = Discord { token: token.clone(), token_type: DiscordTokenType::Bot, ..Default::default() };
 
-        let mongo_client = block_on(
-            async {
-                mongo_lock_copies.lock().await
+        match block_on(discord_api::get_current_user(account.authorization())) {
+            Ok(bot_user) if bot_user["bot"].as_bool() == Some(true) => {
+                info!("Logged in as bot {}", bot_user["username"].as_str().unwrap_or_default());
+                bot_token.set("".to_string());
+                finish_login(token, DiscordTokenType::Bot, bot_user["id"].as_str().unwrap_or_default().to_string());
             }
-        );
-
-        let mut user = block_on(
-            async {
-                user_lock_copies.lock().await
+            Ok(_) => login_error.set(Some("This is not a bot token".to_string())),
+            Err(e) => {
+                login_error.set(Some(e.to_string()));
+                info!("Bot login failed: {}", e);
             }
-        );
-         // Clone the client if it exists (since we can't return a reference directly)
-        if let Some(client) = mongo_client.as_ref() {
-            // Convert the function into async and spawn it on the current runtime
-            let client_clone = client.clone();  // Clone the client to avoid ownership issues
-
-            // TODO Add all tokens to user profile here
-            user.discord = Discord{
-                token: discord_token.to_string()
-            };
-            
-            // Todo ====================================//
+        }
+    };
 
-            let user_clone = user.clone();
-            
-            // Use `tokio::spawn` to run the async block
-            block_on(async move {
-                let db = client_clone.database(MONGO_DATABASE);
-                let user_collection = db.collection::<User>(MONGO_COLLECTION);
-                
-                match to_bson(&user_clone.discord) {
-                    Ok(discord_bson) => {
-                        match user_collection
-                            .find_one_and_update(
-                                doc! { 
-                                    "$or": [{"username": &user_clone.username}, 
-                                            {"email": &user_clone.email}] 
-                                },
-                                doc! {
-                                    "$set": { "discord": discord_bson }
-                                }
-                            )
-                            .await 
-                        {
-                            Ok(Some(_)) => {
-                                // Document found and updated
-                                info!("Document updated successfully");
-                                logged_in.set(true);
-                                current_platform.set("Discord".to_string());
-                            }
-                            Ok(None) => {
-                                // No document matched the filter
-                                warn!("Document not found");
-                            }
-                            Err(e) => {
-                                error!("Something went wrong: {:#?}", e);
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        error!("Failed to convert Slack to BSON: {:#?}", e);
-                    }
-                }
-                
-            });
+    let handle_browser_login = move |_| {
+        login_error.set(None);
 
-        } else {
-            warn!("MongoDB client not found in global state.");
+        // Open Discord in the browser and capture the token it logs in with
+        match block_on(discord_api::launch_chrome_and_monitor_auth()) {
+            Ok(Some(auth_discord_token)) => finish_login(auth_discord_token, DiscordTokenType::User, String::new()),
+            Ok(None) => {
+                warn!("Browser closed before logging in to Discord");
+                login_error.set(Some("The browser was closed before logging in".to_string()));
+            }
+            Err(e) => {
+                error!("Browser login failed: {}", e);
+                login_error.set(Some(e.to_string()));
+            }
         }
     };
 
@@ -270,26 +208,110 @@ pub fn DiscordLogin(
                     }
                 }
             }
-            input {
-                class: "login-input",
-                value: "{username}",
-                placeholder: "Username/Email",
-                oninput: move |event| username.set(event.value())
-            }
-            input {
-                class: "login-input",
-                r#type: "password",
-                value: "{password}",
-                placeholder: "Password",
-                oninput: move |event| password.set(event.value())
-            }
-            button { 
-                class: "login-button",
-                onclick: handle_login, "Login" 
-            }
-            button { 
-                class: "login-button",
-                onclick: handle_browser_login, "Browser Login (Requires Chrome)" 
+            if let Some(challenge) = mfa_challenge() {
+                p {
+                    style: "color: white; font-family: Arial, sans-serif; text-align: center;",
+                    "This account uses two-factor authentication"
+                }
+                if challenge.methods.len() > 1 {
+                    select {
+                        class: "login-input",
+                        onchange: move |event| {
+                            if let Some(method) = MfaMethod::from_key(&event.value()) {
+                                mfa_method.set(method);
+                                mfa_code.set("".to_string());
+                                mfa_notice.set(None);
+                            }
+                        },
+                        for method in challenge.methods.iter().copied() {
+                            option {
+                                value: method.key(),
+                                selected: method == mfa_method(),
+                                "{method.label()}"
+                            }
+                        }
+                    }
+                }
+                if mfa_method() == MfaMethod::Sms {
+                    button {
+                        class: "login-button",
+                        onclick: handle_send_sms, "Send SMS Code"
+                    }
+                }
+                if let Some(notice) = mfa_notice() {
+                    p {
+                        style: "color: white; font-family: Arial, sans-serif; text-align: center;",
+                        "{notice}"
+                    }
+                }
+                input {
+                    class: "login-input",
+                    value: "{mfa_code}",
+                    placeholder: if mfa_method() == MfaMethod::Backup { "Backup code" } else { "6-digit code" },
+                    oninput: move |event| mfa_code.set(event.value())
+                }
+                button {
+                    class: "login-button",
+                    onclick: handle_mfa_login, "Verify"
+                }
+                button {
+                    class: "login-button",
+                    onclick: move |_| {
+                        mfa_challenge.set(None);
+                        login_error.set(None);
+                    },
+                    "Back"
+                }
+            } else if bot_mode() {
+                input {
+                    class: "login-input",
+                    r#type: "password",
+                    value: "{bot_token}",
+                    placeholder: "Bot Token",
+                    oninput: move |event| bot_token.set(event.value())
+                }
+                button {
+                    class: "login-button",
+                    onclick: handle_bot_login, "Log in as Bot"
+                }
+                button {
+                    class: "login-button",
+                    onclick: move |_| {
+                        bot_mode.set(false);
+                        login_error.set(None);
+                    },
+                    "Back"
+                }
+            } else {
+                input {
+                    class: "login-input",
+                    value: "{username}",
+                    placeholder: "Username/Email",
+                    oninput: move |event| username.set(event.value())
+                }
+                input {
+                    class: "login-input",
+                    r#type: "password",
+                    value: "{password}",
+                    placeholder: "Password",
+                    oninput: move |event| password.set(event.value())
+                }
+                button { 
+                    class: "login-button",
+                    onclick: handle_login, "Login" 
+                }
+                button { 
+                    class: "login-button",
+                    onclick: handle_browser_login, "Browser Login (Requires Chrome)" 
+                }
+                button {
+                    class: "login-button",
+                    onclick: move |_| {
+                        bot_mode.set(true);
+                        login_error.set(None);
+                    },
+                    "Bot Token Login"
+                }
             }
 
             // TODO: provide custom error warnings
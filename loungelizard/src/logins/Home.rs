@@ -3,13 +3,15 @@ use dioxus_logger::tracing::warn;
 use futures::executor::block_on;
 use crate::api::mongo_format::mongo_structs::*;
 use crate::api::mongo_format::mongo_funcs::*;
+use crate::api::mongo_format::vault::*;
+use crate::api::mongo_format::passwords::*;
+use crate::api::mongo_format::user_store::*;
 
-use mongodb::{sync::Client, bson::doc};
+use mongodb::sync::Client;
 use dioxus_logger::tracing::{info, error};
 
 use std::sync::Arc;
 use tokio::sync::Mutex;
-use regex::Regex;
 
 #[component]
 pub fn HomeLogin (confirmation: Signal<bool>) -> Element {
@@ -17,6 +19,8 @@ pub fn HomeLogin (confirmation: Signal<bool>) -> Element {
     // ! User Mutex Lock to access the user data
     let user_lock = use_context::<Signal<Arc<Mutex<User>>>>();
     let client_lock = use_context::<Signal<Arc<Mutex<Option<Client>>>>>();
+    let mut db_status = use_context::<Signal<DbStatus>>();
+    let mut user_store = use_context::<Signal<Arc<dyn UserStore>>>();
     // ! ========================= ! //
 
     // ? Place holders for returning users 
@@ -44,23 +48,44 @@ pub fn HomeLogin (confirmation: Signal<bool>) -> Element {
     };
 
     // ! Helper function to check if all fields are filled in for login
+    // Older accounts may predate the password policy, so it isn't checked here
     let is_valid_login = || {
         // Check if all fields are filled in
         !username().is_empty() && 
-        !password().is_empty() && 
-        correct_password() 
+        !password().is_empty()
     };
 
     // ! Function to handle new user signup
     let handle_new_user = move |_| {
        // ! Once Logged In
 
-        let mongo_lock_copies = client_lock().clone();
+        let store = user_store();
         let user_lock_copies = user_lock().clone();
 
-        let mongo_client = block_on(async {
-            mongo_lock_copies.lock().await
-        });
+        // Only the Argon2 hash of the password is stored
+        if let Err(e) = check_password_policy(&password()) {
+            login_error.set(Some(e));
+            return;
+        }
+        let password_hash = match hash_password(&password()) {
+            Ok(hash) => hash,
+            Err(e) => {
+                error!("Failed to hash password: {}", e);
+                login_error.set(Some("Could not create account".to_string()));
+                return;
+            }
+        };
+
+        // Platform credentials are encrypted with a key derived from the password
+        let vault_salt = new_salt();
+        match VaultKey::derive(&password(), &vault_salt) {
+            Ok(key) => unlock(key),
+            Err(e) => {
+                error!("Failed to create credential vault: {}", e);
+                login_error.set(Some(e.to_string()));
+                return;
+            }
+        }
 
         let mut user = block_on(
             async{
@@ -68,113 +93,135 @@ pub fn HomeLogin (confirmation: Signal<bool>) -> Element {
             }
         );
 
-         // Clone the client if it exists (since we can't return a reference directly)
-        if let Some(client) = mongo_client.as_ref() {
-            // Convert the function into async and spawn it on the current runtime
-            let client_clone = client.clone();  // Clone the client to avoid ownership issues
-
-            // Add personal info to USER structs before checking for duplicates
-            user.username = username().clone();
-            user.email = email().clone();
-            user.password = password().clone();
-
-            let user_clone = user.clone();
-            
-            // Use `tokio::spawn` to run the async block
-            block_on(async move {
-                let db = client_clone.database(MONGO_DATABASE);
-                let user_collection = db.collection::<User>(MONGO_COLLECTION);
-                
-                match user_collection
-                    .find_one(doc! { 
-                        "$or": [{"username": &user_clone.username},
-                                {"email": &user_clone.email}] })
-                    .await {
-
-                    Ok(Some(_)) => {
-                       warn!("User already exists"); 
-                    }
-                    Ok(None) => {
-                        info!("No match found, adding user");
-                        match user_collection.insert_one(user_clone).await{
-                            Ok(_) => {
-                                info!("User added successfully");
-                                confirmation.set(true);
-                                logged_in.set(true);
-                            }
-                            Err(e) => {
-                                error!("Something went wrong: {:#?}", e);
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        error!("Something went wrong: {:#?}", e); 
-                    }
-                }
-            });
-
-        } else {
-            warn!("MongoDB client not found in global state.");
+        // The shared USER only takes the account once the store accepted it
+        let new_user = User {
+            username: username().clone(),
+            email: email().clone(),
+            password: password_hash,
+            vault_salt,
+            ..user.clone()
+        };
+
+        let user_clone = match seal_user(&new_user) {
+            Ok(sealed) => sealed,
+            Err(e) => {
+                error!("Failed to encrypt credentials: {}", e);
+                login_error.set(Some(e.to_string()));
+                return;
+            }
+        };
+
+        match block_on(store.insert_user(&user_clone)) {
+            Ok(id) => {
+                info!("User added successfully to the {} store", store.name());
+                *user = User {
+                    id: Some(id),
+                    revision: 0,
+                    ..new_user
+                };
+                confirmation.set(true);
+                logged_in.set(true);
+            }
+            Err(StoreError::AlreadyExists) => {
+                warn!("User already exists");
+                login_error.set(Some(StoreError::AlreadyExists.to_string()));
+            }
+            Err(e) => {
+                error!("Something went wrong: {}", e);
+                login_error.set(Some("Could not create account".to_string()));
+            }
         }
-    
     };
 
     // ! Function to handle login
     let handle_login = move |_| {
-        let mongo_lock_copies = client_lock().clone();
+        let store = user_store();
         let user_lock_copies = user_lock().clone();
 
-        let mongo_client = block_on(async {
-            mongo_lock_copies.lock().await
-        });
-
         let mut user = block_on(async{
             user_lock_copies.lock().await
         }); 
-    
-         // Clone the client if it exists (since we can't return a reference directly)
-        if let Some(client) = mongo_client.as_ref() {
-            // Convert the function into async and spawn it on the current runtime
-            let client_clone = client.clone();  // Clone the client to avoid ownership issues
 
-            
+        match block_on(store.find_user(&username())) {
+            Ok(Some(mut logged_user)) => {
+                let password_check = verify_password(&password(), &logged_user.password);
+                if password_check == PasswordCheck::Invalid {
+                    login_error.set(Some("Incorrect password".to_string()));
+                    warn!("Passoword is incorrect");
+                    return;
+                }
 
-            // Use `tokio::spawn` to run the async block
-            block_on(async move {
-                let db = client_clone.database(MONGO_DATABASE);
-                let user_collection = db.collection::<User>(MONGO_COLLECTION);
-                // Lock user here for async access
-                
-                match user_collection
-                    .find_one(doc! {
-                        "$or": [
-                            {"username": &username(), "password": &password()},
-                            {"email": &username(), "password": &password()}
-                        ]
-                    })
-                    .await {
-
-                    Ok(Some(logged_user)) => {
-                       info!("Logging in"); 
-                       confirmation.set(true);
-                       logged_in.set(true);
-                       *user = logged_user;
-                    }
-                    Ok(None) => {
-                        login_error.set(Some("User not found".to_string()));
-                        warn!("Passoword is incorrect");
+                // Plain text passwords from before hashing are replaced by their hash below
+                let plaintext_password = password_check == PasswordCheck::ValidPlaintext;
+                if plaintext_password {
+                    match hash_password(&password()) {
+                        Ok(hash) => logged_user.password = hash,
+                        Err(e) => error!("Failed to hash password: {}", e),
                     }
-                    Err(e) => {
-                        error!("Something went wrong: {:#?}", e); 
+                }
+
+                // Records saved before the vault existed get a salt and are encrypted below
+                let unencrypted = logged_user.vault_salt.is_empty();
+                if unencrypted {
+                    logged_user.vault_salt = new_salt();
+                }
+
+                let opened = VaultKey::derive(&password(), &logged_user.vault_salt)
+                    .map(unlock)
+                    .and_then(|_| open_user(&mut logged_user));
+                if let Err(e) = opened {
+                    error!("Failed to decrypt credentials: {}", e);
+                    login_error.set(Some(e.to_string()));
+                    return;
+                }
+
+                // Rewritten in the current format, the login goes ahead even if that fails
+                if unencrypted || plaintext_password {
+                    if let Err(e) = block_on(update_user(&*store, &mut logged_user, &UserField::ALL)) {
+                        error!("Failed to upgrade stored credentials: {}", e);
                     }
                 }
-            });
 
-        } else {
-            warn!("MongoDB client not found in global state.");
+               info!("Logging in"); 
+               confirmation.set(true);
+               logged_in.set(true);
+               *user = logged_user;
+            }
+            Ok(None) => {
+                login_error.set(Some("User not found".to_string()));
+                warn!("User not found");
+            }
+            Err(e) => {
+                error!("Something went wrong: {}", e); 
+                login_error.set(Some(format!("Could not read users from {}", store.name())));
+            }
+        }
+    };
+
+    // ! Function to retry the database connection from the login screen
+    let handle_retry_db = move |_| {
+        match init_mongo_client() {
+            Ok(client) => {
+                let client_lock_copies = client_lock().clone();
+                block_on(async {
+                    *client_lock_copies.lock().await = Some(client);
+                });
+                db_status.set(DbStatus::Connected);
+            }
+            Err(e) => {
+                error!("Failed to connect to MongoDB: {}", e);
+                db_status.set(DbStatus::Unavailable(e.to_string()));
+            }
         }
     };
 
+    // ! Function to keep accounts on this machine when the database is down
+    let handle_use_local = move |_| {
+        user_store.set(open_store(&StoreKind::Local, client_lock().clone()));
+        db_status.set(DbStatus::Local);
+        login_error.set(None);
+    };
+
     // ! Function to toggle new user signup
     let toggle_new_user = 
         move |_| {new_user_setup.set(!new_user_setup());};
@@ -182,6 +229,25 @@ pub fn HomeLogin (confirmation: Signal<bool>) -> Element {
     rsx! {
         div {
             class: format_args!("discord-login {}",  if !logged_in() {"visible"} else {""}),
+            if let DbStatus::Unavailable(reason) = db_status() {
+                p { 
+                    style: "color: white; font-family: Arial, sans-serif; font-weight: bold; text-align: center;",
+                    "Can't reach the database: {reason}" 
+                }
+                div { 
+                    class: "flex flex-row space-x-10",
+                    button { 
+                        class: "login-button",
+                        onclick: handle_retry_db,
+                        "Retry Connection" 
+                    }
+                    button { 
+                        class: "login-button",
+                        onclick: handle_use_local,
+                        "Use Offline Storage" 
+                    }
+                }
+            }
             if !new_user_setup() 
             {
                 // ! Login Form
@@ -197,7 +263,7 @@ pub fn HomeLogin (confirmation: Signal<bool>) -> Element {
                         class: "login-input",
                         r#type: "password",
                         value: "{password}",
-                        placeholder: "Password: 8-16 Aa",
+                        placeholder: "Password: 8+ with a letter and number",
                         oninput: move |event| {
                             password.set(event.value());
                             correct_password.set(
@@ -270,7 +336,7 @@ pub fn HomeLogin (confirmation: Signal<bool>) -> Element {
                         class: "login-input",
                         r#type: "password",
                         value: "{password}",
-                        placeholder: "Password: 8-16 Aa",
+                        placeholder: "Password: 8+ with a letter and number",
                         oninput: move |event| {
                             password.set(event.value());
                             correct_password.set(
@@ -375,7 +441,6 @@ pub fn HomeLogin (confirmation: Signal<bool>) -> Element {
 }
 
 fn is_valid_password(password: &str) -> bool {
-    // Regex for a password that is 8-16 characters long and contains only letters and numbers
-    let re = Regex::new(r"^[a-zA-Z0-9]{8,16}$").unwrap();
-    re.is_match(password)
+    // Same rules the account creation enforces
+    check_password_policy(password).is_ok()
 }
\ No newline at end of file
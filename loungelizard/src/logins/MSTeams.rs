@@ -1,18 +1,15 @@
 use dioxus::prelude::*;
-use bson::to_bson;
-use crate::{MONGO_COLLECTION, MONGO_DATABASE};
 
 use crate::api::mongo_format::mongo_structs::*;
-use crate::api::ms_teams::ms_teams_app_setup::start_ms_teams;
-use dioxus_logger::tracing::{info, error, warn};
+use crate::api::mongo_format::user_store::*;
+use crate::api::mongo_format::mongo_funcs::*;
+use crate::api::ms_teams::ms_teams_app_setup::{start_ms_teams, token_tenant, MS_TEAMS_CLIENT_ID};
+use dioxus_logger::tracing::info;
 use futures::executor::block_on;
-use mongodb::{sync::Client, bson::doc};
 
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
-const MS_TEAMS_CLIENT_ID: &str = "51e0dbc4-59a4-4cb4-a020-1b8ef7495470";
-
 #[component]
 pub fn MSTeamsLogin (
     show_teams_login_pane: Signal<bool>,
@@ -25,7 +22,7 @@ pub fn MSTeamsLogin (
 
    // ! User Mutex Lock to access the user data
    let user_lock = use_context::<Signal<Arc<Mutex<User>>>>();
-   let client_lock = use_context::<Signal<Arc<Mutex<Option<Client>>>>>();
+   let user_store = use_context::<Signal<Arc<dyn UserStore>>>();
    // ! ========================= ! //
     let mut logged_in = use_signal(|| false);
     let mut login_error = use_signal(|| None::<String>);
@@ -54,80 +51,33 @@ pub fn MSTeamsLogin (
         // If there is an issue, prevent the login from happening
         if !login_error().is_none() {return;}
 
-        let mongo_lock_copies = client_lock().clone();
         let user_lock_copies = user_lock().clone();
 
-        let mongo_client = block_on(
-            async {
-                mongo_lock_copies.lock().await
-            }
-        );
-
         let mut user = block_on(
             async {
                 user_lock_copies.lock().await
             }
         );
-         // Clone the client if it exists (since we can't return a reference directly)
-        if let Some(client) = mongo_client.as_ref() {
-            // Convert the function into async and spawn it on the current runtime
-            let client_clone = client.clone();  // Clone the client to avoid ownership issues
-
-            // TODO Add all tokens to user profile here
-            user.ms_teams = MSTeams{
-                access_token: access_token.to_string(),
-                refresh_token: refresh_token.to_string(),
-                expiration: expiration.to_string()
-
-            };
-            
-            // Todo ====================================//
-
-            let user_clone = user.clone();
-            
-            // Use `tokio::spawn` to run the async block
-            spawn(async move {
-                let db = client_clone.database(MONGO_DATABASE);
-                let user_collection = db.collection::<User>(MONGO_COLLECTION);
-                
-                match to_bson(&user_clone.ms_teams) {
-                    Ok(ms_teams_bson) => {
-                        match user_collection
-                            .find_one_and_update(
-                                doc! { 
-                                    "$or": [{"username": &user_clone.username}, 
-                                            {"email": &user_clone.email}] 
-                                },
-                                doc! {
-                                    "$set": { "ms_teams": ms_teams_bson }
-                                }
-                            )
-                            .await 
-                        {
-                            Ok(Some(_)) => {
-                                // Document found and updated
-                                info!("Document updated successfully");
-                                logged_in.set(true);
-                                current_platform.set("MSTeams".to_string());
-                            }
-                            Ok(None) => {
-                                // No document matched the filter
-                                warn!("Document not found");
-                            }
-                            Err(e) => {
-                                error!("Something went wrong: {:#?}", e);
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        error!("Failed to convert Slack to BSON: {:#?}", e);
-                    }
-                }
-                
-            });
 
-        } else {
-            warn!("MongoDB client not found in global state.");
+        // The tenant tells this token set apart from other linked tenants
+        let (tenant_id, account) = token_tenant(&access_token()).unwrap_or_default();
+
+        *user.ms_teams.active_mut() = MSTeams{
+            access_token: access_token.to_string(),
+            refresh_token: refresh_token.to_string(),
+            expiration: expiration.to_string(),
+            tenant_id,
+            account,
+        };
+        user.ms_teams.merge_active(|a, b| !a.tenant_id.is_empty() && a.tenant_id == b.tenant_id && a.account == b.account);
+
+        // update_user encrypts the tokens before they are stored
+        match block_on(update_user(&*user_store(), &mut user, &[UserField::MSTeams])) {
+            Ok(()) => {
+                logged_in.set(true);
+                current_platform.set("MSTeams".to_string());
+            }
+            Err(e) => login_error.set(Some(e.to_string())),
         }
 
     };
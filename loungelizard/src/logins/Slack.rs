@@ -1,12 +1,14 @@
 use dioxus::prelude::*;
 use bson::to_bson;
 use crate::api::slack::server_utils::setup_server::create_slack_app;
+use crate::api::slack::socket_mode::APP_TOKEN_PREFIX;
 use clipboard_rs::{Clipboard, ClipboardContext};
 use crate::api::mongo_format::mongo_structs::*;
+use crate::api::mongo_format::user_store::*;
 use crate::api::mongo_format::mongo_funcs::*;   
 use dioxus_logger::tracing::{info, error, warn};
 use futures::executor::block_on;
-use mongodb::{sync::Client, bson::doc};
+use mongodb::bson::doc;
 
 use std::sync::Arc;
 use tokio::sync::Mutex;
@@ -20,7 +22,7 @@ pub fn SlackLogin (
 
     // ! User Mutex Lock to access the user data
     let user_lock = use_context::<Signal<Arc<Mutex<User>>>>();
-    let client_lock = use_context::<Signal<Arc<Mutex<Option<Client>>>>>();
+    let user_store = use_context::<Signal<Arc<dyn UserStore>>>();
     // ! ========================= ! //
 
     let mut logged_in = use_signal(||false);
@@ -28,6 +30,9 @@ pub fn SlackLogin (
 
     // ! Slack Temp Values ! //
     let mut oauth_url = use_signal(|| String::new());
+    // Socket Mode apps receive events without a public tunnel
+    let mut socket_mode = use_signal(|| false);
+    let mut app_token_saved = use_signal(|| false);
 
     let handle_new_user = move |_| {
 
@@ -42,6 +47,12 @@ pub fn SlackLogin (
                                                 "".to_string()
                                             );
 
+            // The manifest depends on how events will be received
+            user_lock().lock().await.slack.active_mut().transport = match socket_mode() {
+                true => SlackTransport::SocketMode,
+                false => SlackTransport::Events,
+            };
+
             // Create a new Slack app using the provided config token
             // and store the app data in the `User` struct
             create_slack_app(user_lock().clone(), ctx).await
@@ -65,20 +76,53 @@ pub fn SlackLogin (
         
         let user_lock_copies = user_lock().clone();
 
-        let user = block_on(
+        let mut user = block_on(
             async {
                 user_lock_copies.lock().await
             }
         );
 
-        oauth_url.set(user.slack.oauth_url.clone());
+        oauth_url.set(user.slack.active().oauth_url.clone());
         
-        block_on(
+        if let Err(e) = block_on(update_user(&*user_store(), &mut user, &[UserField::Slack])) {
+            login_error.set(Some(e.to_string()));
+        }
+    
+    };
+
+    // Socket Mode needs an app-level token, which Slack only lets you generate
+    // by hand under Basic Information > App-Level Tokens (connections:write)
+    let handle_app_token = move |_| {
+        let app_token = ClipboardContext::new()
+                                        .unwrap()
+                                        .get_text()
+                                        .unwrap_or(
+                                            "".to_string()
+                                        );
+        let app_token = app_token.trim().to_string();
+
+        if !app_token.starts_with(APP_TOKEN_PREFIX) {
+            login_error.set(Some("Copy an app-level token (xapp-...) first".to_string()));
+            return;
+        }
+
+        let user_lock_copies = user_lock().clone();
+
+        let saved = block_on(
             async{
-                update_slack(user.clone(), client_lock().clone()).await;
+                let mut user = user_lock_copies.lock().await;
+                user.slack.active_mut().app_token = app_token;
+                update_user(&*user_store(), &mut user, &[UserField::Slack]).await
             }
         );
-    
+
+        match saved {
+            Ok(()) => {
+                login_error.set(None);
+                app_token_saved.set(true);
+            }
+            Err(e) => login_error.set(Some(e.to_string())),
+        }
     };
 
     rsx! {
@@ -116,7 +160,13 @@ pub fn SlackLogin (
                 }
             }
 
-            if logged_in() {
+            if logged_in() && socket_mode() && !app_token_saved() {
+                button { 
+                    class: "login-button",
+                    onclick: handle_app_token, "Paste App Token" 
+                }
+            }
+            else if logged_in() {
                 a {
                     href: oauth_url().as_str(),  // The URL you want to navigate to
                     target: "_top",               // Opens in a new tab (optional)
@@ -132,6 +182,15 @@ pub fn SlackLogin (
 
             }
             else {
+                label {
+                    style: "color: white; font-family: Arial, sans-serif;",
+                    input {
+                        r#type: "checkbox",
+                        checked: socket_mode(),
+                        oninput: move |event| socket_mode.set(event.value() == "true"),
+                    }
+                    " Use Socket Mode (no ngrok tunnel)"
+                }
                 button { 
                     class: "login-button",
                     onclick: handle_new_user, "Add WorkSpace" 
@@ -1,7 +1,7 @@
 #![allow(non_snake_case)]
 
 use dioxus::prelude::*;
-use dioxus_logger::tracing::{info, error, warn, Level};
+use dioxus_logger::tracing::{info, error, Level};
 use futures::executor::block_on;
 
 // * Front End Files
@@ -18,6 +18,7 @@ mod logins;
 mod api;
 use api::mongo_format::mongo_structs::*;
 use api::mongo_format::mongo_funcs::*; 
+use api::mongo_format::user_store::*;
 
 mod comp;
 use lazy_static::lazy_static;
@@ -38,6 +39,11 @@ enum AppRoute {
 lazy_static! {
     static ref GLOBAL_USER: Arc<Mutex<User>> = Arc::new(Mutex::new(User::default()));
     static ref GLOBAL_MONGO_CLIENT: Arc<Mutex<Option<Client>>> = Arc::new(Mutex::new(None));
+    static ref GLOBAL_DB_STATUS: Arc<Mutex<DbStatus>> = Arc::new(Mutex::new(DbStatus::Connected));
+    static ref STORE_KIND: StoreKind = StoreKind::from_env().unwrap_or_else(|e| {
+        error!("{}, using MongoDB", e);
+        StoreKind::Mongo
+    });
 }
 
 fn main() {
@@ -45,23 +51,29 @@ fn main() {
     dioxus_logger::init(Level::INFO).expect("failed to init logger");
     info!("starting application...");
 
-    // Call init_mongo_client and set the result in GLOBAL_MONGO_CLIENT
-    let client_result: Result<Option<Client>, mongodb::error::Error> = init_mongo_client(); 
-
-    match client_result {
-        Ok(Some(client)) => {
-            let mut global_client = block_on(async {
-                GLOBAL_MONGO_CLIENT.lock().await
-            });
-            //let mut global_client = GLOBAL_MONGO_CLIENT.lock().unwrap(); // Lock the mutex
-            *global_client = Some(client); // Update the client inside the mutex
-            info!("MongoDB client set successfully in global state.");
-        }
-        Ok(None) => {
-            warn!("Failed to initialize MongoDB client.");
-        }
-        Err(e) => {
-            error!("Unexpected error while initializing MongoDB client: {:?}", e);
+    // Accounts kept on this machine don't need a database at all
+    if *STORE_KIND == StoreKind::Local {
+        info!("Using the local user store");
+        *block_on(GLOBAL_DB_STATUS.lock()) = DbStatus::Local;
+    }
+    else {
+        // Call init_mongo_client and set the result in GLOBAL_MONGO_CLIENT
+        let client_result: Result<Client, mongodb::error::Error> = init_mongo_client(); 
+
+        match client_result {
+            Ok(client) => {
+                let mut global_client = block_on(async {
+                    GLOBAL_MONGO_CLIENT.lock().await
+                });
+                //let mut global_client = GLOBAL_MONGO_CLIENT.lock().unwrap(); // Lock the mutex
+                *global_client = Some(client); // Update the client inside the mutex
+                info!("MongoDB client set successfully in global state.");
+            }
+            Err(e) => {
+                error!("Failed to connect to MongoDB: {}", e);
+                // Shown on the login screen
+                *block_on(GLOBAL_DB_STATUS.lock()) = DbStatus::Unavailable(e.to_string());
+            }
         }
     }
 
@@ -80,9 +92,20 @@ fn App() -> Element {
     // Create a global signal for the Arc<Mutex<User>> data
     let user_lock = use_signal(|| GLOBAL_USER.clone());
     let client_lock = use_signal(|| GLOBAL_MONGO_CLIENT.clone());
+    let db_status = use_signal(|| block_on(GLOBAL_DB_STATUS.lock()).clone());
+    let user_store = use_signal(|| open_store(&STORE_KIND, GLOBAL_MONGO_CLIENT.clone()));
 
     provide_context(user_lock.clone());
     provide_context(client_lock.clone());
+    provide_context(db_status);
+    provide_context(user_store);
+
+    // Stop a tunnel process started by the Slack or Teams setup when the app closes
+    dioxus::desktop::use_wry_event_handler(|event, _| {
+        if let dioxus::desktop::tao::event::Event::LoopDestroyed = event {
+            api::tunnel::close_tunnel();
+        }
+    });
 
     rsx! { Router::<AppRoute> {} }
 }
\ No newline at end of file
@@ -4,24 +4,89 @@ use tracing::info;
 use futures::executor::block_on;
 use chrono::{DateTime, Utc};
 use crate::api::discord::discord_api::*;
+use crate::api::unified_message::*;
+use crate::api::chat_provider::*;
+use crate::api::discord::discord_provider::{DiscordProvider, DM_WORKSPACE_ID};
+use crate::api::discord::discord_gateway::*;
+use crate::api::discord::discord_history::*;
+use crate::api::discord::discord_emoji::*;
+use crate::api::discord::discord_http;
+use tokio::sync::broadcast;
+use std::error::Error;
+use std::rc::Rc;
 
 // Api mongo structs
 use crate::api::mongo_format::mongo_structs::*;
 use std::sync::Arc;
 use tokio::sync::Mutex;
+
+// Whether the message list is scrolled (close) to its top. The list is laid out bottom up
+// (column-reverse), so scrollTop is 0 at the newest message and negative above it.
+const MESSAGES_AT_TOP_JS: &str = r#"
+    const list = document.getElementById("discord-messages-list");
+    return list !== null && Math.abs(list.scrollTop) + list.clientHeight >= list.scrollHeight - 50;
+"#;
+
 #[component]
-pub fn Discord(show_discord_server_pane: Signal<bool>, discord_guilds: Signal<Value>) -> Element {
+pub fn Discord(show_discord_server_pane: Signal<bool>, discord_guilds: Signal<Vec<ChatWorkspace>>) -> Element {
    // ! User Mutex Lock to access the user data
    let user_lock = use_context::<Signal<Arc<Mutex<User>>>>();
    
    let user_guilds = Arc::clone(&user_lock());
+   let user_gateway = Arc::clone(&user_lock());
    
    // ! ========================= ! //
 
-   block_on(async move {
-    let discord_token = user_guilds.lock().await.discord.token.clone();
+   // Live message events, every open message pane subscribes to this
+   let gateway_events = use_context_provider(|| broadcast::channel::<GatewayEvent>(256).0);
+
+   // Gateway connection lives as long as the Discord page, dropping the task closes it
+   let _gateway = use_coroutine::<EmptyStruct, _, _>(|_rx| {
+       let gateway_events = gateway_events.clone();
+       async move {
+           // The gateway takes the token without the "Bot " prefix
+           let discord_token = user_gateway.lock().await.discord.active().token.clone();
+           run_gateway(discord_token, gateway_events).await;
+       }
+   });
+
+   let account = block_on(user_guilds.lock()).discord.active().clone();
+
+   // Guilds, channels and messages of the active account all go through its provider
+   let provider = use_signal(|| Rc::new(DiscordProvider::new(&account)));
 
-    match get_guilds(discord_token).await {
+   // Accounts linked before the user id was kept look it up once, it's saved on the next login
+   let user_id_lock = Arc::clone(&user_lock());
+   let authorization = account.authorization();
+   use_hook(move || {
+       if !account_needs_user_id(&user_id_lock) {
+           return;
+       }
+       match block_on(get_current_user(authorization)) {
+           Ok(current_user) => {
+               let user_id = current_user["id"].as_str().unwrap_or_default().to_string();
+               block_on(user_id_lock.lock()).discord.active_mut().user_id = user_id;
+           }
+           Err(e) => info!("Failed to get the Discord user id: {}", e),
+       }
+   });
+
+   // Bots join servers through an invite link, which needs the bot's application id
+   let bot_invite = use_signal(move || {
+       if !account.is_bot() {
+           return None;
+       }
+       match block_on(get_bot_application(account.authorization())) {
+           Ok(application) => application["id"].as_str().map(bot_invite_url),
+           Err(e) => {
+               info!("Failed to get the bot application: {}", e);
+               None
+           }
+       }
+   });
+
+   block_on(async move {
+    match provider().list_workspaces().await {
         Ok(discord_guilds_response) => {
             discord_guilds.set(discord_guilds_response); // Call the success handler
             info!("discord_guilds get successful");
@@ -39,69 +104,82 @@ pub fn Discord(show_discord_server_pane: Signal<bool>, discord_guilds: Signal<Va
         DiscordBottomPane { 
             show_discord_server_pane: show_discord_server_pane.clone(),
             discord_guilds: discord_guilds.clone(),
-            user: user_lock
+            user: user_lock,
+            provider: provider,
+            bot_invite: bot_invite
         }, 
     }
 }
 
 #[component]
-fn DiscordBottomPane(show_discord_server_pane: Signal<bool>, discord_guilds: Signal<Value>, user: Signal<Arc<Mutex<User>>>) -> Element {
-    let discord_guilds_array = discord_guilds().as_array().unwrap_or(&vec![]).clone();
-    let mut channels = use_signal(|| None::<Value>);
+fn DiscordBottomPane(show_discord_server_pane: Signal<bool>, discord_guilds: Signal<Vec<ChatWorkspace>>, user: Signal<Arc<Mutex<User>>>, provider: Signal<Rc<DiscordProvider>>, bot_invite: Signal<Option<String>>) -> Element {
+    let mut channels = use_signal(|| None::<Vec<ChatChannel>>);
     let mut fetch_error = use_signal(|| None::<String>);
     let mut show_channel_pane = use_signal(|| false);
     let mut show_channel_messages_pane = use_signal(|| false);
     let mut show_dm_channel_pane = use_signal(|| false);
     let mut show_dm_channel_messages_pane = use_signal(|| false);
+    // Custom emoji of the selected guild, offered in the reaction picker
+    let mut guild_emojis = use_signal(Vec::<DiscordEmoji>::new);
 
-    // Fetch the channels for the selected guild
-    let handle_get_channels = move |guild_id: String, user_lock_api: Arc<Mutex<User>>| {
+    // Fetch the channels for the selected guild, or the direct messages
+    let handle_get_channels = move |workspace: ChatWorkspace, user_lock_api: Arc<Mutex<User>>| {
         block_on(async move {
-            // Attempt to acquire the lock without blocking
-            if let Ok(user_lock_api) = user_lock_api.try_lock() {
-                let discord_token = user_lock_api.discord.token.clone();
-                
-                match get_guild_channels(discord_token, guild_id).await {
-                    Ok(channels_data) => {
-                        channels.set(Some(channels_data));
+            match provider().list_channels(&workspace).await {
+                Ok(channels_data) => {
+                    channels.set(Some(channels_data));
+                    if workspace.id == DM_WORKSPACE_ID {
+                        show_dm_channel_pane.set(true);
+                    } else {
                         show_channel_pane.set(true);
                     }
-                    Err(e) => {
-                        fetch_error.set(Some(e.to_string()));
-                        info!("Failed to fetch channels for guild");
-                    }
                 }
-            } else {
-                // Log if the lock could not be acquired
-                info!("Unable to acquire user lock; skipping fetch for guild {}.", guild_id);
+                Err(e) => {
+                    fetch_error.set(Some(e.to_string()));
+                    info!("Failed to fetch channels for {}: {}", workspace.name, e);
+                }
+            }
+
+            // Direct messages have no server emoji
+            if workspace.id == DM_WORKSPACE_ID {
+                return;
             }
-        });
-    };
 
-    // Fetch the channels IDs for direct messages if clicked
-    let handle_get_dm_channels = move |user_lock_api: Arc<Mutex<User>>| {
-        block_on(async move {
             // Attempt to acquire the lock without blocking
             if let Ok(user_lock_api) = user_lock_api.try_lock() {
-                let discord_token = user_lock_api.discord.token.clone();
-                
-                match get_channels(discord_token).await {
-                    Ok(channels_data) => {
-                        channels.set(Some(channels_data));
-                        show_dm_channel_pane.set(true);
-                    }
+                let discord_token = user_lock_api.discord.active().authorization();
+
+                // The picker still has the standard emoji without them
+                match get_guild_emojis(discord_token, workspace.id.clone()).await {
+                    Ok(emojis) => guild_emojis.set(usable_emojis(&emojis)),
                     Err(e) => {
-                        fetch_error.set(Some(e.to_string()));
-                        info!("Failed to fetch channels for guild");
+                        guild_emojis.set(Vec::new());
+                        info!("Failed to fetch emojis for guild: {}", e);
                     }
                 }
             } else {
                 // Log if the lock could not be acquired
-                info!("Unable to acquire user lock; skipping fetch for dms.");
+                info!("Unable to acquire user lock; skipping emojis for guild {}.", workspace.id);
             }
         });
     };
 
+    // Open the workspace of a channel picked in the inbox, the channel lists take it from there
+    let pending_channel = use_context::<Signal<Option<ChatChannel>>>();
+    use_effect(move || {
+        if let Some(pending) = pending_channel().filter(|c| c.platform == Platform::Discord) {
+            let workspace = discord_guilds
+                .peek()
+                .iter()
+                .find(|workspace| workspace.id == pending.workspace_id)
+                .cloned();
+
+            if let Some(workspace) = workspace {
+                handle_get_channels(workspace, Arc::clone(&user.peek()));
+            }
+        }
+    });
+
 
     rsx! {
         div {
@@ -143,25 +221,33 @@ fn DiscordBottomPane(show_discord_server_pane: Signal<bool>, discord_guilds: Sig
                     }
                 }
             }
-            if !discord_guilds().is_null() {
-                // Render the discord_guilds data
+            if !discord_guilds().is_empty() {
+                // Render the discord_guilds data, the direct messages come first (bots have none)
                 ul {
                     class: "guild-list",
-                    li {
-                        class: "guild-item",
-                        button {
-                            class: "guild-button",  // You can style this button as you like in CSS
-                            onclick: move |_| handle_get_dm_channels(Arc::clone(&user())) ,
-                            {"Direct Messages"}
-                        }
-                    }
-                    for guild in discord_guilds_array {
+                    for guild in discord_guilds() {
                         li {
                             class: "guild-item",
                             button {
                                 class: "guild-button",  // You can style this button as you like in CSS
-                                onclick: move |_| handle_get_channels(guild["id"].as_str().unwrap().to_string(), Arc::clone(&user())) ,
-                                {guild["name"].as_str().unwrap_or("Unknown Guild")}
+                                onclick: {
+                                    let guild = guild.clone();
+                                    move |_| handle_get_channels(guild.clone(), Arc::clone(&user()))
+                                },
+                                {guild.name.clone()}
+                            }
+                        }
+                    }
+                    if let Some(invite_url) = bot_invite() {
+                        li {
+                            class: "guild-item",
+                            a {
+                                href: "{invite_url}",
+                                target: "_top",
+                                button {
+                                    class: "guild-button",
+                                    "+ Add Bot to a Server"
+                                }
                             }
                         }
                     }
@@ -171,13 +257,16 @@ fn DiscordBottomPane(show_discord_server_pane: Signal<bool>, discord_guilds: Sig
             }
             ChannelList {
                 user: user.clone(),
+                provider: provider,
                 channels: channels.clone(),
+                guild_emojis: guild_emojis,
                 show_channel_pane: show_channel_pane.clone(),
                 show_discord_server_pane: show_discord_server_pane.clone(),
                 show_channel_messages_pane: show_channel_messages_pane.clone()
             }
             DMChannelList {
                 user: user.clone(),
+                provider: provider,
                 channels: channels.clone(),
                 show_channel_pane: show_dm_channel_pane.clone(),
                 show_discord_server_pane: show_discord_server_pane.clone(),
@@ -188,40 +277,94 @@ fn DiscordBottomPane(show_discord_server_pane: Signal<bool>, discord_guilds: Sig
 }
 
 #[component]
-fn ChannelList(user: Signal<Arc<Mutex<User>>>, channels: Signal<Option<Value>>, show_channel_pane: Signal<bool>, show_discord_server_pane: Signal<bool>, show_channel_messages_pane: Signal<bool>) -> Element {
-    let channels_array = channels()?.as_array().unwrap_or(&vec![]).clone();
-    let mut messages = use_signal(|| None::<Value>);
+fn ChannelList(user: Signal<Arc<Mutex<User>>>, provider: Signal<Rc<DiscordProvider>>, channels: Signal<Option<Vec<ChatChannel>>>, guild_emojis: Signal<Vec<DiscordEmoji>>, show_channel_pane: Signal<bool>, show_discord_server_pane: Signal<bool>, show_channel_messages_pane: Signal<bool>) -> Element {
+    let channels_array = channels()?;
+    let mut messages = use_signal(|| None::<Vec<UnifiedMessage>>);
     let mut fetch_error = use_signal(|| None::<String>);
-    let mut current_channel_id = use_signal(|| " ".to_string());
-   
+    let mut current_channel = use_signal(|| None::<ChatChannel>);
+    let mut channel_title = use_signal(|| "Messages".to_string());
 
-    // Fetch the channels for the selected guild
-    let handle_get_channel_messages = move |channel_id: String, user_lock_api: Arc<Mutex<User>>| {
-        let channel_id_clone = channel_id.clone();
+    // Threads (or forum posts) of the channel that is expanded in the list
+    let mut expanded_channel_id = use_signal(|| None::<String>);
+    let mut threads = use_signal(Vec::<ChatChannel>::new);
+    let mut post_title = use_signal(|| "".to_string());
+    let mut post_message = use_signal(|| "".to_string());
 
+    // Fetch the messages of a channel or thread and open them in the message pane
+    let handle_get_channel_messages = move |channel: ChatChannel| {
         block_on(async move {
-            // Attempt to acquire the lock without blocking
-            if let Ok(user_lock_api) = user_lock_api.try_lock() {
-                let discord_token = user_lock_api.discord.token.clone();
-                
-                match get_messages(discord_token.to_string(), channel_id).await {
-                    Ok(messages_data) => {
-                        messages.set(Some(messages_data));
-                        current_channel_id.set(channel_id_clone);
-                        show_channel_messages_pane.set(true);
-                    }
-                    Err(e) => {
-                        fetch_error.set(Some(e.to_string()));
-                        info!("Failed to fetch messages for channel {}: {}", channel_id_clone, e);
-                    }
+            match fetch_page(&provider(), &channel, &HistoryCursor::Latest, MESSAGE_PAGE_SIZE).await {
+                Ok(messages_data) => {
+                    messages.set(Some(messages_data));
+                    channel_title.set(channel_heading(&channel));
+                    current_channel.set(Some(channel));
+                    show_channel_messages_pane.set(true);
+                }
+                Err(e) => {
+                    fetch_error.set(Some(e.to_string()));
+                    info!("Failed to fetch messages for channel {}: {}", channel.id, e);
                 }
-            } else {
-                // Log if the lock could not be acquired
-                info!("Unable to acquire user lock; skipping fetch for channel {}.", channel_id_clone);
             }
         });
     };
 
+    // List the active and archived threads of a channel under it, or hide them again
+    let mut handle_toggle_threads = move |channel: ChatChannel| {
+        if expanded_channel_id().as_deref() == Some(channel.id.as_str()) {
+            expanded_channel_id.set(None);
+            return;
+        }
+
+        block_on(async move {
+            match provider().list_threads(&channel).await {
+                Ok(channel_threads) => {
+                    threads.set(channel_threads);
+                    expanded_channel_id.set(Some(channel.id));
+                }
+                Err(e) => {
+                    fetch_error.set(Some(e.to_string()));
+                    info!("Failed to fetch threads for channel {}: {}", channel.id, e);
+                }
+            }
+        });
+    };
+
+    // Post in a forum channel and open the new post
+    let handle_create_post = move |forum: ChatChannel| {
+        let created = block_on(async move {
+            match provider().create_forum_post(&forum, &post_title(), &post_message()).await {
+                Ok(post) => {
+                    post_title.set(String::new());
+                    post_message.set(String::new());
+                    threads.write().insert(0, post.clone());
+                    Some(post)
+                }
+                Err(e) => {
+                    fetch_error.set(Some(e.to_string()));
+                    info!("Failed to post in channel {}: {}", forum.id, e);
+                    None
+                }
+            }
+        });
+
+        if let Some(post) = created {
+            handle_get_channel_messages(post);
+        }
+    };
+
+    // Open the messages once the inbox pick (a guild channel) shows up in the loaded channels
+    let mut pending_channel = use_context::<Signal<Option<ChatChannel>>>();
+    use_effect(move || {
+        let Some(pending) = pending_channel().filter(|c| c.platform == Platform::Discord && c.workspace_id != DM_WORKSPACE_ID) else { return };
+        let loaded = channels()
+            .and_then(|channels| channels.into_iter().find(|channel| channel.id == pending.id));
+
+        if let Some(channel) = loaded {
+            handle_get_channel_messages(channel);
+            pending_channel.set(None);
+        }
+    });
+
 
     rsx! {
         div {
@@ -258,16 +401,89 @@ fn ChannelList(user: Signal<Arc<Mutex<User>>>, channels: Signal<Option<Value>>,
                     }
                 }
             }
-            if !channels()?.is_null() {
+            if !channels_array.is_empty() {
                 ul {
                     class: "channel-list",
                     for channel in channels_array {
-                        li {
-                            class: "channel-item",
-                            button {
-                                class: "channel-button",
-                                onclick: move |_| {handle_get_channel_messages(channel["id"].as_str().unwrap().to_string(), Arc::clone(&user()))},
-                                {channel["name"].as_str().unwrap_or("Unknown Channel")}
+                        {
+                            let is_forum = channel.kind == ChannelKind::Forum;
+                            let expanded = expanded_channel_id().as_deref() == Some(channel.id.as_str());
+                            let name = channel.name.clone();
+                            let toggled_channel = channel.clone();
+                            let forum = channel.clone();
+                            rsx! {
+                                li {
+                                    class: "channel-item",
+                                    button {
+                                        class: "channel-button",
+                                        // Forum channels have no messages of their own, only posts
+                                        onclick: move |_| {
+                                            if is_forum {
+                                                handle_toggle_threads(channel.clone())
+                                            } else {
+                                                handle_get_channel_messages(channel.clone())
+                                            }
+                                        },
+                                        {name}
+                                        if is_forum {
+                                            span { class: "channel-kind", " (forum)" }
+                                        }
+                                    }
+                                    if toggled_channel.kind == ChannelKind::Channel {
+                                        button {
+                                            class: "thread-toggle-button",
+                                            onclick: move |_| handle_toggle_threads(toggled_channel.clone()),
+                                            if expanded { "Hide threads" } else { "Threads" }
+                                        }
+                                    }
+                                    if expanded {
+                                        ul {
+                                            class: "thread-list",
+                                            if is_forum {
+                                                li {
+                                                    class: "forum-post-form",
+                                                    input {
+                                                        class: "message-input-box",
+                                                        value: "{post_title}",
+                                                        placeholder: "Post title",
+                                                        oninput: move |event| post_title.set(event.value())
+                                                    }
+                                                    input {
+                                                        class: "message-input-box",
+                                                        value: "{post_message}",
+                                                        placeholder: "Write the first message.",
+                                                        oninput: move |event| post_message.set(event.value())
+                                                    }
+                                                    button {
+                                                        class: "thread-toggle-button",
+                                                        disabled: post_title().trim().is_empty() || post_message().trim().is_empty(),
+                                                        onclick: move |_| handle_create_post(forum.clone()),
+                                                        "New post"
+                                                    }
+                                                }
+                                            }
+                                            if threads().is_empty() {
+                                                li { class: "thread-item", if is_forum { "No posts yet." } else { "No threads." } }
+                                            }
+                                            for thread in threads() {
+                                                li {
+                                                    class: "thread-item",
+                                                    button {
+                                                        class: "thread-button",
+                                                        onclick: {
+                                                            let thread = thread.clone();
+                                                            move |_| handle_get_channel_messages(thread.clone())
+                                                        },
+                                                        {thread.name.clone()}
+                                                        if thread.kind == (ChannelKind::Thread { archived: true }) {
+                                                            span { class: "channel-kind", " (archived)" }
+                                                        }
+                                                    }
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
                             }
                         }
                     }
@@ -275,9 +491,12 @@ fn ChannelList(user: Signal<Arc<Mutex<User>>>, channels: Signal<Option<Value>>,
             }
             ChannelMessages {
                 user: user.clone(),
+                provider: provider,
                 messages: messages.clone(),
                 show_channel_messages_pane: show_channel_messages_pane.clone(),
-                current_channel_id: current_channel_id,
+                current_channel: current_channel,
+                title: channel_title,
+                emojis: guild_emojis,
                 show_discord_server_pane: show_discord_server_pane.clone()
             }
         }
@@ -285,40 +504,46 @@ fn ChannelList(user: Signal<Arc<Mutex<User>>>, channels: Signal<Option<Value>>,
 }
 
 #[component]
-fn DMChannelList(user: Signal<Arc<Mutex<User>>>, channels: Signal<Option<Value>>, show_channel_pane: Signal<bool>, show_discord_server_pane: Signal<bool>, show_dm_channel_messages_pane: Signal<bool>) -> Element {
-    let channels_array = channels()?.as_array().unwrap_or(&vec![]).clone();
-    let mut messages = use_signal(|| None::<Value>);
+fn DMChannelList(user: Signal<Arc<Mutex<User>>>, provider: Signal<Rc<DiscordProvider>>, channels: Signal<Option<Vec<ChatChannel>>>, show_channel_pane: Signal<bool>, show_discord_server_pane: Signal<bool>, show_dm_channel_messages_pane: Signal<bool>) -> Element {
+    let channels_array = channels()?;
+    let mut messages = use_signal(|| None::<Vec<UnifiedMessage>>);
     let mut fetch_error = use_signal(|| None::<String>);
-    let mut current_channel_id = use_signal(|| " ".to_string());
+    let mut current_channel = use_signal(|| None::<ChatChannel>);
+    let channel_title = use_signal(|| "Messages".to_string());
+    // Direct messages have no server emoji to offer
+    let guild_emojis = use_signal(Vec::<DiscordEmoji>::new);
    
 
-    // Fetch the channels for the selected guild
-    let handle_get_channel_messages = move |channel_id: String, user_lock_api: Arc<Mutex<User>>| {
-        let channel_id_clone = channel_id.clone();
-
+    // Fetch the messages of the selected conversation
+    let handle_get_channel_messages = move |channel: ChatChannel| {
         block_on(async move {
-            // Attempt to acquire the lock without blocking
-            if let Ok(user_lock_api) = user_lock_api.try_lock() {
-                let discord_token = user_lock_api.discord.token.clone();
-                
-                match get_messages(discord_token.to_string(), channel_id).await {
-                    Ok(messages_data) => {
-                        messages.set(Some(messages_data));
-                        current_channel_id.set(channel_id_clone);
-                        show_dm_channel_messages_pane.set(true);
-                    }
-                    Err(e) => {
-                        fetch_error.set(Some(e.to_string()));
-                        info!("Failed to fetch messages for channel {}: {}", channel_id_clone, e);
-                    }
+            match fetch_page(&provider(), &channel, &HistoryCursor::Latest, MESSAGE_PAGE_SIZE).await {
+                Ok(messages_data) => {
+                    messages.set(Some(messages_data));
+                    current_channel.set(Some(channel));
+                    show_dm_channel_messages_pane.set(true);
+                }
+                Err(e) => {
+                    fetch_error.set(Some(e.to_string()));
+                    info!("Failed to fetch messages for channel {}: {}", channel.id, e);
                 }
-            } else {
-                // Log if the lock could not be acquired
-                info!("Unable to acquire user lock; skipping fetch for channel {}.", channel_id_clone);
             }
         });
     };
 
+    // Open the messages once the inbox pick (a DM) shows up in the loaded channels
+    let mut pending_channel = use_context::<Signal<Option<ChatChannel>>>();
+    use_effect(move || {
+        let Some(pending) = pending_channel().filter(|c| c.platform == Platform::Discord && c.workspace_id == DM_WORKSPACE_ID) else { return };
+        let loaded = channels()
+            .and_then(|channels| channels.into_iter().find(|channel| channel.id == pending.id));
+
+        if let Some(channel) = loaded {
+            handle_get_channel_messages(channel);
+            pending_channel.set(None);
+        }
+    });
+
 
     rsx! {
         div {
@@ -355,7 +580,7 @@ fn DMChannelList(user: Signal<Arc<Mutex<User>>>, channels: Signal<Option<Value>>
                     }
                 }
             }
-            if !channels()?.is_null() {
+            if !channels_array.is_empty() {
                 ul {
                     class: "channel-list",
                     for channel in channels_array {
@@ -363,19 +588,12 @@ fn DMChannelList(user: Signal<Arc<Mutex<User>>>, channels: Signal<Option<Value>>
                             class: "channel-item",
                             button {
                                 class: "channel-button",
-                                onclick: move |_| {handle_get_channel_messages(channel["id"].as_str().unwrap().to_string(), Arc::clone(&user()))},
-                                {
-                                    let mut display = "".to_string();
-                                    // Iterate through each recipient and join their usernames
-                                    let recipients = channel["recipients"].as_array().unwrap();
-                                    let mut usernames: Vec<String> = recipients.iter()
-                                        .map(|recipient| recipient["username"].as_str().unwrap_or("Unknown User").to_string())
-                                        .collect();
-                                    if let Some(name) = channel["name"].as_str() {
-                                        display = display + name + " | ";
-                                    }
-                                    display + &usernames.join(", ")
-                                }
+                                onclick: {
+                                    let channel = channel.clone();
+                                    move |_| handle_get_channel_messages(channel.clone())
+                                },
+                                // Named after the group, or the people in it
+                                {channel.name.clone()}
                             }
                         }
                     }
@@ -383,9 +601,12 @@ fn DMChannelList(user: Signal<Arc<Mutex<User>>>, channels: Signal<Option<Value>>
             }
             ChannelMessages {
                 user: user.clone(),
+                provider: provider,
                 messages: messages.clone(),
                 show_channel_messages_pane: show_dm_channel_messages_pane.clone(),
-                current_channel_id: current_channel_id,
+                current_channel: current_channel,
+                title: channel_title,
+                emojis: guild_emojis,
                 show_discord_server_pane: show_discord_server_pane.clone()
             }
         }
@@ -396,159 +617,299 @@ fn DMChannelList(user: Signal<Arc<Mutex<User>>>, channels: Signal<Option<Value>>
 struct EmptyStruct {} // Empty struct to use for coroutines (when you don't need to send anything into the coroutine)
 
 #[component]
-fn ChannelMessages(user: Signal<Arc<Mutex<User>>>, messages: Signal<Option<Value>>, show_channel_messages_pane: Signal<bool>, current_channel_id: Signal<String>,  show_discord_server_pane: Signal<bool>) -> Element {
+fn ChannelMessages(user: Signal<Arc<Mutex<User>>>, provider: Signal<Rc<DiscordProvider>>, messages: Signal<Option<Vec<UnifiedMessage>>>, show_channel_messages_pane: Signal<bool>, current_channel: Signal<Option<ChatChannel>>, title: Signal<String>, emojis: Signal<Vec<DiscordEmoji>>, show_discord_server_pane: Signal<bool>) -> Element {
     let mut send_error = use_signal(|| None::<String>);
     let mut message_input = use_signal(|| "".to_string());
     let mut message_id_input = use_signal(|| "".to_string());
     let mut reaction_input = use_signal(|| "".to_string());
     let mut attachment_name = use_signal(|| "".to_string());
     let mut attachment_input = use_signal(|| Vec::new());
-    let user_lock_api = Arc::clone(&user());
 
-    let handle_send_message = move |user_lock_api: Arc<Mutex<User>>| {
+    // Own messages get edit and delete actions
+    let own_user_id = use_signal(|| block_on(user.peek().lock()).discord.active().user_id.clone());
+    let mut editing_message_id = use_signal(|| None::<String>);
+    let mut edit_input = use_signal(|| "".to_string());
+    let mut deleting_message_id = use_signal(|| None::<String>);
+
+    // Message the next send replies to
+    let mut replying_to = use_signal(|| None::<UnifiedMessage>);
+    // Channel a thread was opened from, as (channel, title, thread id)
+    let mut thread_parent = use_signal(|| None::<(ChatChannel, String, String)>);
+    use_effect(move || {
+        current_channel();
+        replying_to.set(None);
+    });
+
+    // The shown message with the given id
+    let shown_message = move |message_id: &str| {
+        messages
+            .peek()
+            .as_ref()
+            .and_then(|list| list.iter().find(|message| message.id == message_id).cloned())
+    };
+
+    // Show a channel or thread in this pane instead of the current one
+    let handle_open_channel = move |channel: ChatChannel, channel_title: String| {
         block_on(async move {
-            // Attempt to acquire the lock without blocking.
-            if let Ok(user_lock_api) = user_lock_api.try_lock() {
-                let discord_token = user_lock_api.discord.token.clone();
-    
-                // Check if the attachment_input contains data
-                if !attachment_input.is_empty() {
-                    // Attachment exists, send message with attachment
-                    match send_message_attachment(
-                        discord_token.to_string(),
-                        current_channel_id.to_string(),
-                        message_input.to_string(),
-                        attachment_input(),
-                        attachment_name.to_string(),
-                    )
-                    .await
-                    {
-                        Ok(_send_response) => {
-                            info!("Message with attachment sent successfully");
-                        }
-                        Err(e) => {
-                            send_error.set(Some(e.to_string()));
-                            info!("Message with attachment send failed: {}", e);
-                        }
-                    }
-                } else {
-                    // No attachment, send a text message
-                    match send_message(
-                        discord_token.to_string(),
-                        current_channel_id.to_string(),
-                        message_input.to_string(),
-                    )
-                    .await
-                    {
-                        Ok(_send_response) => {
-                            info!("Message sent successfully");
-                        }
-                        Err(e) => {
-                            send_error.set(Some(e.to_string()));
-                            info!("Message send failed: {}", e);
-                        }
-                    }
+            match fetch_page(&provider(), &channel, &HistoryCursor::Latest, MESSAGE_PAGE_SIZE).await {
+                Ok(messages_data) => {
+                    messages.set(Some(messages_data));
+                    current_channel.set(Some(channel));
+                    title.set(channel_title);
                 }
-    
-                // Fetch messages regardless of success or failure in sending the message
-                match get_messages(discord_token.to_string(), current_channel_id.to_string()).await {
-                    Ok(send_response) => {
-                        messages.set(Some(send_response));
-                        info!("Messages update successful");
-                    }
-                    Err(e) => {
-                        send_error.set(Some(e.to_string()));
-                        info!("Messages update failed: {}", e);
-                    }
+                Err(e) => {
+                    send_error.set(Some(e.to_string()));
+                    info!("Failed to open channel {}: {}", channel.id, e);
                 }
-    
-                // Clear the attachment input and name after sending the message
-                attachment_input.set(Vec::new()); // Assuming attachment_input is a Vec<u8> signal
-                attachment_name.set(String::new()); // Assuming attachment_name is a String signal
-            } else {
-                // Handle the case where the lock could not be acquired
-                info!("Failed to acquire user lock; skipping message send.");
             }
         });
     };
-    
 
-    let handle_send_reaction = move |user_lock_api: Arc<Mutex<User>>| {
+    let handle_edit_message = move || {
+        let Some(channel) = current_channel() else { return };
+        let Some(message) = editing_message_id().and_then(|message_id| shown_message(&message_id)) else { return };
+
         block_on(async move {
-            // Attempt to acquire the lock without blocking.
-            if let Ok(user_lock_api) = user_lock_api.try_lock() {
-                let discord_token = user_lock_api.discord.token.clone();
-                println!("reaction from handler is: {}", reaction_input.to_string());
-    
-                // Send a reaction
-                match send_reaction(
-                    discord_token.to_string(),
-                    current_channel_id.to_string(),
-                    message_id_input.to_string(),
-                    reaction_input.to_string(),
-                )
-                .await
-                {
-                    Ok(_send_response) => {
-                        info!("Reaction sent successfully");
-                    }
-                    Err(e) => {
-                        send_error.set(Some(e.to_string()));
-                        info!("Message send failed: {}", e);
+            match provider().edit_message(&channel, &message, &edit_input()).await {
+                Ok(()) => {
+                    editing_message_id.set(None);
+                    info!("Message edited successfully");
+                }
+                Err(e) => {
+                    send_error.set(Some(e.to_string()));
+                    info!("Message edit failed: {}", e);
+                    return;
+                }
+            }
+
+            if let Err(e) = refresh_message(&provider(), &channel, &message.id, messages).await {
+                info!("Messages update failed: {}", e);
+            }
+        });
+    };
+
+    let handle_delete_message = move || {
+        let Some(channel) = current_channel() else { return };
+        let Some(message) = deleting_message_id().and_then(|message_id| shown_message(&message_id)) else { return };
+
+        block_on(async move {
+            match provider().delete_message(&channel, &message).await {
+                Ok(()) => {
+                    if let Some(list) = messages.write().as_mut() {
+                        list.retain(|shown| shown.id != message.id);
                     }
+                    deleting_message_id.set(None);
+                    info!("Message deleted successfully");
                 }
-    
-                // Fetch messages regardless of success or failure in sending the message
-                match get_messages(discord_token.to_string(), current_channel_id.to_string()).await {
-                    Ok(send_response) => {
-                        messages.set(Some(send_response));
-                        info!("Messages update successful");
+                Err(e) => {
+                    send_error.set(Some(e.to_string()));
+                    info!("Message delete failed: {}", e);
+                }
+            }
+        });
+    };
+
+    // Whether older messages can be loaded above the oldest one shown
+    let mut has_older = use_signal(|| false);
+    use_effect(move || {
+        current_channel();
+        has_older.set(messages.peek().as_ref().is_some_and(|list| is_full_page(list.len())));
+    });
+
+    let handle_load_older = move || {
+        let Some(channel) = current_channel() else { return };
+        let Some(oldest_id) = messages.peek().as_ref().and_then(|list| list.last()).map(|message| message.id.clone()) else { return };
+
+        block_on(async move {
+            match fetch_page(&provider(), &channel, &HistoryCursor::Before(oldest_id), MESSAGE_PAGE_SIZE).await {
+                Ok(page) => {
+                    has_older.set(is_full_page(page.len()));
+                    if let Some(list) = messages.write().as_mut() {
+                        append_older(list, page);
                     }
-                    Err(e) => {
-                        send_error.set(Some(e.to_string()));
-                        info!("Messages update failed: {}", e);
+                }
+                Err(e) => {
+                    send_error.set(Some(e.to_string()));
+                    info!("Loading older messages failed: {}", e);
+                }
+            }
+        });
+    };
+
+    // Scrolling to the top of the list loads the page before it
+    let mut loading_older = use_signal(|| false);
+    let handle_messages_scroll = move |_| {
+        if !has_older() || loading_older() {
+            return;
+        }
+        loading_older.set(true);
+
+        spawn(async move {
+            // Scroll events don't carry the position, the webview has it
+            if let Ok(Value::Bool(true)) = eval(MESSAGES_AT_TOP_JS).join().await {
+                handle_load_older();
+            }
+            loading_older.set(false);
+        });
+    };
+
+    let handle_send_message = move || {
+        let Some(channel) = current_channel() else { return };
+
+        // Check if the attachment_input contains data
+        let attachment = if attachment_input.is_empty() {
+            None
+        } else {
+            Some(OutgoingAttachment {
+                filename: attachment_name(),
+                data: attachment_input(),
+            })
+        };
+        let outgoing = OutgoingMessage {
+            content: message_input(),
+            reply_to: replying_to(),
+            attachment,
+            ..Default::default()
+        };
+
+        block_on(async move {
+            match provider().send_message(&channel, &outgoing).await {
+                Ok(()) => {
+                    info!("Message sent successfully");
+                }
+                Err(e) => {
+                    send_error.set(Some(e.to_string()));
+                    info!("Message send failed: {}", e);
+                }
+            }
+
+            // Fetch new messages regardless of success or failure in sending the message
+            match catch_up(&provider(), &channel, messages).await {
+                Ok(reloaded) => {
+                    if reloaded {
+                        has_older.set(true);
                     }
+                    info!("Messages update successful");
                 }
+                Err(e) => {
+                    send_error.set(Some(e.to_string()));
+                    info!("Messages update failed: {}", e);
+                }
+            }
+
+            // Clear the attachment input and name after sending the message
+            replying_to.set(None);
+            attachment_input.set(Vec::new()); // Assuming attachment_input is a Vec<u8> signal
+            attachment_name.set(String::new()); // Assuming attachment_name is a String signal
+        });
+    };
     
-                // Clear emoji and message ID after sending reaction and fetching messages
-                reaction_input.set(String::new());
-                message_id_input.set(String::new());
+
+    // Reacts to the picked message, or takes the reaction back when it's already ours
+    let handle_toggle_reaction = move || {
+        let Some(channel) = current_channel() else { return };
+        let Some(message) = shown_message(&message_id_input.peek()) else { return };
+        let emoji = reaction_input.peek().clone();
+        let own_reaction = message.reactions.iter().any(|reaction| reaction.key == emoji && reaction.me);
+
+        block_on(async move {
+            let toggled = if own_reaction {
+                provider().remove_reaction(&channel, &message, &emoji).await
             } else {
-                // Handle the case where the lock could not be acquired
-                info!("Failed to acquire user lock; skipping reaction send.");
+                provider().add_reaction(&channel, &message, &emoji).await
+            };
+            match toggled {
+                Ok(()) => {
+                    info!("Reaction {} successfully", if own_reaction { "removed" } else { "sent" });
+                }
+                Err(e) => {
+                    send_error.set(Some(e.to_string()));
+                    info!("Reaction update failed: {}", e);
+                }
+            }
+
+            // Refetch only the reacted message, the loaded history stays as it is
+            match refresh_message(&provider(), &channel, &message.id, messages).await {
+                Ok(()) => {
+                    info!("Messages update successful");
+                }
+                Err(e) => {
+                    send_error.set(Some(e.to_string()));
+                    info!("Messages update failed: {}", e);
+                }
+            }
+
+            // Clear emoji and message ID after sending reaction and fetching messages
+            reaction_input.set(String::new());
+            message_id_input.set(String::new());
+        });
+    };
+
+    // Who reacted with an emoji to the picked message, as (emoji shown, user names)
+    let mut reaction_users = use_signal(|| None::<(String, Vec<String>)>);
+    use_effect(move || {
+        message_id_input();
+        reaction_users.set(None);
+    });
+
+    let handle_show_reaction_users = move |reaction: UnifiedReaction| {
+        let Some(channel) = current_channel() else { return };
+        let Some(message) = shown_message(&message_id_input()) else { return };
+
+        block_on(async move {
+            match provider().reaction_users(&channel, &message, &reaction).await {
+                Ok(names) => {
+                    reaction_users.set(Some((reaction.emoji, names)));
+                }
+                Err(e) => {
+                    send_error.set(Some(e.to_string()));
+                    info!("Loading who reacted failed: {}", e);
+                }
             }
         });
     };
     
 
-    // Coroutine for fetching messages periodically
-    let _fetch_messages = use_coroutine::<EmptyStruct, _, _>(|_rx| {
-        let user_lock_api = user_lock_api.clone();
-        let current_channel_id = current_channel_id.clone();
-        let mut messages = messages.clone();
+    // Apply gateway events to the open channel instead of polling it
+    let gateway_events = use_context::<broadcast::Sender<GatewayEvent>>();
+    let _live_messages = use_coroutine::<EmptyStruct, _, _>(|_rx| {
+        let mut events = gateway_events.subscribe();
 
         async move {
             loop {
-                // Wait for 5 seconds before fetching new messages
-                tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+                let event = match events.recv().await {
+                    Ok(event) => event,
+                    // Missed some events, the refetch below catches up
+                    Err(broadcast::error::RecvError::Lagged(_)) => GatewayEvent::Ready { user_id: String::new() },
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
 
-                if show_channel_messages_pane() && show_discord_server_pane() {
-                    // Attempt to acquire the lock without blocking
-                    if let Ok(user_lock_api) = user_lock_api.try_lock() {
-                        let discord_token = user_lock_api.discord.token.clone();
-                        
-                        match get_messages(discord_token.to_string(), current_channel_id.to_string()).await {
-                            Ok(updated_messages) => {
-                                messages.set(Some(updated_messages)); // Update messages with the latest data
-                                info!("Messages updated successfully.");
+                if !(show_channel_messages_pane() && show_discord_server_pane()) {
+                    continue;
+                }
+                let Some(channel) = current_channel.peek().clone() else { continue };
+
+                match event {
+                    // Reconnected without resuming, messages may have been missed
+                    GatewayEvent::Ready { .. } => {
+                        // Not awaited by a click, so it can wait out the rate limit of a busy reconnect
+                        match discord_http::patiently(catch_up(&provider(), &channel, messages)).await {
+                            Ok(reloaded) => {
+                                if reloaded {
+                                    has_older.set(true);
+                                }
+                                info!("Messages resynced after gateway reconnect.");
                             }
                             Err(e) => {
-                                info!("Failed to fetch updated messages: {}", e);
+                                info!("Failed to resync messages: {}", e);
                             }
                         }
-                    } else {
-                        // Log if the lock could not be acquired
-                        info!("Unable to acquire user lock; skipping message fetch for this cycle.");
+                    }
+                    event if event.channel_id() != Some(channel.id.as_str()) => {}
+                    event => {
+                        if let Some(list) = messages.write().as_mut() {
+                            apply_gateway_event(list, event);
+                        }
                     }
                 }
             }
@@ -567,7 +928,16 @@ fn ChannelMessages(user: Signal<Arc<Mutex<User>>>, messages: Signal<Option<Value
                 // Make the div take up the full width and be clickable
                 style: "width: 100%; cursor: pointer; padding: 10px 20px; text-align: center;",
                 onclick: move |_| { show_channel_messages_pane.set(false); },
-                h2 {class: "discord-heading", "Messages"}
+                h2 {class: "discord-heading", "{title}"}
+            }
+            if let Some((parent, parent_title, thread_id)) = thread_parent() {
+                if current_channel().is_some_and(|channel| channel.id == thread_id) {
+                    button {
+                        class: "thread-back-button",
+                        onclick: move |_| handle_open_channel(parent.clone(), parent_title.clone()),
+                        "← Back to {parent_title}"
+                    }
+                }
             }
             
             button {
@@ -588,12 +958,14 @@ fn ChannelMessages(user: Signal<Arc<Mutex<User>>>, messages: Signal<Option<Value
             }
             if let Some(messages_data) = messages() {
                 ul {
+                    id: "discord-messages-list",
                     class: "messages-list",
-                    for message in messages_data.as_array().unwrap_or(&vec![]) {
+                    onscroll: handle_messages_scroll,
+                    for message in messages_data {
                         li {
                             class: "messages-item",
                             onclick: {
-                                let current_message_id = message["id"].to_string().clone();
+                                let current_message_id = message.id.clone();
                                 move |_| {
                                     message_id_input.set(current_message_id.clone());
                                 }
@@ -603,136 +975,224 @@ fn ChannelMessages(user: Signal<Arc<Mutex<User>>>, messages: Signal<Option<Value
                                 img {
                                     class: "message-avatar",
                                     src: { 
-                                        if let Some(avatar) = message["author"]["avatar"].as_str() {
-                                            format!("https://cdn.discordapp.com/avatars/{}/{}.webp", message["author"]["id"].as_str().unwrap(), avatar)
-                                        } else {
-                                            "assets/defaultpfp.png".to_string() // Path to your default avatar image
-                                        }
+                                        message.author.avatar_url.clone()
+                                            .unwrap_or("assets/defaultpfp.png".to_string()) // Path to your default avatar image
                                     },
                                     alt: "User Avatar"
                                 }
                                 span {
                                     class: "message-username",
-                                    {message["author"]["username"].as_str().unwrap_or("Unknown User")}
+                                    {message.author.name.clone()}
                                 }
                                 span {
                                     class: "message-date",
-                                    {format_timestamp(message["timestamp"].as_str().unwrap_or(""))}
+                                    {format_timestamp(&message.timestamp)}
+                                }
+                                if message.edited {
+                                    span { class: "message-date", "(edited)" }
+                                }
+                                span {
+                                    class: "message-actions",
+                                    button {
+                                        class: "message-action-button",
+                                        onclick: {
+                                            let replied = message.clone();
+                                            move |evt: Event<MouseData>| {
+                                                // Don't open the reaction picker
+                                                evt.stop_propagation();
+                                                replying_to.set(Some(replied.clone()));
+                                            }
+                                        },
+                                        "Reply"
+                                    }
+                                    if message.author.id == own_user_id() {
+                                        button {
+                                            class: "message-action-button",
+                                            onclick: {
+                                                let message_id = message.id.clone();
+                                                let body = message.body.clone();
+                                                move |evt: Event<MouseData>| {
+                                                    // Don't open the reaction picker
+                                                    evt.stop_propagation();
+                                                    edit_input.set(body.clone());
+                                                    editing_message_id.set(Some(message_id.clone()));
+                                                    deleting_message_id.set(None);
+                                                }
+                                            },
+                                            "Edit"
+                                        }
+                                        button {
+                                            class: "message-action-button",
+                                            onclick: {
+                                                let message_id = message.id.clone();
+                                                move |evt: Event<MouseData>| {
+                                                    evt.stop_propagation();
+                                                    deleting_message_id.set(Some(message_id.clone()));
+                                                    editing_message_id.set(None);
+                                                }
+                                            },
+                                            "Delete"
+                                        }
+                                    }
+                                }
+                            }
+                            if let Some(preview) = message.reply_preview.clone() {
+                                div {
+                                    class: "message-reply-preview",
+                                    span { class: "message-reply-author", "↪ {preview.author}" }
+                                    span { class: "message-reply-body", "{preview.body}" }
+                                }
+                            } else if message.reply_to_id.is_some() {
+                                div {
+                                    class: "message-reply-preview",
+                                    span { class: "message-reply-body", "↪ Original message was deleted" }
                                 }
                             }
                             div {
                                 class: "message-content",
-                                // Display the message content
-                                {message["content"].as_str().unwrap_or("Failed to display message.")}
+                                if editing_message_id().as_deref() == Some(message.id.as_str()) {
+                                    div {
+                                        class: "message-edit",
+                                        onclick: move |evt| evt.stop_propagation(),
+                                        input {
+                                            class: "message-input-box",
+                                            value: "{edit_input}",
+                                            oninput: move |event| edit_input.set(event.value())
+                                        }
+                                        button {
+                                            class: "message-action-button",
+                                            onclick: move |_| handle_edit_message(),
+                                            "Save"
+                                        }
+                                        button {
+                                            class: "message-action-button",
+                                            onclick: move |_| editing_message_id.set(None),
+                                            "Cancel"
+                                        }
+                                    }
+                                } else {
+                                    // Display the message content
+                                    {message.body.clone()}
+                                }
+                                if deleting_message_id().as_deref() == Some(message.id.as_str()) {
+                                    div {
+                                        class: "message-edit",
+                                        onclick: move |evt| evt.stop_propagation(),
+                                        span { "Delete this message?" }
+                                        button {
+                                            class: "message-action-button",
+                                            onclick: move |_| handle_delete_message(),
+                                            "Delete"
+                                        }
+                                        button {
+                                            class: "message-action-button",
+                                            onclick: move |_| deleting_message_id.set(None),
+                                            "Cancel"
+                                        }
+                                    }
+                                }
             
                                 // Check if the message has attachments and render them
                                 div {
                                     style: "display: flex; justify-content: center; align-items: center;",
-                                    if let Some(attachments) = message["attachments"].as_array() {
-                                        for attachment in attachments {
-                                            if let Some(content_type) = attachment["content_type"].as_str() {
+                                    for attachment in message.attachments.clone() {
+                                        {
+                                            let url = attachment.url.clone();
+                                            let filename = attachment.filename.clone();
+                                            let content_type = attachment.content_type.clone().unwrap_or_default();
+                                            rsx! {
                                                 if content_type.starts_with("image/") {
                                                     // Display image attachments
-                                                    if let Some(url) = attachment["url"].as_str() {
-                                                        img {
-                                                            src: "{url}",
-                                                            style: "max-height: 25vh; display: block; margin-top: 10px;"
-                                                        }
+                                                    img {
+                                                        src: "{url}",
+                                                        style: "max-height: 25vh; display: block; margin-top: 10px;"
                                                     }
                                                 } else if content_type.starts_with("video/") {
                                                     // Display video attachments
-                                                    if let Some(url) = attachment["url"].as_str() {
-                                                        video {
-                                                            src: "{url}",
-                                                            controls: true,    // Enable controls
-                                                            autoplay: false,    // Enable autoplay
-                                                            muted: true,   
-                                                            height: "30%", // Adjust width as needed
-                                                            style: "display: block; margin: 10px auto;", // Center the video if needed
-                                                        }
+                                                    video {
+                                                        src: "{url}",
+                                                        controls: true,    // Enable controls
+                                                        autoplay: false,    // Enable autoplay
+                                                        muted: true,   
+                                                        height: "30%", // Adjust width as needed
+                                                        style: "display: block; margin: 10px auto;", // Center the video if needed
                                                     }
                                                 } else if content_type.starts_with("audio/") {
                                                     // Display audio attachments
-                                                    if let Some(url) = attachment["url"].as_str() {
-                                                        audio {
-                                                            src: "{url}",
-                                                            controls: true,    // Enable controls
-                                                            autoplay: false,   // Set to true if you want autoplay
-                                                            style: "display: block; margin: 10px auto;", // Center the audio if needed
-                                                            // Fallback message if the audio cannot be loaded
-                                                            p { "Your browser does not support the audio tag." }
-                                                        }
+                                                    audio {
+                                                        src: "{url}",
+                                                        controls: true,    // Enable controls
+                                                        autoplay: false,   // Set to true if you want autoplay
+                                                        style: "display: block; margin: 10px auto;", // Center the audio if needed
+                                                        // Fallback message if the audio cannot be loaded
+                                                        p { "Your browser does not support the audio tag." }
                                                     }
                                                 } else {
                                                     // Display the file name and a download icon for other types of files
-                                                    if let Some(filename) = attachment["filename"].as_str() {
-                                                        if let Some(url) = attachment["url"].as_str() {
-                                                            a {
-                                                                href: "{url}", // Make the whole div clickable
-                                                                class: "file-attachment-other-container", // Center and align the content
-                                                                svg {
-                                                                    view_box: "0 0 24 24",
-                                                                    width: "40px",
-                                                                    height: "40px",
-                                                                    fill: "none",
-                                                                    xmlns: "http://www.w3.org/2000/svg",
-                                                                    stroke: "#ffffff",
-                                                                    g {
-                                                                        id: "SVGRepo_bgCarrier",
-                                                                        stroke_width: "0",
-                                                                    }
-                                                                    g {
-                                                                        id: "SVGRepo_tracerCarrier",
-                                                                        stroke_linecap: "round",
-                                                                        stroke_linejoin: "round",
-                                                                    }
-                                                                    g {
-                                                                        id: "SVGRepo_iconCarrier",
-                                                                        path {
-                                                                            d: "M19 9V17.8C19 18.9201 19 19.4802 18.782 19.908C18.5903 20.2843 18.2843 20.5903 17.908 20.782C17.4802 21 16.9201 21 15.8 21H8.2C7.07989 21 6.51984 21 6.09202 20.782C5.71569 20.5903 5.40973 20.2843 5.21799 19.908C5 19.4802 5 18.9201 5 17.8V6.2C5 5.07989 5 4.51984 5.21799 4.09202C5.40973 3.71569 5.71569 3.40973 6.09202 3.21799C6.51984 3 7.0799 3 8.2 3H13M19 9L13 3M19 9H14C13.4477 9 13 8.55228 13 8V3",
-                                                                            stroke: "#f5f5f5",
-                                                                            stroke_width: "1.176",
-                                                                            stroke_linecap: "round",
-                                                                            stroke_linejoin: "round",
-                                                                        }
-                                                                    }
+                                                    a {
+                                                        href: "{url}", // Make the whole div clickable
+                                                        class: "file-attachment-other-container", // Center and align the content
+                                                        svg {
+                                                            view_box: "0 0 24 24",
+                                                            width: "40px",
+                                                            height: "40px",
+                                                            fill: "none",
+                                                            xmlns: "http://www.w3.org/2000/svg",
+                                                            stroke: "#ffffff",
+                                                            g {
+                                                                id: "SVGRepo_bgCarrier",
+                                                                stroke_width: "0",
+                                                            }
+                                                            g {
+                                                                id: "SVGRepo_tracerCarrier",
+                                                                stroke_linecap: "round",
+                                                                stroke_linejoin: "round",
+                                                            }
+                                                            g {
+                                                                id: "SVGRepo_iconCarrier",
+                                                                path {
+                                                                    d: "M19 9V17.8C19 18.9201 19 19.4802 18.782 19.908C18.5903 20.2843 18.2843 20.5903 17.908 20.782C17.4802 21 16.9201 21 15.8 21H8.2C7.07989 21 6.51984 21 6.09202 20.782C5.71569 20.5903 5.40973 20.2843 5.21799 19.908C5 19.4802 5 18.9201 5 17.8V6.2C5 5.07989 5 4.51984 5.21799 4.09202C5.40973 3.71569 5.71569 3.40973 6.09202 3.21799C6.51984 3 7.0799 3 8.2 3H13M19 9L13 3M19 9H14C13.4477 9 13 8.55228 13 8V3",
+                                                                    stroke: "#f5f5f5",
+                                                                    stroke_width: "1.176",
+                                                                    stroke_linecap: "round",
+                                                                    stroke_linejoin: "round",
                                                                 }
-                                                                p {
-                                                                    style: "margin-left: 5px; text-align: center;", // Center text
-                                                                    "{filename}" // Display the file name
+                                                            }
+                                                        }
+                                                        p {
+                                                            style: "margin-left: 5px; text-align: center;", // Center text
+                                                            "{filename}" // Display the file name
+                                                        }
+                                                        svg {
+                                                            style: "margin-left: 20px; margin-right: 5px",
+                                                            view_box: "0 0 24 24",
+                                                            width: "20px",
+                                                            height: "20px",
+                                                            fill: "none",
+                                                            xmlns: "http://www.w3.org/2000/svg",
+                                                            g {
+                                                                id: "SVGRepo_bgCarrier",
+                                                                stroke_width: "0",
+                                                            }
+                                                            g {
+                                                                id: "SVGRepo_tracerCarrier",
+                                                                stroke_linecap: "round",
+                                                                stroke_linejoin: "round",
+                                                            }
+                                                            g {
+                                                                id: "SVGRepo_iconCarrier",
+                                                                path {
+                                                                    d: "M8 22.0002H16C18.8284 22.0002 20.2426 22.0002 21.1213 21.1215C22 20.2429 22 18.8286 22 16.0002V15.0002C22 12.1718 22 10.7576 21.1213 9.8789C20.3529 9.11051 19.175 9.01406 17 9.00195M7 9.00195C4.82497 9.01406 3.64706 9.11051 2.87868 9.87889C2 10.7576 2 12.1718 2 15.0002L2 16.0002C2 18.8286 2 20.2429 2.87868 21.1215C3.17848 21.4213 3.54062 21.6188 4 21.749",
+                                                                    stroke: "#f5f5f5",
+                                                                    stroke_width: "1.5",
+                                                                    stroke_linecap: "round",
                                                                 }
-                                                                svg {
-                                                                    style: "margin-left: 20px; margin-right: 5px",
-                                                                    view_box: "0 0 24 24",
-                                                                    width: "20px",
-                                                                    height: "20px",
-                                                                    fill: "none",
-                                                                    xmlns: "http://www.w3.org/2000/svg",
-                                                                    g {
-                                                                        id: "SVGRepo_bgCarrier",
-                                                                        stroke_width: "0",
-                                                                    }
-                                                                    g {
-                                                                        id: "SVGRepo_tracerCarrier",
-                                                                        stroke_linecap: "round",
-                                                                        stroke_linejoin: "round",
-                                                                    }
-                                                                    g {
-                                                                        id: "SVGRepo_iconCarrier",
-                                                                        path {
-                                                                            d: "M8 22.0002H16C18.8284 22.0002 20.2426 22.0002 21.1213 21.1215C22 20.2429 22 18.8286 22 16.0002V15.0002C22 12.1718 22 10.7576 21.1213 9.8789C20.3529 9.11051 19.175 9.01406 17 9.00195M7 9.00195C4.82497 9.01406 3.64706 9.11051 2.87868 9.87889C2 10.7576 2 12.1718 2 15.0002L2 16.0002C2 18.8286 2 20.2429 2.87868 21.1215C3.17848 21.4213 3.54062 21.6188 4 21.749",
-                                                                            stroke: "#f5f5f5",
-                                                                            stroke_width: "1.5",
-                                                                            stroke_linecap: "round",
-                                                                        }
-                                                                        path {
-                                                                            d: "M12 2L12 15M12 15L9 11.5M12 15L15 11.5",
-                                                                            stroke: "#f5f5f5",
-                                                                            stroke_width: "1.5",
-                                                                            stroke_linecap: "round",
-                                                                            stroke_linejoin: "round",
-                                                                        }
-                                                                    }
+                                                                path {
+                                                                    d: "M12 2L12 15M12 15L9 11.5M12 15L15 11.5",
+                                                                    stroke: "#f5f5f5",
+                                                                    stroke_width: "1.5",
+                                                                    stroke_linecap: "round",
+                                                                    stroke_linejoin: "round",
                                                                 }
                                                             }
                                                         }
@@ -743,36 +1203,88 @@ fn ChannelMessages(user: Signal<Arc<Mutex<User>>>, messages: Signal<Option<Value
                                     }
                                 }
                             }
+                            // Discord gives a thread the id of the message it was started from
+                            if message.has_thread {
+                                button {
+                                    class: "thread-toggle-button",
+                                    onclick: {
+                                        let thread_id = message.id.clone();
+                                        move |evt: Event<MouseData>| {
+                                            evt.stop_propagation();
+                                            let Some(channel) = current_channel() else { return };
+                                            let thread = ChatChannel {
+                                                id: thread_id.clone(),
+                                                name: "Thread".to_string(),
+                                                kind: ChannelKind::Thread { archived: false },
+                                                last_activity: None,
+                                                ..channel.clone()
+                                            };
+                                            thread_parent.set(Some((channel, title(), thread_id.clone())));
+                                            handle_open_channel(thread, "Thread".to_string());
+                                        }
+                                    },
+                                    if message.reply_count > 0 {
+                                        {format!("View thread ({})", message.reply_count)}
+                                    } else {
+                                        "View thread"
+                                    }
+                                }
+                            }
                             div {
                                 class: "reactions",
-                                if let Some(reactions) = message["reactions"].as_array() {
-                                    for reaction in reactions {
-                                        span {
-                                            class: "reaction",
-                                            onclick: { 
-                                                let reaction_emoji = reaction["emoji"]["name"].to_string().clone();
-                                                let current_message_id = message["id"].to_string().clone();
-                                                // println!("reaction input from div {}", reaction_input);
-                                                move |_|{
-                                                    reaction_input.set(reaction_emoji.clone());
-                                                    message_id_input.set(current_message_id.clone());
-                                                    handle_send_reaction(Arc::clone(&user())) 
-                                                }
-                                            },
-                                            {
-                                                reaction["emoji"]["name"].as_str().unwrap_or("")
-                                            }
-                                            {
-                                                {" ".to_string() + &reaction["count"].as_u64().unwrap_or(0).to_string()}
+                                for reaction in message.reactions.clone() {
+                                    span {
+                                        class: format_args!("reaction {}", if reaction.me { "own" } else { "" }),
+                                        title: if reaction.me { "Click to remove your reaction" } else { "Click to react" },
+                                        onclick: { 
+                                            let reaction_emoji = reaction.key.clone();
+                                            let current_message_id = message.id.clone();
+                                            move |evt: Event<MouseData>| {
+                                                // Don't open the reaction picker
+                                                evt.stop_propagation();
+                                                reaction_input.set(reaction_emoji.clone());
+                                                message_id_input.set(current_message_id.clone());
+                                                handle_toggle_reaction()
                                             }
+                                        },
+                                        if let Some(image_url) = reaction.image_url.clone() {
+                                            img { class: "custom-emoji", src: "{image_url}", alt: "{reaction.emoji}" }
+                                        } else {
+                                            {reaction.emoji.clone()}
+                                        }
+                                        {
+                                            {" ".to_string() + &reaction.count.to_string()}
                                         }
                                     }
                                 }
                             }
                         }
                     }
+                    // Last in the list, so shown above the oldest message. Scrolling up loads
+                    // older messages too, the button is there when the list can't scroll yet.
+                    if has_older() {
+                        li {
+                            class: "load-older-item",
+                            button {
+                                class: "load-older-button",
+                                onclick: move |_| handle_load_older(),
+                                "Load older messages"
+                            }
+                        }
+                    }
                 }
                 div {
+                    if let Some(replied) = replying_to() {
+                        div {
+                            class: "reply-bar",
+                            span { "Replying to {replied.author.name}" }
+                            button {
+                                class: "message-action-button",
+                                onclick: move |_| replying_to.set(None),
+                                "✕"
+                            }
+                        }
+                    }
                     div {
                         class: format_args!("file-name-display {}", if !attachment_name().is_empty() { "show" } else { "" }),
 
@@ -878,7 +1390,7 @@ fn ChannelMessages(user: Signal<Arc<Mutex<User>>>, messages: Signal<Option<Value
                         }
                         button {  
                             class: "send-button", 
-                            onclick: move |_| handle_send_message(Arc::clone(&user())),
+                            onclick: move |_| handle_send_message(),
                             div {
                                 svg {
                                     view_box: "0 0 24 24",
@@ -932,47 +1444,56 @@ fn ChannelMessages(user: Signal<Arc<Mutex<User>>>, messages: Signal<Option<Value
                         }
 
                         // Emojis
-                        button {
-                            class: "reaction-picker-item",
-                            onclick: {
-                                move |_|{
-                                    reaction_input.set("👍".to_string());
-                                    handle_send_reaction(Arc::clone(&user()));
-                                }
-                            },
-                            "👍"  // Thumbs up emoji
-                        }
-                        button {
-                            class: "reaction-picker-item",
-                            onclick: move |_| {
-                                reaction_input.set("👎".to_string());
-                                handle_send_reaction(Arc::clone(&user()));
-                            },
-                            "👎"
-                        }
-                        button {
-                            class: "reaction-picker-item",
-                            onclick: move |_| {
-                                reaction_input.set("❤️".to_string());
-                                handle_send_reaction(Arc::clone(&user()));
-                            },
-                            "❤️"
+                        for emoji in STANDARD_REACTIONS {
+                            button {
+                                class: "reaction-picker-item",
+                                onclick: move |_| {
+                                    reaction_input.set(emoji.to_string());
+                                    handle_toggle_reaction();
+                                },
+                                "{emoji}"
+                            }
                         }
-                        button {
-                            class: "reaction-picker-item",
-                            onclick: move |_| {
-                                reaction_input.set("❗".to_string());
-                                handle_send_reaction(Arc::clone(&user()));
-                            },
-                            "❗"
+                        // The server's own emoji
+                        for emoji in emojis() {
+                            button {
+                                class: "reaction-picker-item",
+                                title: emoji.label(),
+                                onclick: {
+                                    let key = emoji.key();
+                                    move |_| {
+                                        reaction_input.set(key.clone());
+                                        handle_toggle_reaction();
+                                    }
+                                },
+                                img { class: "custom-emoji", src: emoji.image_url().unwrap_or_default(), alt: emoji.label() }
+                            }
                         }
-                        button {
-                            class: "reaction-picker-item",
-                            onclick: move |_| {
-                                reaction_input.set("❓".to_string());
-                                handle_send_reaction(Arc::clone(&user()));
-                            },
-                            "❓"
+
+                        // Who reacted to the picked message
+                        div {
+                            class: "reaction-users",
+                            for reaction in messages().unwrap_or_default().into_iter().find(|message| message.id == message_id_input()).map(|message| message.reactions).unwrap_or_default() {
+                                button {
+                                    class: "message-action-button",
+                                    onclick: {
+                                        let reaction = reaction.clone();
+                                        move |_| handle_show_reaction_users(reaction.clone())
+                                    },
+                                    if let Some(image_url) = reaction.image_url.clone() {
+                                        img { class: "custom-emoji", src: "{image_url}", alt: "{reaction.emoji}" }
+                                    } else {
+                                        {reaction.emoji.clone()}
+                                    }
+                                    " Who reacted?"
+                                }
+                            }
+                            if let Some((label, names)) = reaction_users() {
+                                p {
+                                    class: "reaction-users-list",
+                                    "{label} from {names.join(\", \")}"
+                                }
+                            }
                         }
                     }
                 }
@@ -981,10 +1502,96 @@ fn ChannelMessages(user: Signal<Arc<Mutex<User>>>, messages: Signal<Option<Value
     }
 }
 
-fn format_timestamp(timestamp: &str) -> String {
-    // Parse the timestamp string into a DateTime object
-    let parsed_timestamp = DateTime::parse_from_rfc3339(timestamp).unwrap_or_else(|_| Utc::now().into());
-    
+// FUNCTION: Applies a live event to a newest-first message list
+fn apply_gateway_event(messages: &mut Vec<UnifiedMessage>, event: GatewayEvent) {
+    match event {
+        GatewayEvent::MessageCreate(message) => {
+            // Our own sends are refetched as well, don't show them twice
+            if !messages.iter().any(|m| m.id == message.id) {
+                messages.insert(0, message);
+            }
+        }
+        GatewayEvent::MessageUpdate(message) => {
+            if let Some(existing) = messages.iter_mut().find(|m| m.id == message.id) {
+                *existing = message;
+            }
+        }
+        GatewayEvent::MessageDelete { message_id, .. } => {
+            messages.retain(|m| m.id != message_id);
+        }
+        GatewayEvent::ReactionAdd { message_id, emoji, user_id, me, .. } => {
+            if let Some(message) = messages.iter_mut().find(|m| m.id == message_id) {
+                message.reaction_added(UnifiedReaction {
+                    emoji: emoji.label(),
+                    key: emoji.key(),
+                    image_url: emoji.image_url(),
+                    count: 1,
+                    me,
+                    users: vec![user_id],
+                });
+            }
+        }
+        GatewayEvent::ReactionRemove { message_id, emoji, user_id, me, .. } => {
+            if let Some(message) = messages.iter_mut().find(|m| m.id == message_id) {
+                message.reaction_removed(&emoji.key(), &user_id, me);
+            }
+        }
+        GatewayEvent::Ready { .. } => {}
+    }
+}
+
+// FUNCTION: Whether the active account was linked before its user id was kept
+fn account_needs_user_id(user_lock: &Arc<Mutex<User>>) -> bool {
+    let user = block_on(user_lock.lock());
+    let account = user.discord.active();
+    account.is_linked() && account.user_id.is_empty()
+}
+
+// FUNCTION: Reads a page of a channel newest first, the order the message lists are kept in
+async fn fetch_page(provider: &DiscordProvider, channel: &ChatChannel, cursor: &HistoryCursor, limit: usize) -> Result<Vec<UnifiedMessage>, Box<dyn Error>> {
+    let mut page = provider.fetch_history(channel, cursor, limit).await?;
+    page.reverse();
+    Ok(page)
+}
+
+// FUNCTION: Refetches one shown message, e.g. after editing or reacting to it
+async fn refresh_message(provider: &DiscordProvider, channel: &ChatChannel, message_id: &str, mut messages: Signal<Option<Vec<UnifiedMessage>>>) -> Result<(), Box<dyn Error>> {
+    let page = fetch_page(provider, channel, &HistoryCursor::Around(message_id.to_string()), 1).await?;
+    if let Some(list) = messages.write().as_mut() {
+        refresh_shown(list, page);
+    }
+    Ok(())
+}
+
+// FUNCTION: Adds the messages posted after the newest one shown. Returns true when more were
+// missed than fit in a page, then the latest page replaces the list.
+async fn catch_up(provider: &DiscordProvider, channel: &ChatChannel, mut messages: Signal<Option<Vec<UnifiedMessage>>>) -> Result<bool, Box<dyn Error>> {
+    let newest_id = messages.peek().as_ref().and_then(|list| list.first()).map(|message| message.id.clone());
+
+    if let Some(newest_id) = newest_id {
+        let page = fetch_page(provider, channel, &HistoryCursor::After(newest_id), MESSAGE_PAGE_SIZE).await?;
+        if !is_full_page(page.len()) {
+            if let Some(list) = messages.write().as_mut() {
+                merge_newer(list, page);
+            }
+            return Ok(false);
+        }
+    }
+
+    let latest = fetch_page(provider, channel, &HistoryCursor::Latest, MESSAGE_PAGE_SIZE).await?;
+    messages.set(Some(latest));
+    Ok(true)
+}
+
+// FUNCTION: Heading of the message pane for a channel, thread or forum post
+fn channel_heading(channel: &ChatChannel) -> String {
+    match channel.kind {
+        ChannelKind::Thread { .. } => format!("Thread: {}", channel.name),
+        _ => format!("# {}", channel.name),
+    }
+}
+
+fn format_timestamp(timestamp: &DateTime<Utc>) -> String {
     // Format the date into a readable format, e.g., "Sep 26, 2024 12:45 PM"
-    parsed_timestamp.format("%b %d, %Y %I:%M %p").to_string()
+    timestamp.format("%b %d, %Y %I:%M %p").to_string()
 }
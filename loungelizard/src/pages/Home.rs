@@ -2,16 +2,20 @@
 
 use dioxus::prelude::*;
 
-use serde_json::Value;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use futures::executor::block_on;
 use crate::api::slack::server_utils::setup_server::*;
 use crate::api::ms_teams::ms_teams_app_setup::dummy_token_check;
+use crate::api::ms_teams::ms_teams_tokens::TeamsTokens;
+use crate::api::slack::token_rotation::*;
+use clipboard_rs::{Clipboard, ClipboardContext};
 use dioxus_logger::tracing::{info, error, warn};
-use mongodb::{sync::Client, bson::doc};
+use mongodb::bson::doc;
 use bson::to_bson;
 use crate::api::mongo_format::mongo_funcs::*;
+use crate::api::mongo_format::user_store::*;
+use crate::api::chat_provider::{ChatChannel, ChatWorkspace};
 
 
 
@@ -24,6 +28,7 @@ use crate::logins::Home::* ;
 use crate::pages::Discord::Discord as Discord_p;
 use crate::pages::Slack::Slack as Slack_p;
 use crate::pages::MSTeam::MSTeams as MSTeams_p;
+use crate::pages::Inbox::Inbox as Inbox_p;
 
 // Api mongo structs
 use crate::api::mongo_format::mongo_structs::*;
@@ -32,7 +37,7 @@ use crate::api::mongo_format::mongo_structs::*;
 pub fn Home() -> Element {
    // ! User Mutex Lock to access the user data
    let user_lock = use_context::<Signal<Arc<Mutex<User>>>>();
-   let client_lock = use_context::<Signal<Arc<Mutex<Option<Client>>>>>();
+   let user_store = use_context::<Signal<Arc<dyn UserStore>>>();
    // ! ========================= ! //
 
    let user_lock_clone_slack = Arc::clone(&user_lock());
@@ -43,7 +48,7 @@ pub fn Home() -> Element {
     let mut show_discord_login_pane = use_signal(|| false);
     let mut show_discord_server_pane = use_signal(|| false);
     let discord_token = use_signal(|| "".to_string());
-    let discord_guilds = use_signal(|| Value::Null);
+    let discord_guilds = use_signal(Vec::<ChatWorkspace>::new);
 
 
     // Slack Values
@@ -60,6 +65,54 @@ pub fn Home() -> Element {
 
     let mut current_platform = use_signal(|| "None".to_string());
 
+    // Channel picked in the inbox, consumed by the platform page once it opens it
+    let pending_channel = use_signal(|| None::<ChatChannel>);
+    use_context_provider(|| pending_channel);
+
+    // Slack tokens the refresher could not rotate, shown until fixed
+    let mut slack_token_failures = use_signal(|| Vec::<RotationFailure>::new());
+
+    let user_lock_rotation = Arc::clone(&user_lock());
+    use_future(move || {
+        let user_lock_rotation = user_lock_rotation.clone();
+        // Restarts on login, so the first check doesn't wait a full interval
+        let _ = logged_in();
+        async move {
+            loop {
+                let failures = rotate_due_tokens(&user_lock_rotation, &user_store()).await;
+                for failure in failures.iter() {
+                    warn!("Failed to rotate Slack tokens of {}: {}", failure.workspace, failure.error);
+                }
+                slack_token_failures.set(failures);
+                tokio::time::sleep(CHECK_INTERVAL).await;
+            }
+        }
+    });
+
+    // Linked accounts of the open platform for the switcher, and a counter
+    // that remounts the platform page when the account changes
+    let mut account_labels = use_signal(|| Vec::<String>::new());
+    let mut active_account = use_signal(|| 0usize);
+    let mut account_version = use_signal(|| 0usize);
+
+    let user_lock_accounts = Arc::clone(&user_lock());
+    use_effect(move || {
+        let platform = current_platform();
+        let _ = account_version();
+        let user_lock_accounts = user_lock_accounts.clone();
+        spawn(async move {
+            let user = user_lock_accounts.lock().await;
+            let (labels, active) = match platform.as_str() {
+                "Slack" => (user.slack.labels(), user.slack.active_index()),
+                "Discord" => (user.discord.labels(), user.discord.active_index()),
+                "MSTeams" => (user.ms_teams.labels(), user.ms_teams.active_index()),
+                _ => (Vec::new(), 0),
+            };
+            account_labels.set(labels);
+            active_account.set(active);
+        });
+    });
+
 
     let handle_discord_click = move |_| {
 
@@ -67,7 +120,7 @@ pub fn Home() -> Element {
             user_lock_clone_discord.lock().await
         });
 
-        if user.discord.token == ""{
+        if user.discord.active().token == ""{
             show_discord_login_pane.set(!show_discord_login_pane());
 
             // Set Other tokens to false
@@ -88,7 +141,7 @@ pub fn Home() -> Element {
         });
 
         // Check if the app id has been set, this means the user has logged in
-        if user.slack.app_id == "" {
+        if user.slack.active().app_id == "" {
             show_slack_login_pane.set(!show_slack_login_pane());
 
             // Set Other tokens to false
@@ -105,13 +158,16 @@ pub fn Home() -> Element {
             match result {
                 Ok(new_token_body) => {
                     if new_token_body.ok{
-                        user.slack.config_token = new_token_body.token;
-                        user.slack.refresh_token = new_token_body.refresh_token;
-                        // TODO Implement Refreshing after 12 hours
+                        user.slack.active_mut().config_token = new_token_body.token;
+                        user.slack.active_mut().refresh_token = new_token_body.refresh_token;
+                        // The refresher rotates it again before this
+                        user.slack.active_mut().config_token_exp = new_token_body.exp;
 
                         block_on(
                             async{
-                                update_slack(user.clone(), client_lock().clone()).await;
+                                if let Err(e) = update_user(&*user_store(), &mut user, &[UserField::Slack]).await {
+                                    error!("Failed to save the refreshed Slack token: {}", e);
+                                }
                             }
                         );
                     }
@@ -129,10 +185,14 @@ pub fn Home() -> Element {
 
     let handle_teams_click = move |_| {
 
-        let user = block_on(async {
+        let linked = block_on(async {
             user_lock_clone_teams.lock().await
-        });
-        if user.ms_teams.access_token.is_empty() || !block_on(dummy_token_check(&user.ms_teams.access_token)) {
+        }).ms_teams.active().is_linked();
+
+        // A token that expired since the last session is refreshed instead of
+        // asking for the login again
+        let access_token = block_on(TeamsTokens::new(user_lock_clone_teams.clone(), user_store()).access_token());
+        if !linked || !block_on(dummy_token_check(&access_token)) {
             show_teams_login_pane.set(true);
             show_teams_server_pane.set(false);
 
@@ -147,6 +207,89 @@ pub fn Home() -> Element {
         }
     };
 
+    let user_lock_switch = Arc::clone(&user_lock());
+    let handle_account_switch = move |event: Event<FormData>| {
+        let platform = current_platform();
+        let mut user = block_on(async {
+            user_lock_switch.lock().await
+        });
+
+        if event.value() == "add" {
+            // The login pane fills in the new account and saves it
+            match platform.as_str() {
+                "Slack" => {
+                    user.slack.add();
+                    show_slack_login_pane.set(true);
+                }
+                "Discord" => {
+                    user.discord.add();
+                    show_discord_server_pane.set(false);
+                    show_discord_login_pane.set(true);
+                }
+                "MSTeams" => {
+                    user.ms_teams.add();
+                    show_teams_server_pane.set(false);
+                    show_teams_login_pane.set(true);
+                }
+                _ => return,
+            }
+            current_platform.set("None".to_string());
+        }
+        else if let Ok(index) = event.value().parse::<usize>() {
+            let field = match platform.as_str() {
+                "Slack" if user.slack.switch_to(index) => UserField::Slack,
+                "Discord" if user.discord.switch_to(index) => UserField::Discord,
+                "MSTeams" if user.ms_teams.switch_to(index) => UserField::MSTeams,
+                _ => return,
+            };
+
+            // Remember the choice for the next login
+            if let Err(e) = block_on(update_user(&*user_store(), &mut user, &[field])) {
+                error!("Failed to save the active account: {}", e);
+            }
+        }
+
+        account_version += 1;
+    };
+
+    // A new configuration refresh token copied from api.slack.com/apps
+    let mut handle_slack_config_reauth = move |app_id: String| {
+        let refresh_token = ClipboardContext::new()
+            .and_then(|ctx| ctx.get_text())
+            .unwrap_or_default();
+
+        let result = block_on(replace_config_token(&user_lock(), &user_store(), &app_id, &refresh_token));
+        slack_token_failures.with_mut(|failures| match result {
+            Ok(()) => failures.retain(|failure| failure.app_id != app_id || failure.token != SlackToken::Config),
+            Err(e) => {
+                for failure in failures.iter_mut().filter(|failure| failure.app_id == app_id) {
+                    failure.error = e.to_string();
+                }
+            }
+        });
+    };
+
+    // Installing the app again happens on the active workspace
+    let mut handle_slack_reinstall = move |app_id: String| {
+        let user_lock_reinstall = user_lock();
+        let mut user = block_on(async {
+            user_lock_reinstall.lock().await
+        });
+        let index = user.slack.iter().position(|slack| slack.app_id == app_id);
+        if let Some(index) = index {
+            user.slack.switch_to(index);
+            account_version += 1;
+        }
+        current_platform.set("Slack".to_string());
+    };
+
+    let handle_inbox_click = move |_| {
+        show_discord_login_pane.set(false);
+        show_slack_login_pane.set(false);
+        show_teams_login_pane.set(false);
+        current_platform.set("Inbox".to_string());
+    };
+
 
     rsx! {
         div {
@@ -207,6 +350,33 @@ pub fn Home() -> Element {
                         onclick: handle_teams_click,
                     }
                 }
+
+                div {
+                    class: {
+                        format_args!("white-square {}",
+                            if current_platform() == "Inbox" && logged_in()
+                            { "opaque" }
+                            else
+                            { "transparent" })
+                    },
+                    div {
+                        class: "inbox-button",
+                        title: "Inbox",
+                        onclick: handle_inbox_click,
+                        svg {
+                            width: "34",
+                            height: "34",
+                            view_box: "0 0 24 24",
+                            fill: "none",
+                            stroke: "currentColor",
+                            stroke_width: "2",
+                            stroke_linecap: "round",
+                            stroke_linejoin: "round",
+                            polyline { points: "22 12 16 12 14 15 10 15 8 12 2 12" }
+                            path { d: "M5.45 5.11 2 12v6a2 2 0 0 0 2 2h16a2 2 0 0 0 2-2v-6l-3.45-6.89A2 2 0 0 0 16.76 4H7.24a2 2 0 0 0-1.79 1.11z" }
+                        }
+                    }
+                }
             }
 
             // Main content area
@@ -226,19 +396,72 @@ pub fn Home() -> Element {
             else 
             {
 
+                for failure in slack_token_failures() {
+                    div {
+                        class: "token-banner",
+                        span { "Slack tokens of {failure.workspace} could not be renewed ({failure.error})." }
+                        if failure.token == SlackToken::Config {
+                            span { " Generate a new refresh token on api.slack.com/apps and copy it." }
+                            button {
+                                class: "token-banner-button",
+                                onclick: move |_| handle_slack_config_reauth(failure.app_id.clone()),
+                                "Paste Refresh Token"
+                            }
+                        }
+                        else {
+                            a {
+                                href: "{failure.oauth_url}",
+                                target: "_top",
+                                button {
+                                    class: "token-banner-button",
+                                    onclick: move |_| handle_slack_reinstall(failure.app_id.clone()),
+                                    "Reinstall Workspace"
+                                }
+                            }
+                        }
+                    }
+                }
+
+                if account_labels().len() > 0 {
+                    div {
+                        class: "account-switcher",
+                        select {
+                            class: "account-select",
+                            onchange: handle_account_switch,
+                            for (index, label) in account_labels().into_iter().enumerate() {
+                                option {
+                                    value: "{index}",
+                                    selected: index == active_account(),
+                                    "{label}"
+                                }
+                            }
+                            option { value: "add", "+ Add account" }
+                        }
+                    }
+                }
+
                 if current_platform().to_string() == "Discord" {
                     Discord_p{
+                        key: "{account_version}",
                         show_discord_server_pane: show_discord_server_pane.clone(),
                         discord_guilds: discord_guilds.clone()
                     }
                 }
                 else if current_platform().to_string() == "MSTeams" {
                     MSTeams_p{
+                        key: "{account_version}",
                         show_teams_server_pane: show_teams_server_pane.clone()
                     }
                 }
                 else if current_platform().to_string() == "Slack" {
-                    Slack_p{current_platform: current_platform.clone()}
+                    Slack_p{key: "{account_version}", current_platform: current_platform.clone()}
+                }
+                else if current_platform().to_string() == "Inbox" {
+                    Inbox_p{
+                        current_platform: current_platform.clone(),
+                        show_discord_server_pane: show_discord_server_pane.clone(),
+                        show_teams_server_pane: show_teams_server_pane.clone()
+                    }
                 }
                 else{
                     h2 { 
@@ -0,0 +1,146 @@
+use dioxus::prelude::*;
+use tracing::{info, warn};
+use chrono::Local;
+use crate::api::chat_provider::*;
+use crate::api::inbox::*;
+use crate::api::discord::discord_http;
+use crate::api::ms_teams::ms_teams_tokens::TeamsTokens;
+
+// Api mongo structs
+use crate::api::mongo_format::mongo_structs::*;
+use crate::api::mongo_format::user_store::UserStore;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+#[component]
+pub fn Inbox(current_platform: Signal<String>, show_discord_server_pane: Signal<bool>, show_teams_server_pane: Signal<bool>) -> Element {
+    // ! User Mutex Lock to access the user data
+    let user_lock = use_context::<Signal<Arc<Mutex<User>>>>();
+    let user_store = use_context::<Signal<Arc<dyn UserStore>>>();
+    // Channel the platform page should open once it is shown
+    let mut pending_channel = use_context::<Signal<Option<ChatChannel>>>();
+    // ! ========================= ! //
+
+    let mut inbox_items = use_signal(|| Vec::<InboxItem>::new());
+    let mut fetch_errors = use_signal(|| Vec::<String>::new());
+    let mut is_loading = use_signal(|| false);
+
+    let mut handle_refresh = move || {
+        if is_loading() {
+            return;
+        }
+        is_loading.set(true);
+
+        let user_lock_api = Arc::clone(&user_lock());
+        let tokens = TeamsTokens::new(Arc::clone(&user_lock_api), user_store());
+        spawn(async move {
+            // Renew an expiring Teams token before the providers copy it
+            if user_lock_api.lock().await.ms_teams.active().is_linked() {
+                tokens.access_token().await;
+            }
+            let user = user_lock_api.lock().await.clone();
+            // Runs in the background, so Discord requests may wait out rate limits
+            let (items, errors) = discord_http::patiently(latest_activity(&user)).await;
+
+            for error in errors.iter() {
+                warn!("Inbox fetch failed for {}", error);
+            }
+            info!("Inbox loaded {} messages", items.len());
+
+            inbox_items.set(items);
+            fetch_errors.set(errors);
+            is_loading.set(false);
+        });
+    };
+
+    // Load once when the inbox is opened
+    use_hook(move || handle_refresh());
+
+    // Switch to the native page of the item and let it open the channel
+    let mut handle_open = move |channel: ChatChannel| {
+        match channel.platform {
+            Platform::Discord => show_discord_server_pane.set(true),
+            Platform::MSTeams => show_teams_server_pane.set(true),
+            Platform::Slack => {}
+        }
+        current_platform.set(channel.platform.as_str().to_string());
+        pending_channel.set(Some(channel));
+    };
+
+    rsx! {
+        div {
+            class: "inbox-pane",
+            div {
+                class: "inbox-header",
+                h2 { class: "discord-heading", "Inbox" }
+                button {
+                    class: "login-button",
+                    disabled: is_loading(),
+                    onclick: move |_| handle_refresh(),
+                    if is_loading() { "Loading..." } else { "Refresh" }
+                }
+            }
+
+            for error in fetch_errors() {
+                p { class: "inbox-error", "Could not load {error}" }
+            }
+
+            if inbox_items().is_empty() && !is_loading() {
+                p { class: "inbox-empty", "No recent activity on your connected platforms." }
+            }
+
+            ul {
+                class: "messages-list",
+                for item in inbox_items() {
+                    li {
+                        class: "messages-item inbox-item",
+                        onclick: {
+                            let channel = item.channel.clone();
+                            move |_| handle_open(channel.clone())
+                        },
+                        div {
+                            class: "message-header",
+                            img {
+                                class: "inbox-badge",
+                                src: platform_logo(item.channel.platform),
+                                alt: item.channel.platform.as_str(),
+                            }
+                            span {
+                                class: "inbox-location",
+                                "{item.workspace_name} / {item.channel.name}"
+                            }
+                        }
+                        div {
+                            class: "message-header",
+                            img {
+                                class: "message-avatar",
+                                src: item.message.author.avatar_url.clone().unwrap_or("assets/defaultpfp.png".to_string()),
+                                alt: "User Avatar"
+                            }
+                            span {
+                                class: "message-username",
+                                {item.message.author.name.clone()}
+                            }
+                            span {
+                                class: "message-date",
+                                {item.message.timestamp.with_timezone(&Local).format("%b %d, %Y %I:%M %p").to_string()}
+                            }
+                        }
+                        div {
+                            class: "message-content",
+                            {item.message.body.clone()}
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn platform_logo(platform: Platform) -> &'static str {
+    match platform {
+        Platform::Discord => "assets/discord_logo.png",
+        Platform::Slack => "assets/slack_logo.png",
+        Platform::MSTeams => "assets/msteams_logo.png",
+    }
+}
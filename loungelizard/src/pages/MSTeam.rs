@@ -1,52 +1,47 @@
 use dioxus::prelude::*;
-use serde_json::Value;
 use tracing::info;
 use futures::executor::block_on;
 use chrono::{DateTime, Local, Utc};
 use std::collections::HashMap;
-use crate::api::ms_teams::ms_teams_api::*;
+use std::error::Error;
+use std::rc::Rc;
+use crate::api::ms_teams::ms_teams_notifications::*;
+use crate::api::ms_teams::ms_teams_provider::*;
+use crate::api::ms_teams::ms_teams_tokens::TeamsTokens;
+use crate::api::chat_provider::*;
+use crate::api::unified_message::*;
 
 // Api mongo structs
 use crate::api::mongo_format::mongo_structs::*;
+use crate::api::mongo_format::user_store::UserStore;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
-type UserCache = HashMap<String, (String, String)>; // user_id -> displayName, profilePicture
-
 #[component]
 pub fn MSTeams(show_teams_server_pane: Signal<bool>) -> Element {
-    
+
     let user_lock = use_context::<Signal<Arc<Mutex<User>>>>();
+    let user_store = use_context::<Signal<Arc<dyn UserStore>>>();
 
-    let selected_team_id = use_signal(|| None::<Value>);
-    let selected_channel_id = use_signal(|| None::<Value>);
-    let selected_user_id = use_signal(|| None::<Value>);
+    let provider = use_signal(|| Rc::new(MSTeamsProvider::refreshing(TeamsTokens::new(user_lock(), user_store()))));
 
-    let teams_list = use_signal(|| Value::Null);
-    let channels_list = use_signal(|| Value::Null);
-    let messages_list = use_signal(|| None::<Value>);
-    let users_list = use_signal(|| UserCache::new());
+    let selected_team = use_signal(|| None::<ChatWorkspace>);
+    let selected_channel = use_signal(|| None::<ChatChannel>);
 
-    spawn(async move {
-        let user_lock = Arc::clone(&user_lock());
-        let mut teams_list = teams_list.clone();
-        let mut selected_user_id = selected_user_id.clone(); 
+    let teams_list = use_signal(Vec::<ChatWorkspace>::new);
+    let channels_list = use_signal(Vec::<ChatChannel>::new);
+    // Root messages followed by their replies, see MSTeamsProvider
+    let messages_list = use_signal(|| None::<Vec<UnifiedMessage>>);
 
-        let user_lock_api = user_lock.lock().await;
-        let ms_teams_token = user_lock_api.ms_teams.access_token.clone();
+    // Renews the token while the page is open, even if nothing is sent
+    use_future(move || TeamsTokens::new(user_lock(), user_store()).keep_fresh());
 
-        match get_user(&ms_teams_token).await {
-            Ok(user_data) => {
-                selected_user_id.set(Some(user_data.clone()));
-            }
-            Err(e) => {
-                eprintln!("Failed to retrieve user: {}", e);
-            }
-        }
+    spawn(async move {
+        let mut teams_list = teams_list.clone();
 
-        match get_teams(&ms_teams_token).await {
+        match provider().list_workspaces().await {
             Ok(teams_data) => {
-                teams_list.set(teams_data.clone());
+                teams_list.set(teams_data);
                 info!("Teams list retrieval successful");
             }
             Err(e) => {
@@ -59,62 +54,63 @@ pub fn MSTeams(show_teams_server_pane: Signal<bool>) -> Element {
         div {
             class: "ms-teams-main-container",
             LeftSidebar {
+                provider: provider,
                 teams_list: teams_list.clone(),
                 messages_list: messages_list.clone(),
-                selected_team_id: selected_team_id.clone(),
-                selected_channel_id: selected_channel_id.clone(),
-                channels_list: channels_list.clone(),
-                users_list: users_list.clone()
+                selected_team: selected_team,
+                selected_channel: selected_channel,
+                channels_list: channels_list
             },
             MiddlePanel {
+                provider: provider,
                 channels_list: channels_list.clone(),
                 messages_list: messages_list.clone(),
-                selected_team_id: selected_team_id.clone(),
-                selected_channel_id: selected_channel_id.clone(),
-                users_list: users_list.clone()
-
+                selected_channel: selected_channel
             },
             RightPanel {
+                provider: provider,
                 messages_list: messages_list.clone(),
-                selected_team_id: selected_team_id.clone(),
-                selected_channel_id: selected_channel_id.clone(),
-                selected_user_id: selected_user_id.clone(),
-                users_list: users_list.clone()
+                selected_channel: selected_channel
             }
         }
     }
 }
 
+// Graph only returns whole channels, so the page shows all of it
+async fn load_channel(provider: &MSTeamsProvider, channel: &ChatChannel) -> Result<Vec<UnifiedMessage>, Box<dyn Error>> {
+    provider.fetch_history(channel, &HistoryCursor::Latest, usize::MAX).await
+}
+
 #[component]
-fn LeftSidebar(teams_list: Signal<Value>, messages_list: Signal<Option<Value>>, selected_team_id: Signal<Option<Value>>, selected_channel_id: Signal<Option<Value>>, channels_list: Signal<Value>, users_list: Signal<UserCache>) -> Element {
-    
-    let user_lock = use_context::<Signal<Arc<Mutex<User>>>>();
-    let teams_array = teams_list().as_array().unwrap_or(&Vec::new()).clone();
+fn LeftSidebar(provider: Signal<Rc<MSTeamsProvider>>, teams_list: Signal<Vec<ChatWorkspace>>, messages_list: Signal<Option<Vec<UnifiedMessage>>>, selected_team: Signal<Option<ChatWorkspace>>, selected_channel: Signal<Option<ChatChannel>>, channels_list: Signal<Vec<ChatChannel>>) -> Element {
+
+    let teams_array = teams_list();
     let mut fetch_error = use_signal(|| None::<String>);
     let mut is_loading = use_signal(|| false);
+    // Channel picked in the inbox, opened instead of the first one
+    let mut pending_channel = use_context::<Signal<Option<ChatChannel>>>();
 
-    let mut handle_get_channels = move |user_lock_api: Arc<Mutex<User>>| {
-        let team_id = selected_team_id().as_ref().and_then(|team| team.get("id")).and_then(|v| v.as_str()).unwrap_or("").to_string();
+    let mut handle_get_channels = move || {
+        let Some(team) = selected_team() else { return };
         block_on(async move {
-            let user_lock_api = user_lock_api.lock().await;
-            let ms_teams_token = user_lock_api.ms_teams.access_token.clone();
-
-            match get_users(&ms_teams_token, &team_id).await {
-                Ok(users_data) => {
-                    users_list.set(users_data);
-                }
-                Err(e) => {
-                    fetch_error.set(Some(e.to_string()));
-                }
-            }
+            let provider = provider();
 
-            match get_channels(&ms_teams_token, &team_id).await {
+            match provider.list_channels(&team).await {
                 Ok(channels_data) => {
                     channels_list.set(channels_data.clone());
-                    if let Some(first_channel) = channels_data.get(0) {
-                        selected_channel_id.set(Some(first_channel.clone()));
-                        let channel_id = first_channel.get("id").and_then(|v| v.as_str()).unwrap_or("").to_string();
-                        match get_messages(&ms_teams_token, &team_id, &channel_id, &users_list()).await {
+
+                    let pending = pending_channel.peek().clone()
+                        .filter(|c| c.platform == Platform::MSTeams && c.workspace_id == team.id);
+                    let picked_channel = pending.as_ref().and_then(|pending| {
+                        channels_data.iter().find(|c| c.id == pending.id).cloned()
+                    });
+                    if pending.is_some() {
+                        pending_channel.set(None);
+                    }
+
+                    if let Some(first_channel) = picked_channel.or(channels_data.first().cloned()) {
+                        selected_channel.set(Some(first_channel.clone()));
+                        match load_channel(&provider, &first_channel).await {
                             Ok(messages_data) => {
                                 messages_list.set(Some(messages_data));
                             }
@@ -132,6 +128,17 @@ fn LeftSidebar(teams_list: Signal<Value>, messages_list: Signal<Option<Value>>,
         is_loading.set(false);
     };
 
+    // Select the team of the inbox pick once the teams are loaded
+    use_effect(move || {
+        let Some(pending) = pending_channel().filter(|c| c.platform == Platform::MSTeams) else { return };
+        let team = teams_list().into_iter().find(|team| team.id == pending.workspace_id);
+
+        if let Some(team) = team {
+            selected_team.set(Some(team));
+            handle_get_channels();
+        }
+    });
+
     rsx! {
         div {
             class: "ms-teams-left-sidebar",
@@ -140,19 +147,19 @@ fn LeftSidebar(teams_list: Signal<Value>, messages_list: Signal<Option<Value>>,
                     button {
                         class: {
                             format!("ms-teams-team-icon {}",
-                            if selected_team_id().as_ref().and_then(|v| v.get("id")).and_then(|v| v.as_str()) == team_copy.get("id").and_then(|v| v.as_str()) { "active" } else { "" })
+                            if selected_team().is_some_and(|selected| selected.id == team_copy.id) { "active" } else { "" })
                         },
                         disabled: is_loading(),
                         onclick: move |_| {
                             is_loading.set(true);
-                            selected_team_id.set(Some(team.clone()));
-                            handle_get_channels(Arc::clone(&user_lock()));
+                            selected_team.set(Some(team.clone()));
+                            handle_get_channels();
                         },
                         img {
-                            src: team_copy.get("teamPicture").and_then(|v| v.as_str()).unwrap_or("https://via.placeholder.com/150"),
-                            alt: team_copy.get("displayName").and_then(|v| v.as_str()).unwrap_or("Unknown ID").to_string(),
+                            src: team_copy.icon_url.clone().unwrap_or("https://via.placeholder.com/150".to_string()),
+                            alt: team_copy.name.clone(),
                         }
-                        {team.get("displayName").and_then(|v| v.as_str()).unwrap_or("Unknown ID").to_string()},
+                        {team_copy.name.clone()},
                     }
                 }
             }
@@ -162,26 +169,20 @@ fn LeftSidebar(teams_list: Signal<Value>, messages_list: Signal<Option<Value>>,
 
 #[component]
 fn MiddlePanel(
-    channels_list: Signal<Value>,
-    messages_list: Signal<Option<Value>>,
-    selected_team_id: Signal<Option<Value>>,
-    selected_channel_id: Signal<Option<Value>>,
-    users_list: Signal<UserCache>,
+    provider: Signal<Rc<MSTeamsProvider>>,
+    channels_list: Signal<Vec<ChatChannel>>,
+    messages_list: Signal<Option<Vec<UnifiedMessage>>>,
+    selected_channel: Signal<Option<ChatChannel>>,
 ) -> Element {
-    let user_lock = use_context::<Signal<Arc<Mutex<User>>>>();
-    let channels_array = channels_list().as_array().unwrap_or(&Vec::new()).clone();
+    let channels_array = channels_list();
     let mut fetch_error = use_signal(|| None::<String>);
     let mut is_loading = use_signal(|| false);
 
-    let mut handle_get_channel_messages = move |user_lock_api: Arc<Mutex<User>>| {
-        let team_id = selected_team_id().as_ref().and_then(|team| team.get("id")).and_then(|v| v.as_str()).unwrap_or("").to_string();
-        let channel_id = selected_channel_id().as_ref().and_then(|team| team.get("id")).and_then(|v| v.as_str()).unwrap_or("").to_string();
+    let mut handle_get_channel_messages = move || {
+        let Some(channel) = selected_channel() else { return };
 
         spawn(async move {
-            let user_lock_api = user_lock_api.lock().await;
-            let ms_teams_token = user_lock_api.ms_teams.access_token.clone();
-
-            match get_messages(&ms_teams_token, &team_id, &channel_id, &users_list()).await {
+            match load_channel(&provider(), &channel).await {
                 Ok(messages_data) => {
                     messages_list.set(Some(messages_data));
                 }
@@ -203,15 +204,15 @@ fn MiddlePanel(
                     button {
                         class: {
                             format!("ms-teams-channel-item {}",
-                            if Some(channel.clone()) == selected_channel_id() { "active" } else { "" })
+                            if Some(channel.clone()) == selected_channel() { "active" } else { "" })
                         },
                         disabled: is_loading(),
                         onclick: move |_| {
                             is_loading.set(true);
-                            selected_channel_id.set(Some(channel.clone()));
-                            handle_get_channel_messages(Arc::clone(&user_lock()))
+                            selected_channel.set(Some(channel.clone()));
+                            handle_get_channel_messages()
                         },
-                        {channel.get("displayName").and_then(|v| v.as_str()).unwrap_or("Unknown ID").to_string()}
+                        {channel.name.clone()}
                     }
                 }
             }
@@ -221,15 +222,16 @@ fn MiddlePanel(
 
 #[ignore = "irrefutable_let_patterns"]
 #[component]
-fn RightPanel(messages_list: Signal<Option<Value>>, selected_team_id: Signal<Option<Value>>, selected_channel_id: Signal<Option<Value>>, selected_user_id: Signal<Option<Value>>, users_list: Signal<UserCache>) -> Element {
+fn RightPanel(provider: Signal<Rc<MSTeamsProvider>>, messages_list: Signal<Option<Vec<UnifiedMessage>>>, selected_channel: Signal<Option<ChatChannel>>) -> Element {
     let user_lock = use_context::<Signal<Arc<Mutex<User>>>>();
-    
+    let user_store = use_context::<Signal<Arc<dyn UserStore>>>();
+
     let mut send_error = use_signal(|| None::<String>);
     let mut message_input = use_signal(|| "".to_string());
     let mut message_subject_input = use_signal(|| "".to_string());
     let mut reply_input = use_signal(|| "".to_string());
-    
-    let mut selected_message_id = use_signal(|| None::<Value>);
+
+    let mut selected_message = use_signal(|| None::<UnifiedMessage>);
 
     let mut show_subject_input = use_signal(|| false);
     let mut show_reply_input = use_signal(|| false);
@@ -239,86 +241,125 @@ fn RightPanel(messages_list: Signal<Option<Value>>, selected_team_id: Signal<Opt
 
     let mut is_loading = use_signal(|| false);
 
-    let messages_array = messages_list().as_ref().and_then(|value| value.as_array()).unwrap_or(&Vec::new()).clone();
+    let messages_array = messages_list().unwrap_or_default();
+    let root_messages: Vec<UnifiedMessage> = messages_array.iter().filter(|message| message.thread_id.is_none()).cloned().collect();
+    let replies_to = |root: &UnifiedMessage| -> Vec<UnifiedMessage> {
+        messages_array.iter().filter(|message| message.thread_id.as_deref() == Some(root.id.as_str())).cloned().collect()
+    };
+    let is_selected = move |message: &UnifiedMessage| selected_message().is_some_and(|selected| selected.id == message.id);
 
     use_effect(move || {
-        let user_lock = Arc::clone(&user_lock());
+        let tokens = TeamsTokens::new(user_lock(), user_store());
         let mut messages_list = messages_list.clone();
-        let selected_team_id = selected_team_id.clone();
-        let selected_channel_id = selected_channel_id.clone();
-        let users_list = users_list.clone();
         let mut send_error = send_error.clone();
 
         spawn(async move {
-            loop {
-                if !is_loading() {
-                    if let (Some(team), Some(channel)) = (selected_team_id(), selected_channel_id()) {
-                        let team_id = team.get("id").and_then(|v| v.as_str()).unwrap_or("").to_string();
-                        let channel_id = channel.get("id").and_then(|v| v.as_str()).unwrap_or("").to_string();
-                        
-                        let user_lock_api = user_lock.lock().await;
-                        let access_token = user_lock_api.ms_teams.access_token.clone();
-    
-                        match get_messages(&access_token, &team_id, &channel_id, &users_list()).await {
-                            Ok(messages_data) => {
-                                messages_list.set(Some(messages_data));
-                            }
-                            Err(e) => {
-                                send_error.set(Some(e.to_string()));
-                            }
-                        }
-                    }
-                    tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+            // channel_id -> delta link, only changes since the last sync are downloaded
+            let mut delta_links: HashMap<String, String> = HashMap::new();
+
+            // Optional push notifications, otherwise the delta query runs on a timer
+            let mut listener = None;
+            if notifications_enabled() {
+                match NotificationListener::start().await {
+                    Ok(started) => listener = Some(started),
+                    Err(e) => info!("Teams notifications unavailable, polling instead: {}", e),
                 }
             }
-        });
-    });
 
-    let mut handle_send_message = move |user_lock_api: Arc<Mutex<User>>| {
-        if let Some(team) = selected_team_id() {
-            if let Some(channel) = selected_channel_id() {
-                let team_id = team.get("id").and_then(|v| v.as_str()).unwrap_or("").to_string();
-                let channel_id = channel.get("id").and_then(|v| v.as_str()).unwrap_or("").to_string();
-
-                spawn(async move {
-                    let user_lock_api = user_lock_api.lock().await;
-                    let access_token = user_lock_api.ms_teams.access_token.clone();
-                    match send_message(&access_token, &team_id, &channel_id, &message_input(), &message_subject_input()).await {
-                        Ok(_) => {
-                            info!("Message sent successfully");
-                            message_input.set("".to_string());
-                            message_subject_input.set("".to_string());
-                        }
-                        Err(e) => {
-                            send_error.set(Some(e.to_string()));
-                            info!("Message send failed: {}", e);
+            loop {
+                match listener.as_mut() {
+                    // Still sync now and then in case a notification got lost
+                    Some(listener) => { let _ = tokio::time::timeout(tokio::time::Duration::from_secs(30), listener.next()).await; }
+                    None => tokio::time::sleep(tokio::time::Duration::from_secs(5)).await,
+                }
+
+                if is_loading() {
+                    continue;
+                }
+
+                if let Some(channel) = selected_channel() {
+                    if let Some(listener) = listener.as_mut() {
+                        let access_token = tokens.access_token().await;
+                        if let Err(e) = listener.watch(&access_token, &channel.workspace_id, &channel.id).await {
+                            info!("Failed to subscribe to channel changes: {}", e);
                         }
                     }
-                    match get_messages(&access_token, &team_id, &channel_id, &users_list()).await {
-                        Ok(updated_messages) => {
-                            messages_list.set(Some(updated_messages).clone());
-                            info!("Messages update successful");
+
+                    // The channel was fully loaded when it got selected, start a little before now
+                    let since = Utc::now() - chrono::Duration::minutes(1);
+                    let delta_link = delta_links.get(&channel.id).cloned();
+
+                    match provider().fetch_changes(&channel, delta_link.as_deref(), since).await {
+                        Ok(changes) => {
+                            // Ignore results for a channel the user already left
+                            let still_selected = selected_channel().is_some_and(|c| c.id == channel.id);
+                            if still_selected {
+                                let mut messages = messages_list.peek().clone();
+                                if let Some(list) = messages.as_mut() {
+                                    if apply_changes(list, &changes) {
+                                        messages_list.set(messages);
+                                    }
+                                }
+                            }
+                            delta_links.insert(channel.id, changes.delta_link);
                         }
                         Err(e) => {
+                            // Expired or rejected delta links start over on the next round
+                            delta_links.remove(&channel.id);
                             send_error.set(Some(e.to_string()));
-                            info!("Messages update failed: {}", e);
                         }
                     }
-                });
+                }
             }
+        });
+    });
+
+    let mut handle_send_message = move || {
+        if let Some(channel) = selected_channel() {
+            spawn(async move {
+                let provider = provider();
+                let message = OutgoingMessage {
+                    content: message_input(),
+                    subject: Some(message_subject_input()).filter(|subject| !subject.is_empty()),
+                    ..Default::default()
+                };
+                match provider.send_message(&channel, &message).await {
+                    Ok(_) => {
+                        info!("Message sent successfully");
+                        message_input.set("".to_string());
+                        message_subject_input.set("".to_string());
+                    }
+                    Err(e) => {
+                        send_error.set(Some(e.to_string()));
+                        info!("Message send failed: {}", e);
+                    }
+                }
+                match load_channel(&provider, &channel).await {
+                    Ok(updated_messages) => {
+                        messages_list.set(Some(updated_messages));
+                        info!("Messages update successful");
+                    }
+                    Err(e) => {
+                        send_error.set(Some(e.to_string()));
+                        info!("Messages update failed: {}", e);
+                    }
+                }
+            });
         }
         is_loading.set(false);
     };
 
-    let mut handle_send_reply = move |user_lock_api: Arc<Mutex<User>>| {
-        let team_id = selected_team_id().as_ref().and_then(|team| team.get("id")).and_then(|v| v.as_str()).unwrap_or("").to_string();
-        let channel_id = selected_channel_id().as_ref().and_then(|channel| channel.get("id")).and_then(|v| v.as_str()).unwrap_or("").to_string();
-        let message_id = selected_message_id().as_ref().and_then(|message| message.get("id")).and_then(|v| v.as_str()).unwrap_or("").to_string();
+    let mut handle_send_reply = move || {
+        let (Some(channel), Some(message)) = (selected_channel(), selected_message()) else { return };
 
         spawn(async move {
-            let user_lock_api = user_lock_api.lock().await;
-            let access_token = user_lock_api.ms_teams.access_token.clone();
-            match send_message_reply(&access_token, &team_id, &channel_id, &message_id, &reply_input()).await {
+            let provider = provider();
+            let reply = OutgoingMessage {
+                content: reply_input(),
+                reply_to: Some(message),
+                ..Default::default()
+            };
+            match provider.send_message(&channel, &reply).await {
                 Ok(_) => {
                     info!("Reply sent successfully");
                     reply_input.set("".to_string());
@@ -328,39 +369,9 @@ fn RightPanel(messages_list: Signal<Option<Value>>, selected_team_id: Signal<Opt
                     info!("Reply send failed: {}", e);
                 }
             }
-            match get_messages(&access_token, &team_id, &channel_id, &users_list()).await {
-                Ok(updated_messages) => {
-                    messages_list.set(Some(updated_messages).clone());
-                    info!("Messages update successful");
-                }
-                Err(e) => {
-                    send_error.set(Some(e.to_string()));
-                    info!("Messages update failed: {}", e);
-                }
-            }
-        });
-        is_loading.set(false);
-    };
-
-    let mut handle_send_reaction = move |emoji: &str, user_lock_api: Arc<Mutex<User>>| {
-        let team_id = selected_team_id().as_ref().and_then(|team| team.get("id")).and_then(|v| v.as_str()).unwrap_or("").to_string();
-        let channel_id = selected_channel_id().as_ref().and_then(|channel| channel.get("id")).and_then(|v| v.as_str()).unwrap_or("").to_string();
-        let message_id = selected_message_id().as_ref().and_then(|message| message.get("id")).and_then(|v| v.as_str()).unwrap_or("").to_string();
-
-        block_on(async move {
-            let user_lock_api = user_lock_api.lock().await;
-            let access_token = user_lock_api.ms_teams.access_token.clone();
-
-            match send_reaction(&access_token, &team_id, &channel_id, &message_id, emoji).await {
-                Ok(()) => {
-                }
-                Err(e) => {
-                    send_error.set(Some(e.to_string()));
-                }
-            }
-            match get_messages(&access_token, &team_id, &channel_id, &users_list()).await {
+            match load_channel(&provider, &channel).await {
                 Ok(updated_messages) => {
-                    messages_list.set(Some(updated_messages).clone());
+                    messages_list.set(Some(updated_messages));
                     info!("Messages update successful");
                 }
                 Err(e) => {
@@ -372,26 +383,26 @@ fn RightPanel(messages_list: Signal<Option<Value>>, selected_team_id: Signal<Opt
         is_loading.set(false);
     };
 
-    let mut handle_send_reaction_reply = move |emoji: &str, user_lock_api: Arc<Mutex<User>>| {
-        let team_id = selected_team_id().as_ref().and_then(|team| team.get("id")).and_then(|v| v.as_str()).unwrap_or("").to_string();
-        let channel_id = selected_channel_id().as_ref().and_then(|channel| channel.get("id")).and_then(|v| v.as_str()).unwrap_or("").to_string();
-        let message_id = selected_message_id().as_ref().and_then(|message| message.get("replyFromId")).and_then(|v| v.as_str()).unwrap_or("").to_string();
-        let reply_id = selected_message_id().as_ref().and_then(|reply| reply.get("id")).and_then(|v| v.as_str()).unwrap_or("").to_string();
+    // Adds the reaction to the selected message, or takes it back if it is the user's
+    let mut handle_toggle_reaction = move |emoji: &str, remove: bool| {
+        let (Some(channel), Some(message)) = (selected_channel(), selected_message()) else {
+            is_loading.set(false);
+            return;
+        };
 
         block_on(async move {
-            let user_lock_api = user_lock_api.lock().await;
-            let access_token = user_lock_api.ms_teams.access_token.clone();
-
-            match send_reaction_reply(&access_token, &team_id, &channel_id, &message_id, &reply_id, emoji).await {
-                Ok(()) => {
-                }
-                Err(e) => {
-                    send_error.set(Some(e.to_string()));
-                }
+            let provider = provider();
+            let result = if remove {
+                provider.remove_reaction(&channel, &message, emoji).await
+            } else {
+                provider.add_reaction(&channel, &message, emoji).await
+            };
+            if let Err(e) = result {
+                send_error.set(Some(e.to_string()));
             }
-            match get_messages(&access_token, &team_id, &channel_id, &users_list()).await {
+            match load_channel(&provider, &channel).await {
                 Ok(updated_messages) => {
-                    messages_list.set(Some(updated_messages).clone());
+                    messages_list.set(Some(updated_messages));
                     info!("Messages update successful");
                 }
                 Err(e) => {
@@ -403,87 +414,6 @@ fn RightPanel(messages_list: Signal<Option<Value>>, selected_team_id: Signal<Opt
         is_loading.set(false);
     };
 
-    let mut handle_remove_reaction = move |user_lock_api: Arc<Mutex<User>>| {
-        let team_id = selected_team_id().as_ref().and_then(|team| team.get("id")).and_then(|v| v.as_str()).unwrap_or("").to_string();
-        let channel_id = selected_channel_id().as_ref().and_then(|channel| channel.get("id")).and_then(|v| v.as_str()).unwrap_or("").to_string();
-        let message_id = selected_message_id().as_ref().and_then(|message| message.get("id")).and_then(|v| v.as_str()).unwrap_or("").to_string();
-        let user_id = selected_user_id().as_ref().and_then(|id| id.get("id")).and_then(|v| v.as_str()).unwrap_or("").to_string();
-
-        if let Some(reactions) = selected_message_id().as_ref().and_then(|message| message.get("reactions")).and_then(|r| r.as_array()) {
-            for reaction in reactions {
-                let reaction_user_id = reaction.get("user").and_then(|user| user.get("id")).and_then(|id| id.as_str()).unwrap_or("").to_string();
-                if reaction_user_id == user_id {
-                    let emoji = reaction.get("emoji").and_then(|v| v.as_str()).unwrap_or("");
-                    block_on(async move {
-                        let user_lock_api = user_lock_api.lock().await;
-                        let access_token = user_lock_api.ms_teams.access_token.clone();
-                
-                        match remove_reaction(&access_token, &team_id, &channel_id, &message_id, emoji).await {
-                            Ok(()) => {
-                            }
-                            Err(e) => {
-                                send_error.set(Some(e.to_string()));
-                            }
-                        }
-                        match get_messages(&access_token, &team_id, &channel_id, &users_list()).await {
-                            Ok(updated_messages) => {
-                                messages_list.set(Some(updated_messages).clone());
-                                info!("Messages update successful");
-                            }
-                            Err(e) => {
-                                send_error.set(Some(e.to_string()));
-                                info!("Messages update failed: {}", e);
-                            }
-                        }
-                    });
-                    break;
-                }
-            }
-        }
-        is_loading.set(false);
-    };
-
-    let mut handle_remove_reaction_reply = move |user_lock_api: Arc<Mutex<User>>| {
-        let team_id = selected_team_id().as_ref().and_then(|team| team.get("id")).and_then(|v| v.as_str()).unwrap_or("").to_string();
-        let channel_id = selected_channel_id().as_ref().and_then(|channel| channel.get("id")).and_then(|v| v.as_str()).unwrap_or("").to_string();
-        let message_id = selected_message_id().as_ref().and_then(|message| message.get("replyFromId")).and_then(|v| v.as_str()).unwrap_or("").to_string();
-        let reply_id = selected_message_id().as_ref().and_then(|reply| reply.get("id")).and_then(|v| v.as_str()).unwrap_or("").to_string();
-        let user_id = selected_user_id().as_ref().and_then(|id| id.get("id")).and_then(|v| v.as_str()).unwrap_or("").to_string();
-
-        if let Some(reactions) = selected_message_id().as_ref().and_then(|reply| reply.get("reactions")).and_then(|r| r.as_array()) {
-            for reaction in reactions {
-                let reaction_user_id = reaction.get("user").and_then(|user| user.get("id")).and_then(|id| id.as_str()).unwrap_or("").to_string();
-                if reaction_user_id == user_id {
-                    let emoji = reaction.get("emoji").and_then(|v| v.as_str()).unwrap_or("");
-                    block_on(async move {
-                        let user_lock_api = user_lock_api.lock().await;
-                        let access_token = user_lock_api.ms_teams.access_token.clone();
-                
-                        match remove_reaction_reply(&access_token, &team_id, &channel_id, &message_id, &reply_id, emoji).await {
-                            Ok(()) => {
-                            }
-                            Err(e) => {
-                                send_error.set(Some(e.to_string()));
-                            }
-                        }
-                        match get_messages(&access_token, &team_id, &channel_id, &users_list()).await {
-                            Ok(updated_messages) => {
-                                messages_list.set(Some(updated_messages).clone());
-                                info!("Messages update successful");
-                            }
-                            Err(e) => {
-                                send_error.set(Some(e.to_string()));
-                                info!("Messages update failed: {}", e);
-                            }
-                        }
-                    });
-                    break;
-                }
-            }
-        }
-        is_loading.set(false);
-    };
-
     rsx! {
         div {
             class: "ms-teams-right-panel",
@@ -492,7 +422,7 @@ fn RightPanel(messages_list: Signal<Option<Value>>, selected_team_id: Signal<Opt
             div {
                 class: "ms-teams-message-list",
                 ul {
-                    for message in messages_array.clone() {
+                    for message in root_messages.clone() {
                         if let message_copy = message.clone() {
                             li {
                                 class: "ms-teams-post-container",
@@ -504,7 +434,7 @@ fn RightPanel(messages_list: Signal<Option<Value>>, selected_team_id: Signal<Opt
                                         class: "ms-teams-user-header",
                                         div {
                                             class: "ms-teams-user-picture",
-                                            if let Some(image_url) = message.clone().get("user").and_then(|u| u.get("profilePicture")).and_then(|v| v.as_str()) {
+                                            if let Some(image_url) = message.author.avatar_url.clone() {
                                                 img {
                                                     src: "{image_url}",
                                                     alt: "User Icon",
@@ -514,22 +444,22 @@ fn RightPanel(messages_list: Signal<Option<Value>>, selected_team_id: Signal<Opt
                                         }
                                         span {
                                             class: "ms-teams-user-info",
-                                            {message.get("user").and_then(|u| u.get("displayName")).and_then(|v| v.as_str()).unwrap_or("Unknown User")}
+                                            {message.author.name.clone()}
                                         }
                                         span {
                                             class: "ms-teams-message-time",
-                                            {format_timestamp(message.get("time").and_then(|v| v.as_str()).unwrap_or(""))}
+                                            {format_timestamp(message.timestamp)}
                                         }
                                     }
                                     // Contains the Subject of the Post
                                     div {
                                         class: "ms-teams-subject",
-                                        {message.get("subject").and_then(|v| v.as_str()).unwrap_or("")}
+                                        {message.subject.clone().unwrap_or_default()}
                                     }
                                     // Contains the Content of the Post/Message
                                     div {
                                         class: "ms-teams-content",
-                                        {message.get("content").and_then(|v| v.as_str()).unwrap_or("")}
+                                        {message.body.clone()}
                                     }
                                     // Contains the Reactions of the Message/Post
                                     div {
@@ -537,7 +467,7 @@ fn RightPanel(messages_list: Signal<Option<Value>>, selected_team_id: Signal<Opt
                                         style: "position: relative",
                                         span {
                                             class: "ms-teams-reaction-list",
-                                            for reaction in message.clone().get("reactions").and_then(|r| r.as_array()).unwrap_or(&vec![]).clone() {
+                                            for reaction in message.reactions.clone() {
                                                 if let message_copy_clone = message_copy.clone() {
                                                     button {
                                                         class: "ms-teams-reaction-item",
@@ -548,14 +478,14 @@ fn RightPanel(messages_list: Signal<Option<Value>>, selected_team_id: Signal<Opt
                                                             show_reply_emoji_list.set(false);
                                                             show_reply_input.set(false);
                                                             reply_input.set("".to_string());
-                                                            selected_message_id.set(Some(message_copy_clone.clone()));
-                                                            handle_remove_reaction(Arc::clone(&user_lock()));
+                                                            selected_message.set(Some(message_copy_clone.clone()));
+                                                            handle_toggle_reaction(&reaction.key, reaction.me);
                                                         },
-                                                        {reaction.get("emoji").and_then(|v| v.as_str()).unwrap_or("")}
+                                                        {reaction.emoji.clone()}
                                                         " "
                                                         span {
                                                             class: "ms-teams-reaction-count",
-                                                            {reaction.get("count").and_then(|v| v.as_i64()).unwrap_or(1).to_string()}
+                                                            {reaction.count.to_string()}
                                                         },
                                                     }
                                                 }
@@ -566,12 +496,12 @@ fn RightPanel(messages_list: Signal<Option<Value>>, selected_team_id: Signal<Opt
                                                 class: "ms-teams-reaction-button",
                                                 onclick: move |_| {
                                                     if show_reaction_list() {
-                                                        if selected_message_id().as_ref().and_then(|message| message.get("id")).and_then(|v| v.as_str()).unwrap_or("").to_string() == message_copy_clone.clone().get("id").and_then(|v| v.as_str()).unwrap_or("").to_string() {
-                                                            selected_message_id.set(None);
+                                                        if is_selected(&message_copy_clone) {
+                                                            selected_message.set(None);
                                                             show_reaction_list.set(false);
                                                         }
                                                         else {
-                                                            selected_message_id.set(Some(message_copy_clone.clone()));
+                                                            selected_message.set(Some(message_copy_clone.clone()));
                                                         }
                                                     }
                                                     else {
@@ -579,14 +509,14 @@ fn RightPanel(messages_list: Signal<Option<Value>>, selected_team_id: Signal<Opt
                                                         show_reply_emoji_list.set(false);
                                                         show_reply_input.set(false);
                                                         reply_input.set("".to_string());
-                                                        selected_message_id.set(Some(message_copy_clone.clone()));
+                                                        selected_message.set(Some(message_copy_clone.clone()));
                                                         show_reaction_list.set(true);
                                                     }
                                                 },
                                             }
                                         }
                                         if show_reaction_list() {
-                                            if selected_message_id().as_ref().and_then(|message| message.get("id")).and_then(|v| v.as_str()).unwrap_or("").to_string() == message.clone().get("id").and_then(|v| v.as_str()).unwrap_or("").to_string() {
+                                            if is_selected(&message) {
                                                 div {
                                                     class: "ms-teams-emoji-list",
                                                     for emoji in ["😁", "😂", "😍", "👍", "❤️", "😮", "😢", "😡"] {
@@ -596,7 +526,7 @@ fn RightPanel(messages_list: Signal<Option<Value>>, selected_team_id: Signal<Opt
                                                             onclick: move |_| {
                                                                 is_loading.set(true);
                                                                 show_reaction_list.set(false);
-                                                                handle_send_reaction(emoji, Arc::clone(&user_lock()));
+                                                                handle_toggle_reaction(emoji, false);
                                                             },
                                                             "{emoji}"
                                                         }
@@ -612,7 +542,7 @@ fn RightPanel(messages_list: Signal<Option<Value>>, selected_team_id: Signal<Opt
                                 div {
                                     class: "ms-teams-replies-container",
                                     // Section for the Replies to each Message/Post
-                                    for reply in message.clone().get("replies").and_then(|r| r.as_array()).unwrap_or(&vec![]).clone() {
+                                    for reply in replies_to(&message) {
                                         div {
                                             class: "ms-teams-reply",
                                             // Contains Profile Picture, User, and Time
@@ -620,7 +550,7 @@ fn RightPanel(messages_list: Signal<Option<Value>>, selected_team_id: Signal<Opt
                                                 class: "ms-teams-user-header",
                                                 div {
                                                     class: "ms-teams-user-picture",
-                                                    if let Some(image_url) = reply.clone().get("user").and_then(|u| u.get("profilePicture")).and_then(|v| v.as_str()) {
+                                                    if let Some(image_url) = reply.author.avatar_url.clone() {
                                                         img {
                                                             src: "{image_url}",
                                                             alt: "User Icon",
@@ -630,17 +560,17 @@ fn RightPanel(messages_list: Signal<Option<Value>>, selected_team_id: Signal<Opt
                                                 }
                                                 span {
                                                     class: "ms-teams-user-info-reply",
-                                                    {reply.get("user").and_then(|u| u.get("displayName")).and_then(|v| v.as_str()).unwrap_or("Unknown User")}
+                                                    {reply.author.name.clone()}
                                                 }
                                                 span {
                                                     class: "ms-teams-message-time-reply",
-                                                    {format_timestamp(reply.get("time").and_then(|v| v.as_str()).unwrap_or(""))}
+                                                    {format_timestamp(reply.timestamp)}
                                                 }
                                             }
                                             // Contains Content of Reply
                                             div {
                                                 class: "ms-teams-content-reply",
-                                                {reply.get("content").and_then(|v| v.as_str()).unwrap_or("")}
+                                                {reply.body.clone()}
                                             }
                                             // Contains Reactions of Reply
                                             div {
@@ -648,7 +578,7 @@ fn RightPanel(messages_list: Signal<Option<Value>>, selected_team_id: Signal<Opt
                                                 style: "position: relative",
                                                 span {
                                                     class: "ms-teams-reaction-list",
-                                                    for reaction in reply.clone().get("reactions").and_then(|r| r.as_array()).unwrap_or(&vec![]) {
+                                                    for reaction in reply.reactions.clone() {
                                                         if let reply_copy = reply.clone() {
                                                             button {
                                                                 class: "ms-teams-reaction-item",
@@ -659,14 +589,14 @@ fn RightPanel(messages_list: Signal<Option<Value>>, selected_team_id: Signal<Opt
                                                                     show_reply_emoji_list.set(false);
                                                                     show_reply_input.set(false);
                                                                     reply_input.set("".to_string());
-                                                                    selected_message_id.set(Some(reply_copy.clone()));
-                                                                    handle_remove_reaction_reply(Arc::clone(&user_lock()));
+                                                                    selected_message.set(Some(reply_copy.clone()));
+                                                                    handle_toggle_reaction(&reaction.key, reaction.me);
                                                                 },
-                                                                {reaction.get("emoji").and_then(|v| v.as_str()).unwrap_or("")}
+                                                                {reaction.emoji.clone()}
                                                                 " "
                                                                 span {
                                                                     class: "ms-teams-reaction-count",
-                                                                    {reaction.get("count").and_then(|v| v.as_i64()).unwrap_or(1).to_string()}
+                                                                    {reaction.count.to_string()}
                                                                 },
                                                             }
                                                         }
@@ -674,28 +604,31 @@ fn RightPanel(messages_list: Signal<Option<Value>>, selected_team_id: Signal<Opt
                                                 }
                                                 button {
                                                     class: "ms-teams-reaction-button",
-                                                    onclick: move |_| { 
-                                                        if show_reaction_list() {
-                                                            if selected_message_id().as_ref().and_then(|reply| reply.get("id")).and_then(|v| v.as_str()).unwrap_or("").to_string() == reply.get("id").and_then(|v| v.as_str()).unwrap_or("").to_string() {
-                                                                selected_message_id.set(None);
-                                                                show_reaction_list.set(false);
+                                                    onclick: {
+                                                        let reply_copy = reply.clone();
+                                                        move |_| {
+                                                            if show_reaction_list() {
+                                                                if is_selected(&reply_copy) {
+                                                                    selected_message.set(None);
+                                                                    show_reaction_list.set(false);
+                                                                }
+                                                                else {
+                                                                    selected_message.set(Some(reply_copy.clone()));
+                                                                }
                                                             }
                                                             else {
-                                                                selected_message_id.set(Some(reply.clone()));
+                                                                show_emoji_list.set(false);
+                                                                show_reply_emoji_list.set(false);
+                                                                show_reply_input.set(false);
+                                                                reply_input.set("".to_string());
+                                                                selected_message.set(Some(reply_copy.clone()));
+                                                                show_reaction_list.set(true);
                                                             }
                                                         }
-                                                        else {
-                                                            show_emoji_list.set(false);
-                                                            show_reply_emoji_list.set(false);
-                                                            show_reply_input.set(false);
-                                                            reply_input.set("".to_string());
-                                                            selected_message_id.set(Some(reply.clone()));
-                                                            show_reaction_list.set(true);
-                                                        }
                                                     },
                                                 }
                                                 if show_reaction_list() {
-                                                    if selected_message_id().as_ref().and_then(|reply| reply.get("id")).and_then(|v| v.as_str()).unwrap_or("").to_string() == reply.clone().get("id").and_then(|v| v.as_str()).unwrap_or("").to_string() {
+                                                    if is_selected(&reply) {
                                                         div {
                                                             class: "ms-teams-emoji-list",
                                                             for emoji in ["😁", "😂", "😍", "👍", "❤️", "😮", "😢", "😡"] {
@@ -705,7 +638,7 @@ fn RightPanel(messages_list: Signal<Option<Value>>, selected_team_id: Signal<Opt
                                                                     onclick: move |_| {
                                                                         is_loading.set(true);
                                                                         show_reaction_list.set(false);
-                                                                        handle_send_reaction_reply(emoji, Arc::clone(&user_lock()));
+                                                                        handle_toggle_reaction(emoji, false);
                                                                     },
                                                                     "{emoji}"
                                                                 }
@@ -721,16 +654,16 @@ fn RightPanel(messages_list: Signal<Option<Value>>, selected_team_id: Signal<Opt
                                             class: "ms-teams-reply-button",
                                             onclick: move |_| {
                                                 if show_reply_input() {
-                                                    if selected_message_id().as_ref().and_then(|message| message.get("id")).and_then(|v| v.as_str()).unwrap_or("").to_string() == message_copy_clone.clone().get("id").and_then(|v| v.as_str()).unwrap_or("").to_string() {
+                                                    if is_selected(&message_copy_clone) {
                                                         show_reply_emoji_list.set(false);
                                                         reply_input.set("".to_string());
-                                                        selected_message_id.set(None);
+                                                        selected_message.set(None);
                                                         show_reply_input.set(false);
                                                     }
                                                     else {
                                                         show_reply_emoji_list.set(false);
                                                         reply_input.set("".to_string());
-                                                        selected_message_id.set(Some(message_copy_clone.clone()));
+                                                        selected_message.set(Some(message_copy_clone.clone()));
                                                     }
                                                 }
                                                 else {
@@ -738,7 +671,7 @@ fn RightPanel(messages_list: Signal<Option<Value>>, selected_team_id: Signal<Opt
                                                     show_reaction_list.set(false);
                                                     show_reply_emoji_list.set(false);
                                                     reply_input.set("".to_string());
-                                                    selected_message_id.set(Some(message_copy_clone.clone()));
+                                                    selected_message.set(Some(message_copy_clone.clone()));
                                                     show_reply_input.set(true);
                                                 }
                                             },
@@ -746,7 +679,7 @@ fn RightPanel(messages_list: Signal<Option<Value>>, selected_team_id: Signal<Opt
                                         }
                                     }
                                     //need extra check here
-                                    if show_reply_input() && selected_message_id().as_ref().and_then(|message| message.get("id")).and_then(|v| v.as_str()) == message_copy.clone().get("id").and_then(|v| v.as_str()) {
+                                    if show_reply_input() && is_selected(&message_copy) {
                                         div {
                                             class: "ms-teams-reply-input-container",
                                             position: "relative",
@@ -791,7 +724,7 @@ fn RightPanel(messages_list: Signal<Option<Value>>, selected_team_id: Signal<Opt
                                                 onclick: move |_| {
                                                     is_loading.set(true);
                                                     show_reply_input.set(false);
-                                                    handle_send_reply(Arc::clone(&user_lock()));
+                                                    handle_send_reply();
                                                 },
                                                 "Send"
                                             }
@@ -832,7 +765,7 @@ fn RightPanel(messages_list: Signal<Option<Value>>, selected_team_id: Signal<Opt
                                 value: "{message_subject_input}",
                                 placeholder: "Enter Subject... (Optional)",
                                 onclick: move |_| {
-                                    selected_message_id.set(None);
+                                    selected_message.set(None);
                                     show_reply_input.set(false);
                                     reply_input.set("".to_string());
                                     show_reaction_list.set(false);
@@ -855,7 +788,7 @@ fn RightPanel(messages_list: Signal<Option<Value>>, selected_team_id: Signal<Opt
                             value: "{message_input}",
                             placeholder: "Type here...",
                             onclick: move |_| {
-                                selected_message_id.set(None);
+                                selected_message.set(None);
                                 show_reply_input.set(false);
                                 reply_input.set("".to_string());
                                 show_reaction_list.set(false);
@@ -869,7 +802,7 @@ fn RightPanel(messages_list: Signal<Option<Value>>, selected_team_id: Signal<Opt
                         button {
                             class: "ms-teams-emoji-button",
                             onclick: move |_| {
-                                selected_message_id.set(None);
+                                selected_message.set(None);
                                 show_reply_input.set(false);
                                 reply_input.set("".to_string());
                                 show_reaction_list.set(false);
@@ -898,7 +831,7 @@ fn RightPanel(messages_list: Signal<Option<Value>>, selected_team_id: Signal<Opt
                             disabled: is_loading(),
                             onclick: move |_| {
                                 is_loading.set(true);
-                                handle_send_message(Arc::clone(&user_lock()));
+                                handle_send_message();
                             },
                             "Send"
                         }
@@ -912,13 +845,10 @@ fn RightPanel(messages_list: Signal<Option<Value>>, selected_team_id: Signal<Opt
 #[derive(Debug, Clone)]
 struct EmptyStruct {} // Empty struct to use for coroutines (when you don't need to send anything into the coroutine)
 
-fn format_timestamp(timestamp: &str) -> String {
-    // Parse the timestamp string into a DateTime object
-    let parsed_timestamp = DateTime::parse_from_rfc3339(timestamp).unwrap_or_else(|_| Utc::now().into());
-    
+fn format_timestamp(timestamp: DateTime<Utc>) -> String {
     // Convert to local time
-    let local_timestamp = parsed_timestamp.with_timezone(&Local);
+    let local_timestamp = timestamp.with_timezone(&Local);
 
     // Format the date into a readable format, e.g., "Sep 26, 2024 12:45 PM"
     local_timestamp.format("%b %d, %Y %I:%M %p").to_string()
-}
\ No newline at end of file
+}
@@ -3,45 +3,58 @@ use dioxus:: prelude::*;
 // Api mongo structs
 use futures::executor::block_on;
 use dioxus_logger::tracing::{info, error, warn};
-use mongodb::{sync::Client, bson::doc};
+use mongodb::bson::doc;
 use crate::api::mongo_format::mongo_structs::*;
+use crate::api::mongo_format::user_store::UserStore;
 use crate::api::slack::event_server::*;
+use crate::api::slack::socket_mode::*;
 use crate::api::slack::server_utils::*;
+use crate::api::chat_provider::*;
+use crate::api::slack::slack_provider::SlackProvider;
+use crate::api::unified_message::*;
 use crate::front_ends::Slack::*;
 use slack_morphism::prelude::*;
 use serde_json::Value;
 use std::sync::Arc;
+use std::rc::Rc;
+use std::cell::RefCell;
 use tokio::sync::{Mutex, oneshot};
-use std::collections::HashMap;
 struct EmptyStruct {}
 
+// Messages read when a channel is opened
+const HISTORY_LIMIT: usize = 999;
+
 
 #[component]
 pub fn Slack(current_platform: Signal<String>,) -> Element {
     // ! User Mutex Lock to access the user data
     let user_lock = use_context::<Signal<Arc<Mutex<User>>>>();
-    let client_lock = use_context::<Signal<Arc<Mutex<Option<Client>>>>>();
+    let user_store = use_context::<Signal<Arc<dyn UserStore>>>();
+    // Channel picked in the inbox that should be opened on entry
+    let mut pending_channel = use_context::<Signal<Option<ChatChannel>>>();
 
     // Lock the user context and clone it for async context
     let user_lock_api = Arc::clone(&user_lock());
 
-    let client_lock_new = Arc::clone(&client_lock());
+    let user_store_new = user_store();
     let user_lock_new = Arc::clone(&user_lock());
 
-    let mut public_channels     : Signal<Vec<SlackChannelInfo>> = use_signal(||Vec::new()); 
-    let mut private_channels    : Signal<Vec<SlackChannelInfo>> = use_signal(||Vec::new());
-    let mut mpim_channels       : Signal<Vec<SlackChannelInfo>> = use_signal(||Vec::new());  
-    let mut im_channels         : Signal<Vec<SlackChannelInfo>> = use_signal(||Vec::new());
-    let mut event_messages      : Signal<HashMap<String, SlackMessageEvent>> = use_signal(||HashMap::<String, SlackMessageEvent>::new());
-    let mut event_messages_vec  : Signal<Vec<String>>   = use_signal(|| Vec::<String>::new());
-    let mut history_list        : Signal<HashMap<String, SlackHistoryMessage>> = use_signal(||HashMap::<String, SlackHistoryMessage>::new());
-    let mut history_list_vec    : Signal<Vec<String>> = use_signal(|| Vec::<String>::new());
-    let mut current_channel     : Signal<Option<SlackChannelInfo>> = use_signal(||None);
-    let mut user_list           : Signal<HashMap<String, SlackUser>> = use_signal(||HashMap::<String, SlackUser>::new());
-    
-
     let user_lock_install = Arc::clone(&user_lock());
     let user_lock_first_try = Arc::clone(&user_lock());
+    let user_lock_provider = Arc::clone(&user_lock());
+
+    let mut channels            : Signal<Vec<ChatChannel>> = use_signal(Vec::new); 
+    // Oldest first, the history of the current channel and what arrived since
+    let mut messages            : Signal<Vec<UnifiedMessage>> = use_signal(Vec::new);
+    let mut current_channel     : Signal<Option<ChatChannel>> = use_signal(||None);
+    let mut provider            : Signal<Rc<SlackProvider>> = use_signal(||
+        block_on(async {
+            Rc::new(SlackProvider::new(user_lock_provider.lock().await.slack.active()))
+        })
+    );
+    // The user's own Slack id, to tell which reactions are theirs
+    let own_user_id = use_memo(move || provider().user_id().to_string());
+    
 
     let mut installed = use_signal(|| false);
 
@@ -50,7 +63,7 @@ pub fn Slack(current_platform: Signal<String>,) -> Element {
         block_on(
             async {
                 let  user = user_lock_first_try.lock().await;
-                if user.slack.app_id != "" {
+                if user.slack.active().app_id != "" {
                     true
                 } else {
                     false
@@ -58,23 +71,45 @@ pub fn Slack(current_platform: Signal<String>,) -> Element {
         
     }));
 
-     // Create a oneshot channel to signal the task to stop
-    let (stop_tx, stop_rx) = oneshot::channel();
+    // ! Spawn events api, once for as long as the page is shown
+    let stop_tx = use_hook(move || {
+        // Create a oneshot channel to signal the task to stop
+        let (stop_tx, stop_rx) = oneshot::channel::<()>();
 
-    // ! Spawn events api
-    use_effect({
         // Spawn the task with a signal to stop
         tokio::spawn(async move {
+            // Socket Mode accounts still need the local endpoint for the OAuth redirect
+            let slack = user_lock_api.lock().await.slack.active().clone();
+            let socket_mode = match slack.transport {
+                SlackTransport::SocketMode => match SocketModeListener::start(&slack).await {
+                    Ok(listener) => Some(listener),
+                    Err(err) => {
+                        error!("Failed to start Slack Socket Mode: {}", err);
+                        None
+                    }
+                },
+                SlackTransport::Events => None,
+            };
+
             tokio::select! {
                 _ = events_api(user_lock_api) => {},
                 _ = stop_rx => {
                     info!("Stopping request endpoint");
                 }
             }
+
+            if let Some(listener) = socket_mode {
+                listener.shutdown().await;
+            }
         });
-    
-        // Return a cleanup function if needed (none in this case)
-        || {}
+
+        Rc::new(RefCell::new(Some(stop_tx)))
+    });
+
+    // Leaving the page closes the endpoint and the Socket Mode connections
+    use_drop({
+        let stop_tx = Rc::clone(&stop_tx);
+        move || stop_listeners(&stop_tx)
     });
 
     // ! Fill all necessary components for the page vectors 
@@ -95,13 +130,13 @@ pub fn Slack(current_platform: Signal<String>,) -> Element {
                 // Gracefully exit the loop if the platform is not Slack
                 if !current_platform().eq("Slack") {
                     // Send the stop signal when needed
-                    stop_tx.send(()).unwrap();
+                    stop_listeners(&stop_tx);
                     info!("Consumer loop stopped");
                     break;
                 }
                     
                 let json_response =
-                    main_events::request_consumer(user_lock_new.clone(), client_lock_new.clone()).await;
+                    main_events::request_consumer(user_lock_new.clone(), user_store_new.clone()).await;
 
                 // Step 4: Error handeling
                 match json_response {
@@ -133,80 +168,32 @@ pub fn Slack(current_platform: Signal<String>,) -> Element {
                                                             // Check if the current channel is set and if the message event's channel
                                                             // matches the current channel ID.
                                                             if let Some(current_c) = current_channel() {
-                                                                if let Some(id) = message_event.clone().origin.channel {
-                                                                    if current_c.id.eq(&id) {
+                                                                if let Some(id) = message_event.origin.channel.clone() {
+                                                                    if current_c.id == id.to_string() {
                                                                         info!("Message Pushed");
 
-                                                                        // Check if the message event has a message field with a content field
-                                                                        // (i.e., it's not an empty message). If it does, then we can start
-                                                                        // processing the edited message.
-                                                                        if let Some(edited_message) = message_event.clone().message{
-                                                                            let message_id = edited_message.clone().ts.to_string();
-
-                                                                            // Extract the user ID of the person who edited the message.
-                                                                            let edit_user = edited_message.clone().sender.user.expect("No user");
-
-                                                                            // Check if the edited message has a content field. If it does, then
-                                                                            // we can start processing the edited message.
-                                                                            match edited_message.clone().content{
-                                                                                Some(edited_content) => {
-
-                                                                                    // Check if the edited message is already in the history list.
-                                                                                    // If it is, then we'll update the existing message with the
-                                                                                    // edited content.
-                                                                                    let mut found = false;
-                                                                                    let mut edited_content_clone = edited_content.clone();
-                                                                                    
-                                                                                    history_list
-                                                                                        .write()
-                                                                                        .entry(message_id.clone())
-                                                                                        .and_modify(|new_message| {
-                                                                                            let temp_reactions = new_message.content.reactions.clone();
-                                                                                            found = true;
-                                                                                            new_message.content = edited_content;
-                                                                                            new_message.edited = Some(SlackMessageEdited {user:edit_user.clone(),ts:edited_message.ts.clone()});
-                                                                                            new_message.content.reactions = temp_reactions;
-                                                                                        });
-
-                                                                                    // If the edited message is not in the history list, then we'll add
-                                                                                    // it to the event messages vector and map.
-                                                                                    if !found  {
-                                                                                        event_messages
-                                                                                            .write()
-                                                                                            .entry(message_id.clone())
-                                                                                            .and_modify(|new_message| {
-
-                                                                                                let temp_reactions = match new_message.content.clone() {
-                                                                                                    Some(content) => match content.reactions {
-                                                                                                        Some(reactions) => Some(reactions.clone()),
-                                                                                                        None => None
-                                                                                                    },
-                                                                                                    None => None
-                                                                                                };
-
-                                                                                                found = true;
-                                                                                                edited_content_clone.reactions = temp_reactions.clone();
-                                                                                                new_message.content = Some(edited_content_clone);
-                                                                                                new_message.message = Some(edited_message.clone()); 
-                                                                                                
-                                                                                            });
-                                                                                    }
+                                                                        match UnifiedMessage::from_slack_event(&message_event) {
+                                                                            Some(mut new_message) => {
+                                                                                provider().name_author(&mut new_message).await;
+
+                                                                                // An edited message replaces the one in the list, reactions
+                                                                                // don't come with the edit so they are kept
+                                                                                let mut messages = messages.write();
+                                                                                match messages.iter_mut().find(|message| message.id == new_message.id) {
+                                                                                    Some(message) => {
+                                                                                        new_message.reactions = std::mem::take(&mut message.reactions);
+                                                                                        *message = new_message;
+                                                                                    },
+                                                                                    None => messages.push(new_message),
                                                                                 }
-                                                                                ,None => {
-                                                                                    
+                                                                            },
+                                                                            None => {
+                                                                                // Deleted messages are taken out of the list
+                                                                                if let Some(deleted_ts) = message_event.deleted_ts {
+                                                                                    messages.write().retain(|message| message.id != deleted_ts.to_string());
                                                                                 }
                                                                             }
                                                                         }
-                                                                        else {
-                                                                            // If the message event does not have a message field, then we'll add
-                                                                            // it to the event messages vector and map.
-                                                                            let message_id = message_event.origin.ts.to_string(); // Adjust based on actual ID field
-                                                                            event_messages_vec.write().push(message_id.clone());
-                                                                            event_messages.write().insert(message_id.clone(), message_event.clone());
-
-                                                                        }
-                                                                       
-                                                                        
                                                                     }
                                                                 }
                                                             }
@@ -236,96 +223,27 @@ pub fn Slack(current_platform: Signal<String>,) -> Element {
                                                                     // Check if there is a current channel
                                                                     if let Some(current_c) = current_channel() {
                                                                         // Check if the event belongs to the current channel
-                                                                        if let Some(id) = message.clone().origin.channel {
-                                                                            if current_c.id.eq(&id) {
-                                                                                // Flag to check if the reaction is found
-                                                                                let mut found = false;
-
-                                                                                // Attempt to update the history list with the new reaction
-                                                                                history_list
-                                                                                    .write()
-                                                                                    .entry(message.clone().origin.ts.to_string())
-                                                                                    .and_modify(|new_message| {
-                                                                                        // Check if the message already has reactions
-                                                                                        if let Some(new_reactions) = new_message.content.reactions.as_mut() {
-                                                                                            // Iterate through current reactions to find a match
-                                                                                            info!("Found in reactions");
-                                                                                            for reaction in new_reactions.iter_mut() {
-                                                                                                if reaction.name == reaction_name {
-                                                                                                    info!("Reaction found");
-                                                                                                    reaction.count += 1;
-                                                                                                    reaction.users.push(user_id.clone());
-                                                                                                    found = true;
-                                                                                                }
-                                                                                            }
-                                                                                            // If no matching reaction is found, add a new one
-                                                                                            if !found {
-                                                                                                info!("Reaction added");
-                                                                                                new_reactions.push(SlackReaction {
-                                                                                                    name: reaction_name.clone(),
-                                                                                                    count: 1,
-                                                                                                    users: vec![user_id.clone()],
-                                                                                                });
-                                                                                            }
-                                                                                        } else {
-                                                                                            // Initialize reactions vector if none exists
-                                                                                            info!{"No current vector for reactions, creating one"};
-                                                                                            new_message.content.reactions = Some(vec![SlackReaction {
-                                                                                                name: reaction_name.clone(),
-                                                                                                count: 1,
-                                                                                                users: vec![user_id.clone()],
-                                                                                            }]);
-                                                                                        }
+                                                                        if let Some(id) = message.origin.channel.clone() {
+                                                                            if current_c.id == id.to_string() {
+                                                                                let reaction_name = reaction_name.to_string();
+                                                                                let user_id = user_id.to_string();
+
+                                                                                // Count the reaction on the message it was added to
+                                                                                if let Some(reacted) = messages.write().iter_mut().find(|m| m.id == message.origin.ts.to_string()) {
+                                                                                    reacted.reaction_added(UnifiedReaction {
+                                                                                        emoji: slack_reaction_emoji(&reaction_name),
+                                                                                        key: reaction_name,
+                                                                                        image_url: None,
+                                                                                        count: 1,
+                                                                                        me: user_id == own_user_id(),
+                                                                                        users: vec![user_id],
                                                                                     });
-
-                                                                                // Attempt to update the event messages if not found in history
-                                                                                if !found {
-                                                                                    event_messages
-                                                                                        .write()
-                                                                                        .entry(message.clone().origin.ts.to_string())
-                                                                                        .and_modify(|new_message| {
-                                                                                            // Check if message content exists
-                                                                                            if let Some(content) = new_message.content.as_mut() {
-                                                                                                // Check if reactions exist
-                                                                                                if let Some(reactions) = content.reactions.as_mut() {
-                                                                                                    // Iterate through current reactions to find a match
-                                                                                                    for reaction in reactions.iter_mut() {
-                                                                                                        if reaction.name == reaction_name {
-                                                                                                            info!("Reaction found");
-                                                                                                            reaction.count += 1;
-                                                                                                            reaction.users.push(user_id.clone());
-                                                                                                            found = true;
-                                                                                                        }
-                                                                                                    }
-                                                                                                    // If no matching reaction is found, add a new one
-                                                                                                    if !found {
-                                                                                                        info!("Reaction added");
-                                                                                                        reactions.push(SlackReaction {
-                                                                                                            name: reaction_name,
-                                                                                                            count: 1,
-                                                                                                            users: vec![user_id.clone()],
-                                                                                                        });
-                                                                                                    }
-                                                                                                    // Remove reactions with a count of zero
-                                                                                                    reactions.retain(|u| u.count != 0);
-                                                                                                } else {
-                                                                                                    // Initialize reactions vector if none exists
-                                                                                                    info!{"No current vector for reactions, creating one"};
-                                                                                                    content.reactions = Some(vec![SlackReaction {
-                                                                                                        name: reaction_name.clone(),
-                                                                                                        count: 1,
-                                                                                                        users: vec![user_id.clone()],
-                                                                                                    }]);
-                                                                                                }
-                                                                                            }
-                                                                                        });
                                                                                 }
-                                                                                
                                                                             }
                                                                         }
                                                                     }
                                                                 },
-                                                                SlackReactionsItem::File(file) => {
+                                                                SlackReactionsItem::File(_) => {
                                                                     // Handle file-based reactions if needed
                                                                 }
                                                             }
@@ -360,75 +278,20 @@ pub fn Slack(current_platform: Signal<String>,) -> Element {
                                                                     if let Some(current_c) = current_channel() {
 
                                                                         // Check if the message is in the current channel
-                                                                        if let Some(id) = message.clone().origin.channel{
-                                                                            if current_c.id.eq(&id) {
-
-                                                                                // If the message is in the current channel, update the reaction count in the history list
-                                                                                info!("Reaction Item Found In messages");
-
-                                                                                let mut found = false;
-
-                                                                                // Update the reaction count in the history list
-                                                                                history_list
-                                                                                    .write()
-                                                                                    .entry(message.clone().origin.ts.to_string())
-                                                                                    .and_modify(|new_message| {
-
-                                                                                        // Check if the message has reactions
-                                                                                        if let Some(new_reactions) = new_message.content.reactions.as_mut() {
-
-                                                                                            // Now you can modify the reactions vector
-                                                                                            for reaction in new_reactions.iter_mut() {
-                                                                                                // Update the reaction count and users if the reaction matches
-                                                                                                if reaction.name == reaction_name {
-                                                                                                    reaction.count -= 1;
-                                                                                                    reaction.users.retain(|u| u != &user_id);
-                                                                                                    found = true;
-                                                                                                }
-                                                                                            }
-
-                                                                                            // Remove reactions with a count of 0
-                                                                                            new_reactions.retain(|u| u.count != 0);  
-                                                                                        }
-                                                                                    });
+                                                                        if let Some(id) = message.origin.channel.clone() {
+                                                                            if current_c.id == id.to_string() {
+                                                                                let user_id = user_id.to_string();
 
-                                                                                // If the reaction was not found in the history list, update the event messages list
-                                                                                if !found {
-                                                                                    event_messages
-                                                                                        .write()
-                                                                                        .entry(message.clone().origin.ts.to_string())
-                                                                                        .and_modify(|new_message| {
-
-                                                                                            // Check if the message has content
-                                                                                            if let Some(content) = new_message.content.as_mut() {
-
-                                                                                                // Check if the content has reactions
-                                                                                                if let Some(reactions) = content.reactions.as_mut() {
-
-                                                                                                    // Now you can access the reactions vector
-                                                                                                    for reaction in reactions.iter_mut() {
-                                                                                                        // Update the reaction count and users if the reaction matches
-                                                                                                        if reaction.name == reaction_name {
-                                                                                                            reaction.count -= 1;
-                                                                                                            reaction.users.retain(|u| u != &user_id);
-                                                                                                        }
-                                                                                                    }
-
-                                                                                                    // Remove reactions with a count of 0
-                                                                                                    reactions.retain(|u| u.count != 0); 
-                                                                                                }
-                                                                                            }
-                                                                                        });
+                                                                                // Take the reaction back from the message
+                                                                                if let Some(reacted) = messages.write().iter_mut().find(|m| m.id == message.origin.ts.to_string()) {
+                                                                                    reacted.reaction_removed(reaction_name.as_ref(), &user_id, user_id == own_user_id());
                                                                                 }
-                                                                                
-                                                                                
-                                                                                
                                                                             }
                                                                         }
                                                                     }
 
                                                                 },
-                                                                SlackReactionsItem::File(file)=>{
+                                                                SlackReactionsItem::File(_)=>{
 
                                                                 }
                                                             }
@@ -468,7 +331,7 @@ pub fn Slack(current_platform: Signal<String>,) -> Element {
         }
     });
    
-    let handle_enter_click = move |_| {
+    let mut enter_workspace = move || {
 
         block_on(
             async {
@@ -476,102 +339,47 @@ pub fn Slack(current_platform: Signal<String>,) -> Element {
                 let user = user_lock_install.lock().await;
 
                 // Check if the user has installed the Slack app
-                if user.slack.app_id != "" {
-
-                    // Create a new Slack client
-                    let client  = 
-                        SlackClient::new(SlackClientHyperConnector::new().expect("failed to create hyper connector"));
-
-                    // Create a new token from the environment variable `SLACK_CONFIG_TOKEN`
-                    let token: SlackApiToken = SlackApiToken::new(user.slack.user.token.clone().into());
-
-                    // Create a new session with the client and the token
-                    let session = client.open_session(&token);
-
-                    // Create a request to get the list of channels
-                    let get_channel_request: SlackApiConversationsListRequest = 
-                        SlackApiConversationsListRequest::new()
-                            .with_exclude_archived(true)
-                            .with_types(vec![
-                                
-                                SlackConversationType::Public,
-                                SlackConversationType::Private,
-                                SlackConversationType::Mpim,
-                                SlackConversationType::Im,
-                        ]);
-
-                    // Send the request to get the list of channels
-                    let get_channel_response = 
-                        session
-                            .conversations_list(&get_channel_request)
-                            .await
-                            .unwrap();
-
-                    // Parse the response to get the list of channels
-                    match get_channel_response.channels {
-                        channel_list => {
-
-                            // Iterate over the channels and separate them by type
-                            for channel in channel_list {
-                                match channel.flags.is_channel {
-                                    Some(true) => {
-                                        // Add the channel to the public channels list
-                                        public_channels.push(channel.clone());
-                                    },
-                                    Some(false) => {
-                                        match channel.flags.is_private {
-                                            Some(true) => {
-                                                // Add the channel to the private channels list
-                                                private_channels.push(channel.clone());
-                                            },
-                                            Some(false) => {
-                                                match channel.flags.is_mpim {
-                                                    Some(true) => {
-                                                        // Add the channel to the mpim channels list
-                                                        mpim_channels.push(channel.clone());
-                                                    },
-                                                    Some(false) => {
-                                                        // Add the channel to the im channels list
-                                                        im_channels.push(channel.clone());
-                                                    },
-                                                    None => {} // Do nothing if the channel is not an MPIM
-                                                }
-                                            },
-                                            None => {} // Do nothing if the channel is not private
-                                        }
-                                    },
-                                    None => {} // Do nothing if the channel is not a channel
+                if user.slack.active().app_id != "" {
+
+                    // The account may have been installed since the page was opened
+                    provider.set(Rc::new(SlackProvider::new(user.slack.active())));
+                    let workspace = ChatWorkspace {
+                        id: user.slack.active().team.id.clone(),
+                        name: user.slack.active().team.name.clone(),
+                        icon_url: None,
+                        platform: Platform::Slack,
+                    };
+
+                    // Get the list of channels and conversations
+                    match provider().list_channels(&workspace).await {
+                        Ok(channel_list) => {
+
+                            // Set the current channel to the first channel, or the first conversation
+                            let first = channel_list.iter().find(|channel| channel.kind == ChannelKind::Channel)
+                                .or(channel_list.first())
+                                .cloned();
+                            current_channel.set(first);
+
+                            // Prefer the channel picked in the inbox if it is one of ours
+                            let pending = pending_channel.peek().clone();
+                            if let Some(pending) = pending.filter(|c| c.platform == Platform::Slack) {
+                                let picked = channel_list.iter()
+                                    .find(|channel| channel.id == pending.id)
+                                    .cloned();
+
+                                if picked.is_some() {
+                                    current_channel.set(picked);
                                 }
+                                pending_channel.set(None);
                             }
 
-                            // Set the current channel to the first channel of any of the lists
-                            current_channel.set(
-                                // Get the first channel of any of the lists
-                                match public_channels.first() {
-                                    Some(channel) => Some(channel.clone()),
-                                    None => {
-                                        match private_channels.first() {
-                                            Some(channel) => Some(channel.clone()),
-                                            None => {
-                                                match mpim_channels.first() {
-                                                    Some(channel) => Some(channel.clone()),
-                                                    None => {
-                                                        match im_channels.first() {
-                                                            Some(channel) => Some(channel.clone()),
-                                                            None => None
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                            
-                                        }
-                                    }
-                                        // provide default values for the fields of SlackChannelIn,
-                                }
-                            );
-        
+                            channels.set(channel_list);
+
                             // Set installed to true
                             installed.set(true);
+                        },
+                        Err(e) => {
+                            error!("Failed to list Slack channels: {}", e);
                         }
                     };
 
@@ -581,51 +389,38 @@ pub fn Slack(current_platform: Signal<String>,) -> Element {
        
         
     };
+
+    let handle_enter_click = {
+        let mut enter_workspace = enter_workspace.clone();
+        move |_| enter_workspace()
+    };
+
+    // Coming from the inbox, skip the welcome screen and open the picked channel
+    use_effect(move || {
+        let from_inbox = pending_channel()
+            .is_some_and(|channel| channel.platform == Platform::Slack);
+
+        if from_inbox && *go_to.peek() && !*installed.peek() {
+            enter_workspace();
+        }
+    });
     
     
-    let lock_temp = Arc::clone(&user_lock());
     use_effect(move ||{
          // Clear old messages
             block_on(
                 async{
                     info!("Clearing old messages");
-                    // Clear the history list and its vector
-                    history_list.write().clear();
-                    history_list_vec.write().clear();
+                    messages.write().clear();
 
                     // If there is a current channel, update the history messages
                     if let Some(chan) = current_channel() {
-                        info!("Channel found, updating history messages");
-                        let user = lock_temp.lock().await;
-                        let token = user.clone().slack.user.token.clone();
-                        let team_id = user.clone().slack.team.id.clone();
-
-                        // Update the user list
-                        info!("Updating user list");
-                        for user in get_user_list(token.clone(), team_id.clone()).await.iter() {
-                            let user_id = user.clone().id.to_string();
-                            info!("Adding user: {:?}", user_id);
-                            user_list.write().insert(user_id.clone(), user.clone());
-                        }
-
                         // Get the history messages for the current channel
                         info!("Getting history messages for channel: {:?}", chan.id);
-                        for history_message in get_history_list(token, chan.clone()).await {
-                            let message_id = history_message.origin.ts.to_string(); // Adjust based on actual ID field
-                            info!("Found history message: {:?}", message_id);
-                            let mut moded_hist_mess = history_message;
-                            // ! Include Channel ID and type
-                            info!("Adding channel ID and type to history message");
-                            moded_hist_mess.origin.channel = Some(
-                                chan.id.clone()
-                            );
-                            history_list.write().insert(message_id.clone(), moded_hist_mess.clone());
-                            history_list_vec.write().push(message_id.clone());
-                            info!("Pushed history message: {:?}", message_id);
-
-                        } 
-                        
-
+                        match provider().fetch_history(&chan, &HistoryCursor::Latest, HISTORY_LIMIT).await {
+                            Ok(history) => messages.set(history),
+                            Err(e) => error!("Failed to get Slack history: {}", e),
+                        }
                     }
                     
                 }
@@ -641,16 +436,10 @@ pub fn Slack(current_platform: Signal<String>,) -> Element {
 
         if installed() {
             Slack_fe{
-                public_channels:    public_channels.clone(),
-                private_channels:   private_channels.clone(),
-                mpim_channels:      mpim_channels.clone(),
-                im_channels:        im_channels.clone(),
-                event_messages:     event_messages.clone(),
-                event_messages_vec: event_messages_vec.clone(),
-                history_list:       history_list.clone(),
-                history_list_vec:   history_list_vec.clone(),
-                current_channel:    current_channel.clone(),
-                user_list:          user_list.clone()
+                channels:           channels,
+                messages:           messages,
+                current_channel:    current_channel,
+                provider:           provider
             }
         }
         else {
@@ -684,4 +473,11 @@ pub fn Slack(current_platform: Signal<String>,) -> Element {
 
 }
 
-// ! ========================= ! //
\ No newline at end of file
+// ! ========================= ! //
+
+// Stops the events api task started by the Slack page, only the first call does anything
+fn stop_listeners(stop_tx: &RefCell<Option<oneshot::Sender<()>>>) {
+    if let Some(stop_tx) = stop_tx.borrow_mut().take() {
+        let _ = stop_tx.send(());
+    }
+}
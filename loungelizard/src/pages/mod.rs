@@ -1,4 +1,5 @@
 pub mod Slack;
 pub mod Discord;
 pub mod MSTeam;
-pub mod Home;
\ No newline at end of file
+pub mod Home;
+pub mod Inbox;
\ No newline at end of file